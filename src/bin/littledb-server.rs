@@ -0,0 +1,185 @@
+// littledb-server - serve a database over the Redis protocol, or as a REST API
+//
+//   littledb-server [--db PATH] [--bind ADDRESS] [--port PORT] [--save-every SECONDS] [--http]
+//                   [--replicate-on ADDRESS] [--follow HOST:PORT]
+//
+// Defaults: mydata.db on 127.0.0.1:6379, saved every second when changed
+// (--save-every 0 saves only on SAVE and SHUTDOWN). Stop it with
+// `redis-cli shutdown`, which saves first.
+//
+// With --http it serves the REST API of littledb::http instead, on port 8080
+// unless --port says otherwise. HTTP has no SHUTDOWN, so keep --save-every
+// short: a killed server loses what changed since the last save.
+//
+// Replication (see littledb::replication): --replicate-on HOST:PORT serves
+// this server's changes to followers there; --follow HOST:PORT makes it a
+// read-only copy of the primary replicating on that address.
+//
+//   littledb-server --db a.db --port 6379 --replicate-on 127.0.0.1:7379
+//   littledb-server --db b.db --port 6380 --follow 127.0.0.1:7379
+
+use std::io;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use littledb::Database;
+use littledb::resp::Server;
+
+const USAGE: &str = "usage: littledb-server [--db PATH] [--bind ADDRESS] [--port PORT] [--save-every SECONDS] [--http]
+                       [--replicate-on ADDRESS] [--follow HOST:PORT]";
+
+struct Options {
+    db: String,
+    bind: String,
+    port: Option<u16>, // None = the protocol's usual port
+    save_every: u64,
+    http: bool,
+    replicate_on: Option<String>,
+    follow: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse(std::env::args().skip(1).collect()) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("littledb-server: {}\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match serve(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("littledb-server: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn serve(options: &Options) -> Result<(), String> {
+    let mut db = Database::new(&options.db);
+    db.set_verbose(false);
+    db.load()
+        .map_err(|e| format!("can't read '{}': {}", options.db, e))?;
+    let keys = db.count();
+
+    let default_port = if options.http { 8080 } else { 6379 };
+    let port = options.port.unwrap_or(default_port);
+    let address = (options.bind.as_str(), port);
+    let save_interval = match options.save_every {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
+    let listen_error = |e| format!("can't listen on {}:{}: {}", options.bind, port, e);
+    let announce = |kind: &str, addr: io::Result<SocketAddr>| {
+        let addr = addr.map_err(|e| e.to_string())?;
+        println!(
+            "littledb-server: '{}' ({} keys), {} on {}",
+            options.db, keys, kind, addr
+        );
+        Ok::<(), String>(())
+    };
+    let replication_error = |e| {
+        format!(
+            "can't replicate on {}: {}",
+            options.replicate_on.as_deref().unwrap_or(""),
+            e
+        )
+    };
+    let announce_replication = |addr: Option<SocketAddr>| {
+        if let Some(addr) = addr {
+            println!("littledb-server: replication on {}", addr);
+        }
+        if let Some(primary) = &options.follow {
+            println!("littledb-server: following {}", primary);
+        }
+    };
+
+    let stopped = if options.http {
+        #[cfg(feature = "json")]
+        {
+            let mut server = littledb::http::Server::bind(address, db)
+                .map_err(listen_error)?
+                .save_interval(save_interval);
+            if let Some(addr) = &options.replicate_on {
+                server = server.replicate_on(addr).map_err(replication_error)?;
+            }
+            if let Some(primary) = &options.follow {
+                server = server.follow(primary);
+            }
+            announce("HTTP", server.local_addr())?;
+            announce_replication(server.replication_addr());
+            server.run()
+        }
+        #[cfg(not(feature = "json"))]
+        return Err("--http needs littledb built with the `json` feature".to_string());
+    } else {
+        let mut server = Server::bind(address, db)
+            .map_err(listen_error)?
+            .save_interval(save_interval);
+        if let Some(addr) = &options.replicate_on {
+            server = server.replicate_on(addr).map_err(replication_error)?;
+        }
+        if let Some(primary) = &options.follow {
+            server = server.follow(primary);
+        }
+        announce("Redis protocol", server.local_addr())?;
+        announce_replication(server.replication_addr());
+        server.run()
+    };
+    stopped.map_err(|e| format!("saving failed: {}", e))?;
+    println!("littledb-server: saved and stopped");
+    Ok(())
+}
+
+// None when help was asked for
+fn parse(tokens: Vec<String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        db: "mydata.db".to_string(),
+        bind: "127.0.0.1".to_string(),
+        port: None,
+        save_every: 1,
+        http: false,
+        replicate_on: None,
+        follow: None,
+    };
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        if token == "--help" || token == "-h" {
+            return Ok(None);
+        }
+        if token == "--http" {
+            options.http = true;
+            continue;
+        }
+        let (name, value) = match token.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (token, None),
+        };
+        let mut value = || {
+            value
+                .clone()
+                .or_else(|| tokens.next())
+                .ok_or_else(|| format!("option {} needs a value", name))
+        };
+        match name.as_str() {
+            "--db" => options.db = value()?,
+            "--bind" => options.bind = value()?,
+            "--port" => options.port = Some(number(&name, &value()?)?),
+            "--save-every" => options.save_every = number(&name, &value()?)?,
+            "--replicate-on" => options.replicate_on = Some(value()?),
+            "--follow" => options.follow = Some(value()?),
+            _ => return Err(format!("unknown argument '{}'", name)),
+        }
+    }
+    Ok(Some(options))
+}
+
+fn number<T: std::str::FromStr>(name: &str, text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("invalid value for {}: '{}'", name, text))
+}
@@ -0,0 +1,159 @@
+// NEW: The write log - every change to the data, in order, numbered
+//
+// Replication (see replication/) streams it from a primary to its followers.
+// A log sequence number (LSN) counts the changes since a history began; every
+// history has a random id, so an LSN only means something together with the
+// id of the history it belongs to.
+//
+// The keyspaces record what their writes did (Keyspace::put, remove, ...) and
+// the database numbers those records whenever the log is read. Only recent
+// changes are kept in memory: a follower further behind than that copies a
+// snapshot of the whole database instead.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+use crate::keyspace::CollectionOptions;
+use crate::types::Uuid;
+
+// Changes kept for followers that fall behind
+pub(crate) const BACKLOG: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Change {
+    pub(crate) collection: Option<String>, // None = the database's own keys
+    pub(crate) op: ChangeOp,
+}
+
+// What happened to one keyspace. Each op carries the resulting state rather
+// than the request (a Put holds the value stored, with its absolute expiry
+// deadline), so replaying it gives the same data whenever it is replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum ChangeOp {
+    // Deadlines are nanoseconds since the epoch, as in Keyspace::expires
+    Put {
+        key: String,
+        value: Value,
+        expires: Option<i64>,
+    },
+    Delete {
+        key: String,
+    },
+    Expire {
+        key: String,
+        expires: Option<i64>,
+    },
+    Clear,
+    // New schemas or default TTL; the first op of a new collection
+    Options(CollectionOptions),
+    DropCollection,
+}
+
+// Where a database is in a history; saved next to the data file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LogPosition {
+    pub(crate) id: String,
+    pub(crate) lsn: u64,
+}
+
+pub(crate) struct ChangeLog {
+    pub(crate) id: String,
+    pub(crate) lsn: u64, // the last change numbered
+    // The history this one branched from, and its last LSN the two share
+    previous: Option<LogPosition>,
+    recent: VecDeque<(u64, Change)>,
+}
+
+impl ChangeLog {
+    // A new history
+    pub(crate) fn new() -> Self {
+        ChangeLog::resume(LogPosition {
+            id: new_id(),
+            lsn: 0,
+        })
+    }
+
+    // Continue a history from a saved position; the changes before it are gone
+    pub(crate) fn resume(position: LogPosition) -> Self {
+        ChangeLog {
+            id: position.id,
+            lsn: position.lsn,
+            previous: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn position(&self) -> LogPosition {
+        LogPosition {
+            id: self.id.clone(),
+            lsn: self.lsn,
+        }
+    }
+
+    pub(crate) fn push(&mut self, change: Change) {
+        self.push_numbered(self.lsn + 1, change);
+    }
+
+    // A change numbered by another database (the primary of a follower)
+    pub(crate) fn push_numbered(&mut self, lsn: u64, change: Change) {
+        self.lsn = lsn;
+        if self.recent.len() == BACKLOG {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((lsn, change));
+    }
+
+    // Every change after `lsn`, or None if some of them are no longer kept
+    pub(crate) fn since(&self, lsn: u64) -> Option<Vec<(u64, Change)>> {
+        if lsn > self.lsn {
+            return None;
+        }
+        let first = self
+            .recent
+            .front()
+            .map_or(self.lsn + 1, |(first, _)| *first);
+        if lsn + 1 < first {
+            return None;
+        }
+        let skip = (lsn + 1 - first) as usize;
+        Some(self.recent.iter().skip(skip).cloned().collect())
+    }
+
+    // Whether a database at `position` holds a prefix of this history that
+    // the kept changes can bring up to date
+    pub(crate) fn can_resume(&self, position: &LogPosition) -> bool {
+        let shared = if position.id == self.id {
+            true
+        } else {
+            self.previous
+                .as_ref()
+                .is_some_and(|previous| previous.id == position.id && position.lsn <= previous.lsn)
+        };
+        shared && self.since(position.lsn).is_some()
+    }
+
+    // Start a new history where this one stands (a primary starting up, a
+    // follower being promoted): databases that followed the old one up to
+    // here can carry on, those that went further elsewhere can't
+    pub(crate) fn branch(&mut self) {
+        self.previous = Some(self.position());
+        self.id = new_id();
+    }
+
+    // Continue as part of history `id`, which shares ours up to here (a
+    // follower resuming from a primary that branched)
+    pub(crate) fn adopt(&mut self, id: String) {
+        self.id = id;
+    }
+
+    // Start over at `position` (a follower that loaded a snapshot)
+    pub(crate) fn reset(&mut self, position: LogPosition) {
+        *self = ChangeLog::resume(position);
+    }
+}
+
+fn new_id() -> String {
+    Uuid::new_v4().to_string().replace('-', "")
+}
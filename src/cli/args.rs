@@ -0,0 +1,118 @@
+// Command-line parsing, by hand (the tool has no dependencies beyond the library's)
+//
+//   littledb [--db PATH] [--json] [--collection NAME] <command> [arguments] [options]
+//
+// Options can come before or after the command, as `--name value` or
+// `--name=value`. `--` ends the options, so a key can start with a dash:
+//   littledb delete -- --odd-key
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// Where the database lives when --db isn't given
+pub const DEFAULT_DB: &str = "mydata.db";
+
+// Options that take a value; every other option is a flag
+const VALUE_OPTIONS: &[&str] = &[
+    "db",
+    "collection",
+    "ttl",
+    "limit",
+    "offset",
+    "sort",
+    "select",
+    "format",
+    "prefix",
+];
+
+pub struct Args {
+    pub db: String,
+    pub json: bool,                 // machine-readable output
+    pub collection: Option<String>, // work on a collection instead of the database's own keys
+    pub help: bool,
+    pub command: Option<String>,
+    pub positional: Vec<String>,
+    options: BTreeMap<String, String>, // the command's own options; flags have an empty value
+}
+
+impl Args {
+    pub fn parse(tokens: Vec<String>) -> Result<Args, String> {
+        let mut args = Args {
+            db: DEFAULT_DB.to_string(),
+            json: false,
+            collection: None,
+            help: false,
+            command: None,
+            positional: Vec::new(),
+            options: BTreeMap::new(),
+        };
+        let mut tokens = tokens.into_iter();
+        let mut options_done = false;
+        while let Some(token) = tokens.next() {
+            if options_done || !token.starts_with("--") || token == "-" {
+                if token == "-h" && !options_done {
+                    args.help = true;
+                } else if args.command.is_none() {
+                    args.command = Some(token);
+                } else {
+                    args.positional.push(token);
+                }
+                continue;
+            }
+            if token == "--" {
+                options_done = true;
+                continue;
+            }
+            let (name, inline_value) = match token[2..].split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (token[2..].to_string(), None),
+            };
+            let value = if VALUE_OPTIONS.contains(&name.as_str()) {
+                match inline_value {
+                    Some(value) => value,
+                    None => tokens
+                        .next()
+                        .ok_or_else(|| format!("option --{} needs a value", name))?,
+                }
+            } else if inline_value.is_some() {
+                return Err(format!("option --{} doesn't take a value", name));
+            } else {
+                String::new()
+            };
+            match name.as_str() {
+                "db" => args.db = value,
+                "json" => args.json = true,
+                "collection" => args.collection = Some(value),
+                "help" => args.help = true,
+                _ => {
+                    args.options.insert(name, value);
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    // Command options that were given (for checking them against the command)
+    pub fn option_names(&self) -> impl Iterator<Item = &str> {
+        self.options.keys().map(String::as_str)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    // An option parsed into a number (or anything else with FromStr)
+    pub fn parsed<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.option(name) {
+            Some(text) => text
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid value for --{}: '{}'", name, text)),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,869 @@
+// The littledb command-line tool
+//
+//   littledb --db mydata.db set user:1 '{"name": "Alice", "age": 30}'
+//   littledb --db mydata.db get user:1
+//   littledb --db mydata.db query '{"age": {"$gt": 28}}' --sort age --desc
+//   littledb --db mydata.db --json stats
+//   littledb --db mydata.db --collection users list
+//   littledb shell mydata.db                       (interactive, see shell.rs)
+//
+// Exit status: 0 on success, 1 when the command failed (missing key, bad
+// value, problems found by verify), 2 when the command line itself is wrong.
+
+mod args;
+mod output;
+mod shell;
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use littledb::{
+    Collection, Condition, Database, JsonError, QueryOptions, QueryPlan, SortOrder, Value,
+};
+use serde_json::{Map, Value as Json, json};
+
+use args::Args;
+use output::{format_duration, parse_duration, pretty, print_json, to_json};
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    summary: &'static str,
+    options: &'static [&'static str],
+    creates: bool, // may run on a database file that doesn't exist yet
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "get",
+        usage: "get <key>",
+        summary: "print the value stored under a key",
+        options: &[],
+        creates: false,
+    },
+    Command {
+        name: "set",
+        usage: "set <key> [json] [--ttl 30s] [--string]",
+        summary: "store a JSON value (read from stdin if not given; --string stores the text as is)",
+        options: &["ttl", "string"],
+        creates: true,
+    },
+    Command {
+        name: "delete",
+        usage: "delete <key>...",
+        summary: "delete keys",
+        options: &[],
+        creates: false,
+    },
+    Command {
+        name: "list",
+        usage: "list [prefix] [--limit n]",
+        summary: "list keys, all of them or those starting with a prefix",
+        options: &["limit"],
+        creates: false,
+    },
+    Command {
+        name: "query",
+        usage: "query <filter> [--sort field] [--desc] [--limit n] [--offset n] [--select a,b] [--explain]",
+        summary: "find values matching a JSON filter, e.g. '{\"age\": {\"$gt\": 28}}'",
+        options: &["sort", "desc", "limit", "offset", "select", "explain"],
+        creates: false,
+    },
+    Command {
+        name: "stats",
+        usage: "stats",
+        summary: "show entry counts, collections, indexes and file size",
+        options: &[],
+        creates: false,
+    },
+    Command {
+        name: "import",
+        usage: "import <file|-> [--format json|jsonl]",
+        summary: "insert entries from a JSON object or JSON lines ({\"key\": .., \"value\": ..})",
+        options: &["format"],
+        creates: true,
+    },
+    Command {
+        name: "export",
+        usage: "export [file|-] [--format json|jsonl] [--prefix p]",
+        summary: "write entries as a JSON object or JSON lines (to stdout if no file)",
+        options: &["format", "prefix"],
+        creates: false,
+    },
+    Command {
+        name: "compact",
+        usage: "compact",
+        summary: "rewrite the file without expired entries",
+        options: &[],
+        creates: false,
+    },
+    Command {
+        name: "verify",
+        usage: "verify",
+        summary: "check that the file loads and every value fits its schema",
+        options: &[],
+        creates: false,
+    },
+    Command {
+        name: "dump",
+        usage: "dump",
+        summary: "print everything: collections, indexes, schemas, keys with versions and TTLs",
+        options: &[],
+        creates: false,
+    },
+    Command {
+        name: "shell",
+        usage: "shell [file]",
+        summary: "open an interactive shell on the database (the file defaults to --db)",
+        options: &[],
+        creates: true,
+    },
+    Command {
+        name: "help",
+        usage: "help",
+        summary: "show this help",
+        options: &[],
+        creates: true,
+    },
+];
+
+enum CliError {
+    Usage(String),   // the command line is wrong
+    Failure(String), // the command ran and failed
+}
+
+fn usage(message: impl Into<String>) -> CliError {
+    CliError::Usage(message.into())
+}
+
+fn failure(message: impl Into<String>) -> CliError {
+    CliError::Failure(message.into())
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        failure(e.to_string())
+    }
+}
+
+// Returns the exit status
+pub fn main(tokens: Vec<String>) -> i32 {
+    let (json, result) = match Args::parse(tokens) {
+        Ok(args) => (args.json, run(&args)),
+        Err(message) => (false, Err(usage(message))),
+    };
+    let (status, message) = match result {
+        Ok(()) => return 0,
+        Err(CliError::Usage(message)) => (2, message),
+        Err(CliError::Failure(message)) => (1, message),
+    };
+    if json {
+        eprintln!("{}", json!({ "error": message }));
+    } else {
+        eprintln!("error: {}", message);
+        if status == 2 {
+            eprintln!("run 'littledb help' for the list of commands");
+        }
+    }
+    status
+}
+
+fn run(args: &Args) -> Result<(), CliError> {
+    let Some(name) = args.command.as_deref() else {
+        print_help();
+        return if args.help {
+            Ok(())
+        } else {
+            Err(usage("no command given"))
+        };
+    };
+    let command = COMMANDS
+        .iter()
+        .find(|command| command.name == name)
+        .ok_or_else(|| usage(format!("unknown command '{}'", name)))?;
+    if args.help || command.name == "help" {
+        print_help();
+        return Ok(());
+    }
+    for option in args.option_names() {
+        if !command.options.contains(&option) {
+            return Err(usage(format!(
+                "'{}' has no option --{} (usage: littledb {})",
+                command.name, option, command.usage
+            )));
+        }
+    }
+
+    if command.name == "shell" {
+        if args.collection.is_some() {
+            return Err(usage(
+                "the shell works on the database's own keys (no --collection)",
+            ));
+        }
+        no_more_arguments(args, 1)?;
+        let path = args.positional.first().unwrap_or(&args.db);
+        return shell::run(path).map_err(failure);
+    }
+
+    if !command.creates && !Path::new(&args.db).exists() {
+        return Err(failure(format!("no database at '{}'", args.db)));
+    }
+    let mut db = Database::new(&args.db);
+    db.set_verbose(false);
+    db.load()
+        .map_err(|e| failure(format!("can't read '{}': {}", args.db, e)))?;
+
+    match command.name {
+        "get" => get(&mut db, args),
+        "set" => set(&mut db, args),
+        "delete" => delete(&mut db, args),
+        "list" => list(&mut db, args),
+        "query" => query(&mut db, args),
+        "stats" => stats(&mut db, args),
+        "import" => import(&mut db, args),
+        "export" => export(&mut db, args),
+        "compact" => compact(&mut db, args),
+        "verify" => verify(&mut db, args),
+        "dump" => dump(&mut db, args),
+        _ => unreachable!("every command in COMMANDS is handled"),
+    }
+}
+
+fn print_help() {
+    println!("littledb - inspect and change a littledb database");
+    println!();
+    println!("usage: littledb [--db PATH] [--json] [--collection NAME] <command> [arguments]");
+    println!();
+    println!("commands:");
+    for command in COMMANDS {
+        println!("  {}", command.usage);
+        println!("      {}", command.summary);
+    }
+    println!();
+    println!("options:");
+    println!(
+        "  --db PATH          database file (default {})",
+        args::DEFAULT_DB
+    );
+    println!("  --json             print JSON instead of text");
+    println!("  --collection NAME  work on a collection instead of the database's own keys");
+}
+
+// Positional argument `index`, which the command needs
+fn required<'a>(args: &'a Args, index: usize, what: &str) -> Result<&'a str, CliError> {
+    let command = args.command.as_deref().unwrap_or_default();
+    args.positional
+        .get(index)
+        .map(String::as_str)
+        .ok_or_else(|| usage(format!("'{}' needs {}", command, what)))
+}
+
+fn no_more_arguments(args: &Args, expected: usize) -> Result<(), CliError> {
+    match args.positional.get(expected) {
+        Some(extra) => Err(usage(format!("unexpected argument '{}'", extra))),
+        None => Ok(()),
+    }
+}
+
+// ---------- The keys a command works on ----------
+
+// The database's own keys, or one collection (--collection)
+enum Target<'a> {
+    Main(&'a mut Database),
+    Collection(Collection<'a>),
+}
+
+impl<'a> Target<'a> {
+    // `create`: commands that write may create the collection
+    fn open(db: &'a mut Database, args: &Args, create: bool) -> Result<Target<'a>, CliError> {
+        match &args.collection {
+            None => Ok(Target::Main(db)),
+            Some(name) if create || db.has_collection(name) => {
+                Ok(Target::Collection(db.collection(name)))
+            }
+            Some(name) => Err(failure(format!("no collection '{}'", name))),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Value> {
+        match self {
+            Target::Main(db) => db.get(key),
+            Target::Collection(c) => c.get(key),
+        }
+    }
+
+    fn version(&self, key: &str) -> Option<u64> {
+        match self {
+            Target::Main(db) => db.version(key),
+            Target::Collection(c) => c.version(key),
+        }
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        match self {
+            Target::Main(db) => db.ttl(key),
+            Target::Collection(c) => c.ttl(key),
+        }
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        match self {
+            Target::Main(db) => db.keys_with_prefix(prefix),
+            Target::Collection(c) => c.keys_with_prefix(prefix),
+        }
+    }
+
+    fn query_with(&self, condition: Condition, options: &QueryOptions) -> Vec<(String, Value)> {
+        match self {
+            Target::Main(db) => db.query_with(condition, options),
+            Target::Collection(c) => c.query_with(condition, options),
+        }
+    }
+
+    fn explain(&self, condition: Condition) -> QueryPlan {
+        match self {
+            Target::Main(db) => db.explain(condition),
+            Target::Collection(c) => c.explain(condition),
+        }
+    }
+
+    fn list_indexes(&self) -> Vec<String> {
+        match self {
+            Target::Main(db) => db.list_indexes(),
+            Target::Collection(c) => c.list_indexes(),
+        }
+    }
+
+    fn list_schemas(&self) -> Vec<String> {
+        match self {
+            Target::Main(db) => db.list_schemas(),
+            Target::Collection(c) => c.list_schemas(),
+        }
+    }
+
+    fn insert(&mut self, key: String, value: Value, ttl: Option<Duration>) -> io::Result<()> {
+        match (self, ttl) {
+            (Target::Main(db), None) => db.insert(key, value),
+            (Target::Main(db), Some(ttl)) => db.insert_with_ttl(key, value, ttl),
+            (Target::Collection(c), None) => c.insert(key, value),
+            (Target::Collection(c), Some(ttl)) => c.insert_with_ttl(key, value, ttl),
+        }
+    }
+
+    fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> io::Result<usize> {
+        match self {
+            Target::Main(db) => db.batch_insert(entries),
+            Target::Collection(c) => c.batch_insert(entries),
+        }
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), String> {
+        match self {
+            Target::Main(db) => db.delete(key),
+            Target::Collection(c) => c.delete(key),
+        }
+    }
+}
+
+// ---------- Commands ----------
+
+fn get(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let key = required(args, 0, "a key")?;
+    no_more_arguments(args, 1)?;
+    let target = Target::open(db, args, false)?;
+    let value = target
+        .get(key)
+        .ok_or_else(|| failure(format!("Key '{}' not found", key)))?;
+    if args.json {
+        print_json(&json!({
+            "key": key,
+            "value": to_json(key, &value).map_err(failure)?,
+            "version": target.version(key),
+            "ttl_ms": target.ttl(key).map(|ttl| ttl.as_millis() as u64),
+        }));
+    } else {
+        println!("{}", pretty(&value));
+    }
+    Ok(())
+}
+
+fn set(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let key = required(args, 0, "a key")?;
+    no_more_arguments(args, 2)?;
+    let text = match args.positional.get(1).map(String::as_str) {
+        Some("-") | None => read_input("-")?,
+        Some(text) => text.to_string(),
+    };
+    let value = if args.flag("string") {
+        Value::String(text)
+    } else {
+        Value::from_json_str(&text).map_err(|e| {
+            failure(format!(
+                "the value is not valid JSON ({}); use --string to store it as text",
+                e
+            ))
+        })?
+    };
+    let ttl = args
+        .option("ttl")
+        .map(parse_duration)
+        .transpose()
+        .map_err(usage)?;
+    let mut target = Target::open(db, args, true)?;
+    target.insert(key.to_string(), value, ttl)?;
+    let version = target.version(key);
+    if args.json {
+        print_json(&json!({ "key": key, "version": version }));
+    } else {
+        println!("set '{}' (version {})", key, version.unwrap_or_default());
+    }
+    Ok(())
+}
+
+fn delete(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    required(args, 0, "at least one key")?;
+    let mut deleted = Vec::new();
+    let mut missing = Vec::new();
+    {
+        let mut target = Target::open(db, args, false)?;
+        for key in &args.positional {
+            match target.delete(key) {
+                Ok(()) => deleted.push(key.as_str()),
+                Err(_) => missing.push(key.as_str()),
+            }
+        }
+    }
+    if !deleted.is_empty() {
+        db.save()?;
+    }
+    if args.json {
+        print_json(&json!({ "deleted": deleted, "missing": missing }));
+    } else {
+        println!("deleted {} key(s)", deleted.len());
+    }
+    match missing.as_slice() {
+        [] => Ok(()),
+        [key] => Err(failure(format!("Key '{}' not found", key))),
+        keys => Err(failure(format!("keys not found: {}", keys.join(", ")))),
+    }
+}
+
+fn list(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let prefix = args.positional.first().map(String::as_str).unwrap_or("");
+    no_more_arguments(args, 1)?;
+    let limit: Option<usize> = args.parsed("limit").map_err(usage)?;
+    let target = Target::open(db, args, false)?;
+    let mut keys = target.keys_with_prefix(prefix);
+    if let Some(limit) = limit {
+        keys.truncate(limit);
+    }
+    if args.json {
+        print_json(&json!(keys));
+    } else {
+        for key in &keys {
+            println!("{}", key);
+        }
+    }
+    Ok(())
+}
+
+fn query(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let filter = required(args, 0, "a filter, e.g. '{\"age\": {\"$gt\": 28}}'")?;
+    no_more_arguments(args, 1)?;
+    let condition = Condition::from_json(filter).map_err(|e| usage(e.to_string()))?;
+    let target = Target::open(db, args, false)?;
+
+    if args.flag("explain") {
+        let plan = target.explain(condition);
+        if args.json {
+            print_json(&json!({ "plan": plan.to_string() }));
+        } else {
+            println!("{}", plan.to_string().trim_end());
+        }
+        return Ok(());
+    }
+
+    let mut options = QueryOptions::new();
+    if let Some(field) = args.option("sort") {
+        let order = if args.flag("desc") {
+            SortOrder::Descending
+        } else {
+            SortOrder::Ascending
+        };
+        options = options.order_by(field, order);
+    } else if args.flag("desc") {
+        return Err(usage("--desc needs --sort"));
+    }
+    if let Some(offset) = args.parsed("offset").map_err(usage)? {
+        options = options.offset(offset);
+    }
+    if let Some(limit) = args.parsed("limit").map_err(usage)? {
+        options = options.limit(limit);
+    }
+    if let Some(fields) = args.option("select") {
+        options = options.select(fields.split(',').map(|f| f.trim().to_string()).collect());
+    }
+
+    let results = target.query_with(condition, &options);
+    if args.json {
+        let rows = results
+            .iter()
+            .map(|(key, value)| Ok(json!({ "key": key, "value": to_json(key, value)? })))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(failure)?;
+        print_json(&Json::Array(rows));
+    } else {
+        for (key, value) in &results {
+            println!("{}  {}", key, value);
+        }
+        println!("({} result{})", results.len(), plural(results.len()));
+    }
+    Ok(())
+}
+
+fn stats(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    no_more_arguments(args, 0)?;
+    if let Some(name) = &args.collection {
+        if !db.has_collection(name) {
+            return Err(failure(format!("no collection '{}'", name)));
+        }
+        let collection = db.collection(name);
+        let stats = collection.stats();
+        let schemas = collection.list_schemas();
+        if args.json {
+            print_json(&json!({
+                "collection": stats.name,
+                "entries": stats.entries,
+                "expiring": stats.expiring,
+                "indexes": stats.indexes,
+                "text_indexes": stats.text_indexes,
+                "schemas": schemas,
+                "default_ttl_ms": stats.default_ttl.map(|ttl| ttl.as_millis() as u64),
+            }));
+        } else {
+            println!("=== Collection '{}' ===", stats.name);
+            println!("Entries: {} ({} expiring)", stats.entries, stats.expiring);
+            println!("Indexes: {}", list_or_none(&stats.indexes));
+            println!("Text indexes: {}", list_or_none(&stats.text_indexes));
+            println!("Schemas: {}", list_or_none(&schemas));
+            if let Some(ttl) = stats.default_ttl {
+                println!("Default TTL: {}", format_duration(ttl));
+            }
+        }
+        return Ok(());
+    }
+
+    let stats = db.stats();
+    let collections: Vec<(String, usize)> = db
+        .list_collections()
+        .into_iter()
+        .map(|name| {
+            let count = db.collection(&name).count();
+            (name, count)
+        })
+        .collect();
+    if args.json {
+        print_json(&json!({
+            "file": args.db,
+            "file_size": stats.file_size,
+            "total_entries": stats.total_entries,
+            "indexes": db.list_indexes(),
+            "schemas": db.list_schemas(),
+            "collections": collections
+                .iter()
+                .map(|(name, count)| (name.clone(), json!(count)))
+                .collect::<Map<_, _>>(),
+        }));
+    } else {
+        stats.print();
+        println!("Indexes: {}", list_or_none(&db.list_indexes()));
+        println!("Schemas: {}", list_or_none(&db.list_schemas()));
+        for (name, count) in &collections {
+            println!("  collection '{}': {} entries", name, count);
+        }
+    }
+    Ok(())
+}
+
+fn import(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let file = required(args, 0, "a file to import (- for stdin)")?;
+    no_more_arguments(args, 1)?;
+    let lines = file_format(args, file)?;
+    let text = read_input(file)?;
+    let entries = if lines {
+        parse_json_lines(&text)?
+    } else {
+        match Value::from_json_str(&text) {
+            Ok(Value::Object(fields)) => {
+                let mut entries: Vec<(String, Value)> = fields.into_iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                entries
+            }
+            Ok(other) => {
+                return Err(failure(format!(
+                    "expected a JSON object of key -> value, found {}",
+                    other.type_name()
+                )));
+            }
+            Err(e) => return Err(failure(format!("{}: {}", file, e))),
+        }
+    };
+    let mut target = Target::open(db, args, true)?;
+    // batch_insert writes nothing unless every value fits its schema
+    let count = target.batch_insert(entries)?;
+    if args.json {
+        print_json(&json!({ "imported": count }));
+    } else {
+        println!(
+            "imported {} entr{}",
+            count,
+            if count == 1 { "y" } else { "ies" }
+        );
+    }
+    Ok(())
+}
+
+// One {"key": "...", "value": ...} object per line; blank lines are skipped
+fn parse_json_lines(text: &str) -> Result<Vec<(String, Value)>, CliError> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at_line = |message: String| failure(format!("line {}: {}", i + 1, message));
+        let parsed = Value::from_json_str(line).map_err(|e| match e {
+            JsonError::Parse {
+                message, column, ..
+            } => failure(format!("line {}, column {}: {}", i + 1, column, message)),
+            other => at_line(other.to_string()),
+        })?;
+        let Value::Object(mut fields) = parsed else {
+            return Err(at_line("expected an object".to_string()));
+        };
+        let Some(Value::String(key)) = fields.remove("key") else {
+            return Err(at_line("expected a string \"key\"".to_string()));
+        };
+        let value = fields
+            .remove("value")
+            .ok_or_else(|| at_line("missing \"value\"".to_string()))?;
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+fn export(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    let file = args.positional.first().map(String::as_str).unwrap_or("-");
+    no_more_arguments(args, 1)?;
+    let lines = file_format(args, file)?;
+    let target = Target::open(db, args, false)?;
+    let keys = target.keys_with_prefix(args.option("prefix").unwrap_or(""));
+
+    let mut text = String::new();
+    if lines {
+        for key in &keys {
+            if let Some(value) = target.get(key) {
+                let line = json!({ "key": key, "value": to_json(key, &value).map_err(failure)? });
+                text.push_str(&line.to_string());
+                text.push('\n');
+            }
+        }
+    } else {
+        let mut object = Map::new();
+        for key in &keys {
+            if let Some(value) = target.get(key) {
+                object.insert(key.clone(), to_json(key, &value).map_err(failure)?);
+            }
+        }
+        text = serde_json::to_string_pretty(&Json::Object(object))
+            .map_err(|e| failure(e.to_string()))?;
+        text.push('\n');
+    }
+
+    if file == "-" {
+        io::stdout().write_all(text.as_bytes())?;
+    } else {
+        fs::write(file, text).map_err(|e| failure(format!("{}: {}", file, e)))?;
+        if args.json {
+            print_json(&json!({ "exported": keys.len(), "file": file }));
+        } else {
+            println!("exported {} entries to '{}'", keys.len(), file);
+        }
+    }
+    Ok(())
+}
+
+// Loading already dropped the expired entries; saving writes what is left
+fn compact(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    no_more_arguments(args, 0)?;
+    let before = fs::metadata(&args.db)?.len();
+    db.save()?;
+    let after = fs::metadata(&args.db)?.len();
+    if args.json {
+        print_json(&json!({ "file": args.db, "bytes_before": before, "bytes_after": after }));
+    } else {
+        println!("compacted '{}': {} -> {} bytes", args.db, before, after);
+    }
+    Ok(())
+}
+
+// Loading checks the file itself; this adds the schema checks
+fn verify(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    no_more_arguments(args, 0)?;
+    let mut problems: Vec<(Option<String>, String)> = db
+        .validate_all()
+        .into_iter()
+        .map(|e| (None, e.to_string()))
+        .collect();
+    let mut entries = db.count();
+    let collections = db.list_collections();
+    for name in &collections {
+        let collection = db.collection(name);
+        entries += collection.count();
+        problems.extend(
+            collection
+                .validate_all()
+                .into_iter()
+                .map(|e| (Some(name.clone()), e.to_string())),
+        );
+    }
+
+    if args.json {
+        let problems: Vec<Json> = problems
+            .iter()
+            .map(|(collection, message)| json!({ "collection": collection, "problem": message }))
+            .collect();
+        print_json(&json!({
+            "ok": problems.is_empty(),
+            "entries": entries,
+            "collections": collections.len(),
+            "problems": problems,
+        }));
+    } else {
+        println!(
+            "'{}' loads: {} entries in the database and {} collection(s)",
+            args.db,
+            entries,
+            collections.len()
+        );
+        for (collection, message) in &problems {
+            match collection {
+                Some(name) => println!("  collection '{}': {}", name, message),
+                None => println!("  {}", message),
+            }
+        }
+        if problems.is_empty() {
+            println!("no problems found");
+        }
+    }
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(failure("1 value doesn't fit its schema")),
+        n => Err(failure(format!("{} values don't fit their schemas", n))),
+    }
+}
+
+fn dump(db: &mut Database, args: &Args) -> Result<(), CliError> {
+    no_more_arguments(args, 0)?;
+    let collections = db.list_collections();
+    if args.json {
+        let main = dump_json(&Target::Main(db), None)?;
+        let mut dumped = Map::new();
+        for name in &collections {
+            let collection = db.collection(name);
+            let default_ttl = collection.options().default_ttl;
+            dumped.insert(
+                name.clone(),
+                dump_json(&Target::Collection(collection), default_ttl)?,
+            );
+        }
+        print_json(&json!({ "database": main, "collections": dumped }));
+    } else {
+        println!("=== database '{}' ===", args.db);
+        dump_text(&Target::Main(db));
+        for name in &collections {
+            let collection = db.collection(name);
+            println!();
+            print!("=== collection '{}'", name);
+            if let Some(ttl) = collection.options().default_ttl {
+                print!(" (default TTL {})", format_duration(ttl));
+            }
+            println!(" ===");
+            dump_text(&Target::Collection(collection));
+        }
+    }
+    Ok(())
+}
+
+fn dump_text(target: &Target) {
+    println!("indexes: {}", list_or_none(&target.list_indexes()));
+    println!("schemas: {}", list_or_none(&target.list_schemas()));
+    for key in target.keys_with_prefix("") {
+        let Some(value) = target.get(&key) else {
+            continue;
+        };
+        let mut meta = format!("v{}", target.version(&key).unwrap_or_default());
+        if let Some(ttl) = target.ttl(&key) {
+            meta.push_str(&format!(", ttl {}", format_duration(ttl)));
+        }
+        println!("{} [{}] = {}", key, meta, value);
+    }
+}
+
+fn dump_json(target: &Target, default_ttl: Option<Duration>) -> Result<Json, CliError> {
+    let mut entries = Vec::new();
+    for key in target.keys_with_prefix("") {
+        let Some(value) = target.get(&key) else {
+            continue;
+        };
+        entries.push(json!({
+            "key": key,
+            "type": value.type_name(),
+            "value": to_json(&key, &value).map_err(failure)?,
+            "version": target.version(&key),
+            "ttl_ms": target.ttl(&key).map(|ttl| ttl.as_millis() as u64),
+        }));
+    }
+    Ok(json!({
+        "indexes": target.list_indexes(),
+        "schemas": target.list_schemas(),
+        "default_ttl_ms": default_ttl.map(|ttl| ttl.as_millis() as u64),
+        "entries": entries,
+    }))
+}
+
+// ---------- Helpers ----------
+
+// A file's contents, or stdin for "-"
+fn read_input(file: &str) -> Result<String, CliError> {
+    if file == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        fs::read_to_string(file).map_err(|e| failure(format!("{}: {}", file, e)))
+    }
+}
+
+// true for JSON lines: --format, or else the file extension (.jsonl / .ndjson)
+fn file_format(args: &Args, file: &str) -> Result<bool, CliError> {
+    match args.option("format") {
+        Some("json") => Ok(false),
+        Some("jsonl") => Ok(true),
+        Some(other) => Err(usage(format!("unknown format '{}' (json or jsonl)", other))),
+        None => Ok(file.ends_with(".jsonl") || file.ends_with(".ndjson")),
+    }
+}
+
+fn list_or_none(items: &[String]) -> String {
+    if items.is_empty() {
+        "(none)".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
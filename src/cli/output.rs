@@ -0,0 +1,107 @@
+// How the tool prints things
+//
+// Text mode is for people: values in littledb's own notation (see Value's
+// Display), indented when they don't fit on a line. --json mode prints one
+// JSON document per command, for scripts; values are converted the way
+// json.rs does it (timestamps, bytes, decimals and UUIDs become strings).
+
+use std::time::Duration;
+
+use littledb::Value;
+use serde_json::Value as Json;
+
+// Lines longer than this are split up
+const WIDTH: usize = 80;
+
+// A value, indented over several lines if it doesn't fit on one
+pub fn pretty(value: &Value) -> String {
+    let mut out = String::new();
+    write_pretty(value, 0, &mut out);
+    out
+}
+
+fn write_pretty(value: &Value, indent: usize, out: &mut String) {
+    let compact = value.to_string();
+    if indent + compact.len() <= WIDTH {
+        out.push_str(&compact);
+        return;
+    }
+    let pad = " ".repeat(indent + 2);
+    let close_pad = " ".repeat(indent);
+    match value {
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad);
+                write_pretty(item, indent + 2, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&close_pad);
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            let mut entries: Vec<_> = fields.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push_str("{\n");
+            for (i, (key, field)) in entries.iter().enumerate() {
+                out.push_str(&pad);
+                out.push_str(&Value::String(key.to_string()).to_string());
+                out.push_str(": ");
+                write_pretty(field, indent + 2, out);
+                out.push_str(if i + 1 < entries.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&close_pad);
+            out.push('}');
+        }
+        _ => out.push_str(&compact),
+    }
+}
+
+// A value as JSON; NaN and infinity can't be written and are an error
+pub fn to_json(key: &str, value: &Value) -> Result<Json, String> {
+    Json::try_from(value).map_err(|e| format!("key '{}': {}", key, e))
+}
+
+pub fn print_json(json: &Json) {
+    println!("{}", json);
+}
+
+// "1h 2m 3.5s", "250ms"
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{}ms", millis);
+    }
+    let hours = millis / 3_600_000;
+    let minutes = millis / 60_000 % 60;
+    let seconds = (millis % 60_000) as f64 / 1000.0;
+    let mut parts = Vec::new();
+    if hours > 0 {
+        parts.push(format!("{}h", hours));
+    }
+    if minutes > 0 {
+        parts.push(format!("{}m", minutes));
+    }
+    if seconds > 0.0 {
+        parts.push(format!("{}s", seconds));
+    }
+    parts.join(" ")
+}
+
+// "30" (seconds), "30s", "500ms", "5m", "2h"
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}' (try 30s, 500ms, 5m or 2h)", text);
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(at) => text.split_at(at),
+        None => (text, "s"),
+    };
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
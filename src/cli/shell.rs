@@ -0,0 +1,466 @@
+// `littledb shell mydata.db` - an interactive shell
+//
+//   littledb> set user:1 {"name": "Alice",
+//        ...>   "age": 30}
+//   set 'user:1' (version 1)
+//   (0.41 ms)
+//   littledb> begin
+//   littledb (tx)> set user:2 {"name": "Bob"}
+//   littledb (tx)> commit
+//
+// Line editing and history come from rustyline (history is kept in
+// ~/.littledb_history); Tab completes command names and keys. A line whose
+// brackets or quotes are still open continues on the next line, so JSON
+// values can be typed over several lines.
+//
+// Inside a transaction get/set/delete go through it and nothing is written
+// until commit; list, query and count show the committed data.
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+
+use littledb::{Condition, Database, Transaction, Value};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use super::output::pretty;
+
+const HISTORY_FILE: &str = ".littledb_history";
+
+// Most keys Tab offers at once
+const MAX_COMPLETIONS: usize = 200;
+
+// (name, arguments, what it does)
+const SHELL_COMMANDS: &[(&str, &str, &str)] = &[
+    ("get", "<key>", "print a value"),
+    ("set", "<key> <json>", "store a JSON value"),
+    ("delete", "<key>...", "delete keys"),
+    ("list", "[prefix]", "list keys"),
+    ("query", "<filter>", "find values matching a JSON filter"),
+    ("explain", "<filter>", "show how a query would run"),
+    ("count", "", "number of keys"),
+    ("stats", "", "database statistics"),
+    ("begin", "", "start a transaction"),
+    ("commit", "", "apply the transaction"),
+    ("rollback", "", "discard the transaction"),
+    ("help", "", "show this list"),
+    ("exit", "", "leave the shell (also quit, Ctrl-D)"),
+];
+
+struct Shell {
+    db: Rc<RefCell<Database>>, // shared with the completer
+    tx: Option<Transaction>,
+}
+
+pub fn run(path: &str) -> Result<(), String> {
+    let mut db = Database::new(path);
+    db.set_verbose(false);
+    db.load()
+        .map_err(|e| format!("can't read '{}': {}", path, e))?;
+    let db = Rc::new(RefCell::new(db));
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> =
+        Editor::new().map_err(|e| e.to_string())?;
+    editor.set_helper(Some(ShellHelper { db: Rc::clone(&db) }));
+    let history = history_path();
+    if let Some(history) = &history {
+        let _ = editor.load_history(history); // there may be none yet
+    }
+
+    println!(
+        "littledb shell - '{}', {} keys. Type 'help' for the commands.",
+        path,
+        db.borrow().count()
+    );
+    let mut shell = Shell { db, tx: None };
+    loop {
+        let prompt = if shell.tx.is_some() {
+            "littledb (tx)> "
+        } else {
+            "littledb> "
+        };
+        let input = match read_statement(&mut editor, prompt) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue, // Ctrl-C drops the line
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.to_string()),
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input);
+        if matches!(input, "exit" | "quit") {
+            break;
+        }
+
+        let started = Instant::now();
+        let result = shell.execute(input);
+        let elapsed = started.elapsed();
+        if let Err(message) = result {
+            println!("error: {}", message);
+        }
+        println!("({:.2} ms)", elapsed.as_secs_f64() * 1000.0);
+    }
+
+    if let Some(tx) = &shell.tx
+        && !tx.is_empty()
+    {
+        println!("transaction with {} pending write(s) discarded", tx.len());
+    }
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+// One statement, read over as many lines as it takes to close its brackets
+fn read_statement(
+    editor: &mut Editor<ShellHelper, DefaultHistory>,
+    prompt: &str,
+) -> Result<String, ReadlineError> {
+    let mut input = editor.readline(prompt)?;
+    let continuation = format!("{:>width$}", "...> ", width = prompt.len());
+    while is_incomplete(&input) {
+        input.push('\n');
+        input.push_str(&editor.readline(&continuation)?);
+    }
+    Ok(input)
+}
+
+// true while a JSON string is open or there are more { [ than } ]
+fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in input.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    in_string || depth > 0
+}
+
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(HISTORY_FILE))
+}
+
+impl Shell {
+    fn execute(&mut self, input: &str) -> Result<(), String> {
+        let (command, rest) = split_word(input);
+        match command {
+            "get" => {
+                let key = one_key(rest)?;
+                let db = self.db.borrow();
+                let value = match &mut self.tx {
+                    Some(tx) => tx.get(&db, key),
+                    None => db.get(key),
+                };
+                let value = value.ok_or_else(|| format!("Key '{}' not found", key))?;
+                println!("{}", pretty(&value));
+            }
+            "set" => {
+                let (key, json) = split_word(rest);
+                if key.is_empty() || json.is_empty() {
+                    return Err("usage: set <key> <json>".to_string());
+                }
+                let value = Value::from_json_str(json).map_err(|e| e.to_string())?;
+                match &mut self.tx {
+                    Some(tx) => {
+                        tx.insert(key.to_string(), value);
+                        println!("set '{}' (pending)", key);
+                    }
+                    None => {
+                        let mut db = self.db.borrow_mut();
+                        db.insert(key.to_string(), value)
+                            .map_err(|e| e.to_string())?;
+                        println!(
+                            "set '{}' (version {})",
+                            key,
+                            db.version(key).unwrap_or_default()
+                        );
+                    }
+                }
+            }
+            "delete" => {
+                let keys: Vec<&str> = rest.split_whitespace().collect();
+                if keys.is_empty() {
+                    return Err("usage: delete <key>...".to_string());
+                }
+                match &mut self.tx {
+                    Some(tx) => {
+                        for key in &keys {
+                            tx.delete(key);
+                        }
+                        println!("{} delete(s) pending", keys.len());
+                    }
+                    None => {
+                        let mut db = self.db.borrow_mut();
+                        let deleted = db.batch_delete(keys);
+                        db.save().map_err(|e| e.to_string())?;
+                        println!("deleted {} key(s)", deleted);
+                    }
+                }
+            }
+            "list" => {
+                let prefix = rest.trim();
+                let keys = self.db.borrow().keys_with_prefix(prefix);
+                for key in &keys {
+                    println!("{}", key);
+                }
+                println!("{} key(s)", keys.len());
+            }
+            "query" | "explain" => {
+                if rest.is_empty() {
+                    return Err(format!("usage: {} <filter>", command));
+                }
+                let condition = Condition::from_json(rest).map_err(|e| e.to_string())?;
+                let db = self.db.borrow();
+                if command == "explain" {
+                    println!("{}", db.explain(condition).to_string().trim_end());
+                    return Ok(());
+                }
+                let results = db.query(condition);
+                for (key, value) in &results {
+                    println!("{} = {}", key, pretty(value));
+                }
+                println!("{} result(s)", results.len());
+            }
+            "count" => println!("{}", self.db.borrow().count()),
+            "stats" => self.db.borrow().stats().print(),
+            "begin" => {
+                if self.tx.is_some() {
+                    return Err("a transaction is already open".to_string());
+                }
+                self.tx = Some(Transaction::new());
+                println!("transaction started");
+            }
+            "commit" => {
+                let tx = self.tx.take().ok_or("no transaction is open")?;
+                let writes = tx.len();
+                self.db
+                    .borrow_mut()
+                    .commit(tx)
+                    .map_err(|e| format!("{} (transaction discarded)", e))?;
+                println!("committed {} write(s)", writes);
+            }
+            "rollback" => {
+                let tx = self.tx.take().ok_or("no transaction is open")?;
+                println!("discarded {} pending write(s)", tx.len());
+            }
+            "help" => {
+                for (name, arguments, summary) in SHELL_COMMANDS {
+                    let usage = format!("{} {}", name, arguments);
+                    println!("  {:<22} {}", usage.trim_end(), summary);
+                }
+            }
+            other => return Err(format!("unknown command '{}' (try 'help')", other)),
+        }
+        Ok(())
+    }
+}
+
+// ("set", "key {...}") from "set key {...}"
+fn split_word(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.find(char::is_whitespace) {
+        Some(end) => (&input[..end], input[end..].trim()),
+        None => (input, ""),
+    }
+}
+
+fn one_key(rest: &str) -> Result<&str, String> {
+    let (key, extra) = split_word(rest);
+    if key.is_empty() || !extra.is_empty() {
+        return Err("expected exactly one key".to_string());
+    }
+    Ok(key)
+}
+
+// ---------- rustyline hooks ----------
+
+struct ShellHelper {
+    db: Rc<RefCell<Database>>,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let mut earlier = before[..start].split_whitespace();
+        let candidates: Vec<String> = match (earlier.next(), earlier.count()) {
+            (None, _) => SHELL_COMMANDS
+                .iter()
+                .map(|(name, _, _)| name.to_string())
+                .filter(|name| name.starts_with(word))
+                .collect(),
+            // the key of get/set/list, any key of delete
+            (Some("get" | "set" | "list"), 0) | (Some("delete"), _) => {
+                let mut keys = self.db.borrow().keys_with_prefix(word);
+                keys.truncate(MAX_COMPLETIONS);
+                keys
+            }
+            _ => Vec::new(),
+        };
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell() -> Shell {
+        let mut db = Database::new("littledb-shell-unsaved.db");
+        db.set_verbose(false);
+        db.set_auto_save(false);
+        Shell {
+            db: Rc::new(RefCell::new(db)),
+            tx: None,
+        }
+    }
+
+    #[test]
+    fn open_brackets_and_strings_continue_the_statement() {
+        assert!(!is_incomplete("get user:1"));
+        assert!(!is_incomplete(r#"set k {"a": [1, 2]}"#));
+        assert!(is_incomplete(r#"set k {"a": [1, 2"#));
+        assert!(is_incomplete("set k {\"name\": \"Al"));
+        // Brackets and escaped quotes inside a string don't count
+        assert!(!is_incomplete(r#"set k {"a": "{[ \"x"}"#));
+        assert!(is_incomplete(r#"set k "\""#));
+        assert!(!is_incomplete("set k {\n  \"age\": 30\n}"));
+        // Too many closing brackets is an error for the JSON parser, not more input
+        assert!(!is_incomplete("set k }"));
+    }
+
+    #[test]
+    fn words_split_at_the_first_whitespace() {
+        assert_eq!(split_word("set k {\"a\": 1}"), ("set", "k {\"a\": 1}"));
+        assert_eq!(split_word("  get\t key  "), ("get", "key"));
+        assert_eq!(split_word("count"), ("count", ""));
+        assert_eq!(split_word(""), ("", ""));
+        assert_eq!(one_key(" user:1 "), Ok("user:1"));
+        assert!(one_key("").is_err());
+        assert!(one_key("a b").is_err());
+    }
+
+    #[test]
+    fn commands_check_their_arguments() {
+        let mut shell = shell();
+        assert!(shell.execute("set k").is_err());
+        assert!(shell.execute("set k {oops").is_err());
+        assert!(shell.execute("get").is_err());
+        assert!(shell.execute("delete").is_err());
+        assert!(shell.execute("query").is_err());
+        assert_eq!(
+            shell.execute("frobnicate"),
+            Err("unknown command 'frobnicate' (try 'help')".to_string())
+        );
+        shell.execute("set k {\"a\": [1,\n 2]}").unwrap();
+        let value = shell.db.borrow().get("k").unwrap();
+        assert_eq!(value.to_json_string().unwrap(), r#"{"a":[1,2]}"#);
+    }
+
+    #[test]
+    fn transactions_apply_on_commit_only() {
+        let mut shell = shell();
+        shell.execute("set a 1").unwrap();
+        shell.execute("set gone true").unwrap();
+        shell.execute("begin").unwrap();
+        assert!(shell.execute("begin").is_err());
+        shell.execute("set a 2").unwrap();
+        shell.execute("set b 3").unwrap();
+        shell.execute("delete gone").unwrap();
+        shell.execute("get a").unwrap();
+        assert!(shell.execute("get gone").is_err());
+        assert_eq!(shell.db.borrow().get("a"), Some(Value::Integer(1)));
+        assert!(shell.db.borrow().exists("gone"));
+
+        shell.execute("commit").unwrap();
+        assert!(shell.tx.is_none());
+        let db = shell.db.borrow();
+        assert_eq!(db.list_keys(), vec!["a", "b"]);
+        assert_eq!(db.get("a"), Some(Value::Integer(2)));
+    }
+
+    #[test]
+    fn rollback_discards_and_conflicts_close_the_transaction() {
+        let mut shell = shell();
+        assert_eq!(
+            shell.execute("rollback"),
+            Err("no transaction is open".to_string())
+        );
+        assert!(shell.execute("commit").is_err());
+        shell.execute("begin").unwrap();
+        shell.execute("set a 1").unwrap();
+        shell.execute("rollback").unwrap();
+        assert!(shell.tx.is_none());
+        assert!(!shell.db.borrow().exists("a"));
+
+        // A key the transaction read changes underneath it
+        shell.execute("set a 1").unwrap();
+        shell.execute("begin").unwrap();
+        shell.execute("get a").unwrap();
+        shell.execute("set a 2").unwrap();
+        shell
+            .db
+            .borrow_mut()
+            .insert("a".to_string(), Value::Integer(5))
+            .unwrap();
+        assert_eq!(
+            shell.execute("commit"),
+            Err(
+                "conflict: key 'a' changed since the transaction read it (transaction discarded)"
+                    .to_string()
+            )
+        );
+        assert!(shell.tx.is_none());
+        assert_eq!(shell.db.borrow().get("a"), Some(Value::Integer(5)));
+    }
+}
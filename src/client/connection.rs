@@ -0,0 +1,143 @@
+// One keep-alive HTTP/1.1 connection to the server, and just enough of the
+// protocol to talk to littledb::http (Content-Length bodies, no chunking)
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// Longest status or header line, and largest body, accepted from a server
+const MAX_LINE_LENGTH: u64 = 16 * 1024;
+const MAX_BODY: usize = 512 * 1024 * 1024;
+
+pub(crate) struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) etag: Option<String>,
+    pub(crate) body: Vec<u8>,
+    pub(crate) keep_alive: bool,
+}
+
+impl Connection {
+    // Try each address in turn
+    pub(crate) fn open(
+        addrs: &[SocketAddr],
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> io::Result<Connection> {
+        let mut last_error = io::Error::new(ErrorKind::NotFound, "no address to connect to");
+        for addr in addrs {
+            match TcpStream::connect_timeout(addr, connect_timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    return Ok(Connection {
+                        reader: BufReader::new(stream.try_clone()?),
+                        writer: stream,
+                    });
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    // Send a request and read its response. `target` must already be
+    // percent-encoded. An Err leaves the connection unusable.
+    pub(crate) fn exchange(
+        &mut self,
+        method: &str,
+        target: &str,
+        body: Option<&[u8]>,
+    ) -> io::Result<Response> {
+        let body = body.unwrap_or_default();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: littledb\r\nContent-Length: {}\r\n",
+            method,
+            target,
+            body.len()
+        );
+        if !body.is_empty() {
+            request.push_str("Content-Type: application/json\r\n");
+        }
+        request.push_str("\r\n");
+        self.writer.write_all(request.as_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.flush()?;
+        self.read_response(method == "HEAD")
+    }
+
+    fn read_response(&mut self, head: bool) -> io::Result<Response> {
+        let status_line = self.read_line()?;
+        let status = status_line
+            .strip_prefix("HTTP/1.")
+            .and_then(|rest| rest.get(2..5))
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| invalid(format!("not an HTTP response: {:?}", status_line)))?;
+        let mut response = Response {
+            status,
+            etag: None,
+            body: Vec::new(),
+            keep_alive: status_line.starts_with("HTTP/1.1"),
+        };
+        let mut length = 0;
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(invalid(format!("malformed header {:?}", line)));
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => {
+                    length = value
+                        .parse()
+                        .ok()
+                        .filter(|length| *length <= MAX_BODY)
+                        .ok_or_else(|| invalid(format!("bad Content-Length {:?}", value)))?;
+                }
+                "etag" => response.etag = Some(value.to_string()),
+                "connection" if value.eq_ignore_ascii_case("close") => response.keep_alive = false,
+                _ => {}
+            }
+        }
+        // 1xx (there are none unless we ask for 100-continue) has no body
+        if (100..200).contains(&status) {
+            return self.read_response(head);
+        }
+        if !head && !matches!(status, 204 | 304) {
+            response.body = vec![0; length];
+            self.reader.read_exact(&mut response.body)?;
+        }
+        Ok(response)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        (&mut self.reader)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut line)?;
+        if line.last() != Some(&b'\n') {
+            return Err(if line.is_empty() {
+                io::Error::new(ErrorKind::UnexpectedEof, "the server closed the connection")
+            } else {
+                invalid("response line too long or cut off".to_string())
+            });
+        }
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        String::from_utf8(line).map_err(|_| invalid("response line isn't UTF-8".to_string()))
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
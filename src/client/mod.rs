@@ -0,0 +1,478 @@
+// NEW: A client for a littledb REST server (see http/), shaped like Database
+//
+//   let client = Client::connect("127.0.0.1:8080")?;
+//   client.insert("user:1".to_string(), user)?;
+//   let adults = client.query(Condition::GreaterThan("age".to_string(), 17))?;
+//
+//   let mut tx = Transaction::new();
+//   let balance = tx.read(&client, "account:1")?;
+//   tx.insert("account:1".to_string(), debited);
+//   client.commit(tx)?;   // StoreError::Conflict if account:1 changed meanwhile
+//
+// Client implements Store (store.rs), so code written against Store works
+// with an embedded Database and a remote server alike.
+//
+//   - Pooling: idle keep-alive connections are kept (up to `pool_size`) and
+//     reused. Every method takes &self, so threads can share one Client.
+//   - Timeouts: for connecting, and for each request to be answered.
+//   - Retries: a request that fails to get an answer (connection refused or
+//     dropped, timeout) is retried if repeating it is harmless: reads, PUT,
+//     DELETE and the batch calls. A commit is never retried, since it may have
+//     been applied before the answer was lost. For the same reason a retried
+//     delete that finds no key reports it deleted, and batch_delete's count
+//     leaves out keys an unanswered attempt deleted.
+//   - Values travel as JSON: Bytes, Timestamp, Decimal and Uuid come back as
+//     Strings, and NaN or infinity can't be sent (see json.rs), not even in a
+//     query's condition.
+
+mod connection;
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value as Json, json};
+
+use crate::database::DatabaseStats;
+use crate::http::message::percent_encode;
+use crate::store::{Store, StoreError};
+use crate::{Condition, Transaction, Value};
+use connection::{Connection, Response};
+
+// Keys asked for per request when listing
+const KEYS_PAGE: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub pool_size: usize,          // idle connections kept open for reuse
+    pub connect_timeout: Duration, // to open a connection
+    pub timeout: Duration,         // for the server to take a request and answer it
+    pub retries: u32,              // extra attempts for requests that are safe to repeat
+    pub retry_delay: Duration,     // before the first retry; doubled for each one after
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            pool_size: 8,
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(30),
+            retries: 2,
+            retry_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl ClientOptions {
+    pub fn new() -> Self {
+        ClientOptions::default()
+    }
+
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.pool_size = size;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+}
+
+pub struct Client {
+    addrs: Vec<SocketAddr>,
+    options: ClientOptions,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Client, StoreError> {
+        Client::connect_with(addr, ClientOptions::default())
+    }
+
+    // Fails unless a littledb server answers at `addr`
+    pub fn connect_with(
+        addr: impl ToSocketAddrs,
+        options: ClientOptions,
+    ) -> Result<Client, StoreError> {
+        let addrs = addr
+            .to_socket_addrs()
+            .map_err(|e| StoreError::Unavailable(e.to_string()))?
+            .collect();
+        let client = Client {
+            addrs,
+            options,
+            idle: Mutex::new(Vec::new()),
+        };
+        client.stats()?;
+        Ok(client)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Value>, StoreError> {
+        Ok(self.get_with_version(key)?.map(|(value, _)| value))
+    }
+
+    pub fn get_with_version(&self, key: &str) -> Result<Option<(Value, u64)>, StoreError> {
+        let response = self.request("GET", &key_path(key), None, true)?;
+        match response.status {
+            200 => {
+                let version = response
+                    .etag
+                    .as_deref()
+                    .and_then(|etag| etag.trim_matches('"').parse().ok())
+                    .ok_or_else(|| StoreError::Io("response without a version".to_string()))?;
+                Ok(Some((Value::from(parse(&response)?), version)))
+            }
+            404 => Ok(None),
+            _ => Err(failure(&response)),
+        }
+    }
+
+    pub fn insert(&self, key: String, value: Value) -> Result<(), StoreError> {
+        self.put(&key_path(&key), &value)
+    }
+
+    pub fn insert_with_ttl(
+        &self,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<(), StoreError> {
+        let target = format!("{}?ttl={}", key_path(&key), ttl.as_secs_f64());
+        self.put(&target, &value)
+    }
+
+    fn put(&self, target: &str, value: &Value) -> Result<(), StoreError> {
+        let body = to_json(value)?.to_string();
+        let response = self.request("PUT", target, Some(body.as_bytes()), true)?;
+        match response.status {
+            200 | 201 => Ok(()),
+            _ => Err(failure(&response)),
+        }
+    }
+
+    // Whether the key existed
+    pub fn delete(&self, key: &str) -> Result<bool, StoreError> {
+        let (response, resent) = self.send("DELETE", &key_path(key), None, true)?;
+        match response.status {
+            204 => Ok(true),
+            // The unanswered attempt may be what deleted it
+            404 => Ok(resent),
+            _ => Err(failure(&response)),
+        }
+    }
+
+    pub fn query(&self, condition: Condition) -> Result<Vec<(String, Value)>, StoreError> {
+        check_finite(&condition)?;
+        let body = condition.to_json();
+        let response = self.request("POST", "/query", Some(body.as_bytes()), true)?;
+        let results = expect_ok(&response)?;
+        let mut entries = Vec::new();
+        for result in results["results"].as_array().into_iter().flatten() {
+            let key = result["key"]
+                .as_str()
+                .ok_or_else(|| unexpected("a query result without a key"))?;
+            entries.push((key.to_string(), Value::from(&result["value"])));
+        }
+        Ok(entries)
+    }
+
+    pub fn batch_get(&self, keys: Vec<&str>) -> Result<HashMap<String, Value>, StoreError> {
+        let body = json!({ "keys": keys }).to_string();
+        let response = self.request("POST", "/batch/get", Some(body.as_bytes()), true)?;
+        let found = expect_ok(&response)?;
+        let values = found["values"]
+            .as_object()
+            .ok_or_else(|| unexpected("no values"))?;
+        Ok(values
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::from(value)))
+            .collect())
+    }
+
+    // All or nothing, as Database::batch_insert
+    pub fn batch_insert(&self, entries: Vec<(String, Value)>) -> Result<usize, StoreError> {
+        let mut items = Vec::with_capacity(entries.len());
+        for (key, value) in &entries {
+            items.push(json!({ "key": key, "value": to_json(value)? }));
+        }
+        let body = json!({ "entries": items }).to_string();
+        let response = self.request("POST", "/batch/insert", Some(body.as_bytes()), true)?;
+        count(&expect_ok(&response)?, "inserted")
+    }
+
+    pub fn batch_delete(&self, keys: Vec<&str>) -> Result<usize, StoreError> {
+        let body = json!({ "keys": keys }).to_string();
+        let response = self.request("POST", "/batch/delete", Some(body.as_bytes()), true)?;
+        count(&expect_ok(&response)?, "deleted")
+    }
+
+    // Every key with the prefix, in order, fetched a page at a time
+    pub fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let mut keys = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut target = format!(
+                "/keys?prefix={}&limit={}",
+                percent_encode(prefix),
+                KEYS_PAGE
+            );
+            if let Some(cursor) = &cursor {
+                target.push_str(&format!("&cursor={}", percent_encode(cursor)));
+            }
+            let page = expect_ok(&self.request("GET", &target, None, true)?)?;
+            for key in page["keys"].as_array().into_iter().flatten() {
+                let key = key
+                    .as_str()
+                    .ok_or_else(|| unexpected("a key that isn't a string"))?;
+                keys.push(key.to_string());
+            }
+            match page["cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return Ok(keys),
+            }
+        }
+    }
+
+    pub fn count(&self) -> Result<usize, StoreError> {
+        Ok(self.stats()?.total_entries)
+    }
+
+    pub fn stats(&self) -> Result<DatabaseStats, StoreError> {
+        let stats = expect_ok(&self.request("GET", "/stats", None, true)?)?;
+        serde_json::from_value(stats).map_err(|e| unexpected(&e.to_string()))
+    }
+
+    // Applied on the server all at once, or not at all (see transaction.rs)
+    pub fn commit(&self, tx: Transaction) -> Result<(), StoreError> {
+        let reads: serde_json::Map<String, Json> = tx
+            .reads()
+            .map(|(key, version)| (key.to_string(), json!(version)))
+            .collect();
+        let mut writes = Vec::new();
+        for (key, value) in tx.writes() {
+            writes.push(match value {
+                Some(value) => json!({ "key": key, "value": to_json(value)? }),
+                None => json!({ "key": key, "delete": true }),
+            });
+        }
+        let body = json!({ "reads": reads, "writes": writes }).to_string();
+        let response = self.request("POST", "/transaction", Some(body.as_bytes()), false)?;
+        expect_ok(&response).map(|_| ())
+    }
+
+    // ---------- Connections ----------
+
+    // `retry`: whether the request may be sent again when no answer came back
+    fn request(
+        &self,
+        method: &str,
+        target: &str,
+        body: Option<&[u8]>,
+        retry: bool,
+    ) -> Result<Response, StoreError> {
+        Ok(self.send(method, target, body, retry)?.0)
+    }
+
+    // request(), and whether an earlier attempt was sent without an answer
+    // coming back (so the server may have applied it)
+    fn send(
+        &self,
+        method: &str,
+        target: &str,
+        body: Option<&[u8]>,
+        retry: bool,
+    ) -> Result<(Response, bool), StoreError> {
+        let attempts = if retry { self.options.retries + 1 } else { 1 };
+        let mut delay = self.options.retry_delay;
+        let mut last_error = None;
+        let mut resent = false;
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            let mut connection = match self.take_connection() {
+                Ok(connection) => connection,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            match connection.exchange(method, target, body) {
+                Ok(response) => {
+                    if response.keep_alive {
+                        self.return_connection(connection);
+                    }
+                    return Ok((response, resent));
+                }
+                Err(e) => {
+                    last_error = Some(e); // the connection is dropped
+                    resent = true;
+                }
+            }
+        }
+        let error = last_error.map_or_else(String::new, |e| e.to_string());
+        Err(StoreError::Unavailable(if attempts > 1 {
+            format!("{} (after {} attempts)", error, attempts)
+        } else {
+            error
+        }))
+    }
+
+    fn take_connection(&self) -> std::io::Result<Connection> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        match idle {
+            Some(connection) => Ok(connection),
+            None => Connection::open(
+                &self.addrs,
+                self.options.connect_timeout,
+                self.options.timeout,
+            ),
+        }
+    }
+
+    fn return_connection(&self, connection: Connection) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.options.pool_size {
+            idle.push(connection);
+        }
+    }
+}
+
+fn key_path(key: &str) -> String {
+    format!("/keys/{}", percent_encode(key))
+}
+
+fn to_json(value: &Value) -> Result<Json, StoreError> {
+    Json::try_from(value).map_err(|e| StoreError::Invalid(e.to_string()))
+}
+
+// Condition::to_json writes NaN and infinity as null, which would ask the
+// server for something else; refused here, like NaN values
+fn check_finite(condition: &Condition) -> Result<(), StoreError> {
+    let floats = |floats: &[f64]| {
+        floats
+            .iter()
+            .try_for_each(|f| to_json(&Value::Float(*f)).map(|_| ()))
+    };
+    match condition {
+        Condition::Equals(_, value) => to_json(value).map(|_| ()),
+        Condition::And(conditions) | Condition::Or(conditions) => {
+            conditions.iter().try_for_each(check_finite)
+        }
+        Condition::Not(inner) => check_finite(inner),
+        Condition::NearPoint(_, point, distance) => floats(&[point.lat, point.lng, *distance]),
+        Condition::WithinBox(_, sw, ne) => floats(&[sw.lat, sw.lng, ne.lat, ne.lng]),
+        Condition::WithinPolygon(_, vertices) => vertices
+            .iter()
+            .try_for_each(|point| floats(&[point.lat, point.lng])),
+        _ => Ok(()),
+    }
+}
+
+fn parse(response: &Response) -> Result<Json, StoreError> {
+    serde_json::from_slice(&response.body).map_err(|e| unexpected(&e.to_string()))
+}
+
+// The JSON body of a 200 answer
+fn expect_ok(response: &Response) -> Result<Json, StoreError> {
+    match response.status {
+        200 => parse(response),
+        _ => Err(failure(response)),
+    }
+}
+
+fn count(body: &Json, field: &str) -> Result<usize, StoreError> {
+    body[field]
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| unexpected(&format!("no {} count", field)))
+}
+
+// An error answer as a StoreError; the server's message is in {"error": ...}
+fn failure(response: &Response) -> StoreError {
+    let body = parse(response).unwrap_or(Json::Null);
+    let message = body["error"]
+        .as_str()
+        .map_or_else(|| format!("HTTP {}", response.status), str::to_string);
+    match response.status {
+        409 if body["key"].is_string() => {
+            StoreError::Conflict(body["key"].as_str().unwrap_or_default().to_string())
+        }
+        400..=499 => StoreError::Invalid(message),
+        _ => StoreError::Io(message),
+    }
+}
+
+fn unexpected(what: &str) -> StoreError {
+    StoreError::Io(format!("unexpected response from the server: {}", what))
+}
+
+impl Store for Client {
+    fn get(&self, key: &str) -> Result<Option<Value>, StoreError> {
+        Client::get(self, key)
+    }
+
+    fn get_with_version(&self, key: &str) -> Result<Option<(Value, u64)>, StoreError> {
+        Client::get_with_version(self, key)
+    }
+
+    fn batch_get(&self, keys: Vec<&str>) -> Result<HashMap<String, Value>, StoreError> {
+        Client::batch_get(self, keys)
+    }
+
+    fn query(&self, condition: Condition) -> Result<Vec<(String, Value)>, StoreError> {
+        Client::query(self, condition)
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        Client::keys_with_prefix(self, prefix)
+    }
+
+    fn count(&self) -> Result<usize, StoreError> {
+        Client::count(self)
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), StoreError> {
+        Client::insert(self, key, value)
+    }
+
+    fn insert_with_ttl(
+        &mut self,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<(), StoreError> {
+        Client::insert_with_ttl(self, key, value, ttl)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, StoreError> {
+        Client::delete(self, key)
+    }
+
+    fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> Result<usize, StoreError> {
+        Client::batch_insert(self, entries)
+    }
+
+    fn batch_delete(&mut self, keys: Vec<&str>) -> Result<usize, StoreError> {
+        Client::batch_delete(self, keys)
+    }
+
+    fn commit(&mut self, tx: Transaction) -> Result<(), StoreError> {
+        Client::commit(self, tx)
+    }
+}
@@ -0,0 +1,294 @@
+// NEW: A handle to one collection of a Database
+//
+//   let mut users = db.collection("users");
+//   users.insert("alice".to_string(), alice)?;
+//   users.create_index("age");
+//   let older = users.query(Condition::GreaterThan("age".to_string(), 30));
+//
+// A collection is a keyspace of its own: "alice" in "users" and "alice" in the
+// database (or in another collection) are different keys, and indexes,
+// versions, expiry defaults and stats belong to the one collection. Writes
+// follow the database's auto-save setting, like Database::insert.
+
+use std::io;
+use std::time::Duration;
+
+use crate::database::invalid_input;
+use crate::fulltext::Analyzer;
+use crate::keyspace::{CollectionOptions, Keyspace};
+use crate::patch::{self, PatchError, PatchOp};
+use crate::planner::QueryPlan;
+use crate::schema::{Schema, ValidationError};
+use crate::{Condition, Database, QueryOptions, Value};
+
+pub struct Collection<'a> {
+    db: &'a mut Database,
+    name: String,
+}
+
+// Result of Collection::stats
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionStats {
+    pub name: String,
+    pub entries: usize,
+    pub expiring: usize, // entries with an expiry time
+    pub indexes: Vec<String>,
+    pub text_indexes: Vec<String>,
+    pub default_ttl: Option<Duration>,
+}
+
+impl<'a> Collection<'a> {
+    // Only Database::collection makes these, after making sure the collection exists
+    pub(crate) fn new(db: &'a mut Database, name: &str) -> Self {
+        Collection {
+            db,
+            name: name.to_string(),
+        }
+    }
+
+    fn keyspace(&self) -> &Keyspace {
+        self.db.keyspace(&self.name)
+    }
+
+    fn keyspace_mut(&mut self) -> &mut Keyspace {
+        self.db.keyspace_mut(&self.name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn options(&self) -> &CollectionOptions {
+        &self.keyspace().options
+    }
+
+    // Applies to keys written from now on
+    pub fn set_default_ttl(&mut self, ttl: Option<Duration>) -> io::Result<()> {
+        let keyspace = self.keyspace_mut();
+        keyspace.options.default_ttl = ttl;
+        keyspace.options_changed();
+        self.db.save_if_auto()
+    }
+
+    // ---------- Reads ----------
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.keyspace().get(key).cloned()
+    }
+
+    pub fn exists(&self, key: &str) -> bool {
+        self.keyspace().contains(key)
+    }
+
+    pub fn list_keys(&self) -> Vec<String> {
+        self.keyspace().keys()
+    }
+
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.keyspace().keys_with_prefix(prefix)
+    }
+
+    pub fn count(&self) -> usize {
+        self.keyspace().len()
+    }
+
+    pub fn version(&self, key: &str) -> Option<u64> {
+        self.keyspace().versions.keys.get(key).copied()
+    }
+
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        self.keyspace().ttl(key)
+    }
+
+    pub fn query(&self, condition: Condition) -> Vec<(String, Value)> {
+        self.keyspace().query(&condition)
+    }
+
+    pub fn query_with(&self, condition: Condition, options: &QueryOptions) -> Vec<(String, Value)> {
+        options.apply(self.query(condition))
+    }
+
+    pub fn explain(&self, condition: Condition) -> QueryPlan {
+        self.keyspace().explain(&condition)
+    }
+
+    // ---------- Writes ----------
+
+    // Insert or replace; the key gets the collection's default TTL (if any)
+    pub fn insert(&mut self, key: String, value: Value) -> io::Result<()> {
+        self.keyspace().check(&key, &value)?;
+        let ttl = self.keyspace().options.default_ttl;
+        let keyspace = self.keyspace_mut();
+        keyspace.put(key.clone(), value);
+        keyspace.set_ttl(&key, ttl);
+        self.db.save_if_auto()
+    }
+
+    pub fn insert_with_ttl(&mut self, key: String, value: Value, ttl: Duration) -> io::Result<()> {
+        self.keyspace().check(&key, &value)?;
+        let keyspace = self.keyspace_mut();
+        keyspace.put(key.clone(), value);
+        keyspace.set_ttl(&key, Some(ttl));
+        self.db.save_if_auto()
+    }
+
+    pub fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> io::Result<usize> {
+        let count = entries.len();
+        for (key, value) in &entries {
+            self.keyspace().check(key, value)?;
+        }
+        let ttl = self.keyspace().options.default_ttl;
+        let keyspace = self.keyspace_mut();
+        for (key, value) in entries {
+            keyspace.put(key.clone(), value);
+            keyspace.set_ttl(&key, ttl);
+        }
+        self.db.save_if_auto()?;
+        Ok(count)
+    }
+
+    pub fn update(&mut self, key: &str, value: Value) -> Result<(), String> {
+        if !self.exists(key) {
+            return Err(format!("Key '{}' not found in '{}'", key, self.name));
+        }
+        self.keyspace()
+            .check(key, &value)
+            .map_err(|e| e.to_string())?;
+        self.keyspace_mut().put(key.to_string(), value);
+        self.db.save_if_auto().map_err(|e| e.to_string())
+    }
+
+    pub fn patch(&mut self, key: &str, ops: Vec<PatchOp>) -> Result<Value, PatchError> {
+        let current = self
+            .keyspace()
+            .get(key)
+            .ok_or_else(|| PatchError::KeyNotFound(key.to_string()))?;
+        let patched = patch::apply(current, &ops)?;
+        self.keyspace()
+            .check(key, &patched)
+            .map_err(PatchError::Invalid)?;
+        self.keyspace_mut().put(key.to_string(), patched.clone());
+        self.db
+            .save_if_auto()
+            .map_err(|e| PatchError::Io(e.to_string()))?;
+        Ok(patched)
+    }
+
+    pub fn incr_by(&mut self, key: &str, n: i64) -> io::Result<i64> {
+        let updated = match self.keyspace().get(key) {
+            None => n,
+            Some(Value::Integer(i)) => i
+                .checked_add(n)
+                .ok_or_else(|| invalid_input(format!("Key '{}': {} + {} overflows", key, i, n)))?,
+            Some(other) => {
+                return Err(invalid_input(format!(
+                    "Key '{}' holds a {}, not an Integer",
+                    key,
+                    other.type_name()
+                )));
+            }
+        };
+        let value = Value::Integer(updated);
+        self.keyspace().check(key, &value)?;
+        self.keyspace_mut().put(key.to_string(), value);
+        self.db.save_if_auto()?;
+        Ok(updated)
+    }
+
+    pub fn delete(&mut self, key: &str) -> Result<(), String> {
+        if !self.exists(key) || self.keyspace_mut().remove(key).is_none() {
+            return Err(format!("Key '{}' not found in '{}'", key, self.name));
+        }
+        self.db.save_if_auto().map_err(|e| e.to_string())
+    }
+
+    pub fn expire(&mut self, key: &str, ttl: Duration) -> io::Result<bool> {
+        if !self.exists(key) {
+            return Ok(false);
+        }
+        self.keyspace_mut().set_ttl(key, Some(ttl));
+        self.db.save_if_auto()?;
+        Ok(true)
+    }
+
+    // Remove every key (indexes stay defined, and empty)
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.keyspace_mut().clear();
+        self.db.save_if_auto()
+    }
+
+    // ---------- Schemas ----------
+
+    // prefix "" covers every key of the collection
+    pub fn set_schema(&mut self, prefix: &str, schema: Schema) -> io::Result<()> {
+        let keyspace = self.keyspace_mut();
+        keyspace.options.schemas.insert(prefix.to_string(), schema);
+        keyspace.options_changed();
+        self.db.save_if_auto()
+    }
+
+    pub fn remove_schema(&mut self, prefix: &str) -> io::Result<bool> {
+        let keyspace = self.keyspace_mut();
+        if keyspace.options.schemas.remove(prefix).is_none() {
+            return Ok(false);
+        }
+        keyspace.options_changed();
+        self.db.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn list_schemas(&self) -> Vec<String> {
+        self.keyspace().options.schemas.keys().cloned().collect()
+    }
+
+    pub fn validate_all(&self) -> Vec<ValidationError> {
+        self.keyspace().violations()
+    }
+
+    // ---------- Indexes ----------
+
+    pub fn create_index(&mut self, field: &str) -> io::Result<bool> {
+        let keyspace = self.keyspace_mut();
+        if !keyspace.indexes.create(field, keyspace.store.iter()) {
+            return Ok(false);
+        }
+        self.db.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn create_text_index(&mut self, field: &str, analyzer: Analyzer) -> io::Result<bool> {
+        let keyspace = self.keyspace_mut();
+        if !keyspace
+            .indexes
+            .create_text(field, analyzer, keyspace.store.iter())
+        {
+            return Ok(false);
+        }
+        self.db.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn drop_index(&mut self, field: &str) -> io::Result<bool> {
+        if !self.keyspace_mut().indexes.drop(field) {
+            return Ok(false);
+        }
+        self.db.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn list_indexes(&self) -> Vec<String> {
+        self.keyspace().indexes.fields()
+    }
+
+    pub fn stats(&self) -> CollectionStats {
+        let keyspace = self.keyspace();
+        CollectionStats {
+            name: self.name.clone(),
+            entries: keyspace.len(),
+            expiring: keyspace.expires.len(),
+            indexes: keyspace.indexes.fields(),
+            text_indexes: keyspace.indexes.text_fields(),
+            default_ttl: keyspace.options.default_ttl,
+        }
+    }
+}
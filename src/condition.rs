@@ -1,16 +1,44 @@
+use crate::fulltext::{self, Analyzer};
+use crate::geo::{GeoField, GeoPoint, GeoShape};
+use crate::types::Decimal;
 use crate::value::Value;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::fmt;
 // NEW: Query filter conditions
 #[derive(Debug, Clone)]
 pub enum Condition {
-    Equals(String, Value),    // field equals value
+    Equals(String, Value), // field equals value
+    // Integer comparisons also apply to Timestamps (as nanoseconds since the
+    // epoch) and to Decimals (compared exactly)
     GreaterThan(String, i64), // field > value (for integers)
     LessThan(String, i64),    // field < value
     Contains(String, String), // string field contains substring
     Between(String, i64, i64),
+    Regex(String, Regex), // string field matches a regular expression
+    // NEW: Full-text match, e.g. Matches("body", "rust OR \"key value\"")
+    // Words are compared after analysis (lowercased, see fulltext::Analyzer)
+    Matches(String, String),
+    // NEW: Boolean combinators so conditions can be nested into trees
+    And(Vec<Condition>), // every sub-condition matches (empty = always true)
+    Or(Vec<Condition>),  // at least one sub-condition matches (empty = never)
+    Not(Box<Condition>), // the sub-condition does not match
+    // NEW: Matches on the key rather than the value (e.g. "user:")
+    // Only `matches_entry` can evaluate it, `matches` treats it as unmatched
+    KeyPrefix(String),
+    // NEW: Location conditions (see geo::GeoField for where a location is read from)
+    NearPoint(GeoField, GeoPoint, f64), // within this many meters of the point
+    WithinBox(GeoField, GeoPoint, GeoPoint), // inside the box (south-west, north-east corners)
+    WithinPolygon(GeoField, Vec<GeoPoint>), // inside the polygon
+    // NEW: The field is there, whatever its value (null included)
+    Exists(String),
 }
 
 impl Condition {
     // Check if a value matches this condition
+    // Matches splits text with the default Analyzer; queries, conditional
+    // writes and watches check it with the analyzer of the field's full-text
+    // index instead (matches_entry_with)
     pub fn matches(&self, value: &Value) -> bool {
         match self {
             Condition::Equals(field, expected) => {
@@ -21,38 +49,214 @@ impl Condition {
                 }
             }
             Condition::GreaterThan(field, threshold) => {
-                if let Some(actual) = value.get_field(field) {
-                    if let Some(num) = actual.as_integer() {
-                        return num > *threshold;
-                    }
-                }
-                false
+                compare_integer(value.get_field(field), *threshold) == Some(Ordering::Greater)
             }
             Condition::LessThan(field, threshold) => {
-                if let Some(actual) = value.get_field(field) {
-                    if let Some(num) = actual.as_integer() {
-                        return num < *threshold;
-                    }
-                }
-                false
+                compare_integer(value.get_field(field), *threshold) == Some(Ordering::Less)
             }
             Condition::Contains(field, substring) => {
-                if let Some(actual) = value.get_field(field) {
-                    if let Some(s) = actual.as_string() {
-                        return s.contains(substring);
-                    }
+                if let Some(actual) = value.get_field(field)
+                    && let Some(s) = actual.as_string()
+                {
+                    return s.contains(substring);
                 }
                 false
             }
 
             Condition::Between(field, min, max) => {
-                if let Some(actual) = value.get_field(field) {
-                    if let Some(num) = actual.as_integer() {
-                        return num >= *min && num <= *max;
-                    }
+                let above_min = compare_integer(value.get_field(field), *min)
+                    .is_some_and(|o| o != Ordering::Less);
+                let below_max = compare_integer(value.get_field(field), *max)
+                    .is_some_and(|o| o != Ordering::Greater);
+                above_min && below_max
+            }
+            Condition::Regex(field, pattern) => {
+                if let Some(Value::String(s)) = value.get_field(field) {
+                    return pattern.is_match(s);
                 }
                 false
             }
+            Condition::Matches(field, query) => {
+                fulltext::text_matches(value, field, query, &Analyzer::default())
+            }
+            Condition::And(conditions) => conditions.iter().all(|c| c.matches(value)),
+            Condition::Or(conditions) => conditions.iter().any(|c| c.matches(value)),
+            Condition::Not(condition) => !condition.matches(value),
+            Condition::KeyPrefix(_) => false,
+            Condition::Exists(field) => value.get_field(field).is_some(),
+            Condition::NearPoint(..) | Condition::WithinBox(..) | Condition::WithinPolygon(..) => {
+                match self.geo_shape() {
+                    Some((field, shape)) => field
+                        .point_of(value)
+                        .is_some_and(|point| shape.contains(&point)),
+                    None => false,
+                }
+            }
+        }
+    }
+
+    // The location and area of a geo condition
+    pub fn geo_shape(&self) -> Option<(&GeoField, GeoShape)> {
+        let shape = match self {
+            Condition::NearPoint(field, center, radius) => (
+                field,
+                GeoShape::Circle {
+                    center: *center,
+                    radius: *radius,
+                },
+            ),
+            Condition::WithinBox(field, sw, ne) => (field, GeoShape::Box { sw: *sw, ne: *ne }),
+            Condition::WithinPolygon(field, vertices) => {
+                (field, GeoShape::Polygon(vertices.clone()))
+            }
+            _ => return None,
+        };
+        Some(shape)
+    }
+
+    // The point results should be sorted around: a NearPoint on its own or
+    // directly inside a top-level AND
+    pub fn near_point(&self) -> Option<(&GeoField, &GeoPoint)> {
+        match self {
+            Condition::NearPoint(field, center, _) => Some((field, center)),
+            Condition::And(conditions) => conditions.iter().find_map(|c| match c {
+                Condition::NearPoint(field, center, _) => Some((field, center)),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+
+    // Check if a key-value pair matches this condition
+    // Same as `matches`, but key conditions (KeyPrefix) are evaluated against the key
+    pub fn matches_entry(&self, key: &str, value: &Value) -> bool {
+        self.matches_entry_with(key, value, &|_| Analyzer::default())
+    }
+
+    // Same as `matches_entry`, but Matches uses the analyzer picked for its field
+    // (the database passes the analyzer of the field's full-text index)
+    pub(crate) fn matches_entry_with(
+        &self,
+        key: &str,
+        value: &Value,
+        analyzer_for: &dyn Fn(&str) -> Analyzer,
+    ) -> bool {
+        match self {
+            Condition::KeyPrefix(prefix) => key.starts_with(prefix.as_str()),
+            Condition::Matches(field, query) => {
+                fulltext::text_matches(value, field, query, &analyzer_for(field))
+            }
+            Condition::And(conditions) => conditions
+                .iter()
+                .all(|c| c.matches_entry_with(key, value, analyzer_for)),
+            Condition::Or(conditions) => conditions
+                .iter()
+                .any(|c| c.matches_entry_with(key, value, analyzer_for)),
+            Condition::Not(condition) => !condition.matches_entry_with(key, value, analyzer_for),
+            other => other.matches(value),
+        }
+    }
+}
+
+// How a field compares to an integer, for values integer conditions apply to
+fn compare_integer(actual: Option<&Value>, n: i64) -> Option<Ordering> {
+    match actual? {
+        Value::Integer(i) | Value::Timestamp(i) => Some(i.cmp(&n)),
+        Value::Decimal(d) => Some(d.cmp(&Decimal::from(n))),
+        _ => None,
+    }
+}
+
+impl Condition {
+    // Render a value the way the query language writes literals ('text', 42, true)
+    pub(crate) fn literal(value: &Value) -> String {
+        match value {
+            Value::String(s) => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+            other => other.to_string(),
+        }
+    }
+}
+
+// SQL-like text form, e.g. "age > 28 AND (active = true OR name ~ '^A')"
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Nested AND/OR get parentheses so the text reads unambiguously
+        fn write_nested(f: &mut fmt::Formatter<'_>, condition: &Condition) -> fmt::Result {
+            match condition {
+                Condition::And(c) | Condition::Or(c) if c.len() > 1 => write!(f, "({})", condition),
+                _ => write!(f, "{}", condition),
+            }
+        }
+        fn write_joined(
+            f: &mut fmt::Formatter<'_>,
+            conditions: &[Condition],
+            separator: &str,
+        ) -> fmt::Result {
+            for (i, condition) in conditions.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", separator)?;
+                }
+                write_nested(f, condition)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Condition::Equals(field, value) => {
+                write!(f, "{} = {}", field, Condition::literal(value))
+            }
+            Condition::GreaterThan(field, n) => write!(f, "{} > {}", field, n),
+            Condition::LessThan(field, n) => write!(f, "{} < {}", field, n),
+            Condition::Contains(field, s) => {
+                write!(
+                    f,
+                    "{} CONTAINS {}",
+                    field,
+                    Condition::literal(&Value::String(s.clone()))
+                )
+            }
+            Condition::Between(field, low, high) => {
+                write!(f, "{} BETWEEN {} AND {}", field, low, high)
+            }
+            Condition::Regex(field, regex) => {
+                write!(
+                    f,
+                    "{} ~ {}",
+                    field,
+                    Condition::literal(&Value::String(regex.as_str().to_string()))
+                )
+            }
+            Condition::Matches(field, query) => write!(
+                f,
+                "{} MATCHES {}",
+                field,
+                Condition::literal(&Value::String(query.clone()))
+            ),
+            Condition::And(conditions) if conditions.is_empty() => write!(f, "TRUE"),
+            Condition::Or(conditions) if conditions.is_empty() => write!(f, "FALSE"),
+            Condition::And(conditions) => write_joined(f, conditions, "AND"),
+            Condition::Or(conditions) => write_joined(f, conditions, "OR"),
+            Condition::Not(inner) => {
+                write!(f, "NOT ")?;
+                match inner.as_ref() {
+                    Condition::And(_) | Condition::Or(_) => write_nested(f, inner),
+                    _ => write!(f, "({})", inner),
+                }
+            }
+            Condition::KeyPrefix(prefix) => {
+                write!(
+                    f,
+                    "key PREFIX {}",
+                    Condition::literal(&Value::String(prefix.clone()))
+                )
+            }
+            Condition::Exists(field) => write!(f, "{} EXISTS", field),
+            Condition::NearPoint(..) | Condition::WithinBox(..) | Condition::WithinPolygon(..) => {
+                match self.geo_shape() {
+                    Some((field, shape)) => write!(f, "{} {}", field, shape),
+                    None => Ok(()),
+                }
+            }
         }
     }
 }
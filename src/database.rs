@@ -1,14 +1,55 @@
+use crate::changelog::{Change, ChangeLog, ChangeOp, LogPosition};
+use crate::collection::Collection;
+use crate::fulltext::{Analyzer, SearchHit};
+use crate::geo::GeoField;
+use crate::index::{IndexStats, Indexes};
+use crate::keyspace::{CollectionOptions, Keyspace, KeyspaceImage, KeyspaceImageRef};
+use crate::patch::{self, PatchError, PatchOp};
+use crate::planner::QueryPlan;
+use crate::pubsub::{PubSub, Subscriber};
+use crate::ql::{self, QueryError, QueryOutput};
+use crate::query::QueryOptions;
+use crate::replication::ReplicationStats;
+use crate::schema::{Schema, ValidationError};
+use crate::storage::DataFile;
+use crate::transaction::{Transaction, TransactionError};
+use crate::typed::{self, TypedError};
+use crate::vector::{Neighbor, VectorIndexOptions};
+use crate::watch::{Feed, WatchError, WatchFilter, Watcher};
 use crate::{Condition, StorageEngine, Value};
-use std::{collections::HashMap, io};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    time::Duration,
+};
+
+// Extension of the file (next to the data file) holding the vector indexes
+const VECTOR_INDEX_FILE: &str = "vectors";
+// ... and the definitions of the other indexes (their contents are rebuilt)
+const INDEX_FILE: &str = "indexes";
+// ... and the per-key version numbers
+const VERSION_FILE: &str = "versions";
+// ... and the position in the write log (see changelog.rs)
+const LOG_POSITION_FILE: &str = "replication";
 
 // Our Database struct - this is like a class in other languages
 // It holds all our data
 pub struct Database {
-    // HashMap is Rust's hash table - stores key-value pairs
-    // String = key type, Value (Enum) = value type
-    store: HashMap<String, Value>,
+    // The database's own keys: entries (in a BTreeMap, Rust's sorted map, so the
+    // query planner can scan a key prefix without touching the rest), their
+    // indexes, versions and expiry times (see keyspace.rs)
+    main: Keyspace,
+    // NEW: Named collections, each with a keyspace of its own
+    collections: BTreeMap<String, Keyspace>,
     storage: StorageEngine,
     auto_save: bool, // Automatically save after each write operation
+    verbose: bool,   // NEW: print progress messages ("✓ Data inserted successfully")
+    // NEW: The write log, kept while the database replicates (see change_log)
+    log: Option<ChangeLog>,
+    // NEW: Pub/sub channels (see pubsub.rs); nothing about them is saved
+    pubsub: PubSub,
 }
 
 impl Database {
@@ -16,38 +57,158 @@ impl Database {
     // 'Self' refers to Database
     pub fn new(file_path: &str) -> Self {
         Database {
-            store: HashMap::new(),
+            main: Keyspace::default(),
+            collections: BTreeMap::new(),
             storage: StorageEngine::new(file_path),
             auto_save: true,
+            verbose: true,
+            log: None,
+            pubsub: PubSub::new(),
         }
     }
 
     // Load database from disk (if file exists)
     pub fn load(&mut self) -> io::Result<()> {
-        self.store = self.storage.load()?;
+        let (main, collections) = match self.storage.load_file::<FileImage>()? {
+            DataFile::Missing => (KeyspaceImage::default(), BTreeMap::new()),
+            DataFile::Entries(entries) => (
+                KeyspaceImage {
+                    entries,
+                    ..KeyspaceImage::default()
+                },
+                BTreeMap::new(),
+            ),
+            DataFile::Image(image) => (image.main, image.collections),
+        };
+        // Watchers carry on; a feed saved earlier starts again
+        let feed = match self.main.feed.take() {
+            Some(feed) => Some(feed),
+            None => Feed::open(&self.storage)?,
+        };
+        self.main = self.restore_keyspace(None, main, feed)?;
+        self.collections.clear();
+        for (name, image) in collections {
+            let keyspace = self.restore_keyspace(Some(&name), image, None)?;
+            self.collections.insert(name, keyspace);
+        }
         Ok(())
     }
 
+    fn restore_keyspace(
+        &self,
+        collection: Option<&str>,
+        image: KeyspaceImage,
+        feed: Option<Feed>,
+    ) -> io::Result<Keyspace> {
+        let versions = self
+            .storage
+            .load_sidecar(&sidecar_name(collection, VERSION_FILE))?;
+        let mut keyspace = Keyspace::restore(image, versions);
+        if let Some(definitions) = self
+            .storage
+            .load_sidecar(&sidecar_name(collection, INDEX_FILE))?
+        {
+            keyspace.indexes.restore_definitions(definitions);
+        }
+        if let Some(vectors) = self
+            .storage
+            .load_sidecar(&sidecar_name(collection, VECTOR_INDEX_FILE))?
+        {
+            keyspace.indexes.restore_vector_indexes(vectors);
+        }
+        keyspace.indexes.rebuild(&keyspace.store);
+        keyspace.feed = feed;
+        keyspace.purge_expired();
+        Ok(keyspace)
+    }
+
     // Save database to disk
+    // A database without collections or expiry times is saved as a plain
+    // key-value map, the format every earlier version reads
     pub fn save(&self) -> io::Result<()> {
-        self.storage.save(&self.store)
+        // The events first: a crash in between reports writes that weren't
+        // saved rather than losing the report of writes that were
+        if let Some(feed) = &self.main.feed {
+            feed.save(&self.storage)?;
+        }
+        if self.collections.is_empty()
+            && self.main.expires.is_empty()
+            && self.main.options == CollectionOptions::default()
+        {
+            self.storage.save(&self.main.store)?;
+        } else {
+            self.storage.save_image(&self.image())?;
+        }
+        self.save_sidecars(None, &self.main)?;
+        for (name, keyspace) in &self.collections {
+            self.save_sidecars(Some(name), keyspace)?;
+        }
+        // Saved without a log, the data no longer matches any log position
+        match self.log_position() {
+            Some(position) => self.storage.save_sidecar(LOG_POSITION_FILE, &position),
+            None => self.storage.delete_sidecar(LOG_POSITION_FILE),
+        }
+    }
+
+    fn image(&self) -> FileImageRef<'_> {
+        FileImageRef {
+            main: self.main.image(),
+            collections: self
+                .collections
+                .iter()
+                .map(|(name, keyspace)| (name.as_str(), keyspace.image()))
+                .collect(),
+        }
+    }
+
+    // Indexes and versions live in files next to the data file
+    fn save_sidecars(&self, collection: Option<&str>, keyspace: &Keyspace) -> io::Result<()> {
+        let definitions = keyspace.indexes.definitions();
+        let index_file = sidecar_name(collection, INDEX_FILE);
+        if definitions.is_empty() {
+            self.storage.delete_sidecar(&index_file)?;
+        } else {
+            self.storage.save_sidecar(&index_file, &definitions)?;
+        }
+        let vectors = keyspace.indexes.vector_indexes();
+        let vector_file = sidecar_name(collection, VECTOR_INDEX_FILE);
+        if vectors.is_empty() {
+            self.storage.delete_sidecar(&vector_file)?;
+        } else {
+            self.storage.save_sidecar(&vector_file, vectors)?;
+        }
+        self.storage
+            .save_sidecar(&sidecar_name(collection, VERSION_FILE), &keyspace.versions)
     }
 
     // Enable or disable auto-save (useful for batch operations)
     pub fn set_auto_save(&mut self, enabled: bool) {
         self.auto_save = enabled;
-        println!("Auto-save {}", if enabled { "enabled" } else { "disabled" });
+        if self.verbose {
+            println!("Auto-save {}", if enabled { "enabled" } else { "disabled" });
+        }
+    }
+
+    // NEW: Turn the progress messages (of the database and its storage) on or
+    // off; programs whose output is read by other programs turn them off
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+        self.storage.set_verbose(verbose);
     }
 
     // Insert a key-value pair
     // &mut self = mutable reference to self (we need to modify the database)
     pub fn insert(&mut self, key: String, value: Value) -> io::Result<()> {
-        self.store.insert(key, value);
+        self.main.check(&key, &value)?;
+        self.main.set_ttl(&key, None); // a plain insert replaces any expiry
+        self.put(key, value);
 
         if self.auto_save {
             self.save()?;
         }
-        println!("✓ Data inserted successfully");
+        if self.verbose {
+            println!("✓ Data inserted successfully");
+        }
         Ok(())
     }
 
@@ -55,13 +216,19 @@ impl Database {
     pub fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> io::Result<usize> {
         //usize is guaranteed to be large enough to represent any memory address on the machine it's compiled for. On a 32-bit system, usize will be 32 bits wide (like u32), and on a 64-bit system, it will be 64 bits wide (like u64). usize is the standard type used for indexing into collections (like Vec or HashMap) and for representing sizes or lengths of data structures in Rust's standard library. This ensures compatibility and correctness across different architectures.
         let count = entries.len();
+        // Nothing is written unless every value fits its schema
+        for (key, value) in &entries {
+            self.main.check(key, value)?;
+        }
         for (key, value) in entries {
-            self.store.insert(key, value);
+            self.put(key, value);
         }
         if self.auto_save {
             self.save()?;
         }
-        println!("✓ Batch inserted {} entries", count);
+        if self.verbose {
+            println!("✓ Batch inserted {} entries", count);
+        }
         Ok(count)
     }
 
@@ -88,7 +255,30 @@ impl Database {
     pub fn get(&self, key: &str) -> Option<Value> {
         // .get() returns Option<&String>, we clone to return owned String
         // .get() returns Option<&Value>, we clone to return owned Value
-        self.store.get(key).cloned()
+        self.main.get(key).cloned()
+    }
+
+    // NEW: Typed documents - store and read back any serde type
+    //   db.insert_typed("user:1".to_string(), &user)?;
+    //   let user: Option<User> = db.get_typed("user:1")?;
+    pub fn insert_typed<T: Serialize + ?Sized>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> io::Result<()> {
+        let value = typed::to_value(value).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, e.for_key(&key).to_string())
+        })?;
+        self.insert(key, value)
+    }
+
+    pub fn get_typed<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, TypedError> {
+        match self.get(key) {
+            Some(value) => typed::from_value(value)
+                .map(Some)
+                .map_err(|e| e.for_key(key)),
+            None => Ok(None),
+        }
     }
 
     pub fn batch_get(&self, keys: Vec<&str>) -> HashMap<String, Value> {
@@ -104,17 +294,440 @@ impl Database {
     // NEW: Batch get - retrieve multiple keys at once
 
     pub fn update(&mut self, key: String, value: Value) -> Result<(), String> {
-        if self.store.contains_key(&key) {
-            self.store.insert(key, value);
+        if self.main.contains(&key) {
+            self.main.check(&key, &value).map_err(|e| e.to_string())?;
+            self.put(key, value);
             Ok(())
         } else {
             Err(format!("Key '{}' not found", key))
         }
     }
 
+    // NEW: Change part of a value in place (see patch.rs for the operations)
+    // All operations apply or none do; returns the new value
+    pub fn patch(&mut self, key: &str, ops: Vec<PatchOp>) -> Result<Value, PatchError> {
+        let current = self
+            .main
+            .get(key)
+            .ok_or_else(|| PatchError::KeyNotFound(key.to_string()))?;
+        let patched = patch::apply(current, &ops)?;
+        self.main
+            .check(key, &patched)
+            .map_err(PatchError::Invalid)?;
+        self.write(key.to_string(), patched.clone())
+            .map_err(|e| PatchError::Io(e.to_string()))?;
+        Ok(patched)
+    }
+
+    // NEW: Atomic counters and optimistic concurrency
+    //
+    // Every key has a version number that changes on every write to it.
+    // Versions come from one counter for the whole database, so a key that is
+    // deleted and written again never gets a version it had before.
+
+    // Add n to an Integer (a missing key starts at 0); returns the new value
+    pub fn incr_by(&mut self, key: &str, n: i64) -> io::Result<i64> {
+        let updated = match self.main.get(key) {
+            None => n,
+            Some(Value::Integer(i)) => i
+                .checked_add(n)
+                .ok_or_else(|| invalid_input(format!("Key '{}': {} + {} overflows", key, i, n)))?,
+            Some(other) => {
+                return Err(invalid_input(format!(
+                    "Key '{}' holds a {}, not an Integer",
+                    key,
+                    other.type_name()
+                )));
+            }
+        };
+        self.write(key.to_string(), Value::Integer(updated))?;
+        Ok(updated)
+    }
+
+    // Add n to a Float or Integer (a missing key starts at 0.0); the result is a Float
+    pub fn incr_float(&mut self, key: &str, n: f64) -> io::Result<f64> {
+        let updated = match self.main.get(key) {
+            None => n,
+            Some(Value::Float(f)) => f + n,
+            Some(Value::Integer(i)) => *i as f64 + n,
+            Some(other) => {
+                return Err(invalid_input(format!(
+                    "Key '{}' holds a {}, not a number",
+                    key,
+                    other.type_name()
+                )));
+            }
+        };
+        if !updated.is_finite() {
+            return Err(invalid_input(format!(
+                "Key '{}': result {} is not a finite number",
+                key, updated
+            )));
+        }
+        self.write(key.to_string(), Value::Float(updated))?;
+        Ok(updated)
+    }
+
+    // Write `new` only if the current value equals `expected`
+    // (expected = None means the key must not exist). Returns whether it wrote.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> io::Result<bool> {
+        if self.main.get(key) != expected.as_ref() {
+            return Ok(false);
+        }
+        self.write(key.to_string(), new)?;
+        Ok(true)
+    }
+
+    // None for a missing key, and for an expired one that isn't purged yet
+    pub fn version(&self, key: &str) -> Option<u64> {
+        if !self.exists(key) {
+            return None;
+        }
+        self.main.versions.keys.get(key).copied()
+    }
+
+    pub fn get_with_version(&self, key: &str) -> Option<(Value, u64)> {
+        let value = self.main.get(key)?.clone();
+        Some((value, self.version(key)?))
+    }
+
+    // Write only if the key is still at `version` (0 = the key must not exist)
+    // Read with get_with_version, change the value, then write it back here;
+    // false means someone else wrote in between and the caller should retry
+    pub fn set_if_version(&mut self, key: &str, version: u64, value: Value) -> io::Result<bool> {
+        if self.version(key).unwrap_or(0) != version {
+            return Ok(false);
+        }
+        self.write(key.to_string(), value)?;
+        Ok(true)
+    }
+
+    // NEW: Conditional writes - each returns whether it wrote
+
+    // Insert only if the key doesn't exist yet
+    pub fn insert_if_absent(&mut self, key: String, value: Value) -> io::Result<bool> {
+        if self.main.contains(&key) {
+            return Ok(false);
+        }
+        self.write(key, value)?;
+        Ok(true)
+    }
+
+    // Store `default` if the key is missing, otherwise replace the value with
+    // update(current); returns the value now stored
+    //   db.upsert_with("visits", Value::Integer(1), |v| match v { ... })?;
+    pub fn upsert_with<F>(&mut self, key: &str, default: Value, update: F) -> io::Result<Value>
+    where
+        F: FnOnce(&Value) -> Value,
+    {
+        let value = match self.main.get(key) {
+            Some(current) => update(current),
+            None => default,
+        };
+        self.write(key.to_string(), value.clone())?;
+        Ok(value)
+    }
+
+    // Replace the value only if the key exists and its current value matches
+    //   db.update_if("order:7", &Condition::Equals("status".into(), pending), shipped)?;
+    pub fn update_if(
+        &mut self,
+        key: &str,
+        condition: &Condition,
+        value: Value,
+    ) -> io::Result<bool> {
+        if !self.current_matches(key, condition) {
+            return Ok(false);
+        }
+        self.write(key.to_string(), value)?;
+        Ok(true)
+    }
+
+    // Delete the key only if its current value matches
+    pub fn delete_if(&mut self, key: &str, condition: &Condition) -> io::Result<bool> {
+        if !self.current_matches(key, condition) || self.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    fn current_matches(&self, key: &str, condition: &Condition) -> bool {
+        let analyzer_for = |field: &str| self.main.indexes.analyzer_for(field);
+        self.main
+            .get(key)
+            .is_some_and(|value| condition.matches_entry_with(key, value, &analyzer_for))
+    }
+
+    // NEW: Apply a transaction's writes (see transaction.rs), then save once
+    pub fn commit(&mut self, tx: Transaction) -> Result<(), TransactionError> {
+        tx.check(self)?;
+        for (key, value) in tx.into_writes() {
+            match value {
+                Some(value) => {
+                    self.main.set_ttl(&key, None); // like insert
+                    self.put(key, value);
+                }
+                None => {
+                    self.remove(&key);
+                }
+            }
+        }
+        self.save_if_auto()
+            .map_err(|e| TransactionError::Io(e.to_string()))
+    }
+
+    pub(crate) fn check_schema(&self, key: &str, value: &Value) -> Result<(), ValidationError> {
+        self.main.check(key, value)
+    }
+
+    // NEW: Watching keys (see watch.rs)
+    //   let mut watcher = db.watch("user:")?;
+    //   let mut watcher = db.watch_from(Condition::Equals(..), last_seq)?;
+
+    // The changes to matching keys from now on
+    pub fn watch(&mut self, filter: impl Into<WatchFilter>) -> Result<Watcher, WatchError> {
+        let (feed, indexes) = self.feed()?;
+        feed.subscribe(filter.into(), None, indexes)
+    }
+
+    // The changes to matching keys after event `after`, then on
+    pub fn watch_from(
+        &mut self,
+        filter: impl Into<WatchFilter>,
+        after: u64,
+    ) -> Result<Watcher, WatchError> {
+        let (feed, indexes) = self.feed()?;
+        feed.subscribe(filter.into(), Some(after), indexes)
+    }
+
+    // Started by the first watch: from the saved events, if there are any.
+    // Comes with the indexes, whose analyzers the watch filters use.
+    fn feed(&mut self) -> Result<(&mut Feed, &Indexes), WatchError> {
+        if self.main.feed.is_none() {
+            let saved = Feed::open(&self.storage).map_err(|e| WatchError::Io(e.to_string()))?;
+            self.main.feed = Some(saved.unwrap_or_default());
+        }
+        let main = &mut self.main;
+        let feed = main.feed.as_mut().expect("the feed was just started");
+        Ok((feed, &main.indexes))
+    }
+
+    // The sequence number of the latest event (0 before the first)
+    pub fn watch_seq(&self) -> u64 {
+        self.main.feed.as_ref().map_or(0, Feed::seq)
+    }
+
+    // NEW: Pub/sub (see pubsub.rs)
+    //   let subscriber = db.subscriber();
+    //   subscriber.psubscribe("orders.*");
+    //   db.publish("orders.new", value);
+
+    // How many subscriptions the message was queued for
+    pub fn publish(&self, channel: &str, payload: Value) -> usize {
+        self.pubsub.publish(channel, payload)
+    }
+
+    pub fn subscriber(&self) -> Subscriber {
+        self.pubsub.subscriber()
+    }
+
+    // A handle on the same channels, for publishing and subscribing from
+    // threads that don't have the database
+    pub fn pubsub(&self) -> PubSub {
+        self.pubsub.clone()
+    }
+
+    // NEW: Expiring keys
+    // An expired key reads as missing right away; its entry is dropped by the
+    // next write to it, by purge_expired, or when the file is loaded again
+
+    pub fn insert_with_ttl(&mut self, key: String, value: Value, ttl: Duration) -> io::Result<()> {
+        self.main.check(&key, &value)?;
+        self.put(key.clone(), value);
+        self.main.set_ttl(&key, Some(ttl));
+        self.save_if_auto()
+    }
+
+    // Set the time to live of an existing key; false if the key doesn't exist
+    pub fn expire(&mut self, key: &str, ttl: Duration) -> io::Result<bool> {
+        if !self.main.contains(key) {
+            return Ok(false);
+        }
+        self.main.set_ttl(key, Some(ttl));
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    // Remove a key's expiry; false if it had none
+    pub fn persist(&mut self, key: &str) -> io::Result<bool> {
+        if self.main.ttl(key).is_none() {
+            return Ok(false);
+        }
+        self.main.set_ttl(key, None);
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    // Time left before the key expires (None if it doesn't exist or never expires)
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        self.main.ttl(key)
+    }
+
+    // Drop every expired entry, in the database and in all collections
+    pub fn purge_expired(&mut self) -> usize {
+        let mut purged = self.main.purge_expired().len();
+        for keyspace in self.collections.values_mut() {
+            purged += keyspace.purge_expired().len();
+        }
+        purged
+    }
+
+    // NEW: Schemas (see schema.rs)
+    // Writes to keys starting with the prefix must fit the schema; values
+    // already stored are left alone (validate_all finds the ones that don't fit)
+    //   db.set_schema("user:", Schema::object().required("name"))?;
+
+    pub fn set_schema(&mut self, prefix: &str, schema: Schema) -> io::Result<()> {
+        self.main.options.schemas.insert(prefix.to_string(), schema);
+        self.main.options_changed();
+        self.save_if_auto()
+    }
+
+    // false if there was no schema for the prefix
+    pub fn remove_schema(&mut self, prefix: &str) -> io::Result<bool> {
+        if self.main.options.schemas.remove(prefix).is_none() {
+            return Ok(false);
+        }
+        self.main.options_changed();
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn schema(&self, prefix: &str) -> Option<&Schema> {
+        self.main.options.schemas.get(prefix)
+    }
+
+    // Prefixes that have a schema
+    pub fn list_schemas(&self) -> Vec<String> {
+        self.main.options.schemas.keys().cloned().collect()
+    }
+
+    // Check the stored values against their schemas; every problem found, in
+    // key order (collections have their own validate_all)
+    pub fn validate_all(&self) -> Vec<ValidationError> {
+        self.main.violations()
+    }
+
+    // NEW: Collections - named sets of keys with their own indexes, versions
+    // and expiry defaults, saved in the same file as the database
+    //
+    //   db.collection("users").insert("alice".to_string(), value)?;
+    //   db.collection("users").query(Condition::GreaterThan("age".into(), 30));
+
+    // The collection with this name, created (with default options) if needed
+    pub fn collection(&mut self, name: &str) -> Collection<'_> {
+        self.create_collection(name, CollectionOptions::default());
+        Collection::new(self, name)
+    }
+
+    // Create a collection with options; false if it already exists
+    pub fn create_collection(&mut self, name: &str, options: CollectionOptions) -> bool {
+        if self.collections.contains_key(name) {
+            return false;
+        }
+        let mut keyspace = self.new_keyspace(options);
+        keyspace.options_changed(); // the write log's record of the new collection
+        self.collections.insert(name.to_string(), keyspace);
+        true
+    }
+
+    // Drop a collection with all its keys and indexes; false if there is none
+    pub fn drop_collection(&mut self, name: &str) -> io::Result<bool> {
+        self.sync_log(); // what was written to it is logged before the drop
+        if self.collections.remove(name).is_none() {
+            return Ok(false);
+        }
+        if let Some(log) = &mut self.log {
+            log.push(Change {
+                collection: Some(name.to_string()),
+                op: ChangeOp::DropCollection,
+            });
+        }
+        self.storage
+            .delete_sidecar(&sidecar_name(Some(name), INDEX_FILE))?;
+        self.storage
+            .delete_sidecar(&sidecar_name(Some(name), VECTOR_INDEX_FILE))?;
+        self.storage
+            .delete_sidecar(&sidecar_name(Some(name), VERSION_FILE))?;
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn list_collections(&self) -> Vec<String> {
+        self.collections.keys().cloned().collect()
+    }
+
+    pub fn has_collection(&self, name: &str) -> bool {
+        self.collections.contains_key(name)
+    }
+
+    // Move every key starting with `prefix` into a collection, without the
+    // prefix: "user:alice" becomes "alice" in collection "users".
+    // Expiry times move along; returns the number of keys moved.
+    pub fn migrate_prefix(&mut self, prefix: &str, collection: &str) -> io::Result<usize> {
+        let keys = self.main.keys_with_prefix(prefix);
+        self.create_collection(collection, CollectionOptions::default());
+        let target = self
+            .collections
+            .get_mut(collection)
+            .expect("the collection was just created");
+        // Nothing moves unless every value fits the collection's schemas
+        for key in &keys {
+            if let Some(value) = self.main.get(key) {
+                target.check(&key[prefix.len()..], value)?;
+            }
+        }
+        for key in &keys {
+            let ttl = self.main.ttl(key);
+            let Some(value) = self.main.remove(key) else {
+                continue;
+            };
+            let new_key = key[prefix.len()..].to_string();
+            target.put(new_key.clone(), value);
+            target.set_ttl(&new_key, ttl);
+        }
+        if self.verbose {
+            println!(
+                "✓ Moved {} keys from '{}' to collection '{}'",
+                keys.len(),
+                prefix,
+                collection
+            );
+        }
+        self.save_if_auto()?;
+        Ok(keys.len())
+    }
+
+    // Used by Collection, which only exists for collections that do
+    pub(crate) fn keyspace(&self, name: &str) -> &Keyspace {
+        &self.collections[name]
+    }
+
+    pub(crate) fn keyspace_mut(&mut self, name: &str) -> &mut Keyspace {
+        self.collections
+            .get_mut(name)
+            .expect("collection exists while a handle to it is alive")
+    }
+
     // Delete a key-value pair
     pub fn delete(&mut self, key: &str) -> Result<(), String> {
-        if self.store.remove(key).is_some() {
+        if self.remove(key).is_some() {
             Ok(())
         } else {
             Err(format!("Key '{}' not found", key))
@@ -124,40 +737,47 @@ impl Database {
     pub fn batch_delete(&mut self, keys: Vec<&str>) -> usize {
         let mut deleted = 0;
         for key in keys {
-            if self.store.remove(key).is_some() {
+            if self.remove(key).is_some() {
                 deleted += 1;
             }
         }
 
-        println!("✓ Batch deleted {} entries", deleted);
+        if self.verbose {
+            println!("✓ Batch deleted {} entries", deleted);
+        }
         deleted
     }
 
     // List all keys (useful for debugging)
     pub fn list_keys(&self) -> Vec<String> {
-        self.store.keys().cloned().collect()
+        self.main.keys()
     }
 
     // Get total number of entries
     pub fn count(&self) -> usize {
-        self.store.len()
+        self.main.len()
     }
 
     // clear the database
     pub fn clear(&mut self) {
-        self.store.clear();
+        self.main.clear();
 
-        println!("Database is cleared");
+        if self.verbose {
+            println!("Database is cleared");
+        }
     }
 
     pub fn exists(&self, key: &str) -> bool {
-        return self.store.contains_key(key);
+        self.main.contains(key)
     }
 
     // New: Get all entries of a specific type
     pub fn get_all_integers(&self) -> Vec<(String, i64)> {
-        self.store
+        let now = crate::types::timestamp_now();
+        self.main
+            .store
             .iter() // Start iterating over all key-value pairs in the HashMap `store`
+            .filter(|(k, _)| !self.main.is_expired(k, now))
             .filter_map(|(k, v)| {
                 // What filter_map does: If you return Some(value) → it keeps value. If you return None → it discards it.
                 // converts some items into (String, i64) and drops others,  filter_map will transform each (k, v) pair , - if the value is an Integer, return Some((key.clone(), value)) , - if not, return None (and filter_map will discard it)
@@ -182,52 +802,488 @@ impl Database {
         */
     }
 
+    // The planner picks a full scan, a key-prefix scan or index lookups,
+    // whichever it expects to touch the fewest entries (results are in key order)
+    // Results come sorted by key, except for NearPoint queries: those are
+    // sorted by distance from the point, closest first
     pub fn query(&self, condition: Condition) -> Vec<(String, Value)> {
-        self.store
-            .iter() //This returns an iterator over references: So each item is a tuple: (&key, &value)
-            .filter(|(_key, value)| condition.matches(value)) // filter() always receives a reference to each iterator item. , Because iterator items are passed by reference to the closure. Actual iterator item: (&String, &Value)   // 1 layer of reference What the closure in filter receives: &(&String, &Value)  // extra reference → 2 layers
-            .map(|(k, v)| (k.clone(), v.clone())) //At this point, keys and values are references: But we want to return owned values in a Vec.So .map() takes references and clones them:
-            .collect() // looks at the return type of the function. Then Rust automatically collects all (String, Value) items into a Vec.
+        self.main.query(&condition)
+    }
+
+    // Like query(), but every match is converted to T; fails on the first
+    // entry that doesn't fit (the error names its key)
+    pub fn query_typed<T: DeserializeOwned>(
+        &self,
+        condition: Condition,
+    ) -> Result<Vec<(String, T)>, TypedError> {
+        self.query(condition)
+            .into_iter()
+            .map(|(key, value)| match typed::from_value(value) {
+                Ok(document) => Ok((key, document)),
+                Err(e) => Err(e.for_key(&key)),
+            })
+            .collect()
     }
 
     // NEW: Query with multiple conditions (AND logic)
     pub fn query_multiple(&self, conditions: Vec<Condition>) -> Vec<(String, Value)> {
-        self.store
-            .iter()
-            .filter(|(_key, value)| conditions.iter().all(|cond| cond.matches(value)))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect()
+        self.query(Condition::And(conditions))
+    }
+
+    // NEW: Run a query and report the plan the planner chose,
+    // with estimated and actual row counts for every step
+    pub fn explain(&self, condition: Condition) -> QueryPlan {
+        self.main.explain(&condition)
+    }
+
+    // NEW: Create a secondary index on an object field
+    // Returns false if the field is already indexed
+    // The definition is saved next to the data file and the index filled
+    // again on load
+    pub fn create_index(&mut self, field: &str) -> io::Result<bool> {
+        if !self.main.indexes.create(field, self.main.store.iter()) {
+            return Ok(false);
+        }
+        if self.verbose {
+            println!("✓ Created index on '{}'", field);
+        }
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    // NEW: Create a full-text index on a string field (for Condition::Matches and search)
+    // Returns false if the field already has one
+    pub fn create_text_index(&mut self, field: &str, analyzer: Analyzer) -> io::Result<bool> {
+        let indexes = &mut self.main.indexes;
+        if !indexes.create_text(field, analyzer, self.main.store.iter()) {
+            return Ok(false);
+        }
+        if self.verbose {
+            println!("✓ Created full-text index on '{}'", field);
+        }
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    // NEW: Full-text search ranked by relevance (BM25), best match first
+    // Needs a full-text index on the field; `limit` caps the number of hits
+    pub fn search(&self, field: &str, query: &str, limit: usize) -> Result<Vec<SearchHit>, String> {
+        let index = self
+            .main
+            .indexes
+            .text(field)
+            .ok_or_else(|| format!("No full-text index on field '{}'", field))?;
+        let hits = index
+            .score(query)
+            .into_iter()
+            .take(limit)
+            .filter_map(|(key, score)| {
+                let value = self.main.get(&key)?.clone();
+                Some(SearchHit { key, score, value })
+            })
+            .collect();
+        Ok(hits)
+    }
+
+    // NEW: Create a vector index on a field holding arrays of numbers (embeddings)
+    // Returns false if the field already has one
+    pub fn create_vector_index(
+        &mut self,
+        field: &str,
+        options: VectorIndexOptions,
+    ) -> io::Result<bool> {
+        let indexes = &mut self.main.indexes;
+        if !indexes.create_vector(field, options, self.main.store.iter()) {
+            return Ok(false);
+        }
+        if self.verbose {
+            println!("✓ Created vector index on '{}'", field);
+        }
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    // NEW: Create a location index for NearPoint / WithinBox / WithinPolygon queries
+    // e.g. GeoField::lat_lng("lat", "lng") or GeoField::array("location")
+    // Returns false if the location already has one; drop it with drop_index(&field.name())
+    pub fn create_geo_index(&mut self, field: GeoField) -> io::Result<bool> {
+        let name = field.name();
+        if !self.main.indexes.create_geo(field, self.main.store.iter()) {
+            return Ok(false);
+        }
+        if self.verbose {
+            println!("✓ Created geo index on '{}'", name);
+        }
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    // NEW: The k entries whose vector in `field` is closest to `query`, closest first
+    // An optional filter restricts which entries may be returned
+    pub fn nearest(
+        &self,
+        field: &str,
+        query: &[f32],
+        k: usize,
+        filter: Option<Condition>,
+    ) -> Result<Vec<Neighbor>, String> {
+        let index = self
+            .main
+            .indexes
+            .vector(field)
+            .ok_or_else(|| format!("No vector index on field '{}'", field))?;
+        if let Some(dimension) = index.dimension()
+            && dimension != query.len()
+        {
+            return Err(format!(
+                "Query vector has {} dimensions, index on '{}' expects {}",
+                query.len(),
+                field,
+                dimension
+            ));
+        }
+
+        let analyzer_for = |f: &str| self.main.indexes.analyzer_for(f);
+        let accept = |key: &str| match (&filter, self.main.get(key)) {
+            (None, Some(_)) => true,
+            (Some(condition), Some(value)) => {
+                condition.matches_entry_with(key, value, &analyzer_for)
+            }
+            (_, None) => false,
+        };
+        let neighbors = index
+            .search(query, k, &accept)
+            .into_iter()
+            .filter_map(|(key, distance)| {
+                let value = self.main.get(&key)?.clone();
+                Some(Neighbor {
+                    key,
+                    distance,
+                    value,
+                })
+            })
+            .collect();
+        Ok(neighbors)
+    }
+
+    // Drop every index on the field (regular, full-text and vector)
+    pub fn drop_index(&mut self, field: &str) -> io::Result<bool> {
+        if !self.main.indexes.drop(field) {
+            return Ok(false);
+        }
+        self.save_if_auto()?;
+        Ok(true)
+    }
+
+    pub fn list_indexes(&self) -> Vec<String> {
+        self.main.indexes.fields()
+    }
+
+    pub fn list_text_indexes(&self) -> Vec<String> {
+        self.main.indexes.text_fields()
+    }
+
+    pub fn list_vector_indexes(&self) -> Vec<String> {
+        self.main.indexes.vector_fields()
+    }
+
+    pub fn list_geo_indexes(&self) -> Vec<String> {
+        self.main.indexes.geo_fields()
+    }
+
+    // Selectivity statistics the planner keeps for an indexed field
+    pub fn index_stats(&self, field: &str) -> Option<IndexStats> {
+        self.main.indexes.get(field).map(|index| index.stats(field))
+    }
+
+    // NEW: Query with sorting, paging and projection
+    pub fn query_with(&self, condition: Condition, options: &QueryOptions) -> Vec<(String, Value)> {
+        // A NearPoint query is ordered by distance unless told otherwise
+        if options.near.is_none()
+            && options.order_by.is_empty()
+            && let Some((field, point)) = condition.near_point()
+        {
+            let options = options.clone().order_by_distance(field.clone(), *point);
+            return options.apply(self.query(condition));
+        }
+        options.apply(self.query(condition))
+    }
+
+    // NEW: Run a query language statement (SELECT / INSERT / UPDATE / DELETE)
+    // Example: db.execute("SELECT name FROM 'user:' WHERE age > 28 ORDER BY age DESC")
+    pub fn execute(&mut self, statement: &str) -> Result<QueryOutput, QueryError> {
+        ql::execute(self, statement)
     }
 
     // NEW: Get all keys matching a prefix pattern
     pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
-        self.store
-            .keys() // .keys() returns: Iterator<Item = &String> So each item is a reference to a key.
-            .filter(|k| k.starts_with(prefix))
-            .cloned() //At this stage, each item is still &String. .cloned() converts: &String → String (owned) . It is shorthand for: .map(|k| k.clone())
-            .collect() // Rust knows the return type is Vec<String>, so it builds a vector of the cloned keys.
+        self.main.keys_with_prefix(prefix) // keys are sorted, so only the matching range is walked
+    }
+
+    // Every write goes through the keyspace's put/remove so the indexes,
+    // versions and expiry times stay in sync with the store
+    fn put(&mut self, key: String, value: Value) -> Option<Value> {
+        self.main.put(key, value)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Value> {
+        self.main.remove(key)
+    }
+
+    // A single-key write, checked against the key's schemas and saved right
+    // away when auto-save is on
+    fn write(&mut self, key: String, value: Value) -> io::Result<()> {
+        self.main.check(&key, &value)?;
+        self.put(key, value);
+        self.save_if_auto()
+    }
+
+    // NEW: Remove the data file and the files saved next to it
+    pub(crate) fn delete_files(&self) -> io::Result<()> {
+        self.storage.delete_file()?;
+        let collections = self.collections.keys().map(|name| Some(name.as_str()));
+        for collection in std::iter::once(None).chain(collections) {
+            self.storage
+                .delete_sidecar(&sidecar_name(collection, VERSION_FILE))?;
+            self.storage
+                .delete_sidecar(&sidecar_name(collection, INDEX_FILE))?;
+            self.storage
+                .delete_sidecar(&sidecar_name(collection, VECTOR_INDEX_FILE))?;
+        }
+        Feed::delete_file(&self.storage)?;
+        self.storage.delete_sidecar(LOG_POSITION_FILE)
+    }
+
+    pub(crate) fn save_if_auto(&self) -> io::Result<()> {
+        if self.auto_save {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    // NEW: The write log and snapshots, for replication (see changelog.rs
+    // and replication/). Changes are only recorded once something asks for
+    // the log, so a database that doesn't replicate pays nothing for it.
+
+    // The log with every change recorded so far numbered; starts one if needed
+    pub(crate) fn change_log(&mut self) -> &mut ChangeLog {
+        if self.log.is_none() {
+            self.log = Some(ChangeLog::new());
+            self.start_journals();
+        }
+        self.sync_log();
+        self.log.as_mut().expect("the log was just started")
+    }
+
+    // Start the log where the saved data left it (a follower restarting), if
+    // it was saved with one; the data must not have changed since it was loaded
+    pub(crate) fn resume_change_log(&mut self) -> io::Result<()> {
+        if self.log.is_none()
+            && let Some(position) = self.storage.load_sidecar(LOG_POSITION_FILE)?
+        {
+            self.log = Some(ChangeLog::resume(position));
+            self.start_journals();
+        }
+        Ok(())
+    }
+
+    // Every key and collection (not the indexes), with the log position they're at
+    pub(crate) fn snapshot(&mut self) -> io::Result<(LogPosition, Vec<u8>)> {
+        let position = self.change_log().position();
+        Ok((position, self.image_bytes()?))
+    }
+
+    // Replace every key and collection with a snapshot's
+    pub(crate) fn load_snapshot(&mut self, position: LogPosition, image: &[u8]) -> io::Result<()> {
+        self.load_image(image)?;
+        self.change_log().reset(position);
+        self.start_journals();
+        Ok(())
+    }
+
+    // Every key and collection (not the indexes), encoded
+    pub(crate) fn image_bytes(&self) -> io::Result<Vec<u8>> {
+        bincode::serialize(&self.image()).map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    // Replace every key and collection with those of image_bytes(); the
+    // indexes defined here stay and are rebuilt
+    pub(crate) fn load_image(&mut self, image: &[u8]) -> io::Result<()> {
+        let image: FileImage =
+            bincode::deserialize(image).map_err(|e| io::Error::other(e.to_string()))?;
+        let mut old = std::mem::take(&mut self.collections);
+        self.main = rebuilt(Some(std::mem::take(&mut self.main)), image.main);
+        for (name, image) in image.collections {
+            let keyspace = rebuilt(old.remove(&name), image);
+            self.collections.insert(name, keyspace);
+        }
+        Ok(())
+    }
+
+    // Replay a change another database numbered (the primary this one follows)
+    pub(crate) fn apply_change(&mut self, lsn: u64, change: Change) {
+        self.replay(change.clone());
+        self.change_log().push_numbered(lsn, change);
+    }
+
+    // Make a change recorded elsewhere (by a primary, or in a raft/ log)
+    // without recording it here
+    pub(crate) fn replay(&mut self, change: Change) {
+        let op = change.op;
+        match &change.collection {
+            None => self.main.apply(op),
+            Some(name) if op == ChangeOp::DropCollection => {
+                self.collections.remove(name);
+            }
+            Some(name) => {
+                if !self.collections.contains_key(name) {
+                    let keyspace = self.new_keyspace(CollectionOptions::default());
+                    self.collections.insert(name.clone(), keyspace);
+                }
+                self.collections
+                    .get_mut(name)
+                    .expect("the collection was just created")
+                    .apply(op);
+            }
+        }
+    }
+
+    // The database's own keyspace (None) or a collection's, if it exists
+    pub(crate) fn keyspace_for(&self, collection: Option<&str>) -> Option<&Keyspace> {
+        match collection {
+            None => Some(&self.main),
+            Some(name) => self.collections.get(name),
+        }
+    }
+
+    pub(crate) fn storage(&self) -> &StorageEngine {
+        &self.storage
+    }
+
+    // Where the log will stand once every recorded change is numbered
+    pub(crate) fn log_position(&self) -> Option<LogPosition> {
+        let log = self.log.as_ref()?;
+        let pending = std::iter::once(&self.main)
+            .chain(self.collections.values())
+            .map(|keyspace| keyspace.journal.as_ref().map_or(0, Vec::len) as u64)
+            .sum::<u64>();
+        Some(LogPosition {
+            id: log.id.clone(),
+            lsn: log.lsn + pending,
+        })
+    }
+
+    // Number what the keyspaces recorded and move it into the log. Changes to
+    // different keyspaces may be logged in another order than they were made;
+    // they touch different keys, so replaying them gives the same data.
+    fn sync_log(&mut self) {
+        let Some(log) = &mut self.log else {
+            return;
+        };
+        for op in self.main.take_journal() {
+            log.push(Change {
+                collection: None,
+                op,
+            });
+        }
+        for (name, keyspace) in &mut self.collections {
+            for op in keyspace.take_journal() {
+                log.push(Change {
+                    collection: Some(name.clone()),
+                    op,
+                });
+            }
+        }
+    }
+
+    fn start_journals(&mut self) {
+        for keyspace in std::iter::once(&mut self.main).chain(self.collections.values_mut()) {
+            keyspace.journal.get_or_insert_with(Vec::new);
+        }
+    }
+
+    // A keyspace that records its changes if the database keeps a log
+    fn new_keyspace(&self, options: CollectionOptions) -> Keyspace {
+        let mut keyspace = Keyspace::new(options);
+        if self.log.is_some() {
+            keyspace.journal = Some(Vec::new());
+        }
+        keyspace
     }
 
     // Get database statistics
     pub fn stats(&self) -> DatabaseStats {
         DatabaseStats {
             total_entries: self.count(),
+            collections: self.collections.len(),
             file_size: self.storage.file_size().unwrap_or(0),
             auto_save_enabled: self.auto_save,
+            replication: None,
         }
     }
 }
 
+// A keyspace from a snapshot, with the index definitions of the one it replaces
+fn rebuilt(old: Option<Keyspace>, image: KeyspaceImage) -> Keyspace {
+    let mut keyspace = Keyspace::restore(image, None);
+    if let Some(old) = old {
+        keyspace.indexes = old.indexes;
+        keyspace.feed = old.feed;
+    }
+    keyspace.indexes.rebuild(&keyspace.store);
+    keyspace
+}
+
+pub(crate) fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// "vectors" for the database's own keys, "<collection>.vectors" for a
+// collection; characters that don't belong in a file name are %-escaped
+fn sidecar_name(collection: Option<&str>, file: &str) -> String {
+    let Some(name) = collection else {
+        return file.to_string();
+    };
+    let mut escaped = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("{}.{}", escaped, file)
+}
+
+// The data file once it holds collections or expiry times (see storage.rs)
+#[derive(Deserialize)]
+struct FileImage {
+    main: KeyspaceImage,
+    collections: BTreeMap<String, KeyspaceImage>,
+}
+
+#[derive(Serialize)]
+struct FileImageRef<'a> {
+    main: KeyspaceImageRef<'a>,
+    collections: BTreeMap<&'a str, KeyspaceImageRef<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DatabaseStats {
-    pub total_entries: usize,
+    pub total_entries: usize, // keys of the database itself, not counting collections
+    pub collections: usize,
     pub file_size: u64,
     pub auto_save_enabled: bool,
+    // NEW: Filled in by a server that replicates (see replication/)
+    #[serde(default)]
+    pub replication: Option<ReplicationStats>,
 }
 
 impl DatabaseStats {
     pub fn print(&self) {
         println!("=== Database Statistics ===");
         println!("Total entries: {}", self.total_entries);
+        println!("Collections: {}", self.collections);
         println!(
             "File size: {} bytes ({:.2} KB)",
             self.file_size,
@@ -241,5 +1297,24 @@ impl DatabaseStats {
                 "disabled"
             }
         );
+        if let Some(replication) = &self.replication {
+            match &replication.primary {
+                Some(primary) => println!(
+                    "Replication: following {} ({}, {} changes behind)",
+                    primary,
+                    if replication.connected {
+                        "connected"
+                    } else {
+                        "disconnected"
+                    },
+                    replication.lag
+                ),
+                None => println!(
+                    "Replication: primary at change {}, {} followers",
+                    replication.lsn,
+                    replication.followers.len()
+                ),
+            }
+        }
     }
 }
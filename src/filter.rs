@@ -0,0 +1,398 @@
+// NEW: Mongo-style JSON filter documents for Condition
+//
+//   {"age": {"$gt": 28}, "$or": [{"active": true}, {"name": {"$regex": "^A"}}]}
+//
+// Field operators: $eq, $ne, $gt, $lt, $gte, $lte, $in, $nin, $contains, $regex,
+//                  $matches (full-text query), $exists (true or false), $not
+// Location operators (points are [lng, lat], distances in meters):
+//   {"location": {"$near": {"$point": [13.4, 52.5], "$maxDistance": 5000}}}
+//   {"location": {"$within": {"$box": [[13.3, 52.4], [13.5, 52.6]]}}}
+//   {"location": {"$within": {"$polygon": [[13.3, 52.4], [13.5, 52.4], [13.4, 52.6]]}}}
+// A "lat,lng" field name reads the location from two number fields.
+// Top-level operators: $and, $or, $nor, $not, $prefix (key prefix)
+// A plain value is shorthand for $eq, and several entries in one document are ANDed.
+//
+// $gt, $lt, $gte and $lte take integers, like Condition::GreaterThan: a float
+// argument (5.0 included) is a TypeMismatch, never rounded.
+//
+// Condition -> JSON -> Condition gives back the same condition tree as long as
+// its values are ones JSON has a type for: null, booleans, integers, finite
+// floats, strings, and arrays and objects of those. Values are written the way
+// json.rs writes them, so Bytes, Timestamp, Decimal and Uuid come back as
+// strings, and NaN and infinity as null.
+// (JSON -> Condition -> JSON may be spelled differently, e.g. $in comes back as $or.)
+
+use std::fmt;
+
+use regex::Regex;
+use serde_json::{Map, Value as Json};
+
+use crate::geo::{GeoField, GeoPoint};
+use crate::json::to_json_lossy;
+use crate::{Condition, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterError {
+    // The text is not valid JSON at all
+    InvalidJson(String),
+    // An operator we don't know, e.g. {"age": {"$gtt": 5}}
+    UnknownOperator {
+        path: String,
+        operator: String,
+    },
+    // An operator argument of the wrong type, e.g. {"age": {"$gt": "5"}}
+    TypeMismatch {
+        path: String,
+        expected: String,
+        found: String,
+    },
+    // A $regex pattern that doesn't compile
+    InvalidRegex {
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::InvalidJson(message) => write!(f, "invalid JSON: {}", message),
+            FilterError::UnknownOperator { path, operator } => {
+                write!(f, "at {}: unknown operator '{}'", path, operator)
+            }
+            FilterError::TypeMismatch {
+                path,
+                expected,
+                found,
+            } => write!(f, "at {}: expected {}, found {}", path, expected, found),
+            FilterError::InvalidRegex { path, message } => {
+                write!(f, "at {}: invalid regex: {}", path, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl Condition {
+    // Parse a JSON filter document into a Condition
+    pub fn from_json(text: &str) -> Result<Condition, FilterError> {
+        let json: Json =
+            serde_json::from_str(text).map_err(|e| FilterError::InvalidJson(e.to_string()))?;
+        parse_document(&json, "$")
+    }
+
+    // Convert this condition into a JSON filter document
+    pub fn to_json(&self) -> String {
+        condition_to_json(self).to_string()
+    }
+}
+
+// ---------- JSON -> Condition ----------
+
+fn parse_document(json: &Json, path: &str) -> Result<Condition, FilterError> {
+    let Json::Object(entries) = json else {
+        return Err(type_mismatch(path, "a filter document (object)", json));
+    };
+
+    let mut conditions = Vec::new();
+    for (name, argument) in entries {
+        let entry_path = format!("{}.{}", path, name);
+        let condition = match name.as_str() {
+            "$and" => Condition::And(parse_document_list(argument, &entry_path)?),
+            "$or" => Condition::Or(parse_document_list(argument, &entry_path)?),
+            "$nor" => Condition::Not(Box::new(Condition::Or(parse_document_list(
+                argument,
+                &entry_path,
+            )?))),
+            "$not" => Condition::Not(Box::new(parse_document(argument, &entry_path)?)),
+            "$prefix" => Condition::KeyPrefix(expect_string(argument, &entry_path)?),
+            operator if operator.starts_with('$') => {
+                return Err(unknown_operator(&entry_path, operator));
+            }
+            field => parse_field(field, argument, &entry_path)?,
+        };
+        conditions.push(condition);
+    }
+
+    Ok(if conditions.len() == 1 {
+        conditions.remove(0)
+    } else {
+        Condition::And(conditions)
+    })
+}
+
+fn parse_document_list(json: &Json, path: &str) -> Result<Vec<Condition>, FilterError> {
+    let Json::Array(items) = json else {
+        return Err(type_mismatch(path, "an array of filter documents", json));
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| parse_document(item, &format!("{}[{}]", path, i)))
+        .collect()
+}
+
+// The value of a field entry: either a plain value (equality)
+// or an operator document like {"$gt": 5, "$lt": 10}
+fn parse_field(field: &str, json: &Json, path: &str) -> Result<Condition, FilterError> {
+    let operators = match json {
+        Json::Object(map) if map.keys().any(|k| k.starts_with('$')) => map,
+        _ => return Ok(Condition::Equals(field.to_string(), Value::from(json))),
+    };
+
+    // {"$gte": a, "$lte": b} is a Between; the two halves alone are open-ended Betweens
+    let gte = operators.get("$gte");
+    let lte = operators.get("$lte");
+    let mut conditions = Vec::new();
+    if gte.is_some() || lte.is_some() {
+        let low = match gte {
+            Some(v) => expect_integer(v, &format!("{}.$gte", path))?,
+            None => i64::MIN,
+        };
+        let high = match lte {
+            Some(v) => expect_integer(v, &format!("{}.$lte", path))?,
+            None => i64::MAX,
+        };
+        conditions.push(Condition::Between(field.to_string(), low, high));
+    }
+
+    for (operator, argument) in operators {
+        let op_path = format!("{}.{}", path, operator);
+        let field = field.to_string();
+        let condition = match operator.as_str() {
+            "$gte" | "$lte" => continue,
+            "$eq" => Condition::Equals(field, Value::from(argument)),
+            "$ne" => Condition::Not(Box::new(Condition::Equals(field, Value::from(argument)))),
+            "$gt" => Condition::GreaterThan(field, expect_integer(argument, &op_path)?),
+            "$lt" => Condition::LessThan(field, expect_integer(argument, &op_path)?),
+            "$contains" => Condition::Contains(field, expect_string(argument, &op_path)?),
+            "$matches" => Condition::Matches(field, expect_string(argument, &op_path)?),
+            "$regex" => {
+                let pattern = expect_string(argument, &op_path)?;
+                let regex = Regex::new(&pattern).map_err(|e| FilterError::InvalidRegex {
+                    path: op_path.clone(),
+                    message: e.to_string(),
+                })?;
+                Condition::Regex(field, regex)
+            }
+            "$in" | "$nin" => {
+                let Json::Array(items) = argument else {
+                    return Err(type_mismatch(&op_path, "an array", argument));
+                };
+                let any = Condition::Or(
+                    items
+                        .iter()
+                        .map(|item| Condition::Equals(field.clone(), Value::from(item)))
+                        .collect(),
+                );
+                if operator == "$in" {
+                    any
+                } else {
+                    Condition::Not(Box::new(any))
+                }
+            }
+            "$exists" => {
+                let exists = Condition::Exists(field);
+                match argument.as_bool() {
+                    Some(true) => exists,
+                    Some(false) => Condition::Not(Box::new(exists)),
+                    None => return Err(type_mismatch(&op_path, "a boolean", argument)),
+                }
+            }
+            "$not" => Condition::Not(Box::new(parse_field(&field, argument, &op_path)?)),
+            "$near" => parse_near(&field, argument, &op_path)?,
+            "$within" => parse_within(&field, argument, &op_path)?,
+            _ => return Err(unknown_operator(&op_path, operator)),
+        };
+        conditions.push(condition);
+    }
+
+    Ok(if conditions.len() == 1 {
+        conditions.remove(0)
+    } else {
+        Condition::And(conditions)
+    })
+}
+
+// {"$point": [lng, lat], "$maxDistance": meters}
+fn parse_near(field: &str, json: &Json, path: &str) -> Result<Condition, FilterError> {
+    let Json::Object(map) = json else {
+        return Err(type_mismatch(path, "an object", json));
+    };
+    let mut point = None;
+    let mut distance = None;
+    for (name, argument) in map {
+        let arg_path = format!("{}.{}", path, name);
+        match name.as_str() {
+            "$point" => point = Some(expect_point(argument, &arg_path)?),
+            "$maxDistance" => {
+                let meters = argument
+                    .as_f64()
+                    .ok_or_else(|| type_mismatch(&arg_path, "a number", argument))?;
+                distance = Some(meters);
+            }
+            _ => return Err(unknown_operator(&arg_path, name)),
+        }
+    }
+    let point = point.ok_or_else(|| type_mismatch(path, "a $point", json))?;
+    let distance = distance.ok_or_else(|| type_mismatch(path, "a $maxDistance", json))?;
+    Ok(Condition::NearPoint(
+        GeoField::from_name(field),
+        point,
+        distance,
+    ))
+}
+
+// {"$box": [south-west, north-east]} or {"$polygon": [point, point, point, ...]}
+fn parse_within(field: &str, json: &Json, path: &str) -> Result<Condition, FilterError> {
+    let Json::Object(map) = json else {
+        return Err(type_mismatch(path, "an object", json));
+    };
+    let (name, argument) = match map.iter().next() {
+        Some(entry) if map.len() == 1 => entry,
+        _ => return Err(type_mismatch(path, "one of $box or $polygon", json)),
+    };
+    let arg_path = format!("{}.{}", path, name);
+    if name != "$box" && name != "$polygon" {
+        return Err(unknown_operator(&arg_path, name));
+    }
+    let Json::Array(items) = argument else {
+        return Err(type_mismatch(&arg_path, "an array of points", argument));
+    };
+    let points = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| expect_point(item, &format!("{}[{}]", arg_path, i)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let field = GeoField::from_name(field);
+    match name.as_str() {
+        "$box" if points.len() == 2 => Ok(Condition::WithinBox(field, points[0], points[1])),
+        "$box" => Err(type_mismatch(&arg_path, "two points", argument)),
+        _ => Ok(Condition::WithinPolygon(field, points)),
+    }
+}
+
+// A point is written [lng, lat], like GeoJSON
+fn expect_point(json: &Json, path: &str) -> Result<GeoPoint, FilterError> {
+    match json.as_array().map(|a| a.as_slice()) {
+        Some([lng, lat]) => match (lng.as_f64(), lat.as_f64()) {
+            (Some(lng), Some(lat)) => Ok(GeoPoint::new(lat, lng)),
+            _ => Err(type_mismatch(path, "a [lng, lat] point", json)),
+        },
+        _ => Err(type_mismatch(path, "a [lng, lat] point", json)),
+    }
+}
+
+fn unknown_operator(path: &str, operator: &str) -> FilterError {
+    FilterError::UnknownOperator {
+        path: path.to_string(),
+        operator: operator.to_string(),
+    }
+}
+
+fn expect_integer(json: &Json, path: &str) -> Result<i64, FilterError> {
+    json.as_i64()
+        .ok_or_else(|| type_mismatch(path, "an integer", json))
+}
+
+fn expect_string(json: &Json, path: &str) -> Result<String, FilterError> {
+    json.as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| type_mismatch(path, "a string", json))
+}
+
+fn type_mismatch(path: &str, expected: &str, found: &Json) -> FilterError {
+    let found = match found {
+        Json::Null => "null",
+        Json::Bool(_) => "a boolean",
+        Json::Number(n) if n.is_i64() => "an integer",
+        Json::Number(n) if n.is_u64() => "an integer larger than an i64",
+        Json::Number(_) => "a float",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    };
+    FilterError::TypeMismatch {
+        path: path.to_string(),
+        expected: expected.to_string(),
+        found: found.to_string(),
+    }
+}
+
+// ---------- Condition -> JSON ----------
+
+fn condition_to_json(condition: &Condition) -> Json {
+    match condition {
+        // A plain value is shorthand for $eq, except for objects that could be
+        // mistaken for an operator document
+        Condition::Equals(field, value) => match value {
+            Value::Object(_) => field_operator(field, "$eq", to_json_lossy(value)),
+            _ => single(field, to_json_lossy(value)),
+        },
+        Condition::GreaterThan(field, n) => field_operator(field, "$gt", Json::from(*n)),
+        Condition::LessThan(field, n) => field_operator(field, "$lt", Json::from(*n)),
+        Condition::Contains(field, s) => field_operator(field, "$contains", Json::from(s.clone())),
+        Condition::Matches(field, query) => {
+            field_operator(field, "$matches", Json::from(query.clone()))
+        }
+        Condition::Regex(field, regex) => {
+            field_operator(field, "$regex", Json::from(regex.as_str()))
+        }
+        Condition::Between(field, low, high) => {
+            let mut operators = Map::new();
+            if *high == i64::MAX {
+                operators.insert("$gte".to_string(), Json::from(*low));
+            } else if *low == i64::MIN {
+                operators.insert("$lte".to_string(), Json::from(*high));
+            } else {
+                operators.insert("$gte".to_string(), Json::from(*low));
+                operators.insert("$lte".to_string(), Json::from(*high));
+            }
+            single(field, Json::Object(operators))
+        }
+        Condition::And(conditions) => single(
+            "$and",
+            Json::Array(conditions.iter().map(condition_to_json).collect()),
+        ),
+        Condition::Or(conditions) => single(
+            "$or",
+            Json::Array(conditions.iter().map(condition_to_json).collect()),
+        ),
+        Condition::Not(inner) => single("$not", condition_to_json(inner)),
+        Condition::KeyPrefix(prefix) => single("$prefix", Json::from(prefix.clone())),
+        Condition::Exists(field) => field_operator(field, "$exists", Json::from(true)),
+        Condition::NearPoint(field, point, distance) => {
+            let mut near = Map::new();
+            near.insert("$point".to_string(), point_to_json(point));
+            near.insert("$maxDistance".to_string(), float_to_json(*distance));
+            field_operator(&field.name(), "$near", Json::Object(near))
+        }
+        Condition::WithinBox(field, sw, ne) => {
+            let points = Json::Array(vec![point_to_json(sw), point_to_json(ne)]);
+            field_operator(&field.name(), "$within", single("$box", points))
+        }
+        Condition::WithinPolygon(field, vertices) => {
+            let points = Json::Array(vertices.iter().map(point_to_json).collect());
+            field_operator(&field.name(), "$within", single("$polygon", points))
+        }
+    }
+}
+
+fn point_to_json(point: &GeoPoint) -> Json {
+    Json::Array(vec![float_to_json(point.lng), float_to_json(point.lat)])
+}
+
+fn float_to_json(f: f64) -> Json {
+    to_json_lossy(&Value::Float(f))
+}
+
+fn single(name: &str, json: Json) -> Json {
+    let mut map = Map::new();
+    map.insert(name.to_string(), json);
+    Json::Object(map)
+}
+
+fn field_operator(field: &str, operator: &str, argument: Json) -> Json {
+    single(field, single(operator, argument))
+}
@@ -0,0 +1,377 @@
+// NEW: Full-text search over string fields
+//
+// Text is split into words by an Analyzer (lowercased, split on anything that
+// isn't a letter or digit, optionally stemmed and stripped of stop words).
+// A TextIndex is an inverted index: for every word it remembers which keys
+// contain it and at which word positions, which is enough for AND/OR/phrase
+// queries and for BM25 ranking.
+//
+// Query syntax (used by Condition::Matches and Database::search):
+//   rust database          both words (AND)
+//   rust OR go             either word
+//   "key value" store      the phrase "key value" and the word store
+// OR binds loosest: `a b OR c` means (a AND b) OR c.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+
+// BM25 tuning constants (the usual defaults)
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+// How text is turned into index terms
+// The same analyzer must be used for documents and queries, so it is fixed
+// when the index is created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Analyzer {
+    pub stemming: bool,   // reduce words to a stem: "running" -> "run"
+    pub stop_words: bool, // drop common English words ("the", "and", ...)
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Analyzer::default()
+    }
+
+    pub fn with_stemming(mut self) -> Self {
+        self.stemming = true;
+        self
+    }
+
+    pub fn with_stop_words(mut self) -> Self {
+        self.stop_words = true;
+        self
+    }
+
+    // Split text into terms; a term's position is its index in the returned list
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .filter(|word| !(self.stop_words && STOP_WORDS.contains(&word.as_str())))
+            .map(|word| if self.stemming { stem(&word) } else { word })
+            .collect()
+    }
+}
+
+// A light English suffix stripper (in the spirit of Porter's step 1)
+// Good enough to make "runs", "running" and "run" meet, without a dictionary
+fn stem(word: &str) -> String {
+    if !word.is_ascii() || word.len() <= 3 {
+        return word.to_string();
+    }
+    let has_vowel = |s: &str| s.chars().any(|c| "aeiouy".contains(c));
+
+    let mut w = word.to_string();
+    if let Some(base) = w.strip_suffix("sses") {
+        w = format!("{}ss", base);
+    } else if let Some(base) = w.strip_suffix("ies") {
+        w = format!("{}y", base);
+    } else if !w.ends_with("ss") && !w.ends_with("us") && w.ends_with('s') {
+        w.pop();
+    }
+
+    for suffix in ["ingly", "edly", "ing", "ed", "ly"] {
+        if let Some(base) = w.strip_suffix(suffix)
+            && base.len() >= 3
+            && has_vowel(base)
+        {
+            let mut base = base.to_string();
+            // "running" -> "runn" -> "run"
+            let bytes = base.as_bytes();
+            if bytes.len() >= 2
+                && bytes[bytes.len() - 1] == bytes[bytes.len() - 2]
+                && !"lsz".contains(bytes[bytes.len() - 1] as char)
+            {
+                base.pop();
+            }
+            w = base;
+            break;
+        }
+    }
+    w
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryItem {
+    Term(String),
+    Phrase(Vec<String>),
+}
+
+// A parsed query: OR of groups, each group an AND of terms and phrases
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextQuery {
+    groups: Vec<Vec<QueryItem>>,
+}
+
+impl TextQuery {
+    pub fn parse(query: &str, analyzer: &Analyzer) -> TextQuery {
+        let mut groups = vec![Vec::new()];
+        let mut rest = query;
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                rest = &rest[c.len_utf8()..];
+            } else if c == '"' {
+                // A phrase runs to the closing quote (or the end of the query)
+                let inner = &rest[1..];
+                let end = inner.find('"').unwrap_or(inner.len());
+                let terms = analyzer.tokenize(&inner[..end]);
+                match terms.len() {
+                    0 => {}
+                    1 => push_item(&mut groups, QueryItem::Term(terms[0].clone())),
+                    _ => push_item(&mut groups, QueryItem::Phrase(terms)),
+                }
+                rest = inner.get(end + 1..).unwrap_or("");
+            } else {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '"')
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                if word == "OR" {
+                    groups.push(Vec::new());
+                } else {
+                    for term in analyzer.tokenize(word) {
+                        push_item(&mut groups, QueryItem::Term(term));
+                    }
+                }
+                rest = &rest[end..];
+            }
+        }
+        // A group that lost all its words (e.g. only stop words) matches nothing
+        groups.retain(|g| !g.is_empty());
+        TextQuery { groups }
+    }
+
+    // Every distinct term in the query (used for scoring)
+    fn terms(&self) -> BTreeSet<&str> {
+        let mut terms = BTreeSet::new();
+        for item in self.groups.iter().flatten() {
+            match item {
+                QueryItem::Term(t) => {
+                    terms.insert(t.as_str());
+                }
+                QueryItem::Phrase(words) => terms.extend(words.iter().map(|w| w.as_str())),
+            }
+        }
+        terms
+    }
+
+    // Does an already tokenized document match? (used when there is no index)
+    pub fn matches_tokens(&self, tokens: &[String]) -> bool {
+        self.groups.iter().any(|group| {
+            group.iter().all(|item| match item {
+                QueryItem::Term(t) => tokens.contains(t),
+                QueryItem::Phrase(words) => {
+                    tokens.windows(words.len()).any(|w| w == words.as_slice())
+                }
+            })
+        })
+    }
+}
+
+fn push_item(groups: &mut [Vec<QueryItem>], item: QueryItem) {
+    if let Some(group) = groups.last_mut() {
+        group.push(item);
+    }
+}
+
+// Check a value's field against a query without an index
+pub fn text_matches(value: &Value, field: &str, query: &str, analyzer: &Analyzer) -> bool {
+    match value.get_field(field) {
+        Some(Value::String(text)) => {
+            TextQuery::parse(query, analyzer).matches_tokens(&analyzer.tokenize(text))
+        }
+        _ => false,
+    }
+}
+
+// One search result, best first
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub key: String,
+    pub score: f64, // BM25 relevance, higher is better
+    pub value: Value,
+}
+
+// Inverted index over one string field
+#[derive(Debug, Default)]
+pub struct TextIndex {
+    analyzer: Analyzer,
+    // term -> key -> positions of the term in that key's text
+    postings: HashMap<String, BTreeMap<String, Vec<usize>>>,
+    // key -> number of terms in its text
+    lengths: HashMap<String, usize>,
+    total_length: usize,
+}
+
+impl TextIndex {
+    pub fn new(analyzer: Analyzer) -> Self {
+        TextIndex {
+            analyzer,
+            ..TextIndex::default()
+        }
+    }
+
+    pub fn analyzer(&self) -> Analyzer {
+        self.analyzer
+    }
+
+    // Number of indexed documents
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+
+    pub fn add(&mut self, key: &str, text: &str) {
+        let tokens = self.analyzer.tokenize(text);
+        for (position, term) in tokens.iter().enumerate() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .entry(key.to_string())
+                .or_default()
+                .push(position);
+        }
+        self.total_length += tokens.len();
+        self.lengths.insert(key.to_string(), tokens.len());
+    }
+
+    pub fn remove(&mut self, key: &str, text: &str) {
+        let Some(length) = self.lengths.remove(key) else {
+            return;
+        };
+        self.total_length -= length;
+        for term in self.analyzer.tokenize(text) {
+            if let Some(keys) = self.postings.get_mut(&term) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    // Keys matching the query
+    pub fn lookup(&self, query: &str) -> BTreeSet<String> {
+        let query = TextQuery::parse(query, &self.analyzer);
+        let mut found = BTreeSet::new();
+        for group in &query.groups {
+            let mut keys: Option<BTreeSet<String>> = None;
+            for item in group {
+                let item_keys = self.item_keys(item);
+                keys = Some(match keys {
+                    None => item_keys,
+                    Some(acc) => acc.intersection(&item_keys).cloned().collect(),
+                });
+            }
+            found.extend(keys.unwrap_or_default());
+        }
+        found
+    }
+
+    // Rough number of matches, for the query planner
+    pub fn estimate(&self, query: &str) -> f64 {
+        let query = TextQuery::parse(query, &self.analyzer);
+        let rows: usize = query
+            .groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|item| match item {
+                        QueryItem::Term(t) => self.document_frequency(t),
+                        QueryItem::Phrase(words) => words
+                            .iter()
+                            .map(|w| self.document_frequency(w))
+                            .min()
+                            .unwrap_or(0),
+                    })
+                    .min()
+                    .unwrap_or(0)
+            })
+            .sum();
+        rows.min(self.len()) as f64
+    }
+
+    // BM25 score of every matching key, best first
+    pub fn score(&self, query: &str) -> Vec<(String, f64)> {
+        let keys = self.lookup(query);
+        let parsed = TextQuery::parse(query, &self.analyzer);
+        let documents = self.len() as f64;
+        let average_length = if self.is_empty() {
+            0.0
+        } else {
+            self.total_length as f64 / documents
+        };
+
+        let mut scored: Vec<(String, f64)> = keys
+            .into_iter()
+            .map(|key| {
+                let length = *self.lengths.get(&key).unwrap_or(&0) as f64;
+                let mut score = 0.0;
+                for term in parsed.terms() {
+                    let Some(keys) = self.postings.get(term) else {
+                        continue;
+                    };
+                    let Some(positions) = keys.get(&key) else {
+                        continue;
+                    };
+                    let tf = positions.len() as f64;
+                    let df = keys.len() as f64;
+                    let idf = ((documents - df + 0.5) / (df + 0.5) + 1.0).ln();
+                    let norm = 1.0 - BM25_B + BM25_B * length / average_length.max(1.0);
+                    score += idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm);
+                }
+                (key, score)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored
+    }
+
+    fn document_frequency(&self, term: &str) -> usize {
+        self.postings.get(term).map_or(0, |keys| keys.len())
+    }
+
+    fn item_keys(&self, item: &QueryItem) -> BTreeSet<String> {
+        match item {
+            QueryItem::Term(t) => self
+                .postings
+                .get(t)
+                .map(|keys| keys.keys().cloned().collect())
+                .unwrap_or_default(),
+            QueryItem::Phrase(words) => {
+                let Some(first) = self.postings.get(&words[0]) else {
+                    return BTreeSet::new();
+                };
+                // A key matches when word i sits at position p + i for some start p
+                first
+                    .iter()
+                    .filter(|(key, starts)| {
+                        starts.iter().any(|start| {
+                            words.iter().enumerate().skip(1).all(|(i, word)| {
+                                self.postings
+                                    .get(word)
+                                    .and_then(|keys| keys.get(*key))
+                                    .is_some_and(|positions| positions.contains(&(start + i)))
+                            })
+                        })
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+// NEW: Geospatial points, shapes and the geo index
+//
+// A location is read from an entry in one of two ways (a GeoField):
+//   - LatLng: two number fields, e.g. { "lat": 52.52, "lng": 13.405 }
+//   - Array:  one field holding [lng, lat] (GeoJSON order), e.g. "location": [13.405, 52.52]
+// Distances are great-circle distances in meters (haversine formula).
+//
+// The index cuts the world into a grid of 2^26 x 2^26 cells and numbers the
+// cells along a Z-order curve (interleaving the bits of the cell's x and y,
+// the same trick geohashes use). Cells that are close on the map mostly have
+// close numbers, so a bounding box becomes a handful of contiguous ranges
+// of the sorted cell map.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+
+// Mean Earth radius in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+// Grid resolution: bits per axis (2^26 cells is ~60 cm at the equator)
+const GRID_BITS: u32 = 26;
+// At most this many grid cells are scanned per bounding box
+const MAX_CELLS: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64, // -90..=90
+    pub lng: f64, // -180..=180
+}
+
+impl GeoPoint {
+    pub fn new(lat: f64, lng: f64) -> Self {
+        GeoPoint { lat, lng }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lng)
+    }
+
+    // Great-circle distance in meters
+    pub fn distance_to(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlng = (other.lng - self.lng).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlng / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+}
+
+impl fmt::Display for GeoPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.lat, self.lng)
+    }
+}
+
+// Where an entry keeps its location
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GeoField {
+    LatLng { lat: String, lng: String },
+    Array(String), // [lng, lat]
+}
+
+impl GeoField {
+    pub fn lat_lng(lat: &str, lng: &str) -> Self {
+        GeoField::LatLng {
+            lat: lat.to_string(),
+            lng: lng.to_string(),
+        }
+    }
+
+    pub fn array(field: &str) -> Self {
+        GeoField::Array(field.to_string())
+    }
+
+    // Name used for the index and in JSON filters: "lat,lng" or "location"
+    pub fn name(&self) -> String {
+        match self {
+            GeoField::LatLng { lat, lng } => format!("{},{}", lat, lng),
+            GeoField::Array(field) => field.clone(),
+        }
+    }
+
+    // Inverse of `name`
+    pub fn from_name(name: &str) -> Self {
+        match name.split_once(',') {
+            Some((lat, lng)) => GeoField::lat_lng(lat.trim(), lng.trim()),
+            None => GeoField::array(name),
+        }
+    }
+
+    // The entry's location, if it has a valid one
+    pub fn point_of(&self, value: &Value) -> Option<GeoPoint> {
+        let point = match self {
+            GeoField::LatLng { lat, lng } => GeoPoint::new(
+                number(value.get_field(lat)?)?,
+                number(value.get_field(lng)?)?,
+            ),
+            GeoField::Array(field) => match value.get_field(field)? {
+                Value::Array(items) if items.len() == 2 => {
+                    GeoPoint::new(number(&items[1])?, number(&items[0])?)
+                }
+                _ => return None,
+            },
+        };
+        point.is_valid().then_some(point)
+    }
+}
+
+impl fmt::Display for GeoField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoField::LatLng { lat, lng } => write!(f, "({}, {})", lat, lng),
+            GeoField::Array(field) => write!(f, "{}", field),
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(f) => Some(*f),
+        Value::Integer(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+// An area on the map a location can be tested against
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoShape {
+    Circle { center: GeoPoint, radius: f64 }, // radius in meters
+    // south-west and north-east corners; a box with sw.lng > ne.lng crosses
+    // the antimeridian (e.g. 170 -> -170)
+    Box { sw: GeoPoint, ne: GeoPoint },
+    Polygon(Vec<GeoPoint>), // vertices in order, closed automatically
+}
+
+impl GeoShape {
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        match self {
+            GeoShape::Circle { center, radius } => center.distance_to(point) <= *radius,
+            GeoShape::Box { sw, ne } => {
+                let in_lat = point.lat >= sw.lat && point.lat <= ne.lat;
+                let in_lng = if sw.lng <= ne.lng {
+                    point.lng >= sw.lng && point.lng <= ne.lng
+                } else {
+                    point.lng >= sw.lng || point.lng <= ne.lng
+                };
+                in_lat && in_lng
+            }
+            GeoShape::Polygon(vertices) => polygon_contains(vertices, point),
+        }
+    }
+
+    // Boxes (without antimeridian crossing) that together cover the shape
+    fn bounding_boxes(&self) -> Vec<(GeoPoint, GeoPoint)> {
+        match self {
+            GeoShape::Circle { center, radius } => {
+                let dlat = (radius / EARTH_RADIUS).to_degrees();
+                let south = (center.lat - dlat).max(-90.0);
+                let north = (center.lat + dlat).min(90.0);
+                // Near a pole the circle can span every longitude
+                let widest = center.lat.abs() + dlat;
+                if widest >= 90.0 {
+                    return vec![(GeoPoint::new(south, -180.0), GeoPoint::new(north, 180.0))];
+                }
+                let dlng = dlat / widest.to_radians().cos();
+                if dlng >= 180.0 {
+                    return vec![(GeoPoint::new(south, -180.0), GeoPoint::new(north, 180.0))];
+                }
+                split_antimeridian(
+                    GeoPoint::new(south, normalize_lng(center.lng - dlng)),
+                    GeoPoint::new(north, normalize_lng(center.lng + dlng)),
+                )
+            }
+            GeoShape::Box { sw, ne } => split_antimeridian(*sw, *ne),
+            GeoShape::Polygon(vertices) => {
+                if vertices.is_empty() {
+                    return Vec::new();
+                }
+                let fold = |f: fn(f64, f64) -> f64, start: f64, get: fn(&GeoPoint) -> f64| {
+                    vertices.iter().map(get).fold(start, f)
+                };
+                let sw = GeoPoint::new(
+                    fold(f64::min, f64::INFINITY, |p| p.lat),
+                    fold(f64::min, f64::INFINITY, |p| p.lng),
+                );
+                let ne = GeoPoint::new(
+                    fold(f64::max, f64::NEG_INFINITY, |p| p.lat),
+                    fold(f64::max, f64::NEG_INFINITY, |p| p.lng),
+                );
+                vec![(sw, ne)]
+            }
+        }
+    }
+}
+
+impl fmt::Display for GeoShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoShape::Circle { center, radius } => write!(f, "NEAR {} WITHIN {}", center, radius),
+            GeoShape::Box { sw, ne } => write!(f, "WITHIN BOX {}, {}", sw, ne),
+            GeoShape::Polygon(vertices) => {
+                let points: Vec<String> = vertices.iter().map(|p| p.to_string()).collect();
+                write!(f, "WITHIN POLYGON ({})", points.join(", "))
+            }
+        }
+    }
+}
+
+fn normalize_lng(lng: f64) -> f64 {
+    if lng > 180.0 {
+        lng - 360.0
+    } else if lng < -180.0 {
+        lng + 360.0
+    } else {
+        lng
+    }
+}
+
+fn split_antimeridian(sw: GeoPoint, ne: GeoPoint) -> Vec<(GeoPoint, GeoPoint)> {
+    if sw.lng <= ne.lng {
+        vec![(sw, ne)]
+    } else {
+        vec![
+            (sw, GeoPoint::new(ne.lat, 180.0)),
+            (GeoPoint::new(sw.lat, -180.0), ne),
+        ]
+    }
+}
+
+// Ray casting on the lat/lng plane (fine for polygons of city or country size)
+fn polygon_contains(vertices: &[GeoPoint], point: &GeoPoint) -> bool {
+    if vertices.len() < 3 {
+        return false;
+    }
+    let mut inside = false;
+    let mut j = vertices.len() - 1;
+    for i in 0..vertices.len() {
+        let (a, b) = (&vertices[i], &vertices[j]);
+        if (a.lat > point.lat) != (b.lat > point.lat) {
+            let lng_at = a.lng + (point.lat - a.lat) / (b.lat - a.lat) * (b.lng - a.lng);
+            if point.lng < lng_at {
+                inside = !inside;
+            }
+        }
+        j = i;
+    }
+    inside
+}
+
+// Grid cell of a point along one axis
+fn cell(value: f64, min: f64, max: f64) -> u64 {
+    let cells = 1u64 << GRID_BITS;
+    let scaled = ((value - min) / (max - min) * cells as f64) as u64;
+    scaled.min(cells - 1)
+}
+
+// Interleave the bits of x (longitude) and y (latitude): the Z-order number
+fn interleave(x: u64, y: u64) -> u64 {
+    let mut z = 0;
+    for bit in 0..GRID_BITS {
+        z |= ((x >> bit) & 1) << (2 * bit);
+        z |= ((y >> bit) & 1) << (2 * bit + 1);
+    }
+    z
+}
+
+// Location index over one GeoField
+#[derive(Debug)]
+pub struct GeoIndex {
+    field: GeoField,
+    cells: BTreeSet<(u64, String)>, // (Z-order cell, key)
+    points: HashMap<String, GeoPoint>,
+}
+
+impl GeoIndex {
+    pub fn new(field: GeoField) -> Self {
+        GeoIndex {
+            field,
+            cells: BTreeSet::new(),
+            points: HashMap::new(),
+        }
+    }
+
+    pub fn field(&self) -> &GeoField {
+        &self.field
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // Index the entry's location (entries without one are skipped)
+    pub fn add_value(&mut self, key: &str, value: &Value) {
+        if let Some(point) = self.field.point_of(value) {
+            self.add(key, point);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.points.clear();
+    }
+
+    pub fn add(&mut self, key: &str, point: GeoPoint) {
+        self.remove(key);
+        self.cells.insert((Self::code(&point), key.to_string()));
+        self.points.insert(key.to_string(), point);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if let Some(point) = self.points.remove(key) {
+            self.cells.remove(&(Self::code(&point), key.to_string()));
+        }
+    }
+
+    fn code(point: &GeoPoint) -> u64 {
+        interleave(cell(point.lng, -180.0, 180.0), cell(point.lat, -90.0, 90.0))
+    }
+
+    // Keys whose location lies inside the shape
+    pub fn lookup(&self, shape: &GeoShape) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        for (sw, ne) in shape.bounding_boxes() {
+            if !(sw.lat <= ne.lat && sw.lng <= ne.lng) {
+                continue; // empty (or NaN) box
+            }
+            let (x0, x1) = (cell(sw.lng, -180.0, 180.0), cell(ne.lng, -180.0, 180.0));
+            let (y0, y1) = (cell(sw.lat, -90.0, 90.0), cell(ne.lat, -90.0, 90.0));
+
+            // Coarsen the grid until the box covers at most MAX_CELLS cells;
+            // a coarse cell is one contiguous range of fine Z-order numbers
+            let mut shift = 0;
+            while shift < GRID_BITS
+                && ((x1 >> shift) - (x0 >> shift) + 1) * ((y1 >> shift) - (y0 >> shift) + 1)
+                    > MAX_CELLS
+            {
+                shift += 1;
+            }
+            for x in (x0 >> shift)..=(x1 >> shift) {
+                for y in (y0 >> shift)..=(y1 >> shift) {
+                    let start = interleave(x << shift, y << shift);
+                    let end = start + (1u64 << (2 * shift));
+                    let range = self.cells.range((
+                        Bound::Included((start, String::new())),
+                        Bound::Excluded((end, String::new())),
+                    ));
+                    for (_, key) in range {
+                        if shape.contains(&self.points[key]) {
+                            found.insert(key.clone());
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+// Distance from `center` to the entry's location (None if it has none)
+pub fn distance(field: &GeoField, center: &GeoPoint, value: &Value) -> Option<f64> {
+    field.point_of(value).map(|p| center.distance_to(&p))
+}
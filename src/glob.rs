@@ -0,0 +1,117 @@
+// NEW: Glob patterns, matched as Redis matches them (KEYS, SCAN MATCH,
+// PSUBSCRIBE)
+
+// * any run of characters, ? one character, [abc] [a-z] [^a] a set, \x a literal x
+pub(crate) struct Glob {
+    tokens: Vec<Token>,
+}
+
+enum Token {
+    Literal(char),
+    Any,
+    Star,
+    Set {
+        negated: bool,
+        items: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '\\' => Token::Literal(chars.next().unwrap_or('\\')),
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut items = Vec::new();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            ']' => break,
+                            '\\' => {
+                                let c = chars.next().unwrap_or('\\');
+                                items.push((c, c));
+                            }
+                            _ if chars.peek() == Some(&'-') => {
+                                chars.next();
+                                match chars.next() {
+                                    Some(']') | None => {
+                                        items.push((c, c));
+                                        items.push(('-', '-'));
+                                        break;
+                                    }
+                                    Some(high) => items.push((c.min(high), c.max(high))),
+                                }
+                            }
+                            _ => items.push((c, c)),
+                        }
+                    }
+                    Token::Set { negated, items }
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+        Glob { tokens }
+    }
+
+    // Every matching key starts with this, so only that range is scanned
+    pub(crate) fn literal_prefix(&self) -> String {
+        self.tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let (mut t, mut p) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None; // (pattern after *, text position)
+        while t < text.len() {
+            match self.tokens.get(p) {
+                Some(Token::Star) => {
+                    backtrack = Some((p + 1, t));
+                    p += 1;
+                    continue;
+                }
+                Some(token) if token.matches_char(text[t]) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            // Mismatch: let the last * swallow one more character
+            match backtrack {
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p;
+                    t = star_t + 1;
+                }
+                None => return false,
+            }
+        }
+        self.tokens[p..]
+            .iter()
+            .all(|token| matches!(token, Token::Star))
+    }
+}
+
+impl Token {
+    fn matches_char(&self, c: char) -> bool {
+        match self {
+            Token::Literal(l) => *l == c,
+            Token::Any => true,
+            Token::Star => false,
+            Token::Set { negated, items } => {
+                items.iter().any(|(low, high)| (*low..=*high).contains(&c)) != *negated
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+// HTTP/1.1 requests and responses, as much of them as the REST API needs
+//
+// Bodies are read by Content-Length (chunked request bodies are refused with
+// 411). Connections stay open between requests unless the client says
+// otherwise (HTTP/1.0 closes by default); pipelined requests are answered in
+// order.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+// Longest request line or header line, and most headers per request
+const MAX_LINE_LENGTH: u64 = 16 * 1024;
+const MAX_HEADERS: usize = 100;
+// Largest request body
+const MAX_BODY: usize = 64 * 1024 * 1024;
+
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String, // decoded, without the query string
+    pub(crate) query: HashMap<String, String>, // decoded query parameters
+    pub(crate) headers: HashMap<String, String>, // names lowercased
+    pub(crate) body: Vec<u8>,
+    pub(crate) keep_alive: bool,
+}
+
+impl Request {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    pub(crate) fn param(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+}
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(&'static str, String)>,
+    pub(crate) body: Vec<u8>, // JSON, unless empty
+}
+
+impl Response {
+    pub(crate) fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub(crate) fn json(status: u16, json: &serde_json::Value) -> Self {
+        let mut response = Response::new(status);
+        response.body = json.to_string().into_bytes();
+        response
+    }
+
+    // {"error": "..."}
+    pub(crate) fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Response::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+
+    pub(crate) fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    // `with_body` is false for HEAD: the headers describe the body, but it isn't sent
+    pub(crate) fn write_to(
+        &self,
+        out: &mut impl Write,
+        keep_alive: bool,
+        with_body: bool,
+    ) -> io::Result<()> {
+        write!(out, "HTTP/1.1 {} {}\r\n", self.status, reason(self.status))?;
+        for (name, value) in &self.headers {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        // 204 and 304 never have a body
+        if !matches!(self.status, 204 | 304) {
+            if !self.body.is_empty() {
+                out.write_all(b"Content-Type: application/json\r\n")?;
+            }
+            write!(out, "Content-Length: {}\r\n", self.body.len())?;
+        }
+        if !keep_alive {
+            out.write_all(b"Connection: close\r\n")?;
+        }
+        out.write_all(b"\r\n")?;
+        if with_body && !matches!(self.status, 204 | 304) {
+            out.write_all(&self.body)?;
+        }
+        Ok(())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        431 => "Request Header Fields Too Large",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    }
+}
+
+// The next request; None when the client closed the connection. A request
+// that can't be read is answered with the Response in Err, and the
+// connection is closed (there's no telling where the next request starts).
+// `out` is there for "Expect: 100-continue".
+pub(crate) fn read_request(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+) -> io::Result<Result<Option<Request>, Response>> {
+    // Blank lines before a request are allowed (RFC 9112 section 2.2)
+    let line = loop {
+        match read_line(input)? {
+            None => return Ok(Ok(None)),
+            Some(Err(too_long)) => return Ok(Err(too_long)),
+            Some(Ok(line)) if line.is_empty() => continue,
+            Some(Ok(line)) => break line,
+        }
+    };
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(Err(Response::error(400, "malformed request line")));
+    };
+    let http_1_0 = match version {
+        "HTTP/1.1" => false,
+        "HTTP/1.0" => true,
+        _ => {
+            return Ok(Err(Response::error(
+                505,
+                "only HTTP/1.0 and 1.1 are supported",
+            )));
+        }
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let line = match read_line(input)? {
+            None => return Ok(Err(Response::error(400, "unexpected end of request"))),
+            Some(Err(too_long)) => return Ok(Err(too_long)),
+            Some(Ok(line)) => line,
+        };
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Ok(Err(Response::error(431, "too many headers")));
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Ok(Err(Response::error(400, "malformed header")));
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim();
+        // Repeated headers are combined, as RFC 9110 allows for lists
+        headers
+            .entry(name)
+            .and_modify(|existing: &mut String| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    if headers.contains_key("transfer-encoding") {
+        return Ok(Err(Response::error(
+            411,
+            "chunked bodies aren't supported; send Content-Length",
+        )));
+    }
+    let length = match headers.get("content-length") {
+        None => 0,
+        Some(text) => match text.parse::<usize>() {
+            Ok(length) if length <= MAX_BODY => length,
+            Ok(_) => return Ok(Err(Response::error(413, "request body too large"))),
+            Err(_) => return Ok(Err(Response::error(400, "invalid Content-Length"))),
+        },
+    };
+    if length > 0
+        && headers
+            .get("expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    {
+        out.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        out.flush()?;
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    let connection = headers
+        .get("connection")
+        .map(|value| value.to_ascii_lowercase())
+        .unwrap_or_default();
+    let keep_alive = if http_1_0 {
+        connection.contains("keep-alive")
+    } else {
+        !connection.contains("close")
+    };
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    let Some(path) = percent_decode(path) else {
+        return Ok(Err(Response::error(
+            400,
+            "invalid percent-encoding in path",
+        )));
+    };
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (Some(name), Some(value)) = (
+            percent_decode(&name.replace('+', " ")),
+            percent_decode(&value.replace('+', " ")),
+        ) else {
+            return Ok(Err(Response::error(
+                400,
+                "invalid percent-encoding in query",
+            )));
+        };
+        params.insert(name, value);
+    }
+
+    Ok(Ok(Some(Request {
+        method: method.to_string(),
+        path,
+        query: params,
+        headers,
+        body,
+        keep_alive,
+    })))
+}
+
+// A line without its line ending; None at end of stream, Err(431) when too long
+fn read_line(input: &mut impl BufRead) -> io::Result<Option<Result<String, Response>>> {
+    let mut line = Vec::new();
+    if input.take(MAX_LINE_LENGTH).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Ok(Some(Err(Response::error(431, "line too long"))));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(Ok(String::from_utf8_lossy(&line).into_owned())))
+}
+
+// "user%3A1" -> "user:1"; None for a bad escape or bytes that aren't UTF-8
+pub(crate) fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// The reverse, for keys put into a URL (a cursor in a response)
+pub(crate) fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
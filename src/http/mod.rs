@@ -0,0 +1,112 @@
+// NEW: A REST server, for programs that speak HTTP and JSON rather than Rust
+//
+//   let server = littledb::http::Server::bind("127.0.0.1:8080", db)?;
+//   let stop = server.shutdown_handle();   // stop.shutdown() from elsewhere
+//   server.run()?;
+//
+//   curl -X PUT localhost:8080/keys/user:1 -d '{"name": "Alice", "age": 30}'
+//   curl localhost:8080/keys/user:1
+//   curl -X POST localhost:8080/query -d '{"age": {"$gt": 25}}'
+//
+// routes.rs lists the routes; message.rs reads and writes HTTP/1.1. Saving
+// works as for the RESP server (see server.rs).
+
+pub(crate) mod message;
+mod routes;
+
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Database;
+use crate::replication::ReplicationHandle;
+use crate::server::{self, DEFAULT_SAVE_INTERVAL, Shared, ShutdownHandle};
+
+pub struct Server {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    save_interval: Option<Duration>,
+}
+
+impl Server {
+    // Listen on `addr` ("127.0.0.1:8080"; port 0 picks a free one)
+    pub fn bind(addr: impl ToSocketAddrs, db: Database) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let shared = Shared::new(db, listener.local_addr()?);
+        Ok(Server {
+            listener,
+            shared,
+            save_interval: Some(DEFAULT_SAVE_INTERVAL),
+        })
+    }
+
+    // How often changes are saved; None saves only when the server stops
+    pub fn save_interval(mut self, interval: Option<Duration>) -> Self {
+        self.save_interval = interval;
+        self
+    }
+
+    // Serve this server's changes to followers connecting to `addr`
+    // (see replication/)
+    pub fn replicate_on(self, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        self.shared.replication.listen(addr)?;
+        Ok(self)
+    }
+
+    // Follow the primary replicating on `primary` ("host:port"): copy its
+    // data and refuse writes until promoted
+    pub fn follow(self, primary: &str) -> Self {
+        self.shared.replication.set_primary(primary);
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Where followers connect, if replicate_on was called
+    pub fn replication_addr(&self) -> Option<SocketAddr> {
+        self.shared.replication.listen_addr()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(&self.shared)
+    }
+
+    pub fn replication_handle(&self) -> ReplicationHandle {
+        ReplicationHandle::new(&self.shared)
+    }
+
+    // Serve until shut down; changes are saved before returning
+    pub fn run(self) -> io::Result<()> {
+        server::run(self.listener, self.shared, self.save_interval, serve)
+    }
+}
+
+fn serve(stream: TcpStream, shared: &Arc<Shared>) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let request = match message::read_request(&mut reader, &mut writer)? {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(refused) => {
+                refused.write_to(&mut writer, false, true)?;
+                break;
+            }
+        };
+        let response = routes::handle(&request, shared);
+        response.write_to(&mut writer, request.keep_alive, request.method != "HEAD")?;
+        if !request.keep_alive {
+            break;
+        }
+        // Pipelined requests still waiting in the buffer are answered first
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+    writer.flush()
+}
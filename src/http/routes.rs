@@ -0,0 +1,498 @@
+// The REST API: which request does what to the database
+//
+//   GET    /keys/{key}              the value, as JSON              200, 404
+//   PUT    /keys/{key}[?ttl=secs]   store the JSON body             201 new, 200 replaced
+//   PATCH  /keys/{key}              apply a JSON Patch (or a merge  200, 404, 409
+//                                   patch with Content-Type
+//                                   application/merge-patch+json)
+//   DELETE /keys/{key}                                              204, 404
+//   GET    /keys?prefix=&cursor=&limit=   a page of keys, in order  200
+//   POST   /query?limit=&offset=&sort=&select=   JSON filter body   200
+//   GET    /stats                   DatabaseStats                   200
+//   POST   /promote                 stop following (see replication/)  200 {"promoted": bool}
+//
+// For the Rust client (client/), bodies and answers as shown:
+//   POST   /batch/get     {"keys": [..]}                       -> {"values": {key: value}}
+//   POST   /batch/insert  {"entries": [{"key", "value"}]}      -> {"inserted": n}
+//   POST   /batch/delete  {"keys": [..]}                       -> {"deleted": n}
+//   POST   /transaction   {"reads": {key: version},
+//                          "writes": [{"key", "value"} or {"key", "delete": true}]}
+//                                                  -> {"written": n}; 409 {"error", "key"}
+//
+// The ETag of a key is its version (see Database::version). GET honours
+// If-None-Match (304 when unchanged); PUT, PATCH and DELETE honour If-Match
+// (412 when someone else wrote first) and If-None-Match: * (412 when the key
+// exists, i.e. create only). Errors are {"error": "..."}; a value that fails
+// its schema is 422, and a write to a follower is 403.
+
+use std::io;
+use std::time::Duration;
+
+use serde_json::{Value as Json, json};
+
+use super::message::{Request, Response, percent_encode};
+use crate::query::{QueryOptions, SortOrder};
+use crate::replication;
+use crate::server::Shared;
+use crate::{Condition, Database, PatchError, PatchOp, Transaction, TransactionError, Value};
+
+// Keys per page of GET /keys, unless ?limit= says otherwise, and the most it may ask for
+const DEFAULT_PAGE: usize = 100;
+const MAX_PAGE: usize = 1000;
+
+pub(crate) fn handle(request: &Request, shared: &Shared) -> Response {
+    let method = request.method.as_str();
+    let path = request.path.as_str();
+    let reading = matches!(method, "GET" | "HEAD");
+
+    if let Some(key) = path.strip_prefix("/keys/")
+        && !key.is_empty()
+    {
+        if !reading && shared.is_read_only() {
+            return read_only();
+        }
+        let mut db = shared.lock();
+        let response = match method {
+            "GET" | "HEAD" => get(&db, key, request),
+            "PUT" => put(&mut db, key, request),
+            "PATCH" => patch(&mut db, key, request),
+            "DELETE" => delete(&mut db, key, request),
+            _ => return not_allowed("GET, HEAD, PUT, PATCH, DELETE"),
+        };
+        if !reading && response.status < 300 {
+            shared.mark_dirty();
+        }
+        return response;
+    }
+    match (path, method) {
+        ("/keys", "GET" | "HEAD") => list(&shared.lock(), request),
+        ("/keys", _) => not_allowed("GET, HEAD"),
+        ("/query", "POST") => query(&shared.lock(), request),
+        ("/query", _) => not_allowed("POST"),
+        ("/stats", "GET" | "HEAD") => stats(&shared.lock(), shared),
+        ("/stats", _) => not_allowed("GET, HEAD"),
+        ("/batch/get", "POST") => batch_get(&shared.lock(), request),
+        ("/promote", "POST") => {
+            let promoted = replication::promote(shared);
+            Response::json(200, &json!({ "promoted": promoted }))
+        }
+        ("/promote", _) => not_allowed("POST"),
+        ("/batch/insert" | "/batch/delete" | "/transaction", "POST") if shared.is_read_only() => {
+            read_only()
+        }
+        ("/batch/insert" | "/batch/delete" | "/transaction", "POST") => {
+            let mut db = shared.lock();
+            let response = match path {
+                "/batch/insert" => batch_insert(&mut db, request),
+                "/batch/delete" => batch_delete(&mut db, request),
+                _ => transaction(&mut db, request),
+            };
+            if response.status < 300 {
+                shared.mark_dirty();
+            }
+            response
+        }
+        ("/batch/get" | "/batch/insert" | "/batch/delete" | "/transaction", _) => {
+            not_allowed("POST")
+        }
+        _ => Response::error(404, format!("no route for {}", path)),
+    }
+}
+
+fn get(db: &Database, key: &str, request: &Request) -> Response {
+    let Some((value, version)) = db.get_with_version(key) else {
+        return Response::error(404, format!("Key '{}' not found", key));
+    };
+    if let Some(refused) = preconditions(request, Some(version)) {
+        return refused;
+    }
+    match Json::try_from(&value) {
+        Ok(json) => Response::json(200, &json).header("ETag", etag(version)),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+fn put(db: &mut Database, key: &str, request: &Request) -> Response {
+    let value = match body_text(request).and_then(|text| {
+        Value::from_json_str(text).map_err(|e| Response::error(400, format!("invalid JSON: {}", e)))
+    }) {
+        Ok(value) => value,
+        Err(response) => return response,
+    };
+    // Seconds, possibly fractional: ?ttl=30 or ?ttl=0.25
+    let ttl = match request.param("ttl").map(str::parse::<f64>) {
+        None => None,
+        Some(Ok(seconds)) if seconds > 0.0 && seconds.is_finite() => {
+            Some(Duration::from_secs_f64(seconds))
+        }
+        Some(_) => return Response::error(400, "ttl must be a positive number of seconds"),
+    };
+    let current = current_version(db, key);
+    if let Some(refused) = preconditions(request, current) {
+        return refused;
+    }
+
+    let written = match ttl {
+        Some(ttl) => db.insert_with_ttl(key.to_string(), value, ttl),
+        None => db.insert(key.to_string(), value),
+    };
+    if let Err(e) = written {
+        return io_error(e);
+    }
+    let version = current_version(db, key).unwrap_or_default();
+    let status = if current.is_some() { 200 } else { 201 };
+    Response::json(status, &json!({ "key": key, "version": version })).header("ETag", etag(version))
+}
+
+fn patch(db: &mut Database, key: &str, request: &Request) -> Response {
+    let current = current_version(db, key);
+    if current.is_none() {
+        return Response::error(404, format!("Key '{}' not found", key));
+    }
+    if let Some(refused) = preconditions(request, current) {
+        return refused;
+    }
+    let text = match body_text(request) {
+        Ok(text) => text,
+        Err(response) => return response,
+    };
+    let merge = request
+        .header("content-type")
+        .is_some_and(|kind| kind.starts_with("application/merge-patch+json"));
+    let ops = if merge {
+        PatchOp::parse_merge_patch(text).map(|op| vec![op])
+    } else {
+        PatchOp::parse_json_patch(text)
+    };
+    let patched = ops.and_then(|ops| db.patch(key, ops));
+    match patched {
+        Ok(value) => {
+            let version = current_version(db, key).unwrap_or_default();
+            match Json::try_from(&value) {
+                Ok(json) => Response::json(200, &json).header("ETag", etag(version)),
+                Err(e) => Response::error(500, e),
+            }
+        }
+        Err(e) => {
+            let status = match &e {
+                PatchError::KeyNotFound(_) => 404,
+                PatchError::InvalidPatch(_) => 400,
+                PatchError::PathNotFound(_)
+                | PatchError::Conflict { .. }
+                | PatchError::TestFailed(_) => 409,
+                PatchError::Invalid(_) => 422,
+                PatchError::Io(_) => 500,
+            };
+            Response::error(status, e)
+        }
+    }
+}
+
+fn delete(db: &mut Database, key: &str, request: &Request) -> Response {
+    if let Some(refused) = preconditions(request, current_version(db, key)) {
+        return refused;
+    }
+    match db.delete(key) {
+        Ok(()) => Response::new(204),
+        Err(message) => Response::error(404, message),
+    }
+}
+
+// GET /keys?prefix=user:&cursor=user:17&limit=100
+// `cursor` is the last key of the previous page; the response gives the
+// next one, or null after the last page
+fn list(db: &Database, request: &Request) -> Response {
+    let prefix = request.param("prefix").unwrap_or("");
+    let limit = match number_param(request, "limit") {
+        Ok(None) => DEFAULT_PAGE,
+        Ok(Some(limit)) if (1..=MAX_PAGE).contains(&limit) => limit,
+        _ => return Response::error(400, format!("limit must be between 1 and {}", MAX_PAGE)),
+    };
+    let keys = db.keys_with_prefix(prefix);
+    let start = match request.param("cursor") {
+        Some(cursor) => keys.partition_point(|key| key.as_str() <= cursor),
+        None => 0,
+    };
+    let end = (start + limit).min(keys.len());
+    let page = &keys[start..end];
+    let cursor = if end < keys.len() {
+        page.last().cloned()
+    } else {
+        None
+    };
+    let next = cursor.as_ref().map(|cursor| {
+        format!(
+            "/keys?prefix={}&cursor={}&limit={}",
+            percent_encode(prefix),
+            percent_encode(cursor),
+            limit
+        )
+    });
+    Response::json(
+        200,
+        &json!({ "keys": page, "cursor": cursor, "next": next }),
+    )
+}
+
+// POST /query with a filter document (see filter.rs) as the body
+//   ?sort=-age,name  ?limit=10  ?offset=20  ?select=name,age
+fn query(db: &Database, request: &Request) -> Response {
+    let text = match body_text(request) {
+        Ok(text) if text.trim().is_empty() => "{}", // no filter: everything
+        Ok(text) => text,
+        Err(response) => return response,
+    };
+    let condition = match Condition::from_json(text) {
+        Ok(condition) => condition,
+        Err(e) => return Response::error(400, e),
+    };
+    let mut options = QueryOptions::new();
+    match (
+        number_param(request, "limit"),
+        number_param(request, "offset"),
+    ) {
+        (Err(refused), _) | (_, Err(refused)) => return refused,
+        (Ok(limit), Ok(offset)) => {
+            if let Some(limit) = limit {
+                options = options.limit(limit);
+            }
+            options = options.offset(offset.unwrap_or(0));
+        }
+    }
+    for field in list_param(request, "sort") {
+        options = match field.strip_prefix('-') {
+            Some(field) => options.order_by(field, SortOrder::Descending),
+            None => options.order_by(&field, SortOrder::Ascending),
+        };
+    }
+    let select = list_param(request, "select");
+    if !select.is_empty() {
+        options = options.select(select);
+    }
+
+    let mut results = Vec::new();
+    for (key, value) in db.query_with(condition, &options) {
+        match Json::try_from(&value) {
+            Ok(value) => results.push(json!({ "key": key, "value": value })),
+            Err(e) => return Response::error(500, format!("key '{}': {}", key, e)),
+        }
+    }
+    Response::json(200, &json!({ "count": results.len(), "results": results }))
+}
+
+fn batch_get(db: &Database, request: &Request) -> Response {
+    let keys = match key_list(request) {
+        Ok(keys) => keys,
+        Err(refused) => return refused,
+    };
+    let mut values = serde_json::Map::new();
+    for (key, value) in db.batch_get(keys.iter().map(String::as_str).collect()) {
+        match Json::try_from(&value) {
+            Ok(json) => values.insert(key, json),
+            Err(e) => return Response::error(500, format!("key '{}': {}", key, e)),
+        };
+    }
+    Response::json(200, &json!({ "values": values }))
+}
+
+fn batch_insert(db: &mut Database, request: &Request) -> Response {
+    let body = match json_body(request) {
+        Ok(body) => body,
+        Err(refused) => return refused,
+    };
+    let Some(items) = body.get("entries").and_then(Json::as_array) else {
+        return Response::error(
+            400,
+            "expected {\"entries\": [{\"key\": .., \"value\": ..}, ..]}",
+        );
+    };
+    let mut entries = Vec::with_capacity(items.len());
+    for item in items {
+        let (Some(key), Some(value)) = (item.get("key").and_then(Json::as_str), item.get("value"))
+        else {
+            return Response::error(400, "every entry needs a \"key\" string and a \"value\"");
+        };
+        entries.push((key.to_string(), Value::from(value)));
+    }
+    match db.batch_insert(entries) {
+        Ok(inserted) => Response::json(200, &json!({ "inserted": inserted })),
+        Err(e) => io_error(e),
+    }
+}
+
+fn batch_delete(db: &mut Database, request: &Request) -> Response {
+    match key_list(request) {
+        Ok(keys) => {
+            let deleted = db.batch_delete(keys.iter().map(String::as_str).collect());
+            Response::json(200, &json!({ "deleted": deleted }))
+        }
+        Err(refused) => refused,
+    }
+}
+
+// A Transaction built by a client: the versions it read and the writes to
+// make if none of them changed (see transaction.rs)
+fn transaction(db: &mut Database, request: &Request) -> Response {
+    let body = match json_body(request) {
+        Ok(body) => body,
+        Err(refused) => return refused,
+    };
+    let malformed = || {
+        Response::error(
+            400,
+            "expected {\"reads\": {key: version}, \"writes\": [{\"key\": .., \"value\": ..} or {\"key\": .., \"delete\": true}]}",
+        )
+    };
+    let mut tx = Transaction::new();
+    if let Some(reads) = body.get("reads") {
+        let Some(reads) = reads.as_object() else {
+            return malformed();
+        };
+        for (key, version) in reads {
+            let Some(version) = version.as_u64() else {
+                return malformed();
+            };
+            tx.expect_version(key, version);
+        }
+    }
+    let writes = match body.get("writes") {
+        None => &Vec::new(),
+        Some(writes) => match writes.as_array() {
+            Some(writes) => writes,
+            None => return malformed(),
+        },
+    };
+    for write in writes {
+        let Some(key) = write.get("key").and_then(Json::as_str) else {
+            return malformed();
+        };
+        match (write.get("value"), write.get("delete")) {
+            (Some(value), None) => tx.insert(key.to_string(), Value::from(value)),
+            (None, Some(Json::Bool(true))) => tx.delete(key),
+            _ => return malformed(),
+        }
+    }
+
+    let written = tx.len();
+    match db.commit(tx) {
+        Ok(()) => Response::json(200, &json!({ "written": written })),
+        Err(TransactionError::Conflict(key)) => Response::json(
+            409,
+            &json!({ "error": TransactionError::Conflict(key.clone()).to_string(), "key": key }),
+        ),
+        Err(e @ TransactionError::Invalid(_)) => Response::error(422, e),
+        Err(e @ TransactionError::Io(_)) => Response::error(500, e),
+    }
+}
+
+fn stats(db: &Database, shared: &Shared) -> Response {
+    let mut stats = db.stats();
+    stats.replication = shared.replication.stats(db);
+    match serde_json::to_value(stats) {
+        Ok(json) => Response::json(200, &json),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+// ---------- Helpers ----------
+
+fn not_allowed(allowed: &str) -> Response {
+    Response::error(405, "method not allowed").header("Allow", allowed)
+}
+
+fn read_only() -> Response {
+    Response::error(403, "this server is a read-only follower")
+}
+
+fn body_text(request: &Request) -> Result<&str, Response> {
+    std::str::from_utf8(&request.body).map_err(|_| Response::error(400, "the body isn't UTF-8"))
+}
+
+fn json_body(request: &Request) -> Result<Json, Response> {
+    serde_json::from_slice(&request.body)
+        .map_err(|e| Response::error(400, format!("invalid JSON: {}", e)))
+}
+
+// {"keys": ["a", "b"]}
+fn key_list(request: &Request) -> Result<Vec<String>, Response> {
+    let body = json_body(request)?;
+    body.get("keys")
+        .and_then(Json::as_array)
+        .and_then(|keys| {
+            keys.iter()
+                .map(|key| key.as_str().map(str::to_string))
+                .collect::<Option<Vec<String>>>()
+        })
+        .ok_or_else(|| Response::error(400, "expected {\"keys\": [\"key\", ..]}"))
+}
+
+fn number_param(request: &Request, name: &str) -> Result<Option<usize>, Response> {
+    match request.param(name).map(str::parse::<usize>) {
+        None => Ok(None),
+        Some(Ok(n)) => Ok(Some(n)),
+        Some(Err(_)) => Err(Response::error(400, format!("invalid {}", name))),
+    }
+}
+
+// "a, b,c" -> ["a", "b", "c"]
+fn list_param(request: &Request, name: &str) -> Vec<String> {
+    request
+        .param(name)
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// The version of a live key (expired keys count as missing)
+fn current_version(db: &Database, key: &str) -> Option<u64> {
+    db.get_with_version(key).map(|(_, version)| version)
+}
+
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+// Does an If-Match / If-None-Match list name this version? "*" names any
+// existing key. Weak tags (W/"3") compare like strong ones.
+fn etag_listed(header: &str, version: Option<u64>) -> bool {
+    let Some(version) = version else {
+        return false;
+    };
+    let current = etag(version);
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == current)
+}
+
+// RFC 9110 section 13.2.2: If-Match first, then If-None-Match
+fn preconditions(request: &Request, version: Option<u64>) -> Option<Response> {
+    if let Some(header) = request.header("if-match")
+        && !etag_listed(header, version)
+    {
+        return Some(Response::error(412, "the key has changed (If-Match)"));
+    }
+    if let Some(header) = request.header("if-none-match")
+        && etag_listed(header, version)
+    {
+        return Some(if matches!(request.method.as_str(), "GET" | "HEAD") {
+            Response::new(304).header("ETag", etag(version.unwrap_or_default()))
+        } else {
+            Response::error(412, "the key exists (If-None-Match)")
+        });
+    }
+    None
+}
+
+// Schema violations arrive as InvalidInput (see schema.rs)
+fn io_error(e: io::Error) -> Response {
+    let status = if e.kind() == io::ErrorKind::InvalidInput {
+        422
+    } else {
+        500
+    };
+    Response::error(status, e)
+}
@@ -0,0 +1,540 @@
+// NEW: Secondary indexes on object fields
+//
+// An index maps every value of one field to the keys holding it, e.g. an index
+// on "age" knows that 30 -> {"user:1"} and 25 -> {"user:2"}.
+// Only scalar values (null, booleans, numbers, strings) are indexed; entries
+// whose field is missing or holds an array/object are simply not in the index.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+use crate::types::{Decimal, Uuid};
+
+use crate::fulltext::{Analyzer, TextIndex};
+use crate::geo::{GeoField, GeoIndex};
+use crate::vector::{self, VectorIndex, VectorIndexOptions};
+
+// The part of a Value an index can order and look up
+// Variants are declared in sort order, so all integers sit next to each other
+// and an integer range is one contiguous BTreeMap range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IndexKey {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(u64), // f64 bits rearranged so that integer order == float order
+    String(String),
+    Timestamp(i64),
+    Decimal(Decimal), // compares by numeric value, so 1.5 and 1.50 are one key
+    Bytes(Vec<u8>),
+    Uuid(Uuid),
+}
+
+impl IndexKey {
+    // Arrays and objects can't be indexed
+    pub fn from_value(value: &Value) -> Option<IndexKey> {
+        match value {
+            Value::Null => Some(IndexKey::Null),
+            Value::Boolean(b) => Some(IndexKey::Boolean(*b)),
+            Value::Integer(i) => Some(IndexKey::Integer(*i)),
+            Value::Float(f) => {
+                // Floats that Value's == calls equal share a key: -0.0 is
+                // stored as 0.0, and every NaN as the one f64::NAN
+                let f = if *f == 0.0 {
+                    0.0
+                } else if f.is_nan() {
+                    f64::NAN
+                } else {
+                    *f
+                };
+                // Flip the sign bit for positives and every bit for negatives:
+                // the result sorts the same way f64::total_cmp does
+                let bits = f.to_bits();
+                let ordered = if bits >> 63 == 1 {
+                    !bits
+                } else {
+                    bits | (1 << 63)
+                };
+                Some(IndexKey::Float(ordered))
+            }
+            Value::String(s) => Some(IndexKey::String(s.clone())),
+            Value::Timestamp(nanos) => Some(IndexKey::Timestamp(*nanos)),
+            Value::Decimal(d) => Some(IndexKey::Decimal(*d)),
+            Value::Bytes(b) => Some(IndexKey::Bytes(b.clone())),
+            Value::Uuid(u) => Some(IndexKey::Uuid(*u)),
+            Value::Array(_) | Value::Object(_) => None,
+        }
+    }
+}
+
+// Statistics the query planner uses to estimate how many rows a lookup returns
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStats {
+    pub field: String,
+    pub entries: usize,         // keys present in the index
+    pub distinct_values: usize, // different values of the field
+    pub integer_entries: usize, // keys whose value is an Integer or a Timestamp
+    pub min_integer: Option<i64>,
+    pub max_integer: Option<i64>,
+}
+
+impl IndexStats {
+    // Expected number of keys for `field = value` (assumes values are evenly spread)
+    pub fn estimate_equal(&self) -> f64 {
+        if self.distinct_values == 0 {
+            0.0
+        } else {
+            self.entries as f64 / self.distinct_values as f64
+        }
+    }
+
+    // Expected number of keys with an integer in low..=high (assumes a uniform spread)
+    pub fn estimate_range(&self, low: i64, high: i64) -> f64 {
+        let (Some(min), Some(max)) = (self.min_integer, self.max_integer) else {
+            return 0.0;
+        };
+        let low = low.max(min) as i128;
+        let high = high.min(max) as i128;
+        if low > high {
+            return 0.0;
+        }
+        let width = (max as i128 - min as i128 + 1) as f64;
+        self.integer_entries as f64 * ((high - low + 1) as f64 / width)
+    }
+}
+
+// The integers within the bounds as low..=high; None if there are none
+pub(crate) fn integer_bounds(low: Bound<i64>, high: Bound<i64>) -> Option<(i64, i64)> {
+    let low = match low {
+        Bound::Included(n) => n,
+        Bound::Excluded(n) => n.checked_add(1)?,
+        Bound::Unbounded => i64::MIN,
+    };
+    let high = match high {
+        Bound::Included(n) => n,
+        Bound::Excluded(n) => n.checked_sub(1)?,
+        Bound::Unbounded => i64::MAX,
+    };
+    (low <= high).then_some((low, high))
+}
+
+// BTreeMap::range panics on these
+fn is_empty_range(from: &Bound<IndexKey>, to: &Bound<IndexKey>) -> bool {
+    match (from, to) {
+        (Bound::Included(a), Bound::Included(b)) => a > b,
+        (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b)) => {
+            a >= b
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FieldIndex {
+    entries: BTreeMap<IndexKey, BTreeSet<String>>,
+    len: usize,         // number of (value, key) pairs
+    integer_len: usize, // how many of them are integers or timestamps
+}
+
+impl FieldIndex {
+    // Keys whose field equals the value
+    pub fn lookup(&self, value: &Value) -> BTreeSet<String> {
+        IndexKey::from_value(value)
+            .and_then(|k| self.entries.get(&k).cloned())
+            .unwrap_or_default()
+    }
+
+    // Keys whose field is within the bounds: integers, timestamps (compared
+    // as nanoseconds) and decimals, the same values integer Conditions accept.
+    // An excluded bound moves by one for integers but stays put for decimals
+    // (x > 98 takes 98.5)
+    pub fn range(&self, low: Bound<i64>, high: Bound<i64>) -> BTreeSet<String> {
+        let mut keys = BTreeSet::new();
+        if let Some((low, high)) = integer_bounds(low, high) {
+            for (from, to) in [
+                (IndexKey::Integer(low), IndexKey::Integer(high)),
+                (IndexKey::Timestamp(low), IndexKey::Timestamp(high)),
+            ] {
+                keys.extend(
+                    self.entries
+                        .range(from..=to)
+                        .flat_map(|(_, k)| k.iter().cloned()),
+                );
+            }
+        }
+        // Decimals reach past i64, so an open end is the largest decimal
+        let decimal = |bound: Bound<i64>, end: i128| match bound {
+            Bound::Included(n) => Bound::Included(IndexKey::Decimal(Decimal::from(n))),
+            Bound::Excluded(n) => Bound::Excluded(IndexKey::Decimal(Decimal::from(n))),
+            Bound::Unbounded => Bound::Included(IndexKey::Decimal(
+                Decimal::new(end, 0).expect("scale 0 is valid"),
+            )),
+        };
+        let (from, to) = (decimal(low, i128::MIN), decimal(high, i128::MAX));
+        if !is_empty_range(&from, &to) {
+            keys.extend(
+                self.entries
+                    .range((from, to))
+                    .flat_map(|(_, k)| k.iter().cloned()),
+            );
+        }
+        keys
+    }
+
+    pub fn stats(&self, field: &str) -> IndexStats {
+        let as_integer = |key: &IndexKey| match key {
+            IndexKey::Integer(i) | IndexKey::Timestamp(i) => Some(*i),
+            _ => None,
+        };
+        let integers = self
+            .entries
+            .range(IndexKey::Integer(i64::MIN)..=IndexKey::Integer(i64::MAX));
+        let timestamps = self
+            .entries
+            .range(IndexKey::Timestamp(i64::MIN)..=IndexKey::Timestamp(i64::MAX));
+        let firsts = [integers.clone().next(), timestamps.clone().next()];
+        let lasts = [integers.last(), timestamps.last()];
+        let min_integer = firsts
+            .iter()
+            .flatten()
+            .filter_map(|(k, _)| as_integer(k))
+            .min();
+        let max_integer = lasts
+            .iter()
+            .flatten()
+            .filter_map(|(k, _)| as_integer(k))
+            .max();
+        IndexStats {
+            field: field.to_string(),
+            entries: self.len,
+            distinct_values: self.entries.len(),
+            integer_entries: self.integer_len,
+            min_integer,
+            max_integer,
+        }
+    }
+
+    fn add(&mut self, key: &str, value: &Value) {
+        if let Some(index_key) = IndexKey::from_value(value) {
+            let is_integer = matches!(index_key, IndexKey::Integer(_) | IndexKey::Timestamp(_));
+            if self
+                .entries
+                .entry(index_key)
+                .or_default()
+                .insert(key.to_string())
+            {
+                self.len += 1;
+                if is_integer {
+                    self.integer_len += 1;
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str, value: &Value) {
+        if let Some(index_key) = IndexKey::from_value(value)
+            && let Some(keys) = self.entries.get_mut(&index_key)
+            && keys.remove(key)
+        {
+            self.len -= 1;
+            if matches!(index_key, IndexKey::Integer(_) | IndexKey::Timestamp(_)) {
+                self.integer_len -= 1;
+            }
+            if keys.is_empty() {
+                self.entries.remove(&index_key);
+            }
+        }
+    }
+}
+
+// What it takes to create the field, full-text and location indexes again:
+// their contents come from the data, so only the definitions are saved
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexDefinitions {
+    fields: Vec<String>,
+    text: Vec<(String, Analyzer)>,
+    geo: Vec<GeoField>,
+}
+
+impl IndexDefinitions {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.text.is_empty() && self.geo.is_empty()
+    }
+}
+
+// All indexes of a database, kept up to date by the write path
+#[derive(Debug, Default)]
+pub struct Indexes {
+    fields: BTreeMap<String, FieldIndex>,
+    text: BTreeMap<String, TextIndex>,      // full-text indexes
+    vectors: BTreeMap<String, VectorIndex>, // vector similarity indexes
+    geo: BTreeMap<String, GeoIndex>,        // location indexes, by GeoField::name
+}
+
+impl Indexes {
+    // Create an index on `field` and fill it from the existing data
+    // Returns false if the index already exists
+    pub fn create<'a>(
+        &mut self,
+        field: &str,
+        data: impl Iterator<Item = (&'a String, &'a Value)>,
+    ) -> bool {
+        if self.fields.contains_key(field) {
+            return false;
+        }
+        let mut index = FieldIndex::default();
+        for (key, value) in data {
+            if let Some(v) = value.get_field(field) {
+                index.add(key, v);
+            }
+        }
+        self.fields.insert(field.to_string(), index);
+        true
+    }
+
+    // Create a full-text index on a string field and fill it from the existing data
+    // Returns false if the field already has one
+    pub fn create_text<'a>(
+        &mut self,
+        field: &str,
+        analyzer: Analyzer,
+        data: impl Iterator<Item = (&'a String, &'a Value)>,
+    ) -> bool {
+        if self.text.contains_key(field) {
+            return false;
+        }
+        let mut index = TextIndex::new(analyzer);
+        for (key, value) in data {
+            if let Some(Value::String(text)) = value.get_field(field) {
+                index.add(key, text);
+            }
+        }
+        self.text.insert(field.to_string(), index);
+        true
+    }
+
+    // Create a vector index on an array field and fill it from the existing data
+    // Returns false if the field already has one
+    pub fn create_vector<'a>(
+        &mut self,
+        field: &str,
+        options: VectorIndexOptions,
+        data: impl Iterator<Item = (&'a String, &'a Value)>,
+    ) -> bool {
+        if self.vectors.contains_key(field) {
+            return false;
+        }
+        let mut index = VectorIndex::new(options);
+        for (key, value) in data {
+            if let Some(v) = value.get_field(field).and_then(vector::vector_of) {
+                index.add(key, v);
+            }
+        }
+        self.vectors.insert(field.to_string(), index);
+        true
+    }
+
+    // Create a location index and fill it from the existing data
+    // Returns false if the location already has one
+    pub fn create_geo<'a>(
+        &mut self,
+        field: GeoField,
+        data: impl Iterator<Item = (&'a String, &'a Value)>,
+    ) -> bool {
+        let name = field.name();
+        if self.geo.contains_key(&name) {
+            return false;
+        }
+        let mut index = GeoIndex::new(field);
+        for (key, value) in data {
+            index.add_value(key, value);
+        }
+        self.geo.insert(name, index);
+        true
+    }
+
+    // Drop every index on the field (regular, full-text, vector and location)
+    pub fn drop(&mut self, field: &str) -> bool {
+        let dropped_field = self.fields.remove(field).is_some();
+        let dropped_text = self.text.remove(field).is_some();
+        let dropped_vector = self.vectors.remove(field).is_some();
+        let dropped_geo = self.geo.remove(field).is_some();
+        dropped_field || dropped_text || dropped_vector || dropped_geo
+    }
+
+    pub fn get(&self, field: &str) -> Option<&FieldIndex> {
+        self.fields.get(field)
+    }
+
+    pub fn text(&self, field: &str) -> Option<&TextIndex> {
+        self.text.get(field)
+    }
+
+    pub fn fields(&self) -> Vec<String> {
+        self.fields.keys().cloned().collect()
+    }
+
+    pub fn text_fields(&self) -> Vec<String> {
+        self.text.keys().cloned().collect()
+    }
+
+    pub fn vector(&self, field: &str) -> Option<&VectorIndex> {
+        self.vectors.get(field)
+    }
+
+    pub fn vector_fields(&self) -> Vec<String> {
+        self.vectors.keys().cloned().collect()
+    }
+
+    pub fn geo(&self, field: &GeoField) -> Option<&GeoIndex> {
+        self.geo.get(&field.name())
+    }
+
+    pub fn geo_fields(&self) -> Vec<String> {
+        self.geo.keys().cloned().collect()
+    }
+
+    // Vector indexes are expensive to build, so they are saved next to the data
+    // file instead of being rebuilt on every load
+    pub fn vector_indexes(&self) -> &BTreeMap<String, VectorIndex> {
+        &self.vectors
+    }
+
+    pub fn restore_vector_indexes(&mut self, vectors: BTreeMap<String, VectorIndex>) {
+        self.vectors = vectors;
+    }
+
+    // The other indexes are saved as definitions only (see IndexDefinitions)
+    pub fn definitions(&self) -> IndexDefinitions {
+        IndexDefinitions {
+            fields: self.fields(),
+            text: self
+                .text
+                .iter()
+                .map(|(field, index)| (field.clone(), index.analyzer()))
+                .collect(),
+            geo: self
+                .geo
+                .values()
+                .map(|index| index.field().clone())
+                .collect(),
+        }
+    }
+
+    // Define the indexes again, empty; `rebuild` fills them
+    pub fn restore_definitions(&mut self, definitions: IndexDefinitions) {
+        for field in definitions.fields {
+            self.fields.insert(field, FieldIndex::default());
+        }
+        for (field, analyzer) in definitions.text {
+            self.text.insert(field, TextIndex::new(analyzer));
+        }
+        for field in definitions.geo {
+            self.geo.insert(field.name(), GeoIndex::new(field));
+        }
+    }
+
+    // The analyzer Condition::Matches uses for a field: the one of its
+    // full-text index, or the default one when the field has no such index
+    pub fn analyzer_for(&self, field: &str) -> Analyzer {
+        self.text
+            .get(field)
+            .map(|index| index.analyzer())
+            .unwrap_or_default()
+    }
+
+    // Called after `key` was written; `old` is the value it replaced (if any)
+    pub fn on_insert(&mut self, key: &str, old: Option<&Value>, new: &Value) {
+        if let Some(old) = old {
+            self.on_remove(key, old);
+        }
+        for (field, index) in self.fields.iter_mut() {
+            if let Some(v) = new.get_field(field) {
+                index.add(key, v);
+            }
+        }
+        for (field, index) in self.text.iter_mut() {
+            if let Some(Value::String(text)) = new.get_field(field) {
+                index.add(key, text);
+            }
+        }
+        for (field, index) in self.vectors.iter_mut() {
+            if let Some(v) = new.get_field(field).and_then(vector::vector_of) {
+                index.add(key, v);
+            }
+        }
+        for index in self.geo.values_mut() {
+            index.add_value(key, new);
+        }
+    }
+
+    // Called after `key` (which held `old`) was deleted
+    pub fn on_remove(&mut self, key: &str, old: &Value) {
+        for (field, index) in self.fields.iter_mut() {
+            if let Some(v) = old.get_field(field) {
+                index.remove(key, v);
+            }
+        }
+        for (field, index) in self.text.iter_mut() {
+            if let Some(Value::String(text)) = old.get_field(field) {
+                index.remove(key, text);
+            }
+        }
+        for index in self.vectors.values_mut() {
+            index.remove(key);
+        }
+        for index in self.geo.values_mut() {
+            index.remove(key);
+        }
+    }
+
+    // Empty every index but keep the definitions
+    pub fn clear(&mut self) {
+        for index in self.fields.values_mut() {
+            *index = FieldIndex::default();
+        }
+        for index in self.text.values_mut() {
+            *index = TextIndex::new(index.analyzer());
+        }
+        for index in self.vectors.values_mut() {
+            index.clear();
+        }
+        for index in self.geo.values_mut() {
+            index.clear();
+        }
+    }
+
+    // Bring every index in line with the data after loading from disk
+    // Field and full-text indexes are rebuilt from scratch; vector indexes were
+    // loaded from their own file, so only the keys that differ are fixed up
+    pub fn rebuild(&mut self, data: &BTreeMap<String, Value>) {
+        let vectors = std::mem::take(&mut self.vectors);
+        self.clear();
+        for (key, value) in data {
+            self.on_insert(key, None, value);
+        }
+
+        self.vectors = vectors;
+        for (field, index) in self.vectors.iter_mut() {
+            for key in index.keys() {
+                let current = data
+                    .get(&key)
+                    .and_then(|v| v.get_field(field))
+                    .and_then(vector::vector_of);
+                if current.as_deref() != index.get(&key) {
+                    index.remove(&key);
+                }
+            }
+            for (key, value) in data {
+                if index.get(key).is_none()
+                    && let Some(v) = value.get_field(field).and_then(vector::vector_of)
+                {
+                    index.add(key, v);
+                }
+            }
+        }
+    }
+}
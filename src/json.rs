@@ -0,0 +1,196 @@
+// NEW: Conversions between Value and serde_json (the `json` feature)
+//
+//   let value = Value::from_json_str(r#"{"name": "Alice", "age": 30}"#)?;
+//   let text = value.to_json_string_pretty()?;
+//   let json: serde_json::Value = value.try_into()?;
+//
+// Rules:
+//   - Numbers: a JSON number written without a fraction or exponent that fits
+//     in an i64 becomes Integer; every other number becomes Float (so 3 is an
+//     Integer, 3.0 and 1e3 are Floats, and 2^64 is a Float that lost precision).
+//     Going the other way Integer stays 3 and Float is written with a fraction
+//     (3.0), so Value -> JSON -> Value gives back the same numeric variant.
+//   - NaN and infinity: JSON can't represent them, so converting a Value
+//     holding one fails with JsonError::NonFiniteFloat (nothing is silently
+//     replaced). JSON text never produces them.
+//   - Key ordering: Value::Object is a HashMap and doesn't remember the order
+//     keys were read in. Output objects always list their keys sorted, so the
+//     same Value always prints the same text.
+//   - Bytes, Timestamp, Decimal and Uuid have no JSON type and are written as
+//     strings (base64, RFC 3339, exact digits, hyphenated hex); they come back
+//     as Value::String.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::{Map, Number, Value as Json};
+
+use crate::Value;
+use crate::types;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonError {
+    // The text is not valid JSON
+    Parse {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    // A Float is NaN or infinite; `path` says where, e.g. "$.prices[2]"
+    NonFiniteFloat { path: String },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Parse {
+                message,
+                line,
+                column,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            JsonError::NonFiniteFloat { path } => {
+                write!(f, "{}: NaN and infinity can't be written as JSON", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(e: serde_json::Error) -> Self {
+        // serde_json's message ends with its own " at line L column C"
+        let message = e.to_string();
+        let location = format!(" at line {} column {}", e.line(), e.column());
+        JsonError::Parse {
+            message: message.trim_end_matches(&location).to_string(),
+            line: e.line(),
+            column: e.column(),
+        }
+    }
+}
+
+impl Value {
+    pub fn from_json_str(text: &str) -> Result<Value, JsonError> {
+        let json: Json = serde_json::from_str(text)?;
+        Ok(Value::from(json))
+    }
+
+    // Compact JSON, e.g. {"age":30,"name":"Alice"}
+    pub fn to_json_string(&self) -> Result<String, JsonError> {
+        let json = Json::try_from(self)?;
+        Ok(json.to_string())
+    }
+
+    // Indented JSON, two spaces per level
+    pub fn to_json_string_pretty(&self) -> Result<String, JsonError> {
+        let json = Json::try_from(self)?;
+        serde_json::to_string_pretty(&json).map_err(JsonError::from)
+    }
+}
+
+impl From<&Json> for Value {
+    fn from(json: &Json) -> Self {
+        match json {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Boolean(*b),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => Value::Integer(i),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(s) => Value::String(s.clone()),
+            Json::Array(items) => Value::Array(items.iter().map(Value::from).collect()),
+            Json::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), Value::from(v)))
+                    .collect::<HashMap<_, _>>(),
+            ),
+        }
+    }
+}
+
+impl From<Json> for Value {
+    fn from(json: Json) -> Self {
+        Value::from(&json)
+    }
+}
+
+impl TryFrom<&Value> for Json {
+    type Error = JsonError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        to_json(value, &mut |path| {
+            Err(JsonError::NonFiniteFloat {
+                path: path.to_string(),
+            })
+        })
+    }
+}
+
+impl TryFrom<Value> for Json {
+    type Error = JsonError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Json::try_from(&value)
+    }
+}
+
+// Same as TryFrom, but NaN and infinity become null instead of failing
+// (used where an approximate rendering is fine, e.g. filter documents)
+pub(crate) fn to_json_lossy(value: &Value) -> Json {
+    to_json(value, &mut |_| Ok(Json::Null)).unwrap_or(Json::Null)
+}
+
+// `non_finite` decides what a NaN/infinite Float at `path` turns into
+fn to_json(
+    value: &Value,
+    non_finite: &mut dyn FnMut(&str) -> Result<Json, JsonError>,
+) -> Result<Json, JsonError> {
+    fn walk(
+        value: &Value,
+        path: &mut String,
+        non_finite: &mut dyn FnMut(&str) -> Result<Json, JsonError>,
+    ) -> Result<Json, JsonError> {
+        let json = match value {
+            Value::Null => Json::Null,
+            Value::Boolean(b) => Json::Bool(*b),
+            Value::Integer(i) => Json::from(*i),
+            Value::Float(f) => match Number::from_f64(*f) {
+                Some(n) => Json::Number(n),
+                None => non_finite(path)?,
+            },
+            Value::String(s) => Json::String(s.clone()),
+            Value::Array(items) => {
+                let mut array = Vec::with_capacity(items.len());
+                for (i, item) in items.iter().enumerate() {
+                    let length = path.len();
+                    path.push_str(&format!("[{}]", i));
+                    array.push(walk(item, path, non_finite)?);
+                    path.truncate(length);
+                }
+                Json::Array(array)
+            }
+            Value::Object(fields) => {
+                // Insert in key order, so the output is sorted even if serde_json's
+                // Map keeps insertion order (its `preserve_order` feature)
+                let mut sorted: Vec<_> = fields.iter().collect();
+                sorted.sort_by(|a, b| a.0.cmp(b.0));
+                let mut map = Map::new();
+                for (key, field) in sorted {
+                    let length = path.len();
+                    path.push('.');
+                    path.push_str(key);
+                    map.insert(key.clone(), walk(field, path, non_finite)?);
+                    path.truncate(length);
+                }
+                Json::Object(map)
+            }
+            Value::Bytes(bytes) => Json::String(types::base64_encode(bytes)),
+            Value::Timestamp(nanos) => Json::String(types::format_timestamp(*nanos)),
+            Value::Decimal(d) => Json::String(d.to_string()),
+            Value::Uuid(u) => Json::String(u.to_string()),
+        };
+        Ok(json)
+    }
+    walk(value, &mut "$".to_string(), non_finite)
+}
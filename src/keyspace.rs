@@ -0,0 +1,437 @@
+// NEW: One set of keys with everything that belongs to it
+//
+// The database's own keys and every collection are each a Keyspace: the
+// entries, their secondary indexes, their version numbers and their expiry
+// times. Database and Collection both work through this type, so a
+// collection behaves exactly like a small database of its own.
+//
+// Expiry: a key with a deadline disappears once the deadline has passed.
+// Reads treat it as gone right away; the entry itself is removed by the next
+// write to the keyspace (or Database::purge_expired).
+//
+// While the database keeps a write log (see changelog.rs), every write also
+// records what it did in `journal`, for the database to number and log.
+// While someone watches the database's keys (see watch.rs), its keyspace
+// also reports every change, with the values before and after, to `feed`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::changelog::ChangeOp;
+use crate::index::Indexes;
+use crate::planner::{Planner, QueryPlan, scan_prefix};
+use crate::query;
+use crate::schema::{Schema, ValidationError};
+use crate::types;
+use crate::watch::{Feed, KeyChange};
+use crate::{Condition, Value};
+
+// Settings of a collection, saved with its data
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CollectionOptions {
+    // Keys written without an explicit TTL expire this long after the write
+    pub default_ttl: Option<Duration>,
+    // Key prefix -> schema the values under it must fit ("" = every key)
+    pub schemas: BTreeMap<String, Schema>,
+}
+
+impl CollectionOptions {
+    pub fn new() -> Self {
+        CollectionOptions::default()
+    }
+
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    // Every value in the collection must fit the schema
+    pub fn schema(self, schema: Schema) -> Self {
+        self.prefix_schema("", schema)
+    }
+
+    // Values whose key starts with the prefix must fit the schema
+    pub fn prefix_schema(mut self, prefix: &str, schema: Schema) -> Self {
+        self.schemas.insert(prefix.to_string(), schema);
+        self
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Keyspace {
+    pub(crate) store: BTreeMap<String, Value>,
+    pub(crate) indexes: Indexes,
+    pub(crate) versions: Versions,
+    pub(crate) expires: BTreeMap<String, i64>, // key -> deadline, nanoseconds since the epoch
+    pub(crate) options: CollectionOptions,
+    pub(crate) journal: Option<Vec<ChangeOp>>, // None = not recording
+    pub(crate) feed: Option<Feed>,             // None = nobody watches
+}
+
+impl Keyspace {
+    pub(crate) fn new(options: CollectionOptions) -> Self {
+        Keyspace {
+            options,
+            ..Keyspace::default()
+        }
+    }
+
+    // Build from loaded data; indexes are rebuilt, versions reconciled
+    pub(crate) fn restore(image: KeyspaceImage, versions: Option<Versions>) -> Self {
+        let mut keyspace = Keyspace {
+            store: image.entries,
+            expires: image.expires,
+            options: image.options,
+            versions: versions.unwrap_or_default(),
+            ..Keyspace::default()
+        };
+        keyspace.versions.reconcile(&keyspace.store);
+        keyspace
+    }
+
+    pub(crate) fn image(&self) -> KeyspaceImageRef<'_> {
+        KeyspaceImageRef {
+            entries: &self.store,
+            expires: &self.expires,
+            options: &self.options,
+        }
+    }
+
+    // ---------- Reads (expired keys are invisible) ----------
+
+    pub(crate) fn is_expired(&self, key: &str, now: i64) -> bool {
+        self.expires
+            .get(key)
+            .is_some_and(|deadline| *deadline <= now)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        let value = self.store.get(key)?;
+        (!self.is_expired(key, types::timestamp_now())).then_some(value)
+    }
+
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub(crate) fn keys(&self) -> Vec<String> {
+        let now = types::timestamp_now();
+        self.store
+            .keys()
+            .filter(|key| !self.is_expired(key, now))
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let now = types::timestamp_now();
+        scan_prefix(&self.store, prefix)
+            .filter(|(key, _)| !self.is_expired(key, now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        let now = types::timestamp_now();
+        let expired = self.expires.values().filter(|d| **d <= now).count();
+        self.store.len() - expired
+    }
+
+    pub(crate) fn query(&self, condition: &Condition) -> Vec<(String, Value)> {
+        let (mut results, _plan) = Planner::new(&self.store, &self.indexes).execute(condition);
+        if !self.expires.is_empty() {
+            let now = types::timestamp_now();
+            results.retain(|(key, _)| !self.is_expired(key, now));
+        }
+        if let Some((field, point)) = condition.near_point() {
+            query::sort_by_distance(&mut results, field, point);
+        }
+        results
+    }
+
+    pub(crate) fn explain(&self, condition: &Condition) -> QueryPlan {
+        let (_results, plan) = Planner::new(&self.store, &self.indexes).execute(condition);
+        plan
+    }
+
+    // Time left before the key expires (None = no expiry or no such key)
+    pub(crate) fn ttl(&self, key: &str) -> Option<Duration> {
+        let now = types::timestamp_now();
+        let deadline = *self.expires.get(key)?;
+        (deadline > now).then(|| Duration::from_nanos((deadline - now) as u64))
+    }
+
+    // Check a value about to be written against every schema covering the key
+    pub(crate) fn check(&self, key: &str, value: &Value) -> Result<(), ValidationError> {
+        for (prefix, schema) in &self.options.schemas {
+            if key.starts_with(prefix.as_str()) {
+                schema.validate(value).map_err(|e| e.for_key(key))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Every stored entry that doesn't fit its schemas, in key order
+    pub(crate) fn violations(&self) -> Vec<ValidationError> {
+        let now = types::timestamp_now();
+        let mut errors = Vec::new();
+        for (prefix, schema) in &self.options.schemas {
+            for (key, value) in scan_prefix(&self.store, prefix) {
+                if self.is_expired(key, now) {
+                    continue;
+                }
+                errors.extend(schema.violations(value).into_iter().map(|e| e.for_key(key)));
+            }
+        }
+        errors.sort_by(|a, b| a.key.cmp(&b.key));
+        errors
+    }
+
+    // ---------- Writes ----------
+
+    // Every write goes through put/remove so the indexes and versions stay in
+    // sync with the store. A key that didn't exist gets the default TTL; an
+    // existing key keeps its expiry (set_ttl changes it).
+    pub(crate) fn put(&mut self, key: String, value: Value) -> Option<Value> {
+        let now = types::timestamp_now();
+        if self.is_expired(&key, now) {
+            self.expire(&key);
+        }
+        if !self.store.contains_key(&key) {
+            self.set_deadline(&key, deadline_after(self.options.default_ttl));
+        }
+        if self.journal.is_some() {
+            let op = ChangeOp::Put {
+                key: key.clone(),
+                value: value.clone(),
+                expires: self.expires.get(&key).copied(),
+            };
+            self.record(op);
+        }
+        self.store_reporting(key, value)
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<Value> {
+        let old = self.take(key)?;
+        self.record(ChangeOp::Delete {
+            key: key.to_string(),
+        });
+        self.report(|| KeyChange::Delete {
+            key: key.to_string(),
+            old: old.clone(),
+        });
+        Some(old)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.wipe();
+        self.record(ChangeOp::Clear);
+        self.report(|| KeyChange::Clear);
+    }
+
+    // None removes the expiry
+    pub(crate) fn set_ttl(&mut self, key: &str, ttl: Option<Duration>) {
+        let expires = deadline_after(ttl);
+        self.set_deadline(key, expires);
+        self.record(ChangeOp::Expire {
+            key: key.to_string(),
+            expires,
+        });
+    }
+
+    // After changing `options` directly
+    pub(crate) fn options_changed(&mut self) {
+        if self.journal.is_some() {
+            self.record(ChangeOp::Options(self.options.clone()));
+        }
+    }
+
+    // Replay a change recorded by another database's keyspace (replication);
+    // it isn't recorded again
+    pub(crate) fn apply(&mut self, op: ChangeOp) {
+        match op {
+            ChangeOp::Put {
+                key,
+                value,
+                expires,
+            } => {
+                self.set_deadline(&key, expires);
+                self.store_reporting(key, value);
+            }
+            ChangeOp::Delete { key } => {
+                if let Some(old) = self.take(&key) {
+                    self.report(|| KeyChange::Delete { key, old });
+                }
+            }
+            ChangeOp::Expire { key, expires } => {
+                if self.store.contains_key(&key) {
+                    self.set_deadline(&key, expires);
+                }
+            }
+            ChangeOp::Clear => {
+                self.wipe();
+                self.report(|| KeyChange::Clear);
+            }
+            ChangeOp::Options(options) => self.options = options,
+            // The database drops the whole keyspace
+            ChangeOp::DropCollection => {}
+        }
+    }
+
+    // Recorded changes not yet in the log
+    pub(crate) fn take_journal(&mut self) -> Vec<ChangeOp> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, op: ChangeOp) {
+        if let Some(journal) = &mut self.journal {
+            journal.push(op);
+        }
+    }
+
+    // Tell the watchers, if any
+    fn report(&mut self, change: impl FnOnce() -> KeyChange) {
+        if let Some(feed) = &mut self.feed {
+            feed.emit(change(), &self.indexes);
+        }
+    }
+
+    // store_value, reported as an Insert or an Update
+    fn store_reporting(&mut self, key: String, value: Value) -> Option<Value> {
+        let watched = self.feed.is_some().then(|| (key.clone(), value.clone()));
+        let old = self.store_value(key, value);
+        if let Some((key, value)) = watched {
+            self.report(|| match &old {
+                Some(old) => KeyChange::Update {
+                    key,
+                    old: old.clone(),
+                    new: value,
+                },
+                None => KeyChange::Insert { key, value },
+            });
+        }
+        old
+    }
+
+    // Remove an entry whose deadline passed
+    fn expire(&mut self, key: &str) -> Option<Value> {
+        let old = self.take(key)?;
+        self.record(ChangeOp::Delete {
+            key: key.to_string(),
+        });
+        self.report(|| KeyChange::Expire {
+            key: key.to_string(),
+            old: old.clone(),
+        });
+        Some(old)
+    }
+
+    fn store_value(&mut self, key: String, value: Value) -> Option<Value> {
+        self.indexes.on_insert(&key, self.store.get(&key), &value);
+        self.versions.bump(&key);
+        self.store.insert(key, value)
+    }
+
+    fn take(&mut self, key: &str) -> Option<Value> {
+        let old = self.store.remove(key)?;
+        self.indexes.on_remove(key, &old);
+        self.versions.keys.remove(key);
+        self.expires.remove(key);
+        Some(old)
+    }
+
+    fn wipe(&mut self) {
+        self.store.clear();
+        self.indexes.clear();
+        self.versions.keys.clear();
+        self.expires.clear();
+    }
+
+    fn set_deadline(&mut self, key: &str, deadline: Option<i64>) {
+        match deadline {
+            Some(deadline) => {
+                self.expires.insert(key.to_string(), deadline);
+            }
+            None => {
+                self.expires.remove(key);
+            }
+        }
+    }
+
+    // Remove every expired entry; returns what was removed
+    pub(crate) fn purge_expired(&mut self) -> Vec<(String, Value)> {
+        if self.expires.is_empty() {
+            return Vec::new();
+        }
+        let now = types::timestamp_now();
+        let expired: Vec<String> = self
+            .expires
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.expire(&key)?;
+                Some((key, value))
+            })
+            .collect()
+    }
+}
+
+// Nanoseconds since the epoch, `ttl` from now
+pub(crate) fn deadline_after(ttl: Option<Duration>) -> Option<i64> {
+    let nanos = i64::try_from(ttl?.as_nanos()).unwrap_or(i64::MAX);
+    Some(types::timestamp_now().saturating_add(nanos))
+}
+
+// What a keyspace looks like in the data file (indexes are saved beside it,
+// see Database::save_sidecars)
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct KeyspaceImage {
+    pub(crate) entries: BTreeMap<String, Value>,
+    pub(crate) expires: BTreeMap<String, i64>,
+    pub(crate) options: CollectionOptions,
+}
+
+// Same layout, borrowed, so saving doesn't copy the data
+#[derive(Serialize)]
+pub(crate) struct KeyspaceImageRef<'a> {
+    entries: &'a BTreeMap<String, Value>,
+    expires: &'a BTreeMap<String, i64>,
+    options: &'a CollectionOptions,
+}
+
+// Per-key versions, saved next to the data file
+// Versions come from one counter per keyspace, so a key that is deleted and
+// written again never gets a version it had before
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Versions {
+    last: u64, // the highest version handed out so far
+    pub(crate) keys: HashMap<String, u64>,
+}
+
+impl Versions {
+    fn bump(&mut self, key: &str) -> u64 {
+        self.last += 1;
+        self.keys.insert(key.to_string(), self.last);
+        self.last
+    }
+
+    // After loading: drop versions of keys that are gone and give keys
+    // without one (e.g. from a file written before versions existed) a new one
+    fn reconcile(&mut self, store: &BTreeMap<String, Value>) {
+        self.keys.retain(|key, _| store.contains_key(key));
+        for key in store.keys() {
+            if !self.keys.contains_key(key) {
+                self.bump(key);
+            }
+        }
+    }
+}
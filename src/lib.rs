@@ -2,14 +2,71 @@
 
 // Declare our modules (each corresponds to a .rs file)
 
+mod changelog;
+#[cfg(feature = "json")]
+pub mod client;
+pub mod collection;
 pub mod condition;
 pub mod database;
+#[cfg(feature = "json")]
+pub mod filter;
+pub mod fulltext;
+pub mod geo;
+mod glob;
+#[cfg(feature = "json")]
+pub mod http;
+pub mod index;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod keyspace;
+pub mod patch;
+pub mod planner;
+pub mod pubsub;
+pub mod ql;
+pub mod query;
+pub mod raft;
+pub mod replication;
+pub mod resp;
+pub mod schema;
+pub mod server;
+pub mod shard;
 pub mod storage;
+pub mod store;
+pub mod transaction;
+pub mod typed;
+pub mod types;
 pub mod value;
+pub mod vector;
+pub mod watch;
 
 // Re-export commonly used types for convenience
 // This allows users to write: use littledb::Database instead of use littledb::database::Database
+#[cfg(feature = "json")]
+pub use client::{Client, ClientOptions};
+pub use collection::{Collection, CollectionStats};
 pub use condition::Condition;
 pub use database::Database;
+#[cfg(feature = "json")]
+pub use filter::FilterError;
+pub use fulltext::{Analyzer, SearchHit};
+pub use geo::{GeoField, GeoPoint};
+pub use index::IndexStats;
+#[cfg(feature = "json")]
+pub use json::JsonError;
+pub use keyspace::CollectionOptions;
+pub use patch::{PatchError, PatchOp};
+pub use planner::QueryPlan;
+pub use pubsub::{Message, Overflow, PubSub, Subscriber};
+pub use ql::{QueryError, QueryOutput};
+pub use query::{QueryOptions, SortOrder};
+pub use replication::{ReplicationHandle, ReplicationStats};
+pub use schema::{Schema, SchemaType, ValidationError};
+pub use shard::ShardedDatabase;
 pub use storage::StorageEngine;
+pub use store::{Store, StoreError};
+pub use transaction::{Transaction, TransactionError};
+pub use typed::{TypedError, from_value, to_value};
+pub use types::{Decimal, Uuid};
 pub use value::Value;
+pub use vector::{Metric, Neighbor, VectorIndexOptions};
+pub use watch::{KeyChange, WatchError, WatchEvent, WatchFilter, Watcher};
@@ -0,0 +1,545 @@
+// NEW: Partial updates - change part of a value without rewriting it
+//
+//   db.patch("user:1", vec![
+//       PatchOp::Set("address.city".to_string(), Value::String("Berlin".to_string())),
+//       PatchOp::Increment("visits".to_string(), Value::Integer(1)),
+//       PatchOp::Push("tags".to_string(), Value::String("admin".to_string())),
+//   ])?;
+//
+// Three flavours of operation can be mixed in one patch:
+//   - Field operations (Set, Unset, Increment, Push, Pull, Rename) take dotted
+//     paths, "address.zip" or "tags.0". Missing parents are created, and
+//     removing something that isn't there does nothing.
+//   - RFC 6902 JSON Patch operations (Add, Remove, Replace, Move, Copy, Test)
+//     take JSON Pointers, "/address/zip" or "/tags/-", and follow the RFC:
+//     the target's parent must exist, Remove/Replace need an existing target,
+//     and a failed Test rejects the whole patch.
+//   - Merge is an RFC 7386 merge patch: objects merge recursively, null
+//     removes a field, anything else replaces.
+//
+// A patch applies all-or-nothing: operations run in order on a copy, and the
+// value is only replaced if every one of them succeeds.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::Value;
+use crate::schema::ValidationError;
+use crate::value::compare_numbers;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOp {
+    Set(String, Value),       // set the field, creating missing parents
+    Unset(String),            // remove the field (or array element)
+    Increment(String, Value), // add a number; a missing field starts at 0
+    Push(String, Value),      // append to an array; a missing field becomes [value]
+    Pull(String, Value),      // remove every array element equal to the value
+    Rename(String, String),   // move a field to a new path
+
+    Add(String, Value),
+    Remove(String),
+    Replace(String, Value),
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test(String, Value),
+
+    Merge(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    KeyNotFound(String),
+    // A path that has to exist doesn't, e.g. Remove("/missing")
+    PathNotFound(String),
+    // The operation doesn't fit what's at the path, e.g. Push onto a string
+    Conflict { path: String, message: String },
+    // A Test operation saw a different value
+    TestFailed(String),
+    // A JSON Patch document that isn't well-formed
+    InvalidPatch(String),
+    // The patched value doesn't fit the key's schema
+    Invalid(ValidationError),
+    // The patch applied but saving the database failed
+    Io(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::KeyNotFound(key) => write!(f, "Key '{}' not found", key),
+            PatchError::PathNotFound(path) => write!(f, "path '{}' not found", path),
+            PatchError::Conflict { path, message } => write!(f, "at '{}': {}", path, message),
+            PatchError::TestFailed(path) => write!(f, "test failed at '{}'", path),
+            PatchError::InvalidPatch(message) => write!(f, "invalid patch: {}", message),
+            PatchError::Invalid(error) => write!(f, "{}", error),
+            PatchError::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+fn conflict(path: &str, message: String) -> PatchError {
+    PatchError::Conflict {
+        path: path.to_string(),
+        message,
+    }
+}
+
+// Apply a patch to a copy of `value`
+pub fn apply(value: &Value, ops: &[PatchOp]) -> Result<Value, PatchError> {
+    let mut document = value.clone();
+    for op in ops {
+        apply_op(&mut document, op)?;
+    }
+    Ok(document)
+}
+
+fn apply_op(document: &mut Value, op: &PatchOp) -> Result<(), PatchError> {
+    match op {
+        PatchOp::Set(path, value) => set(document, &dotted(path), value.clone(), path),
+        PatchOp::Unset(path) => {
+            let segments = dotted(path);
+            if segments.is_empty() {
+                return Err(conflict(path, "can't unset the whole value".to_string()));
+            }
+            if let Ok(parent) = parent(document, &segments, false, path) {
+                take(parent, segments.last().unwrap());
+            }
+            Ok(())
+        }
+        PatchOp::Increment(path, amount) => {
+            if !is_number(amount) {
+                return Err(conflict(
+                    path,
+                    format!("can't increment by a {}", amount.type_name()),
+                ));
+            }
+            let segments = dotted(path);
+            match lookup(document, &segments) {
+                Some(current) => {
+                    *current = add(current, amount)
+                        .ok_or_else(|| increment_error(current, amount, path))?;
+                    Ok(())
+                }
+                None => set(document, &segments, amount.clone(), path),
+            }
+        }
+        PatchOp::Push(path, value) => {
+            let segments = dotted(path);
+            match lookup(document, &segments) {
+                Some(Value::Array(items)) => {
+                    items.push(value.clone());
+                    Ok(())
+                }
+                Some(other) => Err(conflict(
+                    path,
+                    format!("can't push onto a {}", other.type_name()),
+                )),
+                None => set(document, &segments, Value::Array(vec![value.clone()]), path),
+            }
+        }
+        PatchOp::Pull(path, value) => match lookup(document, &dotted(path)) {
+            Some(Value::Array(items)) => {
+                items.retain(|item| item != value);
+                Ok(())
+            }
+            Some(other) => Err(conflict(
+                path,
+                format!("can't pull from a {}", other.type_name()),
+            )),
+            None => Ok(()),
+        },
+        PatchOp::Rename(from, to) => {
+            let source = dotted(from);
+            if source.is_empty() {
+                return Err(conflict(from, "can't rename the whole value".to_string()));
+            }
+            let value = match parent(document, &source, false, from) {
+                Ok(parent) => take(parent, source.last().unwrap()),
+                Err(_) => None,
+            };
+            match value {
+                Some(value) => set(document, &dotted(to), value, to),
+                None => Ok(()),
+            }
+        }
+
+        PatchOp::Add(path, value) => add_at(document, &pointer(path)?, value.clone(), path),
+        PatchOp::Remove(path) => {
+            remove_at(document, &pointer(path)?, path)?;
+            Ok(())
+        }
+        PatchOp::Replace(path, value) => {
+            let target = lookup(document, &pointer(path)?)
+                .ok_or_else(|| PatchError::PathNotFound(path.clone()))?;
+            *target = value.clone();
+            Ok(())
+        }
+        PatchOp::Move { from, path } => {
+            // A value can't be moved into itself
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(conflict(
+                    path,
+                    format!("can't move '{}' into one of its children", from),
+                ));
+            }
+            let value = remove_at(document, &pointer(from)?, from)?;
+            add_at(document, &pointer(path)?, value, path)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = lookup(document, &pointer(from)?)
+                .ok_or_else(|| PatchError::PathNotFound(from.clone()))?
+                .clone();
+            add_at(document, &pointer(path)?, value, path)
+        }
+        PatchOp::Test(path, expected) => match lookup(document, &pointer(path)?) {
+            Some(found) if test_equal(found, expected) => Ok(()),
+            _ => Err(PatchError::TestFailed(path.clone())),
+        },
+
+        PatchOp::Merge(patch) => {
+            merge(document, patch);
+            Ok(())
+        }
+    }
+}
+
+// RFC 6902 section 4.6: numbers are equal when their values are, whatever
+// their variant (1 and 1.0), and arrays and objects when their members are
+fn test_equal(found: &Value, expected: &Value) -> bool {
+    match (found, expected) {
+        (a, b) if is_number(a) && is_number(b) => compare_numbers(a, b).is_eq(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| test_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| test_equal(a, b)))
+        }
+        _ => found == expected,
+    }
+}
+
+// ---------- Paths ----------
+
+// "address.zip" -> ["address", "zip"]; "" is the value itself
+fn dotted(path: &str) -> Vec<String> {
+    if path.is_empty() {
+        Vec::new()
+    } else {
+        path.split('.').map(str::to_string).collect()
+    }
+}
+
+// RFC 6901 JSON Pointer: "/address/zip"; "~1" stands for '/' and "~0" for '~'
+fn pointer(path: &str) -> Result<Vec<String>, PatchError> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = path.strip_prefix('/') else {
+        return Err(PatchError::InvalidPatch(format!(
+            "JSON Pointer '{}' must start with '/'",
+            path
+        )));
+    };
+    Ok(rest
+        .split('/')
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+// Array positions are written in decimal; leading zeros and signs are not indexes
+fn index(segment: &str) -> Option<usize> {
+    if segment == "0" || (!segment.starts_with('0') && segment.bytes().all(|b| b.is_ascii_digit()))
+    {
+        segment.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn child<'a>(value: &'a mut Value, segment: &str) -> Option<&'a mut Value> {
+    match value {
+        Value::Object(fields) => fields.get_mut(segment),
+        Value::Array(items) => items.get_mut(index(segment)?),
+        _ => None,
+    }
+}
+
+fn lookup<'a>(value: &'a mut Value, segments: &[String]) -> Option<&'a mut Value> {
+    segments
+        .iter()
+        .try_fold(value, |current, segment| child(current, segment))
+}
+
+// The value holding the last segment. With `create`, missing (or null)
+// parents become empty objects, so Set("a.b.c") works on {}.
+fn parent<'a>(
+    value: &'a mut Value,
+    segments: &[String],
+    create: bool,
+    path: &str,
+) -> Result<&'a mut Value, PatchError> {
+    let mut current = value;
+    for segment in &segments[..segments.len().saturating_sub(1)] {
+        if create && matches!(current, Value::Null) {
+            *current = Value::Object(HashMap::new());
+        }
+        current = match current {
+            Value::Object(fields) => {
+                if create {
+                    fields
+                        .entry(segment.clone())
+                        .or_insert_with(|| Value::Object(HashMap::new()))
+                } else {
+                    fields
+                        .get_mut(segment)
+                        .ok_or_else(|| PatchError::PathNotFound(path.to_string()))?
+                }
+            }
+            Value::Array(items) => index(segment)
+                .and_then(|i| items.get_mut(i))
+                .ok_or_else(|| PatchError::PathNotFound(path.to_string()))?,
+            other => {
+                return Err(conflict(
+                    path,
+                    format!("'{}' is inside a {}", segment, other.type_name()),
+                ));
+            }
+        };
+    }
+    if create && matches!(current, Value::Null) {
+        *current = Value::Object(HashMap::new());
+    }
+    Ok(current)
+}
+
+fn take(parent: &mut Value, segment: &str) -> Option<Value> {
+    match parent {
+        Value::Object(fields) => fields.remove(segment),
+        Value::Array(items) => match index(segment) {
+            Some(i) if i < items.len() => Some(items.remove(i)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Field operation write: objects get the field, arrays get the element
+// replaced (or appended, at index == length)
+fn set(
+    document: &mut Value,
+    segments: &[String],
+    value: Value,
+    path: &str,
+) -> Result<(), PatchError> {
+    let Some(last) = segments.last() else {
+        *document = value;
+        return Ok(());
+    };
+    match parent(document, segments, true, path)? {
+        Value::Object(fields) => {
+            fields.insert(last.clone(), value);
+            Ok(())
+        }
+        Value::Array(items) => match index(last) {
+            Some(i) if i < items.len() => {
+                items[i] = value;
+                Ok(())
+            }
+            Some(i) if i == items.len() => {
+                items.push(value);
+                Ok(())
+            }
+            _ => Err(PatchError::PathNotFound(path.to_string())),
+        },
+        other => Err(conflict(
+            path,
+            format!("can't set a field on a {}", other.type_name()),
+        )),
+    }
+}
+
+// RFC 6902 "add": inserts into arrays ("-" appends), sets object members
+fn add_at(
+    document: &mut Value,
+    segments: &[String],
+    value: Value,
+    path: &str,
+) -> Result<(), PatchError> {
+    let Some(last) = segments.last() else {
+        *document = value;
+        return Ok(());
+    };
+    match parent(document, segments, false, path)? {
+        Value::Object(fields) => {
+            fields.insert(last.clone(), value);
+            Ok(())
+        }
+        Value::Array(items) if last == "-" => {
+            items.push(value);
+            Ok(())
+        }
+        Value::Array(items) => match index(last) {
+            Some(i) if i <= items.len() => {
+                items.insert(i, value);
+                Ok(())
+            }
+            _ => Err(PatchError::PathNotFound(path.to_string())),
+        },
+        other => Err(conflict(
+            path,
+            format!("can't add a member to a {}", other.type_name()),
+        )),
+    }
+}
+
+fn remove_at(document: &mut Value, segments: &[String], path: &str) -> Result<Value, PatchError> {
+    let Some(last) = segments.last() else {
+        return Err(conflict(path, "can't remove the whole value".to_string()));
+    };
+    let parent = parent(document, segments, false, path)?;
+    take(parent, last).ok_or_else(|| PatchError::PathNotFound(path.to_string()))
+}
+
+// RFC 7386: objects merge key by key, null deletes, anything else replaces
+fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(changes) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !matches!(target, Value::Object(_)) {
+        *target = Value::Object(HashMap::new());
+    }
+    if let Value::Object(fields) = target {
+        for (key, change) in changes {
+            if matches!(change, Value::Null) {
+                fields.remove(key);
+            } else {
+                merge(fields.entry(key.clone()).or_insert(Value::Null), change);
+            }
+        }
+    }
+}
+
+// ---------- Increment ----------
+
+fn is_number(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_)
+    )
+}
+
+// Integers stay integers (None on overflow), a Float on either side gives a
+// Float, and Decimals stay exact when added to Integers or Decimals
+pub(crate) fn add(current: &Value, amount: &Value) -> Option<Value> {
+    use crate::types::Decimal;
+    match (current, amount) {
+        (Value::Integer(a), Value::Integer(b)) => a.checked_add(*b).map(Value::Integer),
+        (Value::Integer(a), Value::Float(b)) => Some(Value::Float(*a as f64 + b)),
+        (Value::Float(a), Value::Integer(b)) => Some(Value::Float(a + *b as f64)),
+        (Value::Float(a), Value::Float(b)) => Some(Value::Float(a + b)),
+        (Value::Decimal(a), Value::Decimal(b)) => a.checked_add(b).map(Value::Decimal),
+        (Value::Decimal(a), Value::Integer(b)) => {
+            a.checked_add(&Decimal::from(*b)).map(Value::Decimal)
+        }
+        (Value::Integer(a), Value::Decimal(b)) => {
+            Decimal::from(*a).checked_add(b).map(Value::Decimal)
+        }
+        (Value::Decimal(a), Value::Float(b)) => Some(Value::Float(a.to_f64() + b)),
+        (Value::Float(a), Value::Decimal(b)) => Some(Value::Float(a + b.to_f64())),
+        _ => None,
+    }
+}
+
+fn increment_error(current: &Value, amount: &Value, path: &str) -> PatchError {
+    if is_number(current) {
+        conflict(path, format!("{} + {} overflows", current, amount))
+    } else {
+        conflict(path, format!("can't increment a {}", current.type_name()))
+    }
+}
+
+// ---------- JSON Patch documents ----------
+
+impl PatchOp {
+    // Read an RFC 6902 document that has already been turned into a Value:
+    //   [{"op": "replace", "path": "/name", "value": "Bob"},
+    //    {"op": "move", "from": "/old", "path": "/new"}]
+    pub fn from_json_patch(document: &Value) -> Result<Vec<PatchOp>, PatchError> {
+        let Value::Array(operations) = document else {
+            return Err(PatchError::InvalidPatch(
+                "a JSON Patch is an array of operations".to_string(),
+            ));
+        };
+        operations
+            .iter()
+            .enumerate()
+            .map(|(i, operation)| {
+                PatchOp::from_json_operation(operation).map_err(|message| {
+                    PatchError::InvalidPatch(format!("operation {}: {}", i, message))
+                })
+            })
+            .collect()
+    }
+
+    fn from_json_operation(operation: &Value) -> Result<PatchOp, String> {
+        let Value::Object(fields) = operation else {
+            return Err(format!(
+                "expected an object, found {}",
+                operation.type_name()
+            ));
+        };
+        let text = |name: &str| match fields.get(name) {
+            Some(Value::String(s)) => Ok(s.clone()),
+            Some(other) => Err(format!(
+                "\"{}\" must be a string, found {}",
+                name,
+                other.type_name()
+            )),
+            None => Err(format!("missing \"{}\"", name)),
+        };
+        let value = || {
+            fields
+                .get("value")
+                .cloned()
+                .ok_or_else(|| "missing \"value\"".to_string())
+        };
+        let path = text("path")?;
+        let op = match text("op")?.as_str() {
+            "add" => PatchOp::Add(path, value()?),
+            "remove" => PatchOp::Remove(path),
+            "replace" => PatchOp::Replace(path, value()?),
+            "move" => PatchOp::Move {
+                from: text("from")?,
+                path,
+            },
+            "copy" => PatchOp::Copy {
+                from: text("from")?,
+                path,
+            },
+            "test" => PatchOp::Test(path, value()?),
+            other => return Err(format!("unknown op '{}'", other)),
+        };
+        Ok(op)
+    }
+
+    // Parse JSON Patch text
+    #[cfg(feature = "json")]
+    pub fn parse_json_patch(text: &str) -> Result<Vec<PatchOp>, PatchError> {
+        let document =
+            Value::from_json_str(text).map_err(|e| PatchError::InvalidPatch(e.to_string()))?;
+        PatchOp::from_json_patch(&document)
+    }
+
+    // Parse RFC 7386 merge patch text
+    #[cfg(feature = "json")]
+    pub fn parse_merge_patch(text: &str) -> Result<PatchOp, PatchError> {
+        Value::from_json_str(text)
+            .map(PatchOp::Merge)
+            .map_err(|e| PatchError::InvalidPatch(e.to_string()))
+    }
+}
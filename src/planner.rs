@@ -0,0 +1,509 @@
+// NEW: Cost-based query planner
+//
+// For a Condition the planner picks how to find candidate keys:
+//   - FullScan:       look at every entry
+//   - KeyPrefixScan:  walk only the keys starting with a prefix (keys are sorted)
+//   - IndexLookup:    field = value through a secondary index
+//   - IndexRange:     integer (and decimal) range through a secondary index
+//   - TextSearch:     full-text query through a full-text index
+//   - GeoSearch:      locations inside an area through a location index
+//   - Intersection:   keys found by several index lookups at once (AND)
+//   - Union:          keys found by any of several lookups (OR)
+// Every candidate is then re-checked against the full condition (the Filter
+// step), so an index only ever narrows the search and never changes results.
+//
+// Costs are counted in "row units": fetching a value and checking it against
+// the condition costs 1, reading a key from an index or the key list costs
+// KEY_READ_COST. Keys are much cheaper than values, which is what makes an
+// intersection of two so-so indexes beat using either one alone.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Bound;
+
+use crate::geo::{GeoField, GeoShape};
+use crate::index::{Indexes, integer_bounds};
+use crate::{Condition, Value};
+
+// Used when a field has no index, so we have nothing better than a guess
+const DEFAULT_EQUALITY_SELECTIVITY: f64 = 0.1;
+const DEFAULT_RANGE_SELECTIVITY: f64 = 0.3;
+// Cost of reading one key relative to fetching and checking one value
+const KEY_READ_COST: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    FullScan,
+    KeyPrefixScan(String),
+    IndexLookup {
+        field: String,
+        value: Value,
+    },
+    IndexRange {
+        field: String,
+        low: Bound<i64>,
+        high: Bound<i64>,
+    },
+    TextSearch {
+        field: String,
+        query: String,
+    },
+    GeoSearch {
+        field: String,
+        shape: String,
+    },
+    Intersection,
+    Union,
+    Filter(String), // the condition every candidate is checked against
+}
+
+// One step of a plan; children feed their rows into their parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanNode {
+    pub operation: Operation,
+    pub estimated_rows: usize, // rows the planner expected this step to produce
+    pub actual_rows: usize,    // rows it really produced
+    pub children: Vec<PlanNode>,
+}
+
+// Result of Database::explain
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    pub root: PlanNode,
+    pub estimated_cost: f64,
+    pub rows_examined: usize, // values checked against the condition
+    pub rows_returned: usize,
+}
+
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "cost={:.1} examined={} returned={}",
+            self.estimated_cost, self.rows_examined, self.rows_returned
+        )?;
+        write_node(f, &self.root, 0)
+    }
+}
+
+fn write_node(f: &mut fmt::Formatter<'_>, node: &PlanNode, depth: usize) -> fmt::Result {
+    let label = match &node.operation {
+        Operation::FullScan => "FullScan".to_string(),
+        Operation::KeyPrefixScan(prefix) => format!("KeyPrefixScan '{}'", prefix),
+        Operation::IndexLookup { field, value } => {
+            format!("IndexLookup {} = {}", field, Condition::literal(value))
+        }
+        Operation::IndexRange { field, low, high } => {
+            let low = match *low {
+                Bound::Included(n) => format!("[{}", n),
+                Bound::Excluded(n) => format!("({}", n),
+                Bound::Unbounded => "(-inf".to_string(),
+            };
+            let high = match *high {
+                Bound::Included(n) => format!("{}]", n),
+                Bound::Excluded(n) => format!("{})", n),
+                Bound::Unbounded => "+inf)".to_string(),
+            };
+            format!("IndexRange {} in {}, {}", field, low, high)
+        }
+        Operation::TextSearch { field, query } => {
+            format!("TextSearch {} MATCHES '{}'", field, query)
+        }
+        Operation::GeoSearch { field, shape } => format!("GeoSearch {} {}", field, shape),
+        Operation::Intersection => "Intersection".to_string(),
+        Operation::Union => "Union".to_string(),
+        Operation::Filter(condition) => format!("Filter {}", condition),
+    };
+    let indent = if depth == 0 {
+        String::new()
+    } else {
+        format!("{}└─ ", "   ".repeat(depth - 1))
+    };
+    writeln!(
+        f,
+        "{}{}  (estimated rows: {}, actual rows: {})",
+        indent, label, node.estimated_rows, node.actual_rows
+    )?;
+    for child in &node.children {
+        write_node(f, child, depth + 1)?;
+    }
+    Ok(())
+}
+
+// Entries whose key starts with `prefix`, walking only that part of the sorted map
+pub(crate) fn scan_prefix<'a>(
+    data: &'a BTreeMap<String, Value>,
+    prefix: &'a str,
+) -> impl Iterator<Item = (&'a String, &'a Value)> {
+    data.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+        .take_while(move |(k, _)| k.starts_with(prefix))
+}
+
+// How to collect candidate keys, before running anything
+#[derive(Debug, Clone)]
+enum Access {
+    FullScan,
+    Prefix(String),
+    IndexEq(String, Value),
+    IndexRange(String, Bound<i64>, Bound<i64>),
+    TextSearch(String, String),
+    GeoSearch(GeoField, GeoShape),
+    Intersect(Vec<Candidate>),
+    Union(Vec<Candidate>),
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    access: Access,
+    rows: f64, // estimated keys produced
+    cost: f64, // estimated cost of producing them (before the Filter step)
+}
+
+pub struct Planner<'a> {
+    data: &'a BTreeMap<String, Value>,
+    indexes: &'a Indexes,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(data: &'a BTreeMap<String, Value>, indexes: &'a Indexes) -> Self {
+        Planner { data, indexes }
+    }
+
+    // Plan and run a query, returning the matching entries and the plan used
+    pub fn execute(&self, condition: &Condition) -> (Vec<(String, Value)>, QueryPlan) {
+        let total = self.data.len() as f64;
+        let full_scan = Candidate {
+            access: Access::FullScan,
+            rows: total,
+            cost: total,
+        };
+        // An access path pays for reading keys and then filtering what it found;
+        // a full scan pays for filtering every entry
+        let chosen = match self.access_for(condition) {
+            Some(candidate) if candidate.cost + candidate.rows < total => candidate,
+            _ => full_scan,
+        };
+        let estimated_cost = match chosen.access {
+            Access::FullScan => total,
+            _ => chosen.cost + chosen.rows,
+        };
+
+        let (keys, access_node) = self.run(&chosen);
+        let mut results = Vec::new();
+        let mut examined = 0;
+        // Full-text matches are checked with the same analyzer their index uses
+        let analyzer_for = |field: &str| self.indexes.analyzer_for(field);
+        match keys {
+            None => {
+                for (key, value) in self.data {
+                    examined += 1;
+                    if condition.matches_entry_with(key, value, &analyzer_for) {
+                        results.push((key.clone(), value.clone()));
+                    }
+                }
+            }
+            Some(keys) => {
+                for key in keys {
+                    if let Some(value) = self.data.get(&key) {
+                        examined += 1;
+                        if condition.matches_entry_with(&key, value, &analyzer_for) {
+                            results.push((key, value.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        let root = PlanNode {
+            operation: Operation::Filter(condition.to_string()),
+            estimated_rows: (total * self.selectivity(condition)).round() as usize,
+            actual_rows: results.len(),
+            children: vec![access_node],
+        };
+        let plan = QueryPlan {
+            root,
+            estimated_cost,
+            rows_examined: examined,
+            rows_returned: results.len(),
+        };
+        (results, plan)
+    }
+
+    // Best way to find candidates for this condition without a full scan (if any)
+    fn access_for(&self, condition: &Condition) -> Option<Candidate> {
+        match condition {
+            Condition::Equals(field, value) => {
+                let stats = self.indexes.get(field)?.stats(field);
+                // Arrays and objects are not in the index
+                crate::index::IndexKey::from_value(value)?;
+                let rows = stats.estimate_equal();
+                Some(Candidate {
+                    access: Access::IndexEq(field.clone(), value.clone()),
+                    rows,
+                    cost: rows * KEY_READ_COST,
+                })
+            }
+            // Decimals between n and n + 1 match too: the bound stays n
+            Condition::GreaterThan(field, n) => {
+                self.range_access(field, Bound::Excluded(*n), Bound::Unbounded)
+            }
+            Condition::LessThan(field, n) => {
+                self.range_access(field, Bound::Unbounded, Bound::Excluded(*n))
+            }
+            Condition::Between(field, low, high) => {
+                self.range_access(field, Bound::Included(*low), Bound::Included(*high))
+            }
+            Condition::Matches(field, query) => {
+                let rows = self.indexes.text(field)?.estimate(query);
+                Some(Candidate {
+                    access: Access::TextSearch(field.clone(), query.clone()),
+                    rows,
+                    cost: rows * KEY_READ_COST,
+                })
+            }
+            Condition::NearPoint(..) | Condition::WithinBox(..) | Condition::WithinPolygon(..) => {
+                let (field, shape) = condition.geo_shape()?;
+                let rows = self.indexes.geo(field)?.lookup(&shape).len() as f64;
+                Some(Candidate {
+                    access: Access::GeoSearch(field.clone(), shape),
+                    rows,
+                    cost: rows * KEY_READ_COST,
+                })
+            }
+            Condition::KeyPrefix(prefix) => {
+                let rows = self.prefix_count(prefix) as f64;
+                Some(Candidate {
+                    access: Access::Prefix(prefix.clone()),
+                    rows,
+                    cost: rows * KEY_READ_COST,
+                })
+            }
+            Condition::And(conditions) => self.and_access(conditions),
+            Condition::Or(conditions) => {
+                // Every branch needs an access path, otherwise we must scan anyway
+                let branches: Vec<Candidate> = conditions
+                    .iter()
+                    .map(|c| self.access_for(c))
+                    .collect::<Option<_>>()?;
+                if branches.is_empty() {
+                    return None;
+                }
+                let total = self.data.len() as f64;
+                let rows = branches.iter().map(|b| b.rows).sum::<f64>().min(total);
+                let cost = branches.iter().map(|b| b.cost).sum();
+                Some(Candidate {
+                    access: Access::Union(branches),
+                    rows,
+                    cost,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn range_access(&self, field: &str, low: Bound<i64>, high: Bound<i64>) -> Option<Candidate> {
+        let stats = self.indexes.get(field)?.stats(field);
+        let rows =
+            integer_bounds(low, high).map_or(0.0, |(low, high)| stats.estimate_range(low, high));
+        Some(Candidate {
+            access: Access::IndexRange(field.to_string(), low, high),
+            rows,
+            cost: rows * KEY_READ_COST,
+        })
+    }
+
+    // For AND, either use the most selective access path alone, or intersect
+    // all selective ones when that is expected to touch fewer rows
+    fn and_access(&self, conditions: &[Condition]) -> Option<Candidate> {
+        let mut options: Vec<Candidate> = conditions
+            .iter()
+            .filter_map(|c| self.access_for(c))
+            .collect();
+        options.sort_by(|a, b| (a.cost + a.rows).total_cmp(&(b.cost + b.rows)));
+        let best = options.first()?.clone();
+
+        let total = self.data.len() as f64;
+        let selective: Vec<Candidate> = options
+            .into_iter()
+            .filter(|o| total > 0.0 && o.rows <= total / 2.0)
+            .collect();
+        if selective.len() >= 2 {
+            // Assume the conditions are independent: selectivities multiply
+            let rows = selective
+                .iter()
+                .fold(total, |acc, o| acc * (o.rows / total));
+            let cost = selective.iter().map(|o| o.cost).sum::<f64>();
+            if cost + rows < best.cost + best.rows {
+                return Some(Candidate {
+                    access: Access::Intersect(selective),
+                    rows,
+                    cost,
+                });
+            }
+        }
+        Some(best)
+    }
+
+    fn prefix_count(&self, prefix: &str) -> usize {
+        scan_prefix(self.data, prefix).count()
+    }
+
+    // Run an access path: None means "every key" (full scan)
+    fn run(&self, candidate: &Candidate) -> (Option<BTreeSet<String>>, PlanNode) {
+        let estimated_rows = candidate.rows.round() as usize;
+        let node = |operation, keys: &BTreeSet<String>, children| PlanNode {
+            operation,
+            estimated_rows,
+            actual_rows: keys.len(),
+            children,
+        };
+
+        match &candidate.access {
+            Access::FullScan => {
+                let node = PlanNode {
+                    operation: Operation::FullScan,
+                    estimated_rows,
+                    actual_rows: self.data.len(),
+                    children: Vec::new(),
+                };
+                (None, node)
+            }
+            Access::Prefix(prefix) => {
+                let keys: BTreeSet<String> = scan_prefix(self.data, prefix)
+                    .map(|(k, _)| k.clone())
+                    .collect();
+                let node = node(Operation::KeyPrefixScan(prefix.clone()), &keys, Vec::new());
+                (Some(keys), node)
+            }
+            Access::IndexEq(field, value) => {
+                let keys = self
+                    .indexes
+                    .get(field)
+                    .map(|index| index.lookup(value))
+                    .unwrap_or_default();
+                let operation = Operation::IndexLookup {
+                    field: field.clone(),
+                    value: value.clone(),
+                };
+                let node = node(operation, &keys, Vec::new());
+                (Some(keys), node)
+            }
+            Access::IndexRange(field, low, high) => {
+                let keys = self
+                    .indexes
+                    .get(field)
+                    .map(|index| index.range(*low, *high))
+                    .unwrap_or_default();
+                let operation = Operation::IndexRange {
+                    field: field.clone(),
+                    low: *low,
+                    high: *high,
+                };
+                let node = node(operation, &keys, Vec::new());
+                (Some(keys), node)
+            }
+            Access::TextSearch(field, query) => {
+                let keys = self
+                    .indexes
+                    .text(field)
+                    .map(|index| index.lookup(query))
+                    .unwrap_or_default();
+                let operation = Operation::TextSearch {
+                    field: field.clone(),
+                    query: query.clone(),
+                };
+                let node = node(operation, &keys, Vec::new());
+                (Some(keys), node)
+            }
+            Access::GeoSearch(field, shape) => {
+                let keys = self
+                    .indexes
+                    .geo(field)
+                    .map(|index| index.lookup(shape))
+                    .unwrap_or_default();
+                let operation = Operation::GeoSearch {
+                    field: field.to_string(),
+                    shape: shape.to_string(),
+                };
+                let node = node(operation, &keys, Vec::new());
+                (Some(keys), node)
+            }
+            Access::Intersect(parts) | Access::Union(parts) => {
+                let is_union = matches!(candidate.access, Access::Union(_));
+                let mut children = Vec::new();
+                let mut combined: Option<BTreeSet<String>> = None;
+                for part in parts {
+                    let (keys, child) = self.run(part);
+                    children.push(child);
+                    let keys = keys.unwrap_or_default();
+                    combined = Some(match combined {
+                        None => keys,
+                        Some(acc) if is_union => acc.union(&keys).cloned().collect(),
+                        Some(acc) => acc.intersection(&keys).cloned().collect(),
+                    });
+                }
+                let keys = combined.unwrap_or_default();
+                let operation = if is_union {
+                    Operation::Union
+                } else {
+                    Operation::Intersection
+                };
+                let node = node(operation, &keys, children);
+                (Some(keys), node)
+            }
+        }
+    }
+
+    // Estimated fraction of all entries matching the condition
+    fn selectivity(&self, condition: &Condition) -> f64 {
+        let total = self.data.len() as f64;
+        if total == 0.0 {
+            return 0.0;
+        }
+        match condition {
+            Condition::Equals(field, _) => match self.indexes.get(field) {
+                Some(index) => index.stats(field).estimate_equal() / total,
+                None => DEFAULT_EQUALITY_SELECTIVITY,
+            },
+            Condition::GreaterThan(field, n) => {
+                self.range_selectivity(field, n.saturating_add(1), i64::MAX)
+            }
+            Condition::LessThan(field, n) => {
+                self.range_selectivity(field, i64::MIN, n.saturating_sub(1))
+            }
+            Condition::Between(field, low, high) => self.range_selectivity(field, *low, *high),
+            Condition::Matches(field, query) => match self.indexes.text(field) {
+                Some(index) => index.estimate(query) / total,
+                None => DEFAULT_EQUALITY_SELECTIVITY,
+            },
+            Condition::Contains(..) | Condition::Regex(..) => DEFAULT_EQUALITY_SELECTIVITY,
+            Condition::KeyPrefix(prefix) => self.prefix_count(prefix) as f64 / total,
+            // Queries mostly name fields their documents have
+            Condition::Exists(_) => 1.0,
+            Condition::NearPoint(..) | Condition::WithinBox(..) | Condition::WithinPolygon(..) => {
+                match condition
+                    .geo_shape()
+                    .and_then(|(field, shape)| Some(self.indexes.geo(field)?.lookup(&shape)))
+                {
+                    Some(keys) => keys.len() as f64 / total,
+                    None => DEFAULT_RANGE_SELECTIVITY,
+                }
+            }
+            Condition::And(conditions) => conditions.iter().map(|c| self.selectivity(c)).product(),
+            Condition::Or(conditions) => {
+                1.0 - conditions
+                    .iter()
+                    .map(|c| 1.0 - self.selectivity(c))
+                    .product::<f64>()
+            }
+            Condition::Not(inner) => 1.0 - self.selectivity(inner),
+        }
+    }
+
+    fn range_selectivity(&self, field: &str, low: i64, high: i64) -> f64 {
+        match self.indexes.get(field) {
+            Some(index) => index.stats(field).estimate_range(low, high) / self.data.len() as f64,
+            None => DEFAULT_RANGE_SELECTIVITY,
+        }
+    }
+}
@@ -0,0 +1,357 @@
+// NEW: Publish/subscribe - messages to named channels, fanned out to whoever
+// is listening
+//
+//   let news = db.subscriber();
+//   news.subscribe("news");
+//   news.psubscribe("news.*");                       // a glob pattern, as in KEYS
+//   let reached = db.publish("news", Value::String("hello".to_string()));
+//   // reached == 2: once per subscription
+//   let message = news.next_message();              // waits for one
+//   // Some(Message { channel: "news", pattern: None, payload: .. })
+//
+// Messages aren't stored: a message goes to the subscriptions there are when
+// it's published, and to nobody else. Publishing never waits for a
+// subscriber. Instead each subscriber has a queue of at most `capacity`
+// messages, and when a slow one's queue is full, its Overflow decides:
+//
+//   Drop        the messages that don't fit are lost (counted by dropped())
+//   Disconnect  the subscriber is cut off: it reads what's queued, then its
+//               subscriptions are gone (is_cut_off()). The RESP server does
+//               this to clients that don't keep up, as Redis does.
+//
+// A PubSub is a handle: clones share the channels. Every Database has one
+// (Database::pubsub), which the servers publish through without locking the
+// database.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::Value;
+use crate::glob::Glob;
+
+// The queue of a subscriber made with subscriber()
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+// What happens to a subscriber whose queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Drop,
+    Disconnect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub channel: String,
+    pub pattern: Option<String>, // the pattern subscription it came through
+    pub payload: Value,
+}
+
+#[derive(Clone, Default)]
+pub struct PubSub {
+    broker: Arc<Mutex<Broker>>,
+}
+
+#[derive(Default)]
+struct Broker {
+    next_id: u64,
+    queues: HashMap<u64, Queue>,
+    channels: HashMap<String, BTreeSet<u64>>, // channel -> subscribers
+    patterns: BTreeMap<String, (Glob, BTreeSet<u64>)>,
+}
+
+struct Queue {
+    sender: SyncSender<Message>,
+    overflow: Overflow,
+    state: Arc<QueueState>,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+}
+
+// What the publishers tell a subscriber
+#[derive(Default)]
+struct QueueState {
+    dropped: AtomicU64,
+    cut_off: AtomicBool,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        PubSub::default()
+    }
+
+    // Send `payload` to the subscribers of `channel` and of the patterns
+    // matching it; how many subscriptions it was queued for
+    pub fn publish(&self, channel: &str, payload: Value) -> usize {
+        let mut broker = self.lock();
+        let mut targets: Vec<(u64, Option<&String>)> = Vec::new();
+        if let Some(ids) = broker.channels.get(channel) {
+            targets.extend(ids.iter().map(|&id| (id, None)));
+        }
+        for (pattern, (glob, ids)) in &broker.patterns {
+            if glob.matches(channel) {
+                targets.extend(ids.iter().map(|&id| (id, Some(pattern))));
+            }
+        }
+
+        let mut reached = 0;
+        let mut gone = Vec::new();
+        for (id, pattern) in targets {
+            let queue = &broker.queues[&id];
+            let message = Message {
+                channel: channel.to_string(),
+                pattern: pattern.cloned(),
+                payload: payload.clone(),
+            };
+            match queue.sender.try_send(message) {
+                Ok(()) => reached += 1,
+                Err(TrySendError::Full(_)) => match queue.overflow {
+                    Overflow::Drop => {
+                        queue.state.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Overflow::Disconnect => {
+                        queue.state.cut_off.store(true, Ordering::Relaxed);
+                        gone.push(id);
+                    }
+                },
+                // The Subscriber was dropped
+                Err(TrySendError::Disconnected(_)) => gone.push(id),
+            }
+        }
+        for id in gone {
+            broker.remove(id);
+        }
+        reached
+    }
+
+    // A subscriber with no subscriptions yet, dropping what doesn't fit in
+    // a queue of DEFAULT_CAPACITY messages
+    pub fn subscriber(&self) -> Subscriber {
+        self.subscriber_with(DEFAULT_CAPACITY, Overflow::Drop)
+    }
+
+    pub fn subscriber_with(&self, capacity: usize, overflow: Overflow) -> Subscriber {
+        let (sender, messages) = mpsc::sync_channel(capacity.max(1));
+        let state = Arc::new(QueueState::default());
+        let mut broker = self.lock();
+        let id = broker.next_id;
+        broker.next_id += 1;
+        broker.queues.insert(
+            id,
+            Queue {
+                sender,
+                overflow,
+                state: Arc::clone(&state),
+                channels: BTreeSet::new(),
+                patterns: BTreeSet::new(),
+            },
+        );
+        Subscriber {
+            id,
+            pubsub: self.clone(),
+            messages: Mutex::new(messages),
+            state,
+        }
+    }
+
+    // The channels with subscribers (matching `pattern`, if given), sorted
+    pub fn channels(&self, pattern: Option<&str>) -> Vec<String> {
+        let glob = pattern.map(Glob::new);
+        let mut channels: Vec<String> = self
+            .lock()
+            .channels
+            .keys()
+            .filter(|channel| glob.as_ref().is_none_or(|glob| glob.matches(channel)))
+            .cloned()
+            .collect();
+        channels.sort();
+        channels
+    }
+
+    // Subscribers to `channel` itself (not counting patterns)
+    pub fn subscriber_count(&self, channel: &str) -> usize {
+        self.lock().channels.get(channel).map_or(0, BTreeSet::len)
+    }
+
+    // Patterns subscribed to, by anyone
+    pub fn pattern_count(&self) -> usize {
+        self.lock().patterns.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Broker> {
+        self.broker
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Broker {
+    // How many subscriptions the subscriber has afterwards; a subscriber
+    // that was cut off (or closed) has none and gets none
+    fn change(&mut self, id: u64, target: &str, pattern: bool, subscribe: bool) -> usize {
+        let Some(queue) = self.queues.get_mut(&id) else {
+            return 0;
+        };
+        let own = if pattern {
+            &mut queue.patterns
+        } else {
+            &mut queue.channels
+        };
+        let changed = if subscribe {
+            own.insert(target.to_string())
+        } else {
+            own.remove(target)
+        };
+        let count = queue.channels.len() + queue.patterns.len();
+        if changed {
+            match (pattern, subscribe) {
+                (false, true) => {
+                    self.channels
+                        .entry(target.to_string())
+                        .or_default()
+                        .insert(id);
+                }
+                (true, true) => {
+                    self.patterns
+                        .entry(target.to_string())
+                        .or_insert_with(|| (Glob::new(target), BTreeSet::new()))
+                        .1
+                        .insert(id);
+                }
+                (false, false) => self.leave_channel(id, target),
+                (true, false) => self.leave_pattern(id, target),
+            }
+        }
+        count
+    }
+
+    fn remove(&mut self, id: u64) {
+        let Some(queue) = self.queues.remove(&id) else {
+            return;
+        };
+        for channel in &queue.channels {
+            self.leave_channel(id, channel);
+        }
+        for pattern in &queue.patterns {
+            self.leave_pattern(id, pattern);
+        }
+    }
+
+    fn leave_channel(&mut self, id: u64, channel: &str) {
+        if let Some(ids) = self.channels.get_mut(channel) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.channels.remove(channel);
+            }
+        }
+    }
+
+    fn leave_pattern(&mut self, id: u64, pattern: &str) {
+        if let Some((_, ids)) = self.patterns.get_mut(pattern) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.patterns.remove(pattern);
+            }
+        }
+    }
+}
+
+// One receiver of messages, with its subscriptions. It can be shared between
+// threads (one reading messages, one changing subscriptions). Dropping it
+// ends its subscriptions.
+pub struct Subscriber {
+    id: u64,
+    pubsub: PubSub,
+    messages: Mutex<Receiver<Message>>,
+    state: Arc<QueueState>,
+}
+
+impl Subscriber {
+    // Each returns how many subscriptions (channels and patterns) there are
+    // afterwards
+    pub fn subscribe(&self, channel: &str) -> usize {
+        self.pubsub.lock().change(self.id, channel, false, true)
+    }
+
+    pub fn unsubscribe(&self, channel: &str) -> usize {
+        self.pubsub.lock().change(self.id, channel, false, false)
+    }
+
+    pub fn psubscribe(&self, pattern: &str) -> usize {
+        self.pubsub.lock().change(self.id, pattern, true, true)
+    }
+
+    pub fn punsubscribe(&self, pattern: &str) -> usize {
+        self.pubsub.lock().change(self.id, pattern, true, false)
+    }
+
+    pub fn channels(&self) -> Vec<String> {
+        self.subscriptions(|queue| &queue.channels)
+    }
+
+    pub fn patterns(&self) -> Vec<String> {
+        self.subscriptions(|queue| &queue.patterns)
+    }
+
+    // The next message, waiting for one; None once the subscriber was cut
+    // off or closed and its queue is empty
+    pub fn next_message(&self) -> Option<Message> {
+        self.lock_messages().recv().ok()
+    }
+
+    // The next message, if one is waiting
+    pub fn try_next(&self) -> Option<Message> {
+        self.lock_messages().try_recv().ok()
+    }
+
+    // The next message, waiting at most `timeout` for one
+    pub fn next_timeout(&self, timeout: Duration) -> Option<Message> {
+        self.lock_messages().recv_timeout(timeout).ok()
+    }
+
+    // Messages lost because the queue was full (Overflow::Drop)
+    pub fn dropped(&self) -> u64 {
+        self.state.dropped.load(Ordering::Relaxed)
+    }
+
+    // Whether it was cut off for falling behind (Overflow::Disconnect)
+    pub fn is_cut_off(&self) -> bool {
+        self.state.cut_off.load(Ordering::Relaxed)
+    }
+
+    // End every subscription; the messages already queued can still be read
+    pub fn close(&self) {
+        self.pubsub.lock().remove(self.id);
+    }
+
+    fn subscriptions(&self, own: impl Fn(&Queue) -> &BTreeSet<String>) -> Vec<String> {
+        let broker = self.pubsub.lock();
+        broker
+            .queues
+            .get(&self.id)
+            .map_or_else(Vec::new, |queue| own(queue).iter().cloned().collect())
+    }
+
+    fn lock_messages(&self) -> MutexGuard<'_, Receiver<Message>> {
+        self.messages
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Iterating waits for each next message
+impl Iterator for Subscriber {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        self.next_message()
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
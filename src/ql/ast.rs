@@ -0,0 +1,105 @@
+// Abstract syntax tree produced by the parser
+
+use super::Position;
+use crate::Value;
+use crate::geo::{GeoField, GeoPoint};
+use crate::query::SortOrder;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(Select),
+    Explain(Select), // EXPLAIN SELECT ...: show the query plan
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+}
+
+// SELECT name, age FROM 'user:' WHERE age > 28 ORDER BY age DESC LIMIT 10 OFFSET 5
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub projection: Projection,
+    pub source: String, // key prefix the statement applies to
+    pub filter: Option<Expr>,
+    pub order_by: Vec<(String, SortOrder)>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection {
+    All,                 // SELECT *
+    Fields(Vec<String>), // SELECT name, age
+}
+
+// INSERT INTO 'user:4' VALUE { name: 'Dan', age: 41 }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub key: String,
+    pub value: Value,
+}
+
+// UPDATE 'user:' SET age = 31, active = false WHERE name = 'Alice'
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub source: String,
+    pub assignments: Vec<(String, Value)>,
+    pub filter: Option<Expr>,
+}
+
+// DELETE FROM 'user:' WHERE active = false
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub source: String,
+    pub filter: Option<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+// WHERE clause expressions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        field: String,
+        op: CompareOp,
+        value: Value,
+        position: Position, // position of the value, used for type errors
+    },
+    Contains {
+        field: String,
+        substring: String,
+    },
+    Matches {
+        field: String,
+        query: String, // full-text query
+    },
+    Between {
+        field: String,
+        low: i64,
+        high: i64,
+    },
+    Near {
+        field: GeoField,
+        center: GeoPoint,
+        radius: f64, // meters
+    },
+    WithinBox {
+        field: GeoField,
+        sw: GeoPoint,
+        ne: GeoPoint,
+    },
+    WithinPolygon {
+        field: GeoField,
+        vertices: Vec<GeoPoint>,
+    },
+}
@@ -0,0 +1,365 @@
+// Lexer: turns query text into a list of tokens, each tagged with its position
+
+use super::{Position, QueryError, QueryErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Select,
+    Explain,
+    From,
+    Where,
+    And,
+    Or,
+    Not,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Limit,
+    Offset,
+    Insert,
+    Into,
+    Value,
+    Values,
+    Update,
+    Set,
+    Delete,
+    Contains,
+    Matches,
+    Between,
+    Near,
+    Within,
+    Box,
+    Polygon,
+    True,
+    False,
+    Null,
+}
+
+impl Keyword {
+    // Keywords are case-insensitive: SELECT, select and Select are the same
+    fn from_word(word: &str) -> Option<Keyword> {
+        let keyword = match word.to_ascii_uppercase().as_str() {
+            "SELECT" => Keyword::Select,
+            "EXPLAIN" => Keyword::Explain,
+            "FROM" => Keyword::From,
+            "WHERE" => Keyword::Where,
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "NOT" => Keyword::Not,
+            "ORDER" => Keyword::Order,
+            "BY" => Keyword::By,
+            "ASC" => Keyword::Asc,
+            "DESC" => Keyword::Desc,
+            "LIMIT" => Keyword::Limit,
+            "OFFSET" => Keyword::Offset,
+            "INSERT" => Keyword::Insert,
+            "INTO" => Keyword::Into,
+            "VALUE" => Keyword::Value,
+            "VALUES" => Keyword::Values,
+            "UPDATE" => Keyword::Update,
+            "SET" => Keyword::Set,
+            "DELETE" => Keyword::Delete,
+            "CONTAINS" => Keyword::Contains,
+            "MATCHES" => Keyword::Matches,
+            "BETWEEN" => Keyword::Between,
+            "NEAR" => Keyword::Near,
+            "WITHIN" => Keyword::Within,
+            "BOX" => Keyword::Box,
+            "POLYGON" => Keyword::Polygon,
+            "TRUE" => Keyword::True,
+            "FALSE" => Keyword::False,
+            "NULL" => Keyword::Null,
+            _ => return None,
+        };
+        Some(keyword)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Ident(String),  // field name: bare word or `backtick quoted`
+    String(String), // 'single' or "double" quoted string literal
+    Integer(i64),
+    Float(f64),
+    Comma,
+    Colon,
+    Semicolon,
+    Star,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Eq,    // =
+    NotEq, // != or <>
+    Lt,    // <
+    LtEq,  // <=
+    Gt,    // >
+    GtEq,  // >=
+    Eof,
+}
+
+impl TokenKind {
+    // Human readable form used in "expected X, found Y" messages
+    pub fn describe(&self) -> String {
+        match self {
+            TokenKind::Keyword(k) => format!("keyword {}", format!("{:?}", k).to_uppercase()),
+            TokenKind::Ident(name) => format!("identifier '{}'", name),
+            TokenKind::String(s) => format!("string \"{}\"", s),
+            TokenKind::Integer(i) => format!("integer {}", i),
+            TokenKind::Float(f) => format!("float {}", f),
+            TokenKind::Comma => "','".to_string(),
+            TokenKind::Colon => "':'".to_string(),
+            TokenKind::Semicolon => "';'".to_string(),
+            TokenKind::Star => "'*'".to_string(),
+            TokenKind::LParen => "'('".to_string(),
+            TokenKind::RParen => "')'".to_string(),
+            TokenKind::LBracket => "'['".to_string(),
+            TokenKind::RBracket => "']'".to_string(),
+            TokenKind::LBrace => "'{'".to_string(),
+            TokenKind::RBrace => "'}'".to_string(),
+            TokenKind::Eq => "'='".to_string(),
+            TokenKind::NotEq => "'!='".to_string(),
+            TokenKind::Lt => "'<'".to_string(),
+            TokenKind::LtEq => "'<='".to_string(),
+            TokenKind::Gt => "'>'".to_string(),
+            TokenKind::GtEq => "'>='".to_string(),
+            TokenKind::Eof => "end of input".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub position: Position,
+}
+
+pub struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    offset: usize, // byte offset of the next character
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer {
+            chars: input.chars().peekable(),
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    // Tokenize the whole input; the last token is always Eof
+    pub fn tokenize(mut self) -> Result<Vec<Token>, QueryError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            let done = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if done {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            offset: self.offset,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next_token(&mut self) -> Result<Token, QueryError> {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+
+        let position = self.position();
+        let Some(c) = self.peek() else {
+            return Ok(Token {
+                kind: TokenKind::Eof,
+                position,
+            });
+        };
+
+        let kind = match c {
+            '\'' | '"' => self.string(c, position)?,
+            '`' => self.quoted_ident(position)?,
+            '0'..='9' | '-' => self.number(position)?,
+            c if c.is_alphabetic() || c == '_' => self.word(),
+            _ => {
+                self.bump();
+                match c {
+                    ',' => TokenKind::Comma,
+                    ':' => TokenKind::Colon,
+                    ';' => TokenKind::Semicolon,
+                    '*' => TokenKind::Star,
+                    '(' => TokenKind::LParen,
+                    ')' => TokenKind::RParen,
+                    '[' => TokenKind::LBracket,
+                    ']' => TokenKind::RBracket,
+                    '{' => TokenKind::LBrace,
+                    '}' => TokenKind::RBrace,
+                    '=' => TokenKind::Eq,
+                    '!' if self.peek() == Some('=') => {
+                        self.bump();
+                        TokenKind::NotEq
+                    }
+                    '<' => match self.peek() {
+                        Some('=') => {
+                            self.bump();
+                            TokenKind::LtEq
+                        }
+                        Some('>') => {
+                            self.bump();
+                            TokenKind::NotEq
+                        }
+                        _ => TokenKind::Lt,
+                    },
+                    '>' => {
+                        if self.peek() == Some('=') {
+                            self.bump();
+                            TokenKind::GtEq
+                        } else {
+                            TokenKind::Gt
+                        }
+                    }
+                    other => {
+                        return Err(QueryError::new(
+                            QueryErrorKind::UnexpectedCharacter(other),
+                            position,
+                        ));
+                    }
+                }
+            }
+        };
+
+        Ok(Token { kind, position })
+    }
+
+    fn word(&mut self) -> TokenKind {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        match Keyword::from_word(&word) {
+            Some(keyword) => TokenKind::Keyword(keyword),
+            None => TokenKind::Ident(word),
+        }
+    }
+
+    fn quoted_ident(&mut self, start: Position) -> Result<TokenKind, QueryError> {
+        self.bump(); // opening backtick
+        let mut name = String::new();
+        loop {
+            match self.bump() {
+                Some('`') => return Ok(TokenKind::Ident(name)),
+                Some(c) => name.push(c),
+                None => {
+                    return Err(QueryError::new(QueryErrorKind::UnterminatedString, start));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self, quote: char, start: Position) -> Result<TokenKind, QueryError> {
+        self.bump(); // opening quote
+        let mut value = String::new();
+        loop {
+            let escape_position = self.position();
+            match self.bump() {
+                Some(c) if c == quote => return Ok(TokenKind::String(value)),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('\'') => '\'',
+                        Some('"') => '"',
+                        Some(other) => {
+                            return Err(QueryError::new(
+                                QueryErrorKind::InvalidEscape(other),
+                                escape_position,
+                            ));
+                        }
+                        None => {
+                            return Err(QueryError::new(QueryErrorKind::UnterminatedString, start));
+                        }
+                    };
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+                None => return Err(QueryError::new(QueryErrorKind::UnterminatedString, start)),
+            }
+        }
+    }
+
+    fn number(&mut self, start: Position) -> Result<TokenKind, QueryError> {
+        let mut text = String::new();
+        if self.peek() == Some('-') {
+            text.push('-');
+            self.bump();
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(QueryError::new(
+                    QueryErrorKind::UnexpectedCharacter('-'),
+                    start,
+                ));
+            }
+        }
+
+        let mut is_float = false;
+        while let Some(c) = self.peek() {
+            match c {
+                '0'..='9' => text.push(c),
+                '.' | 'e' | 'E' => {
+                    is_float = true;
+                    text.push(c);
+                }
+                '+' | '-' if text.ends_with(['e', 'E']) => text.push(c),
+                _ => break,
+            }
+            self.bump();
+        }
+
+        let invalid = || QueryError::new(QueryErrorKind::InvalidNumber(text.clone()), start);
+        if is_float {
+            text.parse::<f64>()
+                .map(TokenKind::Float)
+                .map_err(|_| invalid())
+        } else {
+            text.parse::<i64>()
+                .map(TokenKind::Integer)
+                .map_err(|_| invalid())
+        }
+    }
+}
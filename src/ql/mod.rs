@@ -0,0 +1,338 @@
+// NEW: A small SQL-like query language
+//
+//   SELECT name, age FROM 'user:' WHERE age > 28 AND active = true ORDER BY age DESC LIMIT 10
+//   INSERT INTO 'user:4' VALUE { name: 'Dan', age: 41, active: true }
+//   UPDATE 'user:' SET active = false WHERE age < 30
+//   DELETE FROM 'product:' WHERE name CONTAINS 'Mouse'
+//   SELECT * FROM 'post:' WHERE body MATCHES 'rust OR "key value"'
+//   EXPLAIN SELECT * FROM 'user:' WHERE age > 28
+//
+// The string after FROM / INTO / UPDATE is a key prefix ('' means every key).
+// A comparison only matches rows that have the field, as NULL never compares
+// true in SQL: `status != 'done'` skips rows without a status (NOT (status =
+// 'done') doesn't).
+// <, >, <= and >= compare with integers only, like Condition::GreaterThan:
+// `price > 9.5` is a type error, and fields holding floats never match.
+// Text goes through the lexer (tokens), the parser (AST) and is then compiled
+// to a Condition plus QueryOptions, which the Database already knows how to run.
+
+pub mod ast;
+pub mod lexer;
+pub mod parser;
+
+use std::fmt;
+
+use crate::planner::QueryPlan;
+use crate::query::QueryOptions;
+use crate::{Condition, Database, Value};
+use ast::{CompareOp, Expr, Projection, Select, Statement};
+
+// Where in the query text something happened (line and column start at 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub offset: usize, // byte offset into the query string
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    InvalidEscape(char),
+    InvalidNumber(String),
+    UnexpectedToken { expected: String, found: String },
+    UnexpectedEnd { expected: String },
+    TypeMismatch(String),
+    Execution(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    pub position: Option<Position>, // None for errors raised while running the statement
+}
+
+impl QueryError {
+    pub fn new(kind: QueryErrorKind, position: Position) -> Self {
+        QueryError {
+            kind,
+            position: Some(position),
+        }
+    }
+
+    fn execution(message: String) -> Self {
+        QueryError {
+            kind: QueryErrorKind::Execution(message),
+            position: None,
+        }
+    }
+}
+
+impl fmt::Display for QueryErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            QueryErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            QueryErrorKind::InvalidEscape(c) => write!(f, "invalid escape sequence '\\{}'", c),
+            QueryErrorKind::InvalidNumber(text) => write!(f, "invalid number '{}'", text),
+            QueryErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            QueryErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "expected {}, found end of input", expected)
+            }
+            QueryErrorKind::TypeMismatch(message) => write!(f, "type error: {}", message),
+            QueryErrorKind::Execution(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(p) => write!(f, "line {}, column {}: {}", p.line, p.column, self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+// Result of running a statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryOutput {
+    Rows(Vec<(String, Value)>), // SELECT
+    Affected(usize),            // INSERT / UPDATE / DELETE: number of keys written
+    Plan(QueryPlan),            // EXPLAIN
+}
+
+// Parse query text into a statement without running it
+pub fn parse(input: &str) -> Result<Statement, QueryError> {
+    let tokens = lexer::Lexer::new(input).tokenize()?;
+    parser::Parser::new(tokens).parse_statement()
+}
+
+// Parse and run a statement against the database
+pub fn execute(db: &mut Database, input: &str) -> Result<QueryOutput, QueryError> {
+    match parse(input)? {
+        Statement::Select(select) => {
+            let (condition, options) = compile_select(&select)?;
+            Ok(QueryOutput::Rows(db.query_with(condition, &options)))
+        }
+        Statement::Explain(select) => {
+            let (condition, _options) = compile_select(&select)?;
+            Ok(QueryOutput::Plan(db.explain(condition)))
+        }
+        Statement::Insert(insert) => {
+            db.insert(insert.key, insert.value)
+                .map_err(|e| QueryError::execution(e.to_string()))?;
+            Ok(QueryOutput::Affected(1))
+        }
+        Statement::Update(update) => {
+            let condition = compile_source(&update.source, update.filter.as_ref())?;
+
+            // Build every new value first so a bad row leaves nothing half-updated
+            let mut updated = Vec::new();
+            for (key, value) in db.query(condition) {
+                let Value::Object(mut fields) = value else {
+                    return Err(QueryError::execution(format!(
+                        "cannot SET fields on {} value at key '{}'",
+                        value.type_name(),
+                        key
+                    )));
+                };
+                for (field, new_value) in &update.assignments {
+                    fields.insert(field.clone(), new_value.clone());
+                }
+                updated.push((key, Value::Object(fields)));
+            }
+
+            db.batch_insert(updated)
+                .map(QueryOutput::Affected)
+                .map_err(|e| QueryError::execution(e.to_string()))
+        }
+        Statement::Delete(delete) => {
+            let condition = compile_source(&delete.source, delete.filter.as_ref())?;
+            let keys: Vec<String> = db.query(condition).into_iter().map(|(k, _)| k).collect();
+            let deleted = db.batch_delete(keys.iter().map(|k| k.as_str()).collect());
+            db.save_if_auto()
+                .map_err(|e| QueryError::execution(e.to_string()))?;
+            Ok(QueryOutput::Affected(deleted))
+        }
+    }
+}
+
+// Compile a SELECT into the condition and options Database::query_with takes
+pub fn compile_select(select: &Select) -> Result<(Condition, QueryOptions), QueryError> {
+    let condition = compile_source(&select.source, select.filter.as_ref())?;
+    let options = QueryOptions {
+        order_by: select.order_by.clone(),
+        offset: select.offset.unwrap_or(0),
+        limit: select.limit,
+        fields: match &select.projection {
+            Projection::All => None,
+            Projection::Fields(fields) => Some(fields.clone()),
+        },
+        near: None,
+    };
+    Ok((condition, options))
+}
+
+// FROM 'prefix' WHERE expr  =>  And([KeyPrefix(prefix), expr])
+fn compile_source(source: &str, filter: Option<&Expr>) -> Result<Condition, QueryError> {
+    let mut conditions = Vec::new();
+    if !source.is_empty() {
+        conditions.push(Condition::KeyPrefix(source.to_string()));
+    }
+    if let Some(expr) = filter {
+        match compile_expr(expr)? {
+            // Keep one flat AND so the planner sees every term side by side
+            Condition::And(terms) => conditions.extend(terms),
+            condition => conditions.push(condition),
+        }
+    }
+    Ok(if conditions.len() == 1 {
+        conditions.remove(0)
+    } else {
+        Condition::And(conditions)
+    })
+}
+
+// Compile a WHERE expression into a Condition tree
+pub fn compile_expr(expr: &Expr) -> Result<Condition, QueryError> {
+    let condition = match expr {
+        Expr::And(terms) => {
+            Condition::And(terms.iter().map(compile_expr).collect::<Result<_, _>>()?)
+        }
+        Expr::Or(terms) => Condition::Or(terms.iter().map(compile_expr).collect::<Result<_, _>>()?),
+        Expr::Not(inner) => Condition::Not(Box::new(compile_expr(inner)?)),
+        Expr::Contains { field, substring } => {
+            Condition::Contains(field.clone(), substring.clone())
+        }
+        Expr::Matches { field, query } => Condition::Matches(field.clone(), query.clone()),
+        Expr::Between { field, low, high } => Condition::Between(field.clone(), *low, *high),
+        Expr::Near {
+            field,
+            center,
+            radius,
+        } => Condition::NearPoint(field.clone(), *center, *radius),
+        Expr::WithinBox { field, sw, ne } => Condition::WithinBox(field.clone(), *sw, *ne),
+        Expr::WithinPolygon { field, vertices } => {
+            Condition::WithinPolygon(field.clone(), vertices.clone())
+        }
+        Expr::Compare {
+            field,
+            op,
+            value,
+            position,
+        } => {
+            let field = field.clone();
+            match op {
+                CompareOp::Eq => Condition::Equals(field, value.clone()),
+                CompareOp::NotEq => Condition::And(vec![
+                    Condition::Exists(field.clone()),
+                    Condition::Not(Box::new(Condition::Equals(field, value.clone()))),
+                ]),
+                // Ordering comparisons work on integers, like Condition::GreaterThan
+                // <= and >= become an open-ended Between
+                _ => {
+                    let Some(n) = value.as_integer() else {
+                        return Err(QueryError::new(
+                            QueryErrorKind::TypeMismatch(format!(
+                                "'{}' can only be compared with an integer, found {}",
+                                field,
+                                value.type_name()
+                            )),
+                            *position,
+                        ));
+                    };
+                    match op {
+                        CompareOp::Lt => Condition::LessThan(field, n),
+                        CompareOp::Gt => Condition::GreaterThan(field, n),
+                        CompareOp::LtEq => Condition::Between(field, i64::MIN, n),
+                        _ => Condition::Between(field, n, i64::MAX),
+                    }
+                }
+            }
+        }
+    };
+    Ok(condition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(db: &mut Database, query: &str) -> Vec<(String, Value)> {
+        match execute(db, query) {
+            Ok(QueryOutput::Rows(rows)) => rows,
+            other => panic!("{}: {:?}", query, other),
+        }
+    }
+
+    fn keys(db: &mut Database, query: &str) -> Vec<String> {
+        rows(db, query).into_iter().map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn runs_statements_against_a_database() {
+        let mut db = Database::new("littledb-ql-unsaved.db");
+        db.set_auto_save(false);
+        for statement in [
+            "INSERT INTO 'user:1' VALUE { name: 'Ann', age: 31, status: 'active' }",
+            "INSERT INTO 'user:2' VALUE { name: 'Bob', age: 25, status: 'done' }",
+            "INSERT INTO 'user:3' VALUE { name: 'Cy', age: 40 }",
+            "INSERT INTO 'other' VALUE { name: 'Dee', age: 50 }",
+        ] {
+            assert_eq!(execute(&mut db, statement), Ok(QueryOutput::Affected(1)));
+        }
+
+        assert_eq!(
+            keys(
+                &mut db,
+                "SELECT * FROM 'user:' WHERE age >= 31 ORDER BY age DESC"
+            ),
+            vec!["user:3", "user:1"]
+        );
+        assert_eq!(
+            rows(
+                &mut db,
+                "SELECT name FROM '' WHERE age > 26 AND age < 45 ORDER BY name LIMIT 1 OFFSET 1"
+            ),
+            vec![(
+                "user:3".to_string(),
+                Value::Object([("name".to_string(), Value::String("Cy".to_string()))].into())
+            )]
+        );
+        // user:3 has no status: != skips it, NOT = doesn't
+        assert_eq!(
+            keys(&mut db, "SELECT * FROM 'user:' WHERE status != 'done'"),
+            vec!["user:1"]
+        );
+        assert_eq!(
+            keys(&mut db, "SELECT * FROM 'user:' WHERE NOT status = 'done'"),
+            vec!["user:1", "user:3"]
+        );
+
+        assert_eq!(
+            execute(&mut db, "UPDATE 'user:' SET status = 'done' WHERE age > 30"),
+            Ok(QueryOutput::Affected(2))
+        );
+        assert_eq!(
+            execute(&mut db, "DELETE FROM '' WHERE status = 'done'"),
+            Ok(QueryOutput::Affected(3))
+        );
+        assert_eq!(keys(&mut db, "SELECT * FROM ''"), vec!["other"]);
+        assert!(matches!(
+            execute(&mut db, "EXPLAIN SELECT * FROM '' WHERE age = 50"),
+            Ok(QueryOutput::Plan(plan)) if plan.rows_returned == 1
+        ));
+        assert!(execute(&mut db, "UPDATE 'other' SET age = 1 WHERE age < 'x'").is_err());
+        assert!(matches!(
+            execute(&mut db, "SELECT * FROM '' WHERE age > 9.5"),
+            Err(QueryError { kind: QueryErrorKind::TypeMismatch(message), .. })
+                if message.contains("found Float")
+        ));
+    }
+}
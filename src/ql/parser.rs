@@ -0,0 +1,603 @@
+// Recursive descent parser: turns tokens into a Statement
+//
+// Grammar (keywords are case-insensitive):
+//   statement  := ([EXPLAIN] select | insert | update | delete) [';']
+//   select     := SELECT ('*' | field {',' field}) FROM string [WHERE expr]
+//                 [ORDER BY field [ASC | DESC] {',' field [ASC | DESC]}]
+//                 [LIMIT integer] [OFFSET integer]
+//   insert     := INSERT INTO string (VALUE | VALUES) literal
+//   update     := UPDATE string SET field '=' literal {',' field '=' literal} [WHERE expr]
+//   delete     := DELETE FROM string [WHERE expr]
+//   expr       := and_expr {OR and_expr}
+//   and_expr   := unary {AND unary}
+//   unary      := NOT unary | '(' expr ')' | predicate
+//   predicate  := field ('=' | '!=' | '<>' | '<' | '<=' | '>' | '>=') literal
+//               | field CONTAINS string
+//               | field MATCHES string
+//               | field BETWEEN integer AND integer
+//               | geo_field NEAR point WITHIN number          (radius in meters)
+//               | geo_field WITHIN BOX point ',' point        (south-west, north-east)
+//               | geo_field WITHIN POLYGON '(' point {',' point} ')'
+//   geo_field  := field                  (an array field holding [lng, lat])
+//               | '(' field ',' field ')' (latitude and longitude fields)
+//   point      := '(' number ',' number ')' (latitude, longitude)
+//   literal    := string | number | TRUE | FALSE | NULL
+//               | '[' [literal {',' literal}] ']'
+//               | '{' [(field | string) ':' literal {',' ...}] '}'
+
+use std::collections::HashMap;
+
+use super::ast::{CompareOp, Delete, Expr, Insert, Projection, Select, Statement, Update};
+use super::lexer::{Keyword, Token, TokenKind};
+use super::{Position, QueryError, QueryErrorKind};
+use crate::Value;
+use crate::geo::{GeoField, GeoPoint};
+use crate::query::SortOrder;
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+}
+
+impl Parser {
+    // `tokens` must end with an Eof token (Lexer::tokenize guarantees this)
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Parser { tokens, current: 0 }
+    }
+
+    pub fn parse_statement(&mut self) -> Result<Statement, QueryError> {
+        let statement = match self.peek().kind {
+            TokenKind::Keyword(Keyword::Select) => Statement::Select(self.select()?),
+            TokenKind::Keyword(Keyword::Explain) => {
+                self.advance();
+                Statement::Explain(self.select()?)
+            }
+            TokenKind::Keyword(Keyword::Insert) => Statement::Insert(self.insert()?),
+            TokenKind::Keyword(Keyword::Update) => Statement::Update(self.update()?),
+            TokenKind::Keyword(Keyword::Delete) => Statement::Delete(self.delete()?),
+            _ => return Err(self.unexpected("SELECT, EXPLAIN, INSERT, UPDATE or DELETE")),
+        };
+
+        self.eat(&TokenKind::Semicolon);
+        if self.peek().kind != TokenKind::Eof {
+            return Err(self.unexpected("end of statement"));
+        }
+        Ok(statement)
+    }
+
+    fn select(&mut self) -> Result<Select, QueryError> {
+        self.expect_keyword(Keyword::Select)?;
+        let projection = if self.eat(&TokenKind::Star) {
+            Projection::All
+        } else {
+            let mut fields = vec![self.field()?];
+            while self.eat(&TokenKind::Comma) {
+                fields.push(self.field()?);
+            }
+            Projection::Fields(fields)
+        };
+
+        self.expect_keyword(Keyword::From)?;
+        let source = self.string()?;
+        let filter = self.where_clause()?;
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword(Keyword::Order) {
+            self.expect_keyword(Keyword::By)?;
+            loop {
+                let field = self.field()?;
+                let order = if self.eat_keyword(Keyword::Desc) {
+                    SortOrder::Descending
+                } else {
+                    self.eat_keyword(Keyword::Asc);
+                    SortOrder::Ascending
+                };
+                order_by.push((field, order));
+                if !self.eat(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let limit = if self.eat_keyword(Keyword::Limit) {
+            Some(self.count()?)
+        } else {
+            None
+        };
+        let offset = if self.eat_keyword(Keyword::Offset) {
+            Some(self.count()?)
+        } else {
+            None
+        };
+
+        Ok(Select {
+            projection,
+            source,
+            filter,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn insert(&mut self) -> Result<Insert, QueryError> {
+        self.expect_keyword(Keyword::Insert)?;
+        self.expect_keyword(Keyword::Into)?;
+        let key = self.string()?;
+        if !self.eat_keyword(Keyword::Value) && !self.eat_keyword(Keyword::Values) {
+            return Err(self.unexpected("VALUE"));
+        }
+        let value = self.literal()?;
+        Ok(Insert { key, value })
+    }
+
+    fn update(&mut self) -> Result<Update, QueryError> {
+        self.expect_keyword(Keyword::Update)?;
+        let source = self.string()?;
+        self.expect_keyword(Keyword::Set)?;
+
+        let mut assignments = Vec::new();
+        loop {
+            let field = self.field()?;
+            self.expect(&TokenKind::Eq)?;
+            assignments.push((field, self.literal()?));
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+
+        let filter = self.where_clause()?;
+        Ok(Update {
+            source,
+            assignments,
+            filter,
+        })
+    }
+
+    fn delete(&mut self) -> Result<Delete, QueryError> {
+        self.expect_keyword(Keyword::Delete)?;
+        self.expect_keyword(Keyword::From)?;
+        let source = self.string()?;
+        let filter = self.where_clause()?;
+        Ok(Delete { source, filter })
+    }
+
+    fn where_clause(&mut self) -> Result<Option<Expr>, QueryError> {
+        if self.eat_keyword(Keyword::Where) {
+            Ok(Some(self.expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, QueryError> {
+        let mut terms = vec![self.and_expr()?];
+        while self.eat_keyword(Keyword::Or) {
+            terms.push(self.and_expr()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Expr::Or(terms)
+        })
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, QueryError> {
+        let mut terms = vec![self.unary()?];
+        while self.eat_keyword(Keyword::And) {
+            terms.push(self.unary()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Expr::And(terms)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat_keyword(Keyword::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        // "(lat, lng) NEAR ..." starts like a parenthesized expression
+        if self.peek().kind == TokenKind::LParen && !self.at_geo_pair() {
+            self.advance();
+            let inner = self.expr()?;
+            self.expect(&TokenKind::RParen)?;
+            return Ok(inner);
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Expr, QueryError> {
+        if self.at_geo_pair() {
+            self.advance();
+            let lat = self.field()?;
+            self.expect(&TokenKind::Comma)?;
+            let lng = self.field()?;
+            self.expect(&TokenKind::RParen)?;
+            return self.geo_predicate(GeoField::LatLng { lat, lng });
+        }
+
+        let field = self.field()?;
+        if matches!(
+            self.peek().kind,
+            TokenKind::Keyword(Keyword::Near | Keyword::Within)
+        ) {
+            return self.geo_predicate(GeoField::Array(field));
+        }
+
+        if self.eat_keyword(Keyword::Contains) {
+            let substring = self.string()?;
+            return Ok(Expr::Contains { field, substring });
+        }
+        if self.eat_keyword(Keyword::Matches) {
+            let query = self.string()?;
+            return Ok(Expr::Matches { field, query });
+        }
+        if self.eat_keyword(Keyword::Between) {
+            let low = self.integer()?;
+            self.expect_keyword(Keyword::And)?;
+            let high = self.integer()?;
+            return Ok(Expr::Between { field, low, high });
+        }
+
+        let op = match self.peek().kind {
+            TokenKind::Eq => CompareOp::Eq,
+            TokenKind::NotEq => CompareOp::NotEq,
+            TokenKind::Lt => CompareOp::Lt,
+            TokenKind::LtEq => CompareOp::LtEq,
+            TokenKind::Gt => CompareOp::Gt,
+            TokenKind::GtEq => CompareOp::GtEq,
+            _ => {
+                return Err(self.unexpected(
+                    "comparison operator, CONTAINS, MATCHES, BETWEEN, NEAR or WITHIN",
+                ));
+            }
+        };
+        self.advance();
+
+        let position = self.peek().position;
+        let value = self.literal()?;
+        Ok(Expr::Compare {
+            field,
+            op,
+            value,
+            position,
+        })
+    }
+
+    fn geo_predicate(&mut self, field: GeoField) -> Result<Expr, QueryError> {
+        if self.eat_keyword(Keyword::Near) {
+            let center = self.point()?;
+            self.expect_keyword(Keyword::Within)?;
+            let radius = self.number()?;
+            return Ok(Expr::Near {
+                field,
+                center,
+                radius,
+            });
+        }
+        self.expect_keyword(Keyword::Within)?;
+        if self.eat_keyword(Keyword::Box) {
+            let sw = self.point()?;
+            self.expect(&TokenKind::Comma)?;
+            let ne = self.point()?;
+            return Ok(Expr::WithinBox { field, sw, ne });
+        }
+        if self.eat_keyword(Keyword::Polygon) {
+            self.expect(&TokenKind::LParen)?;
+            let mut vertices = vec![self.point()?];
+            while self.eat(&TokenKind::Comma) {
+                vertices.push(self.point()?);
+            }
+            self.expect(&TokenKind::RParen)?;
+            return Ok(Expr::WithinPolygon { field, vertices });
+        }
+        Err(self.unexpected("BOX or POLYGON"))
+    }
+
+    // '(' latitude ',' longitude ')'
+    fn point(&mut self) -> Result<GeoPoint, QueryError> {
+        self.expect(&TokenKind::LParen)?;
+        let lat = self.number()?;
+        self.expect(&TokenKind::Comma)?;
+        let lng = self.number()?;
+        self.expect(&TokenKind::RParen)?;
+        Ok(GeoPoint::new(lat, lng))
+    }
+
+    // Is the next thing "(field, ..." (a latitude/longitude field pair)?
+    fn at_geo_pair(&self) -> bool {
+        let kind = |offset: usize| self.tokens.get(self.current + offset).map(|t| &t.kind);
+        matches!(kind(0), Some(TokenKind::LParen))
+            && matches!(kind(1), Some(TokenKind::Ident(_)))
+            && matches!(kind(2), Some(TokenKind::Comma))
+    }
+
+    fn literal(&mut self) -> Result<Value, QueryError> {
+        let token = self.advance();
+        let value = match token.kind {
+            TokenKind::String(s) => Value::String(s),
+            TokenKind::Integer(i) => Value::Integer(i),
+            TokenKind::Float(f) => Value::Float(f),
+            TokenKind::Keyword(Keyword::True) => Value::Boolean(true),
+            TokenKind::Keyword(Keyword::False) => Value::Boolean(false),
+            TokenKind::Keyword(Keyword::Null) => Value::Null,
+            TokenKind::LBracket => {
+                let mut items = Vec::new();
+                if !self.eat(&TokenKind::RBracket) {
+                    loop {
+                        items.push(self.literal()?);
+                        if self.eat(&TokenKind::RBracket) {
+                            break;
+                        }
+                        self.expect(&TokenKind::Comma)?;
+                    }
+                }
+                Value::Array(items)
+            }
+            TokenKind::LBrace => {
+                let mut fields = HashMap::new();
+                if !self.eat(&TokenKind::RBrace) {
+                    loop {
+                        let name = match self.peek().kind.clone() {
+                            TokenKind::String(s) => {
+                                self.advance();
+                                s
+                            }
+                            _ => self.field()?,
+                        };
+                        self.expect(&TokenKind::Colon)?;
+                        fields.insert(name, self.literal()?);
+                        if self.eat(&TokenKind::RBrace) {
+                            break;
+                        }
+                        self.expect(&TokenKind::Comma)?;
+                    }
+                }
+                Value::Object(fields)
+            }
+            other => {
+                return Err(unexpected_token(
+                    "a value (string, number, TRUE, FALSE, NULL, array or object)",
+                    &other,
+                    token.position,
+                ));
+            }
+        };
+        Ok(value)
+    }
+
+    // A field name: bare identifier or `backtick quoted`
+    fn field(&mut self) -> Result<String, QueryError> {
+        match self.peek().kind.clone() {
+            TokenKind::Ident(name) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.unexpected("field name")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, QueryError> {
+        match self.peek().kind.clone() {
+            TokenKind::String(s) => {
+                self.advance();
+                Ok(s)
+            }
+            _ => Err(self.unexpected("string")),
+        }
+    }
+
+    fn integer(&mut self) -> Result<i64, QueryError> {
+        match self.peek().kind {
+            TokenKind::Integer(i) => {
+                self.advance();
+                Ok(i)
+            }
+            _ => Err(self.unexpected("integer")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, QueryError> {
+        match self.peek().kind {
+            TokenKind::Integer(i) => {
+                self.advance();
+                Ok(i as f64)
+            }
+            TokenKind::Float(f) => {
+                self.advance();
+                Ok(f)
+            }
+            _ => Err(self.unexpected("number")),
+        }
+    }
+
+    // Non-negative integer for LIMIT and OFFSET
+    fn count(&mut self) -> Result<usize, QueryError> {
+        let position = self.peek().position;
+        let n = self.integer()?;
+        usize::try_from(n).map_err(|_| {
+            QueryError::new(
+                QueryErrorKind::TypeMismatch(format!("expected a non-negative count, found {}", n)),
+                position,
+            )
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    // Consume the current token (Eof is never consumed, so we can't run off the end)
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.current].clone();
+        if token.kind != TokenKind::Eof {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if &self.peek().kind == kind {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: Keyword) -> bool {
+        self.eat(&TokenKind::Keyword(keyword))
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), QueryError> {
+        if self.eat(kind) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&kind.describe()))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: Keyword) -> Result<(), QueryError> {
+        self.expect(&TokenKind::Keyword(keyword))
+    }
+
+    fn unexpected(&self, expected: &str) -> QueryError {
+        let token = self.peek();
+        unexpected_token(expected, &token.kind, token.position)
+    }
+}
+
+fn unexpected_token(expected: &str, found: &TokenKind, position: Position) -> QueryError {
+    let kind = if *found == TokenKind::Eof {
+        QueryErrorKind::UnexpectedEnd {
+            expected: expected.to_string(),
+        }
+    } else {
+        QueryErrorKind::UnexpectedToken {
+            expected: expected.to_string(),
+            found: found.describe(),
+        }
+    };
+    QueryError::new(kind, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{compile_expr, compile_select, parse};
+    use super::*;
+    use crate::Condition;
+
+    fn filter(text: &str) -> Expr {
+        match parse(&format!("SELECT * FROM '' WHERE {}", text)) {
+            Ok(Statement::Select(select)) => select.filter.expect("a WHERE clause"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn condition(text: &str) -> Condition {
+        compile_expr(&filter(text)).unwrap()
+    }
+
+    // Type errors come from compiling the statement, the rest from parsing it
+    fn error_at(input: &str) -> (QueryErrorKind, usize, usize) {
+        let error = match parse(input) {
+            Ok(Statement::Select(select)) => compile_select(&select).map(|_| ()),
+            other => other.map(|_| ()),
+        }
+        .expect_err(input);
+        let position = error.position.expect("a position");
+        (error.kind, position.line, position.column)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let Expr::Or(terms) = filter("a = 1 OR b = 2 AND NOT c = 3") else {
+            panic!("OR at the top")
+        };
+        assert_eq!(terms.len(), 2);
+        assert!(matches!(&terms[0], Expr::Compare { field, .. } if field == "a"));
+        let Expr::And(and) = &terms[1] else {
+            panic!("AND under OR")
+        };
+        assert!(matches!(&and[1], Expr::Not(_)));
+
+        // Parentheses override it
+        let Expr::And(terms) = filter("(a = 1 OR b = 2) AND c = 3") else {
+            panic!("AND at the top")
+        };
+        assert!(matches!(&terms[0], Expr::Or(or) if or.len() == 2));
+    }
+
+    #[test]
+    fn comparisons_compile_to_conditions() {
+        assert!(matches!(
+            filter("age >= 18"),
+            Expr::Compare {
+                op: CompareOp::GtEq,
+                value: Value::Integer(18),
+                ..
+            }
+        ));
+        let text = |c: Condition| format!("{:?}", c);
+        assert_eq!(
+            text(condition("age <= 30")),
+            text(Condition::Between("age".to_string(), i64::MIN, 30))
+        );
+        assert_eq!(
+            text(condition("age >= 18")),
+            text(Condition::Between("age".to_string(), 18, i64::MAX))
+        );
+        // != also needs the field, <> is the same
+        let not_equal = text(Condition::And(vec![
+            Condition::Exists("status".to_string()),
+            Condition::Not(Box::new(Condition::Equals(
+                "status".to_string(),
+                Value::String("done".to_string()),
+            ))),
+        ]));
+        assert_eq!(text(condition("status != 'done'")), not_equal);
+        assert_eq!(text(condition("status <> 'done'")), not_equal);
+    }
+
+    #[test]
+    fn printed_conditions_parse_back() {
+        for text in [
+            "age > 28 AND active = true",
+            "a = 1 OR b = 2 AND NOT c = 'it\\'s'",
+            "(a < 5 OR b BETWEEN 1 AND 9) AND name CONTAINS 'x'",
+            "NOT (a = 1 AND b = 2) OR body MATCHES 'rust OR \"key value\"'",
+            "score = -1.5 OR tag = null",
+        ] {
+            let printed = condition(text).to_string();
+            assert_eq!(condition(&printed).to_string(), printed, "{}", text);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let (kind, line, column) = error_at("SELECT * FROM 'user:' WHERE age > 'old'");
+        assert!(matches!(kind, QueryErrorKind::TypeMismatch(_)));
+        assert_eq!((line, column), (1, 35));
+
+        let (kind, line, column) = error_at("SELECT * FROM 'user:'\nWHERE age >");
+        assert!(
+            matches!(kind, QueryErrorKind::UnexpectedEnd { expected } if expected.starts_with("a value"))
+        );
+        assert_eq!(line, 2);
+        assert_eq!(column, 12);
+
+        let (kind, line, column) = error_at("SELECT * FROM 'user:' WHERE name = 'Al");
+        assert_eq!(kind, QueryErrorKind::UnterminatedString);
+        assert_eq!((line, column), (1, 36));
+
+        let (kind, _, column) = error_at("SELECT * FROM 'x' WHERE a # 1");
+        assert_eq!(kind, QueryErrorKind::UnexpectedCharacter('#'));
+        assert_eq!(column, 27);
+
+        let (kind, _, column) = error_at("SELECT * FROM 'x' LIMIT 5 5");
+        assert!(matches!(kind, QueryErrorKind::UnexpectedToken { .. }));
+        assert_eq!(column, 27);
+    }
+}
@@ -0,0 +1,126 @@
+// Sorting, paging and projection applied on top of a filtered query
+// Used by Database::query_with and by the query language executor
+
+use crate::Value;
+use crate::geo::{self, GeoField, GeoPoint};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+// Options for Database::query_with
+// Built with chained calls: QueryOptions::new().order_by("age", SortOrder::Descending).limit(10)
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    pub order_by: Vec<(String, SortOrder)>, // sort keys, applied left to right
+    pub offset: usize,                      // number of results to skip
+    pub limit: Option<usize>,               // maximum number of results (None = all)
+    pub fields: Option<Vec<String>>,        // projection (None = whole value)
+    pub near: Option<(GeoField, GeoPoint)>, // sort by distance from a point, closest first
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        QueryOptions::default()
+    }
+
+    pub fn order_by(mut self, field: &str, order: SortOrder) -> Self {
+        self.order_by.push((field.to_string(), order));
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn select(mut self, fields: Vec<String>) -> Self {
+        self.fields = Some(fields);
+        self
+    }
+
+    pub fn order_by_distance(mut self, field: GeoField, point: GeoPoint) -> Self {
+        self.near = Some((field, point));
+        self
+    }
+
+    // Sort, page and project a list of query results
+    // Results are always sorted by key first so the output is deterministic,
+    // then by distance (if requested), then by the requested fields (the sort
+    // is stable, so order_by fields win and distance breaks their ties)
+    pub fn apply(&self, mut results: Vec<(String, Value)>) -> Vec<(String, Value)> {
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some((field, point)) = &self.near {
+            sort_by_distance(&mut results, field, point);
+        }
+        if !self.order_by.is_empty() {
+            results.sort_by(|(_, a), (_, b)| {
+                for (field, order) in &self.order_by {
+                    let ordering = compare_fields(a.get_field(field), b.get_field(field), *order);
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                Ordering::Equal
+            });
+        }
+
+        let page = results.into_iter().skip(self.offset);
+        let page: Vec<(String, Value)> = match self.limit {
+            Some(limit) => page.take(limit).collect(),
+            None => page.collect(),
+        };
+
+        match &self.fields {
+            Some(fields) => page
+                .into_iter()
+                .map(|(key, value)| (key, project(&value, fields)))
+                .collect(),
+            None => page,
+        }
+    }
+}
+
+// Keep only the listed fields of an object
+// Fields the value doesn't have are left out; non-objects project to an empty object
+pub fn project(value: &Value, fields: &[String]) -> Value {
+    let mut projected = HashMap::new();
+    for field in fields {
+        if let Some(v) = value.get_field(field) {
+            projected.insert(field.clone(), v.clone());
+        }
+    }
+    Value::Object(projected)
+}
+
+// Closest first; entries without a location go last
+pub fn sort_by_distance(results: &mut [(String, Value)], field: &GeoField, point: &GeoPoint) {
+    results.sort_by_cached_key(|(_, value)| {
+        geo::distance(field, point, value).map_or((1, 0), |d| (0, d.to_bits()))
+    });
+}
+
+// Missing fields always sort last, whatever the direction
+fn compare_fields(a: Option<&Value>, b: Option<&Value>, order: SortOrder) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            let ordering = a.cmp(b);
+            match order {
+                SortOrder::Ascending => ordering,
+                SortOrder::Descending => ordering.reverse(),
+            }
+        }
+    }
+}
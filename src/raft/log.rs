@@ -0,0 +1,143 @@
+// The replicated log: the entries since the last snapshot
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::NodeId;
+use crate::changelog::Change;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) term: u64,
+    pub(crate) index: u64,
+    pub(crate) payload: Payload,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Payload {
+    // A new leader's first entry; committing it commits what earlier leaders left
+    Noop,
+    // One Command batch, worked out into what it does (see changelog.rs)
+    Write(Vec<Change>),
+    // The cluster's configuration from this entry on: id -> address
+    Members(BTreeMap<NodeId, String>),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Log {
+    // The last entry the snapshot covers (0 and 0 before the first)
+    pub(crate) base_index: u64,
+    pub(crate) base_term: u64,
+    entries: Vec<Entry>, // base_index + 1 onwards
+}
+
+impl Log {
+    pub(crate) fn last_index(&self) -> u64 {
+        self.base_index + self.entries.len() as u64
+    }
+
+    pub(crate) fn last_term(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.base_term, |entry| entry.term)
+    }
+
+    // None if the entry was compacted away or isn't there yet
+    pub(crate) fn term(&self, index: u64) -> Option<u64> {
+        if index == self.base_index {
+            return Some(self.base_term);
+        }
+        self.get(index).map(|entry| entry.term)
+    }
+
+    pub(crate) fn get(&self, index: u64) -> Option<&Entry> {
+        let offset = index.checked_sub(self.base_index + 1)?;
+        self.entries.get(offset as usize)
+    }
+
+    // At most `max` entries from `from` on
+    pub(crate) fn entries_from(&self, from: u64, max: usize) -> Vec<Entry> {
+        let start = from.saturating_sub(self.base_index + 1) as usize;
+        self.entries.iter().skip(start).take(max).cloned().collect()
+    }
+
+    pub(crate) fn push(&mut self, term: u64, payload: Payload) -> u64 {
+        let index = self.last_index() + 1;
+        self.entries.push(Entry {
+            term,
+            index,
+            payload,
+        });
+        index
+    }
+
+    // Add a leader's entries, which follow on from an entry both logs agree
+    // on. Ours from the first that conflicts on are dropped; true if any were.
+    pub(crate) fn merge(&mut self, entries: Vec<Entry>) -> bool {
+        let mut truncated = false;
+        for entry in entries {
+            if entry.index <= self.base_index {
+                continue; // in the snapshot already
+            }
+            match self.term(entry.index) {
+                Some(term) if term == entry.term => continue,
+                Some(_) => {
+                    self.entries
+                        .truncate((entry.index - self.base_index - 1) as usize);
+                    truncated = true;
+                }
+                None => {}
+            }
+            self.entries.push(entry);
+        }
+        truncated
+    }
+
+    // The last index before `index` holding an entry of a different term
+    pub(crate) fn term_start(&self, index: u64) -> u64 {
+        let term = self.term(index);
+        let mut before = index.saturating_sub(1);
+        while before > self.base_index && self.term(before) == term {
+            before -= 1;
+        }
+        before
+    }
+
+    // The configuration in force at `index`, if an entry up to it set one
+    pub(crate) fn members_at(&self, index: u64) -> Option<&BTreeMap<NodeId, String>> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.index <= index)
+            .find_map(|entry| match &entry.payload {
+                Payload::Members(members) => Some(members),
+                _ => None,
+            })
+    }
+
+    // A configuration change after `index`, if one is
+    pub(crate) fn has_members_after(&self, index: u64) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.index > index && matches!(entry.payload, Payload::Members(_)))
+    }
+
+    // Drop the entries up to `index`, which a snapshot now covers
+    pub(crate) fn compact(&mut self, index: u64) {
+        let Some(term) = self.term(index) else {
+            return;
+        };
+        let drop = (index - self.base_index) as usize;
+        self.entries.drain(..drop);
+        self.base_index = index;
+        self.base_term = term;
+    }
+
+    // Start over from a snapshot that isn't followed by anything in this log
+    pub(crate) fn reset(&mut self, index: u64, term: u64) {
+        self.entries.clear();
+        self.base_index = index;
+        self.base_term = term;
+    }
+}
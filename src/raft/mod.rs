@@ -0,0 +1,210 @@
+// NEW: A replicated cluster - Raft consensus between littledb databases
+//
+// replication/ copies a primary to followers and leaves failover to an
+// operator. A Raft cluster of 3 or 5 nodes elects its leader itself, and
+// elects another when the leader fails, for as long as a majority of the nodes
+// can reach each other. Writes become entries of a replicated log; a write is
+// committed once a majority has it, and every node applies the committed
+// entries to its own Database in the same order.
+//
+//   let members = BTreeMap::from([
+//       (1, "10.0.0.1:7000".to_string()),
+//       (2, "10.0.0.2:7000".to_string()),
+//       (3, "10.0.0.3:7000".to_string()),
+//   ]);
+//   // On each machine, with its own id:
+//   let node = RaftHandle::start(1, members, db, RaftConfig::default())?;
+//   node.write(vec![Command::insert("greeting", Value::from("hello"))])?;
+//   let value = node.read(|db| db.get("greeting"))?;
+//
+// Writes and reads go to the leader (others answer NotLeader with the leader's
+// id, when they know it). A read runs once the leader has confirmed, with a
+// round of heartbeats, that no other node took over, and has applied every
+// write committed before the read arrived: it sees every write that finished
+// before it started (it's linearizable).
+//
+// node.rs is the algorithm - elections, log replication, snapshots,
+// membership changes, reads - as a state machine without I/O or clock: it's
+// driven by tick() and step(), and what it wants sent is collected with
+// take_messages(). net.rs runs it over TCP; sim.rs runs a whole cluster in one
+// process over a simulated network that loses, delays and partitions
+// messages, reproducibly from a seed, for tests.
+//
+// The log is compacted with snapshots of the database (see
+// RaftConfig::snapshot_after); a node that falls further behind than the log
+// goes back is sent the snapshot. Nodes join and leave one at a time
+// (add_member, remove_member). Only the data is replicated (keys, values,
+// expiry deadlines, collections and their options); index definitions are
+// not, as in replication/.
+
+mod log;
+pub mod net;
+mod node;
+pub mod sim;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+use crate::keyspace::CollectionOptions;
+pub use net::RaftHandle;
+pub use node::{Message, RaftNode};
+pub use sim::Simulator;
+
+pub type NodeId = u64;
+
+#[derive(Debug, Clone)]
+pub struct RaftConfig {
+    // Time is counted in ticks (RaftNode::tick); net.rs ticks every `tick`
+    pub tick: Duration,
+    // A leader sends heartbeats this often ...
+    pub heartbeat_ticks: u32,
+    // ... and a follower that hears nothing for between this and twice this
+    // (at random, so that nodes rarely campaign together) calls an election
+    pub election_ticks: u32,
+    // Applied entries the log keeps before they're replaced by a snapshot
+    pub snapshot_after: u64,
+    // Most entries sent in one message
+    pub max_entries_per_message: usize,
+    // Picks the random election timeouts (mixed with the node's id)
+    pub seed: u64,
+    // net.rs: how long write() and read() wait for the cluster
+    pub request_timeout: Duration,
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        RaftConfig {
+            tick: Duration::from_millis(50),
+            heartbeat_ticks: 1,
+            election_ticks: 10,
+            snapshot_after: 1000,
+            max_entries_per_message: 256,
+            seed: 0,
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+// A write to the replicated data. `collection` None is the database's own
+// keys; a collection that doesn't exist is created with default options.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Without a ttl, the collection's default TTL applies
+    Insert {
+        collection: Option<String>,
+        key: String,
+        value: Value,
+        ttl: Option<Duration>,
+    },
+    Delete {
+        collection: Option<String>,
+        key: String,
+    },
+    Clear {
+        collection: Option<String>,
+    },
+    // Create a collection, or change an existing one's options
+    CreateCollection {
+        name: String,
+        options: CollectionOptions,
+    },
+    DropCollection {
+        name: String,
+    },
+}
+
+impl Command {
+    pub fn insert(key: &str, value: Value) -> Command {
+        Command::Insert {
+            collection: None,
+            key: key.to_string(),
+            value,
+            ttl: None,
+        }
+    }
+
+    pub fn delete(key: &str) -> Command {
+        Command::Delete {
+            collection: None,
+            key: key.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RaftError {
+    // Only the leader takes writes and reads: this is the leader, if known
+    NotLeader(Option<NodeId>),
+    // A value that doesn't fit its schema; a membership change while another
+    // is under way
+    Invalid(String),
+    // The write was replaced by another leader's: it didn't happen
+    Dropped,
+    // The node lost track of the write (it was sent a snapshot meanwhile):
+    // it may or may not have happened
+    Unknown,
+    // No answer in time; the write may still happen
+    Timeout,
+    Io(String),
+}
+
+impl fmt::Display for RaftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaftError::NotLeader(Some(leader)) => write!(f, "not the leader; node {} is", leader),
+            RaftError::NotLeader(None) => write!(f, "not the leader, and no leader is known"),
+            RaftError::Invalid(message) => write!(f, "{}", message),
+            RaftError::Dropped => write!(f, "the write was dropped by a new leader"),
+            RaftError::Unknown => write!(f, "the outcome of the write is unknown"),
+            RaftError::Timeout => write!(f, "timed out waiting for the cluster"),
+            RaftError::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RaftError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Leader,
+    Candidate,
+    Follower,
+}
+
+// Where a proposed write went in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ticket {
+    pub index: u64,
+    pub term: u64,
+}
+
+// What became of the writes and reads started on a node (RaftNode::take_events)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Applied(Ticket),
+    Dropped(Ticket),
+    Unknown(Ticket),
+    // The read can run against the node's database now ...
+    ReadReady(u64),
+    // ... or can't, because the node is no longer the leader
+    ReadFailed(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaftStatus {
+    pub id: NodeId,
+    pub role: Role,
+    pub term: u64,
+    pub leader: Option<NodeId>,
+    // The latest configuration, committed or not: id -> address
+    pub members: BTreeMap<NodeId, String>,
+    pub commit: u64,  // the last entry known to be committed
+    pub applied: u64, // the last entry applied to the database
+    // The log holds the entries after snapshot_index, up to last_index
+    pub snapshot_index: u64,
+    pub last_index: u64,
+}
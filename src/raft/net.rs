@@ -0,0 +1,446 @@
+// Raft nodes in separate processes, talking over TCP (see mod.rs)
+//
+//   let node = RaftHandle::start(1, members, db, RaftConfig::default())?;
+//   // A node for a running cluster, added by its leader afterwards:
+//   let new = RaftHandle::join(4, "10.0.0.4:7000", db, RaftConfig::default())?;
+//   leader.add_member(4, "10.0.0.4:7000")?;
+//
+// Each node listens on its address and connects to the others as it needs to.
+// A connection carries messages one way: MAGIC, a Hello saying who's
+// talking, then bincode messages. A message that can't be sent is dropped;
+// Raft sends it again.
+//
+// A node saves its term, vote and log (`.raft`) and its last snapshot
+// (`.raft-snapshot`) next to the database file before sending anything that
+// depends on them, and restarts from those. The data file itself is only read
+// when a node first starts; start every node of a new cluster with the same
+// data, or with none.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::node::Message;
+use super::{Command, Event, NodeId, RaftConfig, RaftError, RaftNode, RaftStatus, Ticket};
+use crate::Database;
+
+const MAGIC: &[u8; 8] = b"LTDBRAFT";
+// Connections that carry nothing for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    id: NodeId,
+    addr: String, // where the sender listens, for a joining node to answer
+}
+
+// A running node; clones share it
+#[derive(Clone)]
+pub struct RaftHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    id: NodeId,
+    addr: SocketAddr,
+    config: RaftConfig,
+    state: Mutex<State>,
+    answered: Condvar, // an awaited event arrived, or the node stopped
+    shutdown: AtomicBool,
+}
+
+struct State {
+    node: RaftNode,
+    // What write() and read() wait for, and what became of it
+    awaited: BTreeMap<Awaited, Option<Event>>,
+    peers: BTreeMap<NodeId, Peer>,
+    known: BTreeMap<NodeId, String>, // addresses from Hellos, for nodes not (yet) members
+    stopped: Option<String>,         // why the node stopped
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Awaited {
+    Write(Ticket),
+    Read(u64),
+}
+
+// The thread sending to one other node
+struct Peer {
+    addr: String,
+    queue: Sender<Message>,
+}
+
+impl RaftHandle {
+    // Start a node of a new cluster, listening on its address in `members`,
+    // or restart one from what it saved
+    pub fn start(
+        id: NodeId,
+        members: BTreeMap<NodeId, String>,
+        db: Database,
+        config: RaftConfig,
+    ) -> io::Result<RaftHandle> {
+        let addr = members.get(&id).cloned().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("node {} isn't a member", id),
+            )
+        })?;
+        RaftHandle::launch(id, &addr, db, config, |db, config| {
+            RaftNode::new(id, members, db, config)
+        })
+    }
+
+    // Start a node that a running cluster's leader is to add (add_member), or
+    // restart one from what it saved
+    pub fn join(
+        id: NodeId,
+        addr: &str,
+        db: Database,
+        config: RaftConfig,
+    ) -> io::Result<RaftHandle> {
+        RaftHandle::launch(id, addr, db, config, |db, config| {
+            Ok(RaftNode::join(id, db, config))
+        })
+    }
+
+    fn launch(
+        id: NodeId,
+        addr: &str,
+        mut db: Database,
+        config: RaftConfig,
+        fresh: impl FnOnce(Database, RaftConfig) -> io::Result<RaftNode>,
+    ) -> io::Result<RaftHandle> {
+        db.set_verbose(false);
+        db.set_auto_save(false);
+        let listener = TcpListener::bind(addr)?;
+        let node = RaftNode::open(id, db, config.clone(), fresh)?;
+        let (inbox, received) = mpsc::channel();
+        let inner = Arc::new(Inner {
+            id,
+            addr: listener.local_addr()?,
+            config,
+            state: Mutex::new(State {
+                node,
+                awaited: BTreeMap::new(),
+                peers: BTreeMap::new(),
+                known: BTreeMap::new(),
+                stopped: None,
+            }),
+            answered: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        });
+        inner.flush(&mut inner.lock());
+        {
+            let inner = Arc::clone(&inner);
+            thread::spawn(move || accept(inner, listener, inbox));
+        }
+        {
+            let inner = Arc::clone(&inner);
+            thread::spawn(move || drive(inner, received));
+        }
+        Ok(RaftHandle { inner })
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.inner.id
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.inner.addr
+    }
+
+    pub fn status(&self) -> RaftStatus {
+        self.inner.lock().node.status()
+    }
+
+    // Make the writes (all of them or none) and wait until they're applied
+    // here; on the leader only
+    pub fn write(&self, commands: Vec<Command>) -> Result<(), RaftError> {
+        let mut state = self.inner.usable()?;
+        let ticket = state.node.propose(commands)?;
+        self.finish_write(state, ticket)
+    }
+
+    // Run `read` against the data once it has every write that finished
+    // before the call (see mod.rs); on the leader only
+    pub fn read<T>(&self, read: impl FnOnce(&Database) -> T) -> Result<T, RaftError> {
+        let mut state = self.inner.usable()?;
+        let id = state.node.read()?;
+        let (state, event) = self.finish(state, Awaited::Read(id))?;
+        match event {
+            Event::ReadReady(_) => Ok(read(state.node.database())),
+            _ => Err(RaftError::NotLeader(state.node.leader())),
+        }
+    }
+
+    // Add a node started with join(); on the leader only
+    pub fn add_member(&self, id: NodeId, addr: &str) -> Result<(), RaftError> {
+        let mut state = self.inner.usable()?;
+        let ticket = state.node.add_member(id, addr)?;
+        self.finish_write(state, ticket)
+    }
+
+    // Take a node out of the cluster, then shut it down; on the leader only
+    pub fn remove_member(&self, id: NodeId) -> Result<(), RaftError> {
+        let mut state = self.inner.usable()?;
+        let ticket = state.node.remove_member(id)?;
+        self.finish_write(state, ticket)
+    }
+
+    // Stop the node; it can be started again from what it saved
+    pub fn shutdown(&self) {
+        if self.inner.shutdown.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let _ = TcpStream::connect(self.inner.addr); // wakes the accept loop
+        let mut state = self.inner.lock();
+        state
+            .stopped
+            .get_or_insert_with(|| "the node was shut down".to_string());
+        state.peers.clear(); // their threads end
+        self.inner.answered.notify_all();
+    }
+
+    fn finish_write(&self, state: MutexGuard<'_, State>, ticket: Ticket) -> Result<(), RaftError> {
+        match self.finish(state, Awaited::Write(ticket))?.1 {
+            Event::Applied(_) => Ok(()),
+            Event::Dropped(_) => Err(RaftError::Dropped),
+            _ => Err(RaftError::Unknown),
+        }
+    }
+
+    // Send what the call started, then wait for what becomes of it
+    fn finish<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State>,
+        awaited: Awaited,
+    ) -> Result<(MutexGuard<'a, State>, Event), RaftError> {
+        state.awaited.insert(awaited, None);
+        self.inner.flush(&mut state);
+        let deadline = Instant::now() + self.inner.config.request_timeout;
+        loop {
+            if let Some(Some(event)) = state.awaited.get(&awaited).copied() {
+                state.awaited.remove(&awaited);
+                return Ok((state, event));
+            }
+            let now = Instant::now();
+            let error = match &state.stopped {
+                Some(why) => RaftError::Io(why.clone()),
+                None if now >= deadline => RaftError::Timeout,
+                None => {
+                    state = self
+                        .inner
+                        .answered
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0;
+                    continue;
+                }
+            };
+            state.awaited.remove(&awaited);
+            return Err(error);
+        }
+    }
+}
+
+impl Awaited {
+    fn of(event: Event) -> Awaited {
+        match event {
+            Event::Applied(ticket) | Event::Dropped(ticket) | Event::Unknown(ticket) => {
+                Awaited::Write(ticket)
+            }
+            Event::ReadReady(id) | Event::ReadFailed(id) => Awaited::Read(id),
+        }
+    }
+}
+
+impl Inner {
+    // A panic in one caller shouldn't take the node down with it
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn usable(&self) -> Result<MutexGuard<'_, State>, RaftError> {
+        let state = self.lock();
+        match &state.stopped {
+            Some(why) => Err(RaftError::Io(why.clone())),
+            None => Ok(state),
+        }
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    // After anything happened to the node: save what changed, then send what
+    // it wants sent, then hand out what became of writes and reads
+    fn flush(&self, state: &mut State) {
+        if state.stopped.is_none()
+            && let Err(e) = state.node.save()
+        {
+            state.stopped = Some(format!("saving failed: {}", e));
+        }
+        let messages = state.node.take_messages();
+        if state.stopped.is_none() {
+            for (to, message) in messages {
+                self.send(state, to, message);
+            }
+        }
+        for event in state.node.take_events() {
+            if let Some(slot) = state.awaited.get_mut(&Awaited::of(event)) {
+                *slot = Some(event);
+            }
+        }
+        self.answered.notify_all();
+    }
+
+    fn send(&self, state: &mut State, to: NodeId, message: Message) {
+        let Some(addr) = state
+            .node
+            .address(to)
+            .or(state.known.get(&to).map(String::as_str))
+            .map(str::to_string)
+        else {
+            return;
+        };
+        let current = state.peers.get(&to).is_some_and(|peer| peer.addr == addr);
+        if !current {
+            let hello = Hello {
+                id: self.id,
+                addr: self.addr.to_string(),
+            };
+            state
+                .peers
+                .insert(to, Peer::start(hello, addr, self.config.tick));
+        }
+        let _ = state.peers[&to].queue.send(message);
+    }
+}
+
+impl Peer {
+    fn start(hello: Hello, addr: String, retry: Duration) -> Peer {
+        let (queue, messages) = mpsc::channel::<Message>();
+        let target = addr.clone();
+        thread::spawn(move || {
+            let mut link = None;
+            let mut retry_at = Instant::now();
+            // Until the Peer is dropped
+            for message in messages {
+                if link.is_none() && Instant::now() >= retry_at {
+                    match connect(&target, &hello) {
+                        Ok(out) => link = Some(out),
+                        Err(_) => retry_at = Instant::now() + retry,
+                    }
+                }
+                if let Some(out) = &mut link
+                    && write_message(out, &message).is_err()
+                {
+                    link = None;
+                }
+            }
+        });
+        Peer { addr, queue }
+    }
+}
+
+// Feed the node what arrives, and tick it on time
+fn drive(inner: Arc<Inner>, received: Receiver<(NodeId, Message)>) {
+    let tick = inner.config.tick;
+    let mut next_tick = Instant::now() + tick;
+    while !inner.is_shut_down() {
+        let wait = next_tick.saturating_duration_since(Instant::now());
+        let arrived = match received.recv_timeout(wait) {
+            Ok(arrived) => Some(arrived),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let mut state = inner.lock();
+        if let Some((from, message)) = arrived {
+            state.node.step(from, message);
+        }
+        let now = Instant::now();
+        if now >= next_tick {
+            state.node.tick();
+            next_tick = (next_tick + tick).max(now); // no catching up after a stall
+        }
+        inner.flush(&mut state);
+    }
+}
+
+fn accept(inner: Arc<Inner>, listener: TcpListener, inbox: Sender<(NodeId, Message)>) {
+    for stream in listener.incoming() {
+        if inner.is_shut_down() {
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                let inner = Arc::clone(&inner);
+                let inbox = inbox.clone();
+                thread::spawn(move || {
+                    let _ = receive(&inner, stream, &inbox); // a dropped link is nothing to report
+                });
+            }
+            // Out of file descriptors and the like: wait and try again
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+fn receive(inner: &Inner, stream: TcpStream, inbox: &Sender<(NodeId, Message)>) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut input = BufReader::new(stream);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "not a littledb raft node",
+        ));
+    }
+    let hello: Hello = read_message(&mut input)?;
+    inner.lock().known.insert(hello.id, hello.addr);
+    while !inner.is_shut_down() {
+        let message = read_message(&mut input)?;
+        if inbox.send((hello.id, message)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn connect(addr: &str, hello: &Hello) -> io::Result<BufWriter<TcpStream>> {
+    let mut last_error = io::Error::new(ErrorKind::NotFound, "no address to connect to");
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_write_timeout(Some(IDLE_TIMEOUT))?;
+                let mut out = BufWriter::new(stream);
+                out.write_all(MAGIC)?;
+                write_message(&mut out, hello)?;
+                return Ok(out);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn write_message<T: Serialize>(out: &mut BufWriter<TcpStream>, message: &T) -> io::Result<()> {
+    bincode::serialize_into(&mut *out, message).map_err(|e| io::Error::other(e.to_string()))?;
+    out.flush()
+}
+
+fn read_message<T: DeserializeOwned>(input: &mut impl Read) -> io::Result<T> {
+    bincode::deserialize_from(input).map_err(|e| io::Error::other(e.to_string()))
+}
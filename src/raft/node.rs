@@ -0,0 +1,1059 @@
+// The Raft algorithm for one node, without I/O or clock (see mod.rs)
+//
+// As in the Raft paper (Ongaro and Ousterhout, "In Search of an
+// Understandable Consensus Algorithm"), plus single-server membership changes
+// and ReadIndex reads from Ongaro's thesis. A leader sends each follower the
+// entries from where it expects the follower's log to end (`next`) without
+// waiting for answers; a follower that finds a gap or a conflict says where
+// its log agrees at most, and the leader goes back there.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use super::log::{Entry, Log, Payload};
+use super::{Command, Event, NodeId, RaftConfig, RaftError, RaftStatus, Role, Ticket};
+use crate::Database;
+use crate::changelog::{Change, ChangeOp};
+use crate::keyspace::{CollectionOptions, Keyspace, deadline_after};
+
+// What nodes send each other: opaque, but serializable for whatever carries it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message(pub(crate) Rpc);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum Rpc {
+    Vote {
+        term: u64,
+        last_index: u64,
+        last_term: u64,
+    },
+    VoteReply {
+        term: u64,
+        granted: bool,
+    },
+    // Entries following prev_index (none: a heartbeat). `round` numbers the
+    // leader's heartbeats, which confirm reads.
+    Append {
+        term: u64,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+        round: u64,
+    },
+    // `last`: after success, the last entry known to match the leader's;
+    // after failure, how far the logs may agree. `blank`: the node is joining
+    // and has no data yet, so it needs the snapshot.
+    AppendReply {
+        term: u64,
+        success: bool,
+        last: u64,
+        blank: bool,
+        round: u64,
+    },
+    InstallSnapshot {
+        term: u64,
+        snapshot: Snapshot,
+    },
+    SnapshotReply {
+        term: u64,
+        index: u64,
+    },
+}
+
+impl Rpc {
+    fn term(&self) -> u64 {
+        match self {
+            Rpc::Vote { term, .. }
+            | Rpc::VoteReply { term, .. }
+            | Rpc::Append { term, .. }
+            | Rpc::AppendReply { term, .. }
+            | Rpc::InstallSnapshot { term, .. }
+            | Rpc::SnapshotReply { term, .. } => *term,
+        }
+    }
+}
+
+// The database as of a log entry, with the configuration then
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    pub(crate) index: u64,
+    pub(crate) term: u64,
+    pub(crate) members: BTreeMap<NodeId, String>,
+    pub(crate) image: Vec<u8>, // Database::image_bytes
+}
+
+// Where save() keeps a node's state, next to the database file
+const STATE_FILE: &str = "raft";
+const SNAPSHOT_FILE: &str = "raft-snapshot";
+
+// What must survive a restart besides the snapshot
+#[derive(Serialize)]
+struct HardStateRef<'a> {
+    term: u64,
+    voted_for: Option<NodeId>,
+    log: &'a Log,
+}
+
+#[derive(Deserialize)]
+struct HardState {
+    term: u64,
+    voted_for: Option<NodeId>,
+    log: Log,
+}
+
+// A leader's view of one follower
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    next: u64,    // the next entry to send it
+    matched: u64, // the last entry known to be in its log
+    round: u64,   // the last heartbeat round it answered
+    blank: bool,  // it has no data yet: send the snapshot
+}
+
+struct PendingRead {
+    id: u64,
+    index: u64, // applied this far, the read sees what it must
+    round: u64, // confirmed by a majority answering this round
+}
+
+pub struct RaftNode {
+    id: NodeId,
+    config: RaftConfig,
+    db: Database,
+    // Kept across restarts
+    term: u64,
+    voted_for: Option<NodeId>,
+    log: Log,
+    snapshot: Option<Snapshot>, // None until a joining node is sent one
+    // Rebuilt on restart
+    role: Role,
+    leader: Option<NodeId>,
+    members: BTreeMap<NodeId, String>, // the latest configuration, committed or not
+    commit: u64,
+    applied: u64,
+    ticks: u32, // since the last heartbeat (a leader) or word from a leader or candidate
+    timeout: u32,
+    votes: BTreeSet<NodeId>,
+    progress: BTreeMap<NodeId, Progress>,
+    term_start: u64, // a leader's first entry
+    round: u64,
+    reads: Vec<PendingRead>,
+    next_read: u64,
+    proposals: BTreeMap<u64, u64>, // index -> term, of the writes proposed here
+    outbox: Vec<(NodeId, Message)>,
+    events: Vec<Event>,
+    rng: u64,
+    unsaved: bool, // the term, vote or log changed
+    snapshot_unsaved: bool,
+}
+
+impl RaftNode {
+    // A node of a new cluster: every member starts with the same `members`
+    // and the same data in `db`
+    pub fn new(
+        id: NodeId,
+        members: BTreeMap<NodeId, String>,
+        db: Database,
+        config: RaftConfig,
+    ) -> io::Result<RaftNode> {
+        let snapshot = Snapshot {
+            index: 0,
+            term: 0,
+            members,
+            image: db.image_bytes()?,
+        };
+        let mut node = RaftNode::assemble(id, config, db, 0, None, Log::default(), Some(snapshot));
+        node.unsaved = true;
+        node.snapshot_unsaved = true;
+        Ok(node)
+    }
+
+    // A node to be added to a running cluster (see add_member): it waits for
+    // the leader to send it the data
+    pub fn join(id: NodeId, db: Database, config: RaftConfig) -> RaftNode {
+        let mut node = RaftNode::assemble(id, config, db, 0, None, Log::default(), None);
+        node.unsaved = true;
+        node
+    }
+
+    // The node as save() left it next to db's file (the data file itself
+    // isn't used then), or if it never saved, `fresh` (net.rs)
+    pub(crate) fn open(
+        id: NodeId,
+        db: Database,
+        config: RaftConfig,
+        fresh: impl FnOnce(Database, RaftConfig) -> io::Result<RaftNode>,
+    ) -> io::Result<RaftNode> {
+        let Some(state) = db.storage().load_sidecar(STATE_FILE)? else {
+            return fresh(db, config);
+        };
+        let snapshot = db.storage().load_sidecar(SNAPSHOT_FILE)?;
+        RaftNode::recover(id, state, snapshot, db, config)
+    }
+
+    // Save what changed since the last call (net.rs), before sending anything
+    pub(crate) fn save(&mut self) -> io::Result<()> {
+        let storage = self.db.storage();
+        if self.snapshot_unsaved
+            && let Some(snapshot) = &self.snapshot
+        {
+            storage.save_sidecar(SNAPSHOT_FILE, snapshot)?;
+        }
+        self.snapshot_unsaved = false;
+        if self.unsaved {
+            let state = HardStateRef {
+                term: self.term,
+                voted_for: self.voted_for,
+                log: &self.log,
+            };
+            storage.save_sidecar(STATE_FILE, &state)?;
+        }
+        self.unsaved = false;
+        Ok(())
+    }
+
+    // `db` is reloaded from the snapshot
+    fn recover(
+        id: NodeId,
+        state: HardState,
+        snapshot: Option<Snapshot>,
+        mut db: Database,
+        config: RaftConfig,
+    ) -> io::Result<RaftNode> {
+        let mut log = state.log;
+        if let Some(snapshot) = &snapshot {
+            db.load_image(&snapshot.image)?;
+            // Saving the log after the snapshot may not have happened
+            if log.term(snapshot.index) == Some(snapshot.term) {
+                log.compact(snapshot.index);
+            } else if log.base_index < snapshot.index {
+                log.reset(snapshot.index, snapshot.term);
+            }
+        }
+        Ok(RaftNode::assemble(
+            id,
+            config,
+            db,
+            state.term,
+            state.voted_for,
+            log,
+            snapshot,
+        ))
+    }
+
+    // As after a crash: only what's kept across restarts survives (sim.rs)
+    pub(crate) fn restart(self) -> io::Result<RaftNode> {
+        let state = HardState {
+            term: self.term,
+            voted_for: self.voted_for,
+            log: self.log,
+        };
+        RaftNode::recover(self.id, state, self.snapshot, self.db, self.config)
+    }
+
+    fn assemble(
+        id: NodeId,
+        config: RaftConfig,
+        db: Database,
+        term: u64,
+        voted_for: Option<NodeId>,
+        log: Log,
+        snapshot: Option<Snapshot>,
+    ) -> RaftNode {
+        let base = snapshot.as_ref().map_or(0, |snapshot| snapshot.index);
+        let rng = (config.seed ^ id.wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1;
+        let mut node = RaftNode {
+            id,
+            config,
+            db,
+            term,
+            voted_for,
+            log,
+            snapshot,
+            role: Role::Follower,
+            leader: None,
+            members: BTreeMap::new(),
+            commit: base,
+            applied: base,
+            ticks: 0,
+            timeout: 0,
+            votes: BTreeSet::new(),
+            progress: BTreeMap::new(),
+            term_start: 0,
+            round: 0,
+            reads: Vec::new(),
+            next_read: 0,
+            proposals: BTreeMap::new(),
+            outbox: Vec::new(),
+            events: Vec::new(),
+            rng,
+            unsaved: false,
+            snapshot_unsaved: false,
+        };
+        node.update_members();
+        node.reset_timer();
+        node
+    }
+
+    // ---------- Driving it ----------
+
+    // One unit of time passed
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        if self.role == Role::Leader {
+            if self.ticks >= self.config.heartbeat_ticks {
+                self.heartbeat();
+            }
+        } else if self.ticks >= self.timeout && self.members.contains_key(&self.id) {
+            self.campaign();
+        }
+    }
+
+    // A message from another node
+    pub fn step(&mut self, from: NodeId, message: Message) {
+        let rpc = message.0;
+        let term = rpc.term();
+        // A node that can't hear the leader (or was removed without learning
+        // it) shouldn't depose it
+        if matches!(rpc, Rpc::Vote { .. }) && term > self.term && self.hears_leader() {
+            return;
+        }
+        if term > self.term {
+            self.become_follower(term, None);
+        }
+        match rpc {
+            Rpc::Vote {
+                term,
+                last_index,
+                last_term,
+            } => {
+                let up_to_date =
+                    (last_term, last_index) >= (self.log.last_term(), self.log.last_index());
+                let granted = term == self.term
+                    && up_to_date
+                    && self.voted_for.is_none_or(|voted| voted == from);
+                if granted {
+                    self.voted_for = Some(from);
+                    self.unsaved = true;
+                    self.reset_timer();
+                }
+                self.send(
+                    from,
+                    Rpc::VoteReply {
+                        term: self.term,
+                        granted,
+                    },
+                );
+            }
+            Rpc::VoteReply { term, granted } => {
+                if self.role == Role::Candidate && term == self.term && granted {
+                    self.votes.insert(from);
+                    if self.won() {
+                        self.become_leader();
+                    }
+                }
+            }
+            Rpc::Append {
+                term,
+                prev_index,
+                prev_term,
+                entries,
+                commit,
+                round,
+            } => self.append_entries(from, term, prev_index, prev_term, entries, commit, round),
+            Rpc::AppendReply {
+                term,
+                success,
+                last,
+                blank,
+                round,
+            } => {
+                if self.role == Role::Leader && term == self.term {
+                    self.append_reply(from, success, last, blank, round);
+                }
+            }
+            Rpc::InstallSnapshot { term, snapshot } => self.install_snapshot(from, term, snapshot),
+            Rpc::SnapshotReply { term, index } => {
+                if self.role == Role::Leader && term == self.term {
+                    self.snapshot_reply(from, index);
+                }
+            }
+        }
+    }
+
+    // Messages to send, as (recipient, message). Whatever drives the node must
+    // save what changed (net.rs) before sending them.
+    pub fn take_messages(&mut self) -> Vec<(NodeId, Message)> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    // ---------- Using it (on the leader) ----------
+
+    // Append a write to the log; take_events reports whether it was applied
+    pub fn propose(&mut self, commands: Vec<Command>) -> Result<Ticket, RaftError> {
+        self.check_leader()?;
+        let changes = self.changes(commands)?;
+        let ticket = self.append(Payload::Write(changes));
+        self.track(ticket);
+        Ok(ticket)
+    }
+
+    // Start a linearizable read. Once take_events reports ReadReady(id), the
+    // database has every write that was committed when read() was called.
+    pub fn read(&mut self) -> Result<u64, RaftError> {
+        self.check_leader()?;
+        self.next_read += 1;
+        self.round += 1;
+        // A new leader doesn't know what was committed before it until its
+        // first entry is, so the read waits for that too
+        self.reads.push(PendingRead {
+            id: self.next_read,
+            index: self.commit.max(self.term_start),
+            round: self.round,
+        });
+        self.heartbeat();
+        self.check_reads();
+        Ok(self.next_read)
+    }
+
+    // Add a node, started with RaftNode::join, reachable at `addr`
+    pub fn add_member(&mut self, id: NodeId, addr: &str) -> Result<Ticket, RaftError> {
+        let mut members = self.members.clone();
+        members.insert(id, addr.to_string());
+        self.change_members(members)
+    }
+
+    // Take a node out of the cluster (the leader itself included: it steps
+    // down once that's committed). Shut the node down afterwards.
+    pub fn remove_member(&mut self, id: NodeId) -> Result<Ticket, RaftError> {
+        let mut members = self.members.clone();
+        members.remove(&id);
+        if members.is_empty() {
+            return Err(RaftError::Invalid(
+                "can't remove the last member".to_string(),
+            ));
+        }
+        self.change_members(members)
+    }
+
+    // ---------- Looking at it ----------
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn leader(&self) -> Option<NodeId> {
+        self.leader
+    }
+
+    // The data as of the last applied entry. Only a read (read()) is sure to
+    // be up to date.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    pub fn status(&self) -> RaftStatus {
+        RaftStatus {
+            id: self.id,
+            role: self.role,
+            term: self.term,
+            leader: self.leader,
+            members: self.members.clone(),
+            commit: self.commit,
+            applied: self.applied,
+            snapshot_index: self.log.base_index,
+            last_index: self.log.last_index(),
+        }
+    }
+
+    // The address of a node in the configuration
+    pub(crate) fn address(&self, id: NodeId) -> Option<&str> {
+        self.members.get(&id).map(String::as_str)
+    }
+
+    // ---------- Elections ----------
+
+    fn campaign(&mut self) {
+        self.term += 1;
+        self.role = Role::Candidate;
+        self.leader = None;
+        self.voted_for = Some(self.id);
+        self.unsaved = true;
+        self.votes = BTreeSet::from([self.id]);
+        self.reset_timer();
+        if self.won() {
+            self.become_leader();
+            return;
+        }
+        for peer in self.peers() {
+            self.send(
+                peer,
+                Rpc::Vote {
+                    term: self.term,
+                    last_index: self.log.last_index(),
+                    last_term: self.log.last_term(),
+                },
+            );
+        }
+    }
+
+    fn won(&self) -> bool {
+        let votes = self
+            .votes
+            .iter()
+            .filter(|id| self.members.contains_key(id))
+            .count();
+        votes >= self.quorum()
+    }
+
+    fn become_leader(&mut self) {
+        self.role = Role::Leader;
+        self.leader = Some(self.id);
+        let next = self.log.last_index() + 1;
+        self.progress = self
+            .peers()
+            .into_iter()
+            .map(|peer| (peer, Progress::new(next)))
+            .collect();
+        self.term_start = next;
+        self.ticks = 0;
+        self.append(Payload::Noop);
+    }
+
+    fn become_follower(&mut self, term: u64, leader: Option<NodeId>) {
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.unsaved = true;
+        }
+        if self.role == Role::Leader {
+            self.progress.clear();
+            for read in std::mem::take(&mut self.reads) {
+                self.events.push(Event::ReadFailed(read.id));
+            }
+        }
+        self.role = Role::Follower;
+        self.leader = leader;
+        self.reset_timer();
+    }
+
+    // Heard from the leader within the shortest election timeout (or leads)
+    fn hears_leader(&self) -> bool {
+        match self.role {
+            Role::Leader => true,
+            Role::Follower => self.leader.is_some() && self.ticks < self.config.election_ticks,
+            Role::Candidate => false,
+        }
+    }
+
+    fn reset_timer(&mut self) {
+        let spread = u64::from(self.config.election_ticks.max(1));
+        self.ticks = 0;
+        self.timeout = self.config.election_ticks + (self.random() % spread) as u32;
+    }
+
+    // xorshift: enough to spread the timeouts, and reproducible
+    fn random(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+
+    // ---------- Replicating the log ----------
+
+    fn append(&mut self, payload: Payload) -> Ticket {
+        let members = matches!(payload, Payload::Members(_));
+        let index = self.log.push(self.term, payload);
+        self.unsaved = true;
+        if members {
+            self.update_members();
+        }
+        // Followers that are caught up get it now, the others in turn
+        let caught_up: Vec<NodeId> = self
+            .progress
+            .iter()
+            .filter(|(_, progress)| progress.next == index && !progress.blank)
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in caught_up {
+            self.send_append(peer);
+        }
+        self.maybe_commit();
+        Ticket {
+            index,
+            term: self.term,
+        }
+    }
+
+    fn heartbeat(&mut self) {
+        self.ticks = 0;
+        for peer in self.peers() {
+            self.send_append(peer);
+        }
+    }
+
+    fn send_append(&mut self, peer: NodeId) {
+        let Some(progress) = self.progress.get_mut(&peer) else {
+            return;
+        };
+        if progress.blank || progress.next <= self.log.base_index {
+            let Some(snapshot) = &self.snapshot else {
+                return;
+            };
+            progress.next = snapshot.index + 1;
+            let message = Rpc::InstallSnapshot {
+                term: self.term,
+                snapshot: snapshot.clone(),
+            };
+            self.send(peer, message);
+            return;
+        }
+        let prev_index = progress.next - 1;
+        let Some(prev_term) = self.log.term(prev_index) else {
+            return;
+        };
+        let entries = self
+            .log
+            .entries_from(progress.next, self.config.max_entries_per_message);
+        if let Some(last) = entries.last() {
+            progress.next = last.index + 1;
+        }
+        let message = Rpc::Append {
+            term: self.term,
+            prev_index,
+            prev_term,
+            entries,
+            commit: self.commit,
+            round: self.round,
+        };
+        self.send(peer, message);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn append_entries(
+        &mut self,
+        from: NodeId,
+        term: u64,
+        prev_index: u64,
+        prev_term: u64,
+        entries: Vec<Entry>,
+        commit: u64,
+        round: u64,
+    ) {
+        let reply = |node: &mut RaftNode, success: bool, last: u64, blank: bool| {
+            let term = node.term;
+            node.send(
+                from,
+                Rpc::AppendReply {
+                    term,
+                    success,
+                    last,
+                    blank,
+                    round,
+                },
+            );
+        };
+        if term < self.term {
+            reply(self, false, self.log.last_index(), false);
+            return;
+        }
+        if self.role != Role::Follower || self.leader != Some(from) {
+            self.become_follower(term, Some(from));
+        }
+        self.ticks = 0;
+        if self.snapshot.is_none() {
+            reply(self, false, 0, true);
+            return;
+        }
+        // Entries up to the snapshot are committed, so the leader has them too
+        let agrees =
+            prev_index <= self.log.base_index || self.log.term(prev_index) == Some(prev_term);
+        if !agrees {
+            let last = if prev_index > self.log.last_index() {
+                self.log.last_index()
+            } else {
+                self.log.term_start(prev_index) // skip the whole conflicting term
+            };
+            reply(self, false, last, false);
+            return;
+        }
+        let last = (prev_index + entries.len() as u64).max(self.log.base_index);
+        if !entries.is_empty() {
+            let members = entries
+                .iter()
+                .any(|entry| matches!(entry.payload, Payload::Members(_)));
+            if self.log.merge(entries) || members {
+                self.update_members();
+            }
+            self.unsaved = true;
+        }
+        if commit.min(last) > self.commit {
+            self.commit = commit.min(last);
+            self.apply();
+        }
+        reply(self, true, last, false);
+    }
+
+    fn append_reply(&mut self, from: NodeId, success: bool, last: u64, blank: bool, round: u64) {
+        let Some(progress) = self.progress.get_mut(&from) else {
+            return;
+        };
+        // Even a refusal shows the follower still takes this node for leader
+        progress.round = progress.round.max(round);
+        if blank {
+            progress.blank = true;
+        } else if success {
+            progress.matched = progress.matched.max(last);
+            progress.next = progress.next.max(last + 1);
+        } else {
+            progress.next = progress.next.min(last + 1);
+        }
+        let behind = !success || progress.next <= self.log.last_index();
+        self.maybe_commit();
+        self.check_reads();
+        if behind && self.role == Role::Leader {
+            self.send_append(from);
+        }
+    }
+
+    fn install_snapshot(&mut self, from: NodeId, term: u64, snapshot: Snapshot) {
+        if term < self.term {
+            let term = self.term;
+            self.send(from, Rpc::SnapshotReply { term, index: 0 });
+            return;
+        }
+        if self.role != Role::Follower || self.leader != Some(from) {
+            self.become_follower(term, Some(from));
+        }
+        self.ticks = 0;
+        let index = snapshot.index;
+        if self.snapshot.is_none() || index > self.commit {
+            if self.db.load_image(&snapshot.image).is_err() {
+                return; // the leader sends it again
+            }
+            if self.log.term(index) == Some(snapshot.term) {
+                self.log.compact(index);
+            } else {
+                self.log.reset(index, snapshot.term);
+            }
+            // Writes proposed here that the snapshot may or may not contain
+            let lost: Vec<(u64, u64)> = self
+                .proposals
+                .range(..=index)
+                .map(|(index, term)| (*index, *term))
+                .collect();
+            for (index, term) in lost {
+                self.proposals.remove(&index);
+                self.events.push(Event::Unknown(Ticket { index, term }));
+            }
+            self.snapshot = Some(snapshot);
+            self.commit = index;
+            self.applied = index;
+            self.update_members();
+            self.unsaved = true;
+            self.snapshot_unsaved = true;
+        }
+        self.send(
+            from,
+            Rpc::SnapshotReply {
+                term: self.term,
+                index: self.commit,
+            },
+        );
+    }
+
+    fn snapshot_reply(&mut self, from: NodeId, index: u64) {
+        let Some(progress) = self.progress.get_mut(&from) else {
+            return;
+        };
+        progress.blank = false;
+        progress.matched = progress.matched.max(index);
+        progress.next = progress.next.max(index + 1);
+        let behind = progress.next <= self.log.last_index();
+        self.maybe_commit();
+        if behind && self.role == Role::Leader {
+            self.send_append(from);
+        }
+    }
+
+    // Commit what a majority has; only entries of the leader's own term are
+    // committed by counting (the paper's section 5.4.2), earlier ones with them
+    fn maybe_commit(&mut self) {
+        if self.role != Role::Leader || self.members.is_empty() {
+            return;
+        }
+        let mut matched: Vec<u64> = self
+            .members
+            .keys()
+            .map(|id| match self.progress.get(id) {
+                Some(progress) => progress.matched,
+                None if *id == self.id => self.log.last_index(),
+                None => 0,
+            })
+            .collect();
+        matched.sort_unstable_by(|a, b| b.cmp(a));
+        let index = matched[self.quorum() - 1];
+        if index > self.commit && self.log.term(index) == Some(self.term) {
+            self.commit = index;
+            self.apply();
+        }
+    }
+
+    // Apply the committed entries to the database, and compact the log when
+    // it has grown long enough
+    fn apply(&mut self) {
+        while self.applied < self.commit {
+            let index = self.applied + 1;
+            let entry = self
+                .log
+                .get(index)
+                .cloned()
+                .expect("committed entries are in the log");
+            match entry.payload {
+                Payload::Write(changes) => {
+                    for change in changes {
+                        self.db.replay(change);
+                    }
+                }
+                Payload::Members(members)
+                    if !members.contains_key(&self.id) && self.role == Role::Leader =>
+                {
+                    self.become_follower(self.term, None);
+                }
+                Payload::Members(_) | Payload::Noop => {}
+            }
+            self.applied = index;
+            if let Some(term) = self.proposals.remove(&index) {
+                let ticket = Ticket { index, term };
+                self.events.push(if term == entry.term {
+                    Event::Applied(ticket)
+                } else {
+                    Event::Dropped(ticket)
+                });
+            }
+        }
+        self.check_reads();
+        if self.applied - self.log.base_index >= self.config.snapshot_after.max(1) {
+            self.take_snapshot();
+        }
+    }
+
+    fn take_snapshot(&mut self) {
+        let Ok(image) = self.db.image_bytes() else {
+            return; // tried again after the next entry
+        };
+        let members = self
+            .log
+            .members_at(self.applied)
+            .or(self.snapshot.as_ref().map(|snapshot| &snapshot.members))
+            .cloned()
+            .unwrap_or_default();
+        let term = self
+            .log
+            .term(self.applied)
+            .expect("applied entries are in the log");
+        self.log.compact(self.applied);
+        self.snapshot = Some(Snapshot {
+            index: self.applied,
+            term,
+            members,
+            image,
+        });
+        self.unsaved = true;
+        self.snapshot_unsaved = true;
+    }
+
+    // ---------- Reads ----------
+
+    fn check_reads(&mut self) {
+        if self.role != Role::Leader || self.reads.is_empty() {
+            return;
+        }
+        // The last round a majority answered: no other leader existed then
+        let mut rounds: Vec<u64> = self
+            .members
+            .keys()
+            .map(|id| match self.progress.get(id) {
+                Some(progress) => progress.round,
+                None if *id == self.id => self.round,
+                None => 0,
+            })
+            .collect();
+        rounds.sort_unstable_by(|a, b| b.cmp(a));
+        let confirmed = rounds.get(self.quorum() - 1).copied().unwrap_or(0);
+        let applied = self.applied;
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.reads)
+            .into_iter()
+            .partition(|read| read.round <= confirmed && read.index <= applied);
+        self.reads = waiting;
+        self.events
+            .extend(ready.into_iter().map(|read| Event::ReadReady(read.id)));
+    }
+
+    // ---------- Membership ----------
+
+    // One change at a time, and only once this leader's first entry is
+    // committed (so that its predecessor's last change is too)
+    fn change_members(&mut self, members: BTreeMap<NodeId, String>) -> Result<Ticket, RaftError> {
+        self.check_leader()?;
+        if self.commit < self.term_start || self.log.has_members_after(self.commit) {
+            return Err(RaftError::Invalid(
+                "a membership change or election is under way; try again".to_string(),
+            ));
+        }
+        let ticket = self.append(Payload::Members(members));
+        self.track(ticket);
+        Ok(ticket)
+    }
+
+    // The configuration takes effect as soon as it's in the log
+    fn update_members(&mut self) {
+        self.members = self
+            .log
+            .members_at(self.log.last_index())
+            .or(self.snapshot.as_ref().map(|snapshot| &snapshot.members))
+            .cloned()
+            .unwrap_or_default();
+        if self.role == Role::Leader {
+            let next = self.log.last_index() + 1;
+            self.progress.retain(|id, _| self.members.contains_key(id));
+            for peer in self.peers() {
+                self.progress
+                    .entry(peer)
+                    .or_insert_with(|| Progress::new(next));
+            }
+        }
+    }
+
+    // ---------- Helpers ----------
+
+    // What the commands will do, worked out once here so that every node
+    // does exactly that (expiry deadlines in particular). Values are checked
+    // against the schemas as the leader's data stands.
+    fn changes(&self, commands: Vec<Command>) -> Result<Vec<Change>, RaftError> {
+        let mut created: BTreeMap<String, CollectionOptions> = BTreeMap::new();
+        let mut changes = Vec::new();
+        for command in commands {
+            let change = match command {
+                Command::Insert {
+                    collection,
+                    key,
+                    value,
+                    ttl,
+                } => {
+                    let scratch;
+                    let keyspace = match collection.as_ref().and_then(|name| created.get(name)) {
+                        Some(options) => {
+                            scratch = Keyspace::new(options.clone());
+                            &scratch
+                        }
+                        None => match self.db.keyspace_for(collection.as_deref()) {
+                            Some(keyspace) => keyspace,
+                            None => {
+                                scratch = Keyspace::default();
+                                &scratch
+                            }
+                        },
+                    };
+                    keyspace
+                        .check(&key, &value)
+                        .map_err(|e| RaftError::Invalid(e.to_string()))?;
+                    let expires = deadline_after(ttl.or(keyspace.options.default_ttl));
+                    Change {
+                        collection,
+                        op: ChangeOp::Put {
+                            key,
+                            value,
+                            expires,
+                        },
+                    }
+                }
+                Command::Delete { collection, key } => Change {
+                    collection,
+                    op: ChangeOp::Delete { key },
+                },
+                Command::Clear { collection } => Change {
+                    collection,
+                    op: ChangeOp::Clear,
+                },
+                Command::CreateCollection { name, options } => {
+                    created.insert(name.clone(), options.clone());
+                    Change {
+                        collection: Some(name),
+                        op: ChangeOp::Options(options),
+                    }
+                }
+                Command::DropCollection { name } => {
+                    created.remove(&name);
+                    Change {
+                        collection: Some(name),
+                        op: ChangeOp::DropCollection,
+                    }
+                }
+            };
+            changes.push(change);
+        }
+        Ok(changes)
+    }
+
+    // Report on this write in take_events
+    fn track(&mut self, ticket: Ticket) {
+        // An earlier write here at the same index was overwritten
+        if let Some(term) = self.proposals.insert(ticket.index, ticket.term)
+            && term != ticket.term
+        {
+            self.events.push(Event::Dropped(Ticket {
+                index: ticket.index,
+                term,
+            }));
+        }
+    }
+
+    fn check_leader(&self) -> Result<(), RaftError> {
+        match self.role {
+            Role::Leader => Ok(()),
+            _ => Err(RaftError::NotLeader(self.leader)),
+        }
+    }
+
+    fn peers(&self) -> Vec<NodeId> {
+        self.members
+            .keys()
+            .copied()
+            .filter(|id| *id != self.id)
+            .collect()
+    }
+
+    fn quorum(&self) -> usize {
+        self.members.len() / 2 + 1
+    }
+
+    fn send(&mut self, to: NodeId, rpc: Rpc) {
+        self.outbox.push((to, Message(rpc)));
+    }
+}
+
+impl Progress {
+    fn new(next: u64) -> Progress {
+        Progress {
+            next,
+            matched: 0,
+            round: 0,
+            blank: false,
+        }
+    }
+}
@@ -0,0 +1,331 @@
+// A whole cluster in one process, over a simulated network, for tests
+//
+//   let mut sim = Simulator::new(3, 42);          // nodes 1, 2 and 3; seed 42
+//   sim.write(vec![Command::insert("k", Value::Integer(1))])?;
+//   sim.partition(&[&[1], &[2, 3]]);              // 1 is cut off from 2 and 3
+//   sim.run(100);
+//   let value = sim.read(|db| db.get("k"))?;      // through the new leader
+//
+// Time passes in ticks. A message takes between the min and max delay (see
+// set_delay) to arrive, may be lost (set_loss), and is dropped if it would
+// cross a partition or reach a crashed node. All the randomness comes from
+// the seed, so a run, failing or not, happens the same way every time.
+//
+// A crashed node keeps what a real one would have saved (its term, vote, log
+// and snapshot) and loses the rest; restart() brings it back from that.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::node::Message;
+use super::{Command, Event, NodeId, RaftConfig, RaftError, RaftNode, Ticket};
+use crate::Database;
+
+// How long write(), read() and friends wait, in ticks
+const PATIENCE: u64 = 2000;
+
+pub struct Simulator {
+    config: RaftConfig,
+    nodes: BTreeMap<NodeId, RaftNode>,
+    down: BTreeSet<NodeId>,
+    groups: BTreeMap<NodeId, usize>, // partitions; nodes not listed are together
+    in_flight: Vec<InFlight>,
+    events: Vec<(NodeId, Event)>,
+    now: u64,
+    sent: u64, // orders the messages arriving in the same tick
+    rng: u64,
+    loss: f64,
+    delay: (u64, u64),
+}
+
+struct InFlight {
+    arrives: u64,
+    sent: u64,
+    from: NodeId,
+    to: NodeId,
+    message: Message,
+}
+
+impl Simulator {
+    // A new cluster of nodes 1 to `nodes`, with empty databases
+    pub fn new(nodes: u64, seed: u64) -> Simulator {
+        let config = RaftConfig {
+            seed,
+            ..RaftConfig::default()
+        };
+        Simulator::with_config(nodes, config)
+    }
+
+    pub fn with_config(nodes: u64, config: RaftConfig) -> Simulator {
+        let members: BTreeMap<NodeId, String> =
+            (1..=nodes).map(|id| (id, format!("node-{}", id))).collect();
+        let mut sim = Simulator {
+            config: config.clone(),
+            nodes: BTreeMap::new(),
+            down: BTreeSet::new(),
+            groups: BTreeMap::new(),
+            in_flight: Vec::new(),
+            events: Vec::new(),
+            now: 0,
+            sent: 0,
+            rng: config.seed.wrapping_mul(0x2545_F491_4F6C_DD1D) | 1,
+            loss: 0.0,
+            delay: (1, 2),
+        };
+        for id in members.keys() {
+            let node = RaftNode::new(*id, members.clone(), empty_database(*id), config.clone())
+                .expect("an empty database always encodes");
+            sim.nodes.insert(*id, node);
+        }
+        sim
+    }
+
+    // ---------- The network ----------
+
+    // The chance that a message is lost, 0.0 to 1.0
+    pub fn set_loss(&mut self, probability: f64) {
+        self.loss = probability;
+    }
+
+    // How many ticks a message takes, at least and at most
+    pub fn set_delay(&mut self, min: u64, max: u64) {
+        self.delay = (min, max.max(min));
+    }
+
+    // Only nodes in the same group can talk; nodes not listed form one more
+    pub fn partition(&mut self, groups: &[&[NodeId]]) {
+        self.groups = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group, ids)| ids.iter().map(move |id| (*id, group + 1)))
+            .collect();
+    }
+
+    pub fn heal(&mut self) {
+        self.groups.clear();
+    }
+
+    // ---------- The nodes ----------
+
+    pub fn crash(&mut self, id: NodeId) {
+        self.down.insert(id);
+    }
+
+    pub fn restart(&mut self, id: NodeId) {
+        let node = self.nodes.remove(&id).expect("no such node");
+        let node = node.restart().expect("the node's own snapshot decodes");
+        self.nodes.insert(id, node);
+        self.down.remove(&id);
+    }
+
+    // A new node, not yet a member: add it with add_member
+    pub fn add_node(&mut self, id: NodeId) {
+        let node = RaftNode::join(id, empty_database(id), self.config.clone());
+        self.nodes.insert(id, node);
+    }
+
+    pub fn node(&self, id: NodeId) -> &RaftNode {
+        &self.nodes[&id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut RaftNode {
+        self.nodes.get_mut(&id).expect("no such node")
+    }
+
+    // The nodes that are up
+    pub fn running(&self) -> Vec<NodeId> {
+        self.nodes
+            .keys()
+            .copied()
+            .filter(|id| !self.down.contains(id))
+            .collect()
+    }
+
+    // The running leader of the latest term, if there is one
+    pub fn leader(&self) -> Option<NodeId> {
+        self.running()
+            .into_iter()
+            .map(|id| self.node(id).status())
+            .filter(|status| status.leader == Some(status.id))
+            .max_by_key(|status| status.term)
+            .map(|status| status.id)
+    }
+
+    // ---------- Time ----------
+
+    pub fn tick(&mut self) {
+        self.now += 1;
+        let (mut due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|message| message.arrives <= self.now);
+        self.in_flight = waiting;
+        due.sort_by_key(|message| (message.arrives, message.sent));
+        for message in due {
+            if self.down.contains(&message.to) || !self.connected(message.from, message.to) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get_mut(&message.to) {
+                node.step(message.from, message.message);
+                self.collect(message.to);
+            }
+        }
+        for id in self.running() {
+            self.node_mut(id).tick();
+            self.collect(id);
+        }
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    // Tick until `done` holds, at most `ticks` times; whether it came to hold
+    pub fn run_until(&mut self, ticks: u64, mut done: impl FnMut(&Simulator) -> bool) -> bool {
+        for _ in 0..ticks {
+            if done(self) {
+                return true;
+            }
+            self.tick();
+        }
+        done(self)
+    }
+
+    // ---------- Using the cluster (through its leader) ----------
+
+    // Write and wait until the leader applied it
+    pub fn write(&mut self, commands: Vec<Command>) -> Result<Ticket, RaftError> {
+        let leader = self.await_leader()?;
+        let ticket = self.node_mut(leader).propose(commands)?;
+        self.collect(leader);
+        self.await_ticket(leader, ticket)
+    }
+
+    // A linearizable read, run on the leader's database
+    pub fn read<T>(&mut self, read: impl FnOnce(&Database) -> T) -> Result<T, RaftError> {
+        let leader = self.await_leader()?;
+        let id = self.node_mut(leader).read()?;
+        self.collect(leader);
+        for _ in 0..PATIENCE {
+            let outcome = self.take_event(leader, |event| match event {
+                Event::ReadReady(ready) if ready == id => Some(true),
+                Event::ReadFailed(failed) if failed == id => Some(false),
+                _ => None,
+            });
+            match outcome {
+                Some(true) => return Ok(read(self.node(leader).database())),
+                Some(false) => return Err(RaftError::NotLeader(self.node(leader).leader())),
+                None => self.tick(),
+            }
+        }
+        Err(RaftError::Timeout)
+    }
+
+    pub fn add_member(&mut self, id: NodeId) -> Result<Ticket, RaftError> {
+        let leader = self.await_leader()?;
+        let ticket = self
+            .node_mut(leader)
+            .add_member(id, &format!("node-{}", id))?;
+        self.collect(leader);
+        self.await_ticket(leader, ticket)
+    }
+
+    pub fn remove_member(&mut self, id: NodeId) -> Result<Ticket, RaftError> {
+        let leader = self.await_leader()?;
+        let ticket = self.node_mut(leader).remove_member(id)?;
+        self.collect(leader);
+        self.await_ticket(leader, ticket)
+    }
+
+    // ---------- Helpers ----------
+
+    fn await_leader(&mut self) -> Result<NodeId, RaftError> {
+        if self.run_until(PATIENCE, |sim| sim.leader().is_some()) {
+            Ok(self.leader().expect("run_until saw one"))
+        } else {
+            Err(RaftError::NotLeader(None))
+        }
+    }
+
+    fn await_ticket(&mut self, leader: NodeId, ticket: Ticket) -> Result<Ticket, RaftError> {
+        for _ in 0..PATIENCE {
+            let outcome = self.take_event(leader, |event| match event {
+                Event::Applied(applied) if applied == ticket => Some(Ok(ticket)),
+                Event::Dropped(dropped) if dropped == ticket => Some(Err(RaftError::Dropped)),
+                Event::Unknown(lost) if lost == ticket => Some(Err(RaftError::Unknown)),
+                _ => None,
+            });
+            if let Some(outcome) = outcome {
+                return outcome;
+            }
+            self.tick();
+        }
+        Err(RaftError::Timeout)
+    }
+
+    fn take_event<T>(
+        &mut self,
+        id: NodeId,
+        mut matches: impl FnMut(Event) -> Option<T>,
+    ) -> Option<T> {
+        let (position, outcome) = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, (node, _))| *node == id)
+            .find_map(|(position, (_, event))| Some((position, matches(*event)?)))?;
+        self.events.remove(position);
+        Some(outcome)
+    }
+
+    // Put what a node sent on the wire, and keep what it reported
+    fn collect(&mut self, id: NodeId) {
+        let node = self.nodes.get_mut(&id).expect("no such node");
+        let messages = node.take_messages();
+        let events = node.take_events();
+        self.events
+            .extend(events.into_iter().map(|event| (id, event)));
+        for (to, message) in messages {
+            if self.chance() < self.loss || !self.connected(id, to) {
+                continue;
+            }
+            let (min, max) = self.delay;
+            let delay = min + self.random() % (max - min + 1);
+            self.sent += 1;
+            self.in_flight.push(InFlight {
+                arrives: self.now + delay,
+                sent: self.sent,
+                from: id,
+                to,
+                message,
+            });
+        }
+    }
+
+    fn connected(&self, a: NodeId, b: NodeId) -> bool {
+        self.groups.get(&a).unwrap_or(&0) == self.groups.get(&b).unwrap_or(&0)
+    }
+
+    // xorshift, as in node.rs
+    fn random(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+
+    // 0.0 up to (not including) 1.0
+    fn chance(&mut self) -> f64 {
+        (self.random() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Never saved: the simulated nodes live in memory only
+fn empty_database(id: NodeId) -> Database {
+    let mut db = Database::new(&format!("raft-sim-node-{}.db", id));
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db
+}
@@ -0,0 +1,114 @@
+// The follower side: stay connected to the primary and apply what it sends
+
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::LINK_TIMEOUT;
+use super::protocol::{self, MAGIC, Message};
+use crate::server::Shared;
+
+// Waits between attempts to reach the primary
+const RETRY_MIN: Duration = Duration::from_millis(100);
+const RETRY_MAX: Duration = Duration::from_secs(2);
+
+// Until promoted, told to follow another primary, or shut down
+pub(crate) fn follow(shared: Arc<Shared>, generation: u64) {
+    let mut delay = RETRY_MIN;
+    while shared.replication.is_current(generation) && !shared.is_shutting_down() {
+        let _ = session(&shared, generation, &mut delay); // the primary is down: retry
+        shared.replication.disconnected(generation);
+        thread::sleep(delay);
+        delay = (delay * 2).min(RETRY_MAX);
+    }
+}
+
+// One connection to the primary; `delay` goes back to the minimum once it's up
+fn session(shared: &Shared, generation: u64, delay: &mut Duration) -> io::Result<()> {
+    let Some(primary) = shared.replication.primary(generation) else {
+        return Ok(());
+    };
+    let stream = connect(&primary)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(LINK_TIMEOUT))?;
+    stream.set_write_timeout(Some(LINK_TIMEOUT))?;
+    if !shared.replication.connected(generation, &stream)? {
+        return Ok(());
+    }
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    writer.write_all(MAGIC)?;
+    let position = shared.lock().change_log().position();
+    protocol::send(&mut writer, &Message::Hello { position })?;
+
+    loop {
+        let message = protocol::receive(&mut reader)?;
+        let mut db = shared.lock();
+        // Promoted (or re-pointed) while the message was on its way
+        if !shared.replication.is_current(generation) {
+            return Ok(());
+        }
+        let mut changed = true;
+        let primary_lsn = match message {
+            Message::Snapshot { position, image } => {
+                db.load_snapshot(position, &image)?;
+                *delay = RETRY_MIN;
+                None
+            }
+            Message::Resume { id } => {
+                db.change_log().adopt(id);
+                *delay = RETRY_MIN;
+                changed = false;
+                None
+            }
+            Message::Changes(changes) => {
+                let last = changes.last().map(|(lsn, _)| *lsn);
+                for (lsn, change) in changes {
+                    let expected = db.change_log().lsn + 1;
+                    if lsn != expected {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("expected change {}, got {}", expected, lsn),
+                        ));
+                    }
+                    db.apply_change(lsn, change);
+                }
+                last
+            }
+            Message::Heartbeat { lsn } => {
+                changed = false;
+                Some(lsn)
+            }
+            other => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unexpected {:?}", other),
+                ));
+            }
+        };
+        let applied = db.change_log().lsn;
+        drop(db);
+        if changed {
+            shared.mark_dirty();
+        }
+        shared
+            .replication
+            .heard_from(generation, primary_lsn.unwrap_or(applied));
+        protocol::send(&mut writer, &Message::Ack { lsn: applied })?;
+    }
+}
+
+fn connect(primary: &str) -> io::Result<TcpStream> {
+    let addrs: Vec<SocketAddr> = primary.to_socket_addrs()?.collect();
+    let mut last_error = io::Error::new(ErrorKind::NotFound, "no address to connect to");
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, LINK_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
@@ -0,0 +1,347 @@
+// NEW: Leader-follower replication, for read replicas and hot standbys
+//
+// A primary streams its write log (see changelog.rs) to followers over TCP;
+// a follower applies the changes in order and refuses writes from clients
+// until it is promoted.
+//
+//   // The primary: clients on 6379, followers connect to 7379
+//   resp::Server::bind("127.0.0.1:6379", db)?.replicate_on("127.0.0.1:7379")?.run()?;
+//   // A follower
+//   resp::Server::bind("127.0.0.1:6380", db)?.follow("127.0.0.1:7379").run()?;
+//
+// (littledb-server --replicate-on and --follow do the same.) A new follower
+// starts with a snapshot of the primary's data. After losing the connection,
+// or restarting, it asks for the changes since the last one it applied, and
+// only needs a new snapshot if the primary no longer keeps them (it keeps the
+// last changelog::BACKLOG).
+//
+// Promotion (REPLICAOF NO ONE, POST /promote, ReplicationHandle::promote)
+// makes a follower writable and starts a new history branching off the old
+// one. The other followers can then follow it (REPLICAOF host port) and carry
+// on from where they were, unless they got further than it did. A follower
+// that also replicates_on serves followers of its own, before promotion
+// (chained) and after.
+//
+// Replicated: keys, values, expiry times, collections and their options. Not
+// replicated: index definitions (create them on every server) and version
+// numbers (each server numbers its own, so ETags differ between servers).
+// Expiry deadlines are absolute times, so the servers' clocks should agree.
+
+mod follower;
+mod primary;
+pub(crate) mod protocol;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Database;
+use crate::server::Shared;
+
+// How often a primary with nothing to send says it's still there
+pub(crate) const HEARTBEAT: Duration = Duration::from_millis(500);
+// A link that carried nothing for this long is given up (and reconnected)
+pub(crate) const LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Primary,
+    Follower,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationStats {
+    pub role: Role,
+    // The history the data belongs to, and the changes in it so far
+    pub id: String,
+    pub lsn: u64,
+    // For a follower: its primary, whether it's connected, how many changes
+    // the primary has that aren't applied here yet, and how long ago (in
+    // milliseconds) the primary was last heard from
+    pub primary: Option<String>,
+    pub connected: bool,
+    pub lag: u64,
+    pub last_contact_ms: Option<u64>,
+    // Followers connected to this server
+    pub followers: Vec<FollowerStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FollowerStats {
+    pub addr: String,
+    pub lsn: u64, // the last change it acknowledged
+    pub lag: u64, // changes it hasn't acknowledged yet
+}
+
+// The replication side of a server (part of server::Shared)
+#[derive(Default)]
+pub(crate) struct Replication {
+    listener: Mutex<Option<TcpListener>>, // until the server runs
+    listen_addr: Mutex<Option<SocketAddr>>,
+    upstream: Mutex<Upstream>,
+    followers: Mutex<BTreeMap<u64, FollowerStats>>,
+    next_follower: AtomicU64,
+    // Counts writes, so the threads feeding followers can wait for the next one
+    writes: Mutex<u64>,
+    written: Condvar,
+}
+
+#[derive(Default)]
+struct Upstream {
+    primary: Option<String>, // None = this server is a primary
+    generation: u64,         // bumped whenever `primary` changes
+    link: Option<TcpStream>, // the connection to the primary, while there is one
+    primary_lsn: u64,        // the last LSN the primary reported
+    last_contact: Option<Instant>,
+}
+
+impl Replication {
+    pub(crate) fn listen(&self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        *lock(&self.listen_addr) = Some(listener.local_addr()?);
+        *lock(&self.listener) = Some(listener);
+        Ok(())
+    }
+
+    pub(crate) fn listen_addr(&self) -> Option<SocketAddr> {
+        *lock(&self.listen_addr)
+    }
+
+    // Before the server runs; see follow() for later
+    pub(crate) fn set_primary(&self, primary: &str) {
+        lock(&self.upstream).primary = Some(primary.to_string());
+    }
+
+    pub(crate) fn is_following(&self) -> bool {
+        lock(&self.upstream).primary.is_some()
+    }
+
+    pub(crate) fn notify_write(&self) {
+        *lock(&self.writes) += 1;
+        self.written.notify_all();
+    }
+
+    // Until there was a write after the `seen`th, or the timeout; returns the count
+    fn wait_for_write(&self, seen: u64, timeout: Duration) -> u64 {
+        let writes = lock(&self.writes);
+        let (writes, _) = self
+            .written
+            .wait_timeout_while(writes, timeout, |count| *count == seen)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *writes
+    }
+
+    // Wake the thread accepting followers, so it notices the shutdown
+    pub(crate) fn shut_down(&self) {
+        if let Some(addr) = self.listen_addr() {
+            let _ = TcpStream::connect(addr);
+        }
+        if let Some(link) = &lock(&self.upstream).link {
+            let _ = link.shutdown(Shutdown::Both);
+        }
+    }
+
+    // ---------- The follower side (see follower.rs) ----------
+
+    // The primary to follow, unless the thread of `generation` should stop
+    fn primary(&self, generation: u64) -> Option<String> {
+        let upstream = lock(&self.upstream);
+        (upstream.generation == generation)
+            .then(|| upstream.primary.clone())
+            .flatten()
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.primary(generation).is_some()
+    }
+
+    // false if the thread should stop instead
+    fn connected(&self, generation: u64, link: &TcpStream) -> io::Result<bool> {
+        let mut upstream = lock(&self.upstream);
+        if upstream.generation != generation || upstream.primary.is_none() {
+            return Ok(false);
+        }
+        upstream.link = Some(link.try_clone()?);
+        upstream.last_contact = Some(Instant::now());
+        Ok(true)
+    }
+
+    fn heard_from(&self, generation: u64, primary_lsn: u64) {
+        let mut upstream = lock(&self.upstream);
+        if upstream.generation == generation {
+            upstream.primary_lsn = primary_lsn;
+            upstream.last_contact = Some(Instant::now());
+        }
+    }
+
+    fn disconnected(&self, generation: u64) {
+        let mut upstream = lock(&self.upstream);
+        if upstream.generation == generation {
+            upstream.link = None;
+        }
+    }
+
+    // ---------- The primary side (see primary.rs) ----------
+
+    fn add_follower(&self, addr: SocketAddr, lsn: u64) -> u64 {
+        let id = self.next_follower.fetch_add(1, Ordering::SeqCst);
+        let follower = FollowerStats {
+            addr: addr.to_string(),
+            lsn,
+            lag: 0,
+        };
+        lock(&self.followers).insert(id, follower);
+        id
+    }
+
+    fn acknowledged(&self, follower: u64, lsn: u64) {
+        if let Some(stats) = lock(&self.followers).get_mut(&follower) {
+            stats.lsn = stats.lsn.max(lsn);
+        }
+    }
+
+    fn remove_follower(&self, follower: u64) {
+        lock(&self.followers).remove(&follower);
+    }
+
+    // None if the database doesn't replicate
+    pub(crate) fn stats(&self, db: &Database) -> Option<ReplicationStats> {
+        let position = db.log_position()?;
+        let upstream = lock(&self.upstream);
+        let followers = lock(&self.followers)
+            .values()
+            .map(|follower| FollowerStats {
+                lag: position.lsn.saturating_sub(follower.lsn),
+                ..follower.clone()
+            })
+            .collect();
+        Some(ReplicationStats {
+            role: match upstream.primary {
+                Some(_) => Role::Follower,
+                None => Role::Primary,
+            },
+            id: position.id,
+            lsn: position.lsn,
+            primary: upstream.primary.clone(),
+            connected: upstream.primary.is_some() && upstream.link.is_some(),
+            lag: match upstream.primary {
+                Some(_) => upstream.primary_lsn.saturating_sub(position.lsn),
+                None => 0,
+            },
+            last_contact_ms: upstream
+                .primary
+                .as_ref()
+                .and(upstream.last_contact)
+                .map(|at| at.elapsed().as_millis() as u64),
+            followers,
+        })
+    }
+}
+
+// Start replicating as configured, when the server starts running
+pub(crate) fn start(shared: &Arc<Shared>) -> io::Result<()> {
+    let listener = lock(&shared.replication.listener).take();
+    let following = shared.replication.is_following();
+    if listener.is_none() && !following {
+        return Ok(());
+    }
+    {
+        let mut db = shared.lock();
+        // Picks up where the saved data left off (a follower restarting) ...
+        db.resume_change_log()?;
+        if !following {
+            // ... but a primary starts a new history: followers that got
+            // changes it lost (say, it was killed before saving) can't resume
+            db.change_log().branch();
+        }
+    }
+    if let Some(listener) = listener {
+        let shared = Arc::clone(shared);
+        thread::spawn(move || primary::serve(listener, shared));
+    }
+    if following {
+        let generation = lock(&shared.replication.upstream).generation;
+        let shared = Arc::clone(shared);
+        thread::spawn(move || follower::follow(shared, generation));
+    }
+    Ok(())
+}
+
+// Stop following and accept writes; false if the server wasn't following
+pub(crate) fn promote(shared: &Shared) -> bool {
+    let mut db = shared.lock(); // no change is half applied while we switch
+    let mut upstream = lock(&shared.replication.upstream);
+    if upstream.primary.take().is_none() {
+        return false;
+    }
+    db.change_log().branch();
+    end_link(&mut upstream);
+    true
+}
+
+// Follow another primary from now on, read-only (a promoted server's old
+// followers, or a primary becoming a follower)
+pub(crate) fn follow(shared: &Arc<Shared>, primary: &str) {
+    let generation = {
+        let mut db = shared.lock();
+        db.change_log(); // the position to resume from
+        let mut upstream = lock(&shared.replication.upstream);
+        upstream.primary = Some(primary.to_string());
+        upstream.primary_lsn = 0;
+        end_link(&mut upstream);
+        upstream.generation
+    };
+    let shared = Arc::clone(shared);
+    thread::spawn(move || follower::follow(shared, generation));
+}
+
+// The thread following the old primary stops
+fn end_link(upstream: &mut Upstream) {
+    upstream.generation += 1;
+    if let Some(link) = upstream.link.take() {
+        let _ = link.shutdown(Shutdown::Both);
+    }
+}
+
+// Promotes or re-points a running server from another thread; see the
+// servers' replication_handle()
+#[derive(Clone)]
+pub struct ReplicationHandle {
+    shared: Arc<Shared>,
+}
+
+impl ReplicationHandle {
+    pub(crate) fn new(shared: &Arc<Shared>) -> Self {
+        ReplicationHandle {
+            shared: Arc::clone(shared),
+        }
+    }
+
+    // Stop following and accept writes; false if the server wasn't following
+    pub fn promote(&self) -> bool {
+        promote(&self.shared)
+    }
+
+    // Follow the primary replicating on `primary` ("host:port") from now on
+    pub fn follow(&self, primary: &str) {
+        follow(&self.shared, primary);
+    }
+
+    // None if the server doesn't replicate
+    pub fn stats(&self) -> Option<ReplicationStats> {
+        let db = self.shared.lock();
+        self.shared.replication.stats(&db)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
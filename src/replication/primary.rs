@@ -0,0 +1,117 @@
+// The primary side: accept followers and keep each one fed with changes
+
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::protocol::{self, Message};
+use super::{HEARTBEAT, LINK_TIMEOUT};
+use crate::changelog::LogPosition;
+use crate::server::Shared;
+
+pub(crate) fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.is_shutting_down() {
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || {
+                    let _ = feed(stream, &shared); // the follower reconnects
+                });
+            }
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+fn feed(stream: TcpStream, shared: &Arc<Shared>) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(LINK_TIMEOUT))?;
+    stream.set_write_timeout(Some(LINK_TIMEOUT))?;
+    let peer = stream.peer_addr()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream.try_clone()?);
+
+    protocol::expect_magic(&mut reader)?;
+    let Message::Hello { position } = protocol::receive(&mut reader)? else {
+        return Err(io::Error::new(ErrorKind::InvalidData, "expected Hello"));
+    };
+    let (greeting, mut sent) = {
+        let mut db = shared.lock();
+        let log = db.change_log();
+        if log.can_resume(&position) {
+            let id = log.id.clone();
+            (
+                Message::Resume { id: id.clone() },
+                LogPosition { id, ..position },
+            )
+        } else {
+            let (position, image) = db.snapshot()?;
+            (
+                Message::Snapshot {
+                    position: position.clone(),
+                    image,
+                },
+                position,
+            )
+        }
+    };
+    protocol::send(&mut writer, &greeting)?;
+    drop(greeting);
+
+    let follower = shared.replication.add_follower(peer, sent.lsn);
+    let acks = {
+        let shared = Arc::clone(shared);
+        thread::spawn(move || read_acks(reader, &shared, follower))
+    };
+    let result = send_changes(&mut writer, shared, &mut sent);
+    shared.replication.remove_follower(follower);
+    let _ = stream.shutdown(Shutdown::Both); // ends read_acks
+    let _ = acks.join();
+    result
+}
+
+// Until the server stops or the follower can't keep up; `sent` is where the
+// follower will be once it has applied everything sent so far
+fn send_changes(out: &mut impl Write, shared: &Shared, sent: &mut LogPosition) -> io::Result<()> {
+    let mut writes = 0;
+    while !shared.is_shutting_down() {
+        let (renamed, changes, lsn) = {
+            let mut db = shared.lock();
+            let log = db.change_log();
+            // Gone if a follower of ours loaded a snapshot; renamed if we
+            // were promoted (the follower continues in the new history)
+            if !log.can_resume(sent) {
+                return Err(io::Error::other(
+                    "the follower fell too far behind, or the history changed",
+                ));
+            }
+            let renamed = (log.id != sent.id).then(|| log.id.clone());
+            let changes = log.since(sent.lsn).unwrap_or_default();
+            (renamed, changes, log.lsn)
+        };
+        if let Some(id) = renamed {
+            sent.id = id.clone();
+            protocol::send(out, &Message::Resume { id })?;
+        }
+        match changes.last() {
+            Some((last, _)) => {
+                sent.lsn = *last;
+                protocol::send(out, &Message::Changes(changes))?;
+            }
+            None => protocol::send(out, &Message::Heartbeat { lsn })?,
+        }
+        writes = shared.replication.wait_for_write(writes, HEARTBEAT);
+    }
+    Ok(())
+}
+
+fn read_acks(mut input: BufReader<TcpStream>, shared: &Shared, follower: u64) {
+    while let Ok(Message::Ack { lsn }) = protocol::receive(&mut input) {
+        shared.replication.acknowledged(follower, lsn);
+    }
+}
@@ -0,0 +1,69 @@
+// What primaries and followers say to each other
+//
+// A follower connects and sends MAGIC, then Hello with where its data stands.
+// The primary answers Resume if the changes since then are still in its log,
+// otherwise Snapshot with the whole database; from then on it sends Changes
+// as they are made and a Heartbeat when there are none. The follower sends
+// Ack after applying, so the primary knows how far behind each follower is.
+// Every message is one bincode value.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::changelog::{Change, LogPosition};
+
+pub(crate) const MAGIC: &[u8; 8] = b"LTDBREP1";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Message {
+    // Follower to primary
+    Hello {
+        position: LogPosition,
+    },
+    Ack {
+        lsn: u64,
+    },
+    // Primary to follower
+    Snapshot {
+        position: LogPosition,
+        image: Vec<u8>,
+    },
+    // The changes that follow continue the follower's data, in history `id`
+    Resume {
+        id: String,
+    },
+    Changes(Vec<(u64, Change)>),
+    Heartbeat {
+        lsn: u64,
+    }, // the primary's last LSN
+}
+
+pub(crate) fn send(out: &mut impl Write, message: &Message) -> io::Result<()> {
+    bincode::serialize_into(&mut *out, message).map_err(|e| bincode_error(*e))?;
+    out.flush()
+}
+
+pub(crate) fn receive(input: &mut impl Read) -> io::Result<Message> {
+    bincode::deserialize_from(input).map_err(|e| bincode_error(*e))
+}
+
+pub(crate) fn expect_magic(input: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "not a littledb follower",
+        ));
+    }
+    Ok(())
+}
+
+// I/O errors as they were, so timeouts and dropped links look like themselves
+fn bincode_error(error: bincode::ErrorKind) -> io::Error {
+    match error {
+        bincode::ErrorKind::Io(error) => error,
+        other => io::Error::new(ErrorKind::InvalidData, other.to_string()),
+    }
+}
@@ -0,0 +1,688 @@
+// The Redis commands the server understands, mapped onto Database
+//
+// Redis values are byte strings; littledb values are typed. SET stores a
+// String (Bytes if the data isn't UTF-8), INCR stores an Integer, and GET
+// returns any value as text: numbers in decimal, objects and arrays as JSON.
+//
+// PUBLISH sends a message as SET stores a value, and a subscriber gets it
+// back as GET would. A connection with subscriptions gets its messages as
+// pushes ("message", channel, payload); in RESP2 it can then only change its
+// subscriptions, PING and QUIT, as in Redis.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::protocol::Reply;
+use crate::glob::Glob;
+use crate::pubsub::{Message, Overflow, Subscriber};
+use crate::replication::{self, ReplicationStats};
+use crate::server::Shared;
+use crate::{Database, Value};
+
+// Redis' default SCAN COUNT
+const DEFAULT_SCAN_COUNT: usize = 10;
+// Unfinished SCANs a connection may have going at once
+const MAX_OPEN_SCANS: usize = 1024;
+// Messages waiting for a subscribed client; one that falls further behind is
+// disconnected
+const SUBSCRIBER_QUEUE: usize = 8192;
+// What a subscribed RESP2 connection may do
+const SUBSCRIBED_COMMANDS: &[&str] = &[
+    "subscribe",
+    "unsubscribe",
+    "psubscribe",
+    "punsubscribe",
+    "ping",
+    "quit",
+];
+
+// Per-connection state
+pub(crate) struct Connection {
+    pub(crate) protocol: u8, // 2 or 3 (HELLO)
+    pub(crate) closing: bool,
+    scans: HashMap<u64, String>, // SCAN cursor -> last key returned
+    next_cursor: u64,
+    // Made by the first SUBSCRIBE or PSUBSCRIBE; serve() forwards its messages
+    subscriber: Option<Arc<Subscriber>>,
+}
+
+// (name, min arguments, max arguments (None = any number), writes)
+type Spec = (&'static str, usize, Option<usize>, bool);
+
+const COMMANDS: &[Spec] = &[
+    ("ping", 0, Some(1), false),
+    ("echo", 1, Some(1), false),
+    ("hello", 0, None, false),
+    ("quit", 0, Some(0), false),
+    ("select", 1, Some(1), false),
+    ("command", 0, None, false),
+    ("client", 1, None, false),
+    ("info", 0, None, false),
+    ("dbsize", 0, Some(0), false),
+    ("get", 1, Some(1), false),
+    ("set", 2, None, true),
+    ("del", 1, None, true),
+    ("exists", 1, None, false),
+    ("keys", 1, Some(1), false),
+    ("scan", 1, Some(5), false),
+    ("incr", 1, Some(1), true),
+    ("decr", 1, Some(1), true),
+    ("incrby", 2, Some(2), true),
+    ("decrby", 2, Some(2), true),
+    ("expire", 2, Some(2), true),
+    ("pexpire", 2, Some(2), true),
+    ("ttl", 1, Some(1), false),
+    ("pttl", 1, Some(1), false),
+    ("persist", 1, Some(1), true),
+    ("mget", 1, None, false),
+    ("mset", 2, None, true),
+    ("flushdb", 0, Some(1), true),
+    ("flushall", 0, Some(1), true),
+    ("save", 0, Some(0), false),
+    ("shutdown", 0, Some(1), false),
+    ("replicaof", 2, Some(2), false),
+    ("slaveof", 2, Some(2), false),
+    ("publish", 2, Some(2), false),
+    ("subscribe", 1, None, false),
+    ("unsubscribe", 0, None, false),
+    ("psubscribe", 1, None, false),
+    ("punsubscribe", 0, None, false),
+    ("pubsub", 1, None, false),
+];
+
+impl Connection {
+    pub(crate) fn new() -> Self {
+        Connection {
+            protocol: 2,
+            closing: false,
+            scans: HashMap::new(),
+            next_cursor: 1,
+            subscriber: None,
+        }
+    }
+
+    pub(crate) fn subscriber(&self) -> Option<&Arc<Subscriber>> {
+        self.subscriber.as_ref()
+    }
+
+    // The replies to a request: one, except for (P)(UN)SUBSCRIBE, which
+    // answer once per channel
+    pub(crate) fn execute(&mut self, request: Vec<Vec<u8>>, shared: &Arc<Shared>) -> Vec<Reply> {
+        let Some((name, args)) = request.split_first() else {
+            return vec![Reply::error("empty command")];
+        };
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        let Some((_, min, max, writes)) = COMMANDS.iter().find(|spec| spec.0 == name) else {
+            return vec![Reply::error(format!("unknown command '{}'", name))];
+        };
+        if args.len() < *min || max.is_some_and(|max| args.len() > max) {
+            return vec![Reply::error(format!(
+                "wrong number of arguments for '{}' command",
+                name
+            ))];
+        }
+        if self.protocol == 2 && self.subscribed() && !SUBSCRIBED_COMMANDS.contains(&name.as_str())
+        {
+            return vec![Reply::error(format!(
+                "Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context",
+                name
+            ))];
+        }
+        if *writes && shared.is_read_only() {
+            return vec![Reply::Error(
+                "READONLY You can't write against a read only replica.".to_string(),
+            )];
+        }
+        if matches!(
+            name.as_str(),
+            "subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe"
+        ) {
+            return self
+                .subscriptions(&name, args, shared)
+                .unwrap_or_else(|error| vec![error]);
+        }
+        let reply = self.run(&name, args, shared).unwrap_or_else(|error| error);
+        if *writes && !matches!(reply, Reply::Error(_)) {
+            shared.mark_dirty();
+        }
+        vec![reply]
+    }
+
+    // Err holds the error reply, so argument parsing can use `?`
+    fn run(&mut self, name: &str, args: &[Vec<u8>], shared: &Arc<Shared>) -> Result<Reply, Reply> {
+        // Channels aren't in the database: no need to lock it
+        match name {
+            "publish" => {
+                let reached = shared
+                    .pubsub
+                    .publish(&channel(&args[0])?, bytes_value(&args[1]));
+                return Ok(Reply::Integer(reached as i64));
+            }
+            "pubsub" => return pubsub(args, shared),
+            _ => {}
+        }
+        let mut db = shared.lock();
+        let reply = match name {
+            "ping" if self.protocol == 2 && self.subscribed() => Reply::Array(vec![
+                Reply::bulk("pong"),
+                Reply::Bulk(args.first().cloned().unwrap_or_default()),
+            ]),
+            "ping" => match args.first() {
+                Some(message) => Reply::Bulk(message.clone()),
+                None => Reply::Simple("PONG".to_string()),
+            },
+            "echo" => Reply::Bulk(args[0].clone()),
+            "hello" => return self.hello(args, shared.is_read_only()),
+            "quit" => {
+                self.closing = true;
+                Reply::ok()
+            }
+            "select" => match integer(&args[0])? {
+                0 => Reply::ok(),
+                _ => Reply::error("DB index is out of range"),
+            },
+            // Clients ask for command docs and set their name on connect
+            "command" => Reply::Array(Vec::new()),
+            "client" => Reply::ok(),
+            "info" => Reply::bulk(format!(
+                "# Server\r\nlittledb_version:{}\r\nredis_mode:standalone\r\n\r\n# Replication\r\n{}\r\n# Keyspace\r\ndb0:keys={}\r\n",
+                env!("CARGO_PKG_VERSION"),
+                replication_info(shared.replication.stats(&db)),
+                db.count()
+            )),
+            "dbsize" => Reply::Integer(db.count() as i64),
+
+            "get" => match db.get(&key(&args[0])?) {
+                Some(value) => Reply::Bulk(value_bytes(&value)),
+                None => Reply::Null,
+            },
+            "set" => set(&mut db, args)?,
+            "del" => {
+                let keys = keys(args)?;
+                Reply::Integer(db.batch_delete(keys.iter().map(String::as_str).collect()) as i64)
+            }
+            "exists" => {
+                let keys = keys(args)?;
+                Reply::Integer(keys.iter().filter(|key| db.exists(key)).count() as i64)
+            }
+            "keys" => {
+                let pattern = Glob::new(&key(&args[0])?);
+                let keys = db
+                    .keys_with_prefix(&pattern.literal_prefix())
+                    .into_iter()
+                    .filter(|key| pattern.matches(key))
+                    .map(Reply::bulk)
+                    .collect();
+                Reply::Array(keys)
+            }
+            "scan" => return self.scan(&db, args),
+            "incr" => incr(&mut db, &key(&args[0])?, 1)?,
+            "decr" => incr(&mut db, &key(&args[0])?, -1)?,
+            "incrby" => incr(&mut db, &key(&args[0])?, integer(&args[1])?)?,
+            "decrby" => {
+                let by = integer(&args[1])?
+                    .checked_neg()
+                    .ok_or_else(|| Reply::error("decrement would overflow"))?;
+                incr(&mut db, &key(&args[0])?, by)?
+            }
+            "expire" | "pexpire" => {
+                let amount = integer(&args[1])?;
+                let key = key(&args[0])?;
+                let ttl = if name == "expire" {
+                    Duration::from_secs(amount.max(0) as u64)
+                } else {
+                    Duration::from_millis(amount.max(0) as u64)
+                };
+                // A TTL of zero or less deletes the key right away, as in Redis
+                let done = if amount <= 0 {
+                    db.delete(&key).is_ok()
+                } else {
+                    db.expire(&key, ttl).map_err(Reply::error)?
+                };
+                Reply::Integer(done as i64)
+            }
+            "ttl" | "pttl" => {
+                let key = key(&args[0])?;
+                match db.ttl(&key) {
+                    _ if !db.exists(&key) => Reply::Integer(-2),
+                    None => Reply::Integer(-1),
+                    Some(ttl) if name == "ttl" => {
+                        Reply::Integer(((ttl.as_millis() + 500) / 1000) as i64)
+                    }
+                    Some(ttl) => Reply::Integer(ttl.as_millis() as i64),
+                }
+            }
+            "persist" => {
+                let persisted = db.persist(&key(&args[0])?).map_err(Reply::error)?;
+                Reply::Integer(persisted as i64)
+            }
+            "mget" => {
+                let keys = keys(args)?;
+                let found = db.batch_get(keys.iter().map(String::as_str).collect());
+                let values = keys
+                    .iter()
+                    .map(|key| match found.get(key) {
+                        Some(value) => Reply::Bulk(value_bytes(value)),
+                        None => Reply::Null,
+                    })
+                    .collect();
+                Reply::Array(values)
+            }
+            "mset" => {
+                if !args.len().is_multiple_of(2) {
+                    return Err(Reply::error("wrong number of arguments for 'mset' command"));
+                }
+                let mut entries = Vec::new();
+                for pair in args.chunks(2) {
+                    entries.push((key(&pair[0])?, bytes_value(&pair[1])));
+                }
+                for (key, _) in &entries {
+                    db.persist(key).map_err(Reply::error)?; // MSET, like SET, drops any TTL
+                }
+                db.batch_insert(entries).map_err(Reply::error)?;
+                Reply::ok()
+            }
+            "flushdb" | "flushall" => {
+                db.clear();
+                Reply::ok()
+            }
+            "save" => {
+                shared.save(&db).map_err(Reply::error)?;
+                Reply::ok()
+            }
+            "shutdown" => {
+                let save = !args
+                    .first()
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case(b"nosave"));
+                if save {
+                    shared.save(&db).map_err(Reply::error)?;
+                } else {
+                    shared.discard_changes();
+                }
+                drop(db);
+                shared.shut_down();
+                self.closing = true;
+                Reply::ok()
+            }
+            "replicaof" | "slaveof" => {
+                drop(db); // promoting and following lock it themselves
+                return replicaof(args, shared);
+            }
+            _ => unreachable!("every command in COMMANDS is handled"),
+        };
+        Ok(reply)
+    }
+
+    fn subscribed(&self) -> bool {
+        self.subscriber.as_ref().is_some_and(|subscriber| {
+            !subscriber.channels().is_empty() || !subscriber.patterns().is_empty()
+        })
+    }
+
+    // (P)SUBSCRIBE channel..., (P)UNSUBSCRIBE [channel...] (none = all):
+    // a push (name, channel, subscriptions left) per channel
+    fn subscriptions(
+        &mut self,
+        name: &str,
+        args: &[Vec<u8>],
+        shared: &Arc<Shared>,
+    ) -> Result<Vec<Reply>, Reply> {
+        let mut targets = args
+            .iter()
+            .map(|arg| channel(arg))
+            .collect::<Result<Vec<String>, Reply>>()?;
+        let subscribing = matches!(name, "subscribe" | "psubscribe");
+        if !subscribing && self.subscriber.is_none() {
+            return Ok(vec![Reply::Push(vec![
+                Reply::bulk(name),
+                Reply::Null,
+                Reply::Integer(0),
+            ])]);
+        }
+        let subscriber = self.subscriber.get_or_insert_with(|| {
+            Arc::new(
+                shared
+                    .pubsub
+                    .subscriber_with(SUBSCRIBER_QUEUE, Overflow::Disconnect),
+            )
+        });
+        if targets.is_empty() {
+            targets = match name {
+                "unsubscribe" => subscriber.channels(),
+                _ => subscriber.patterns(),
+            };
+        }
+        if targets.is_empty() {
+            let left = subscriber.channels().len() + subscriber.patterns().len();
+            return Ok(vec![Reply::Push(vec![
+                Reply::bulk(name),
+                Reply::Null,
+                Reply::Integer(left as i64),
+            ])]);
+        }
+        Ok(targets
+            .into_iter()
+            .map(|target| {
+                let left = match name {
+                    "subscribe" => subscriber.subscribe(&target),
+                    "unsubscribe" => subscriber.unsubscribe(&target),
+                    "psubscribe" => subscriber.psubscribe(&target),
+                    _ => subscriber.punsubscribe(&target),
+                };
+                Reply::Push(vec![
+                    Reply::bulk(name),
+                    Reply::bulk(target),
+                    Reply::Integer(left as i64),
+                ])
+            })
+            .collect())
+    }
+
+    // HELLO [protover [AUTH user pass] [SETNAME name]]
+    fn hello(&mut self, args: &[Vec<u8>], following: bool) -> Result<Reply, Reply> {
+        if let Some(version) = args.first() {
+            match integer(version)? {
+                version @ (2 | 3) => self.protocol = version as u8,
+                _ => {
+                    return Err(Reply::Error(
+                        "NOPROTO unsupported protocol version".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(Reply::Map(vec![
+            (Reply::bulk("server"), Reply::bulk("littledb")),
+            (
+                Reply::bulk("version"),
+                Reply::bulk(env!("CARGO_PKG_VERSION")),
+            ),
+            (Reply::bulk("proto"), Reply::Integer(self.protocol as i64)),
+            (Reply::bulk("mode"), Reply::bulk("standalone")),
+            (
+                Reply::bulk("role"),
+                Reply::bulk(if following { "replica" } else { "master" }),
+            ),
+            (Reply::bulk("modules"), Reply::Array(Vec::new())),
+        ]))
+    }
+
+    // SCAN cursor [MATCH pattern] [COUNT count]
+    // A cursor remembers the last key returned, so keys that exist for the
+    // whole scan are returned exactly once even if others come and go
+    fn scan(&mut self, db: &Database, args: &[Vec<u8>]) -> Result<Reply, Reply> {
+        let cursor = integer(&args[0])?;
+        let after = match cursor {
+            0 => None,
+            n => Some(
+                self.scans
+                    .remove(&(n as u64))
+                    .ok_or_else(|| Reply::error("invalid cursor"))?,
+            ),
+        };
+        let mut pattern = Glob::new("*");
+        let mut count = DEFAULT_SCAN_COUNT;
+        for option in args[1..].chunks(2) {
+            let [name, value] = option else {
+                return Err(Reply::error("syntax error"));
+            };
+            if name.eq_ignore_ascii_case(b"match") {
+                pattern = Glob::new(&key(value)?);
+            } else if name.eq_ignore_ascii_case(b"count") {
+                count = integer(value)?.max(1) as usize;
+            } else {
+                return Err(Reply::error("syntax error"));
+            }
+        }
+
+        let keys = db.keys_with_prefix(&pattern.literal_prefix());
+        let start = match &after {
+            Some(last) => keys.partition_point(|key| key <= last),
+            None => 0,
+        };
+        let end = (start + count).min(keys.len());
+        let batch: Vec<Reply> = keys[start..end]
+            .iter()
+            .filter(|key| pattern.matches(key))
+            .map(|key| Reply::bulk(key.clone()))
+            .collect();
+        let next = if end < keys.len() {
+            if self.scans.len() >= MAX_OPEN_SCANS {
+                self.scans.clear(); // abandoned scans; their cursors become invalid
+            }
+            let cursor = self.next_cursor;
+            self.next_cursor += 1;
+            self.scans.insert(cursor, keys[end - 1].clone());
+            cursor
+        } else {
+            0
+        };
+        Ok(Reply::Array(vec![
+            Reply::bulk(next.to_string()),
+            Reply::Array(batch),
+        ]))
+    }
+}
+
+// A connection's subscriptions end with it (serve()'s forwarding thread
+// holds on to the subscriber until then)
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Some(subscriber) = &self.subscriber {
+            subscriber.close();
+        }
+    }
+}
+
+// What a subscribed connection is pushed for a message
+pub(crate) fn message_reply(message: &Message) -> Reply {
+    let mut reply = match &message.pattern {
+        Some(pattern) => vec![Reply::bulk("pmessage"), Reply::bulk(pattern.clone())],
+        None => vec![Reply::bulk("message")],
+    };
+    reply.push(Reply::bulk(message.channel.clone()));
+    reply.push(Reply::Bulk(value_bytes(&message.payload)));
+    Reply::Push(reply)
+}
+
+// PUBSUB CHANNELS [pattern] | NUMSUB [channel...] | NUMPAT
+fn pubsub(args: &[Vec<u8>], shared: &Arc<Shared>) -> Result<Reply, Reply> {
+    let subcommand = String::from_utf8_lossy(&args[0]).to_lowercase();
+    let rest = &args[1..];
+    match (subcommand.as_str(), rest.len()) {
+        ("channels", 0 | 1) => {
+            let pattern = rest.first().map(|arg| channel(arg)).transpose()?;
+            let channels = shared.pubsub.channels(pattern.as_deref());
+            Ok(Reply::Array(
+                channels.into_iter().map(Reply::bulk).collect(),
+            ))
+        }
+        ("numsub", _) => {
+            let mut counts = Vec::new();
+            for arg in rest {
+                let channel = channel(arg)?;
+                let count = shared.pubsub.subscriber_count(&channel);
+                counts.push(Reply::bulk(channel));
+                counts.push(Reply::Integer(count as i64));
+            }
+            Ok(Reply::Array(counts))
+        }
+        ("numpat", 0) => Ok(Reply::Integer(shared.pubsub.pattern_count() as i64)),
+        _ => Err(Reply::error(format!(
+            "unknown subcommand or wrong number of arguments for 'pubsub|{}'",
+            subcommand
+        ))),
+    }
+}
+
+// SET key value [NX|XX] [EX seconds|PX milliseconds|KEEPTTL]
+fn set(db: &mut Database, args: &[Vec<u8>]) -> Result<Reply, Reply> {
+    let key = key(&args[0])?;
+    let value = bytes_value(&args[1]);
+    let (mut nx, mut xx, mut keep_ttl) = (false, false, false);
+    let mut ttl: Option<Duration> = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let option = String::from_utf8_lossy(option).to_lowercase();
+        match option.as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "keepttl" => keep_ttl = true,
+            "ex" | "px" => {
+                let amount = options
+                    .next()
+                    .ok_or_else(|| Reply::error("syntax error"))
+                    .and_then(|amount| integer(amount))?;
+                if amount <= 0 || ttl.is_some() {
+                    return Err(Reply::error("invalid expire time in 'set' command"));
+                }
+                ttl = Some(if option == "ex" {
+                    Duration::from_secs(amount as u64)
+                } else {
+                    Duration::from_millis(amount as u64)
+                });
+            }
+            _ => return Err(Reply::error("syntax error")),
+        }
+    }
+    if (nx && xx) || (keep_ttl && ttl.is_some()) {
+        return Err(Reply::error("syntax error"));
+    }
+
+    let exists = db.exists(&key);
+    if (nx && exists) || (xx && !exists) {
+        return Ok(Reply::Null);
+    }
+    let kept = if keep_ttl { db.ttl(&key) } else { None };
+    match ttl.or(kept) {
+        Some(ttl) => db.insert_with_ttl(key, value, ttl),
+        None => db.insert(key, value),
+    }
+    .map_err(Reply::error)?;
+    Ok(Reply::ok())
+}
+
+// REPLICAOF NO ONE promotes; REPLICAOF host port follows the primary whose
+// replication port (see replication/) that is
+fn replicaof(args: &[Vec<u8>], shared: &Arc<Shared>) -> Result<Reply, Reply> {
+    let host = String::from_utf8_lossy(&args[0]);
+    let port = String::from_utf8_lossy(&args[1]);
+    if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+        replication::promote(shared);
+        return Ok(Reply::ok());
+    }
+    let port: u16 = port
+        .parse()
+        .map_err(|_| Reply::error("Invalid master port"))?;
+    let primary = if host.contains(':') {
+        format!("[{}]:{}", host, port) // IPv6
+    } else {
+        format!("{}:{}", host, port)
+    };
+    replication::follow(shared, &primary);
+    Ok(Reply::ok())
+}
+
+// INFO's replication section, with Redis' field names; lags count changes
+fn replication_info(stats: Option<ReplicationStats>) -> String {
+    let Some(stats) = stats else {
+        return "role:master\r\nconnected_slaves:0\r\n".to_string();
+    };
+    let mut lines = Vec::new();
+    match &stats.primary {
+        Some(primary) => {
+            let (host, port) = primary.rsplit_once(':').unwrap_or((primary, ""));
+            lines.push("role:slave".to_string());
+            lines.push(format!("master_host:{}", host));
+            lines.push(format!("master_port:{}", port));
+            let status = if stats.connected { "up" } else { "down" };
+            lines.push(format!("master_link_status:{}", status));
+            if let Some(ms) = stats.last_contact_ms {
+                lines.push(format!("master_last_io_seconds_ago:{}", ms / 1000));
+            }
+            lines.push(format!("slave_repl_offset:{}", stats.lsn));
+            lines.push(format!("slave_lag:{}", stats.lag));
+            lines.push("slave_read_only:1".to_string());
+        }
+        None => lines.push("role:master".to_string()),
+    }
+    lines.push(format!("connected_slaves:{}", stats.followers.len()));
+    for (i, follower) in stats.followers.iter().enumerate() {
+        let (ip, port) = follower
+            .addr
+            .rsplit_once(':')
+            .unwrap_or((&follower.addr, ""));
+        lines.push(format!(
+            "slave{}:ip={},port={},state=online,offset={},lag={}",
+            i, ip, port, follower.lsn, follower.lag
+        ));
+    }
+    lines.push(format!("master_replid:{}", stats.id));
+    lines.push(format!("master_repl_offset:{}", stats.lsn));
+    lines.join("\r\n") + "\r\n"
+}
+
+// INCR and friends: the value must be an Integer, or a string holding one
+fn incr(db: &mut Database, key: &str, by: i64) -> Result<Reply, Reply> {
+    let not_integer = || Reply::error("value is not an integer or out of range");
+    match db.get(key) {
+        None | Some(Value::Integer(_)) => {
+            let value = db.incr_by(key, by).map_err(|_| not_integer())?;
+            Ok(Reply::Integer(value))
+        }
+        Some(Value::String(text)) => {
+            let current: i64 = text.parse().map_err(|_| not_integer())?;
+            let value = current.checked_add(by).ok_or_else(not_integer)?;
+            // compare_and_swap keeps the key's TTL, as INCR does
+            db.compare_and_swap(key, Some(Value::String(text)), Value::Integer(value))
+                .map_err(Reply::error)?;
+            Ok(Reply::Integer(value))
+        }
+        Some(_) => Err(Reply::Error(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        )),
+    }
+}
+
+// ---------- Arguments and values ----------
+
+fn key(arg: &[u8]) -> Result<String, Reply> {
+    String::from_utf8(arg.to_vec()).map_err(|_| Reply::error("keys must be valid UTF-8"))
+}
+
+fn keys(args: &[Vec<u8>]) -> Result<Vec<String>, Reply> {
+    args.iter().map(|arg| key(arg)).collect()
+}
+
+fn channel(arg: &[u8]) -> Result<String, Reply> {
+    String::from_utf8(arg.to_vec()).map_err(|_| Reply::error("channels must be valid UTF-8"))
+}
+
+fn integer(arg: &[u8]) -> Result<i64, Reply> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| Reply::error("value is not an integer or out of range"))
+}
+
+fn bytes_value(arg: &[u8]) -> Value {
+    match String::from_utf8(arg.to_vec()) {
+        Ok(text) => Value::String(text),
+        Err(e) => Value::Bytes(e.into_bytes()),
+    }
+}
+
+// What GET returns for a value
+fn value_bytes(value: &Value) -> Vec<u8> {
+    match value {
+        Value::String(text) => text.as_bytes().to_vec(),
+        Value::Bytes(bytes) => bytes.clone(),
+        Value::Integer(i) => i.to_string().into_bytes(),
+        #[cfg(feature = "json")]
+        Value::Array(_) | Value::Object(_) => match value.to_json_string() {
+            Ok(json) => json.into_bytes(),
+            Err(_) => value.to_string().into_bytes(),
+        },
+        other => other.to_string().into_bytes(),
+    }
+}
@@ -0,0 +1,180 @@
+// NEW: A server speaking the Redis protocol (RESP2 and RESP3)
+//
+//   let db = Database::new("mydata.db");
+//   db.load()?;
+//   Server::bind("127.0.0.1:6379", db)?.run()?;
+//
+// Then any Redis client works:  redis-cli SET greeting hello
+//
+// A client may pipeline (send many commands without waiting): replies are
+// written in order and flushed once the requests read so far are answered.
+// Besides the background saves (see server.rs) the database is saved on SAVE
+// and SHUTDOWN. See commands.rs for the supported commands.
+//
+// Once a connection subscribes to a channel, a second thread writes it the
+// messages as they come, taking turns with the replies. A client that doesn't
+// read them fast enough is disconnected (see pubsub.rs).
+
+mod commands;
+pub mod protocol;
+
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::Database;
+use crate::pubsub::Subscriber;
+use crate::replication::ReplicationHandle;
+use crate::server::{self, DEFAULT_SAVE_INTERVAL, Shared, ShutdownHandle};
+use commands::{Connection, message_reply};
+pub use protocol::Reply;
+
+pub struct Server {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    save_interval: Option<Duration>,
+}
+
+impl Server {
+    // Listen on `addr` ("127.0.0.1:6379"; port 0 picks a free one)
+    pub fn bind(addr: impl ToSocketAddrs, db: Database) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let shared = Shared::new(db, listener.local_addr()?);
+        Ok(Server {
+            listener,
+            shared,
+            save_interval: Some(DEFAULT_SAVE_INTERVAL),
+        })
+    }
+
+    // How often changes are saved; None saves only on SAVE and SHUTDOWN
+    pub fn save_interval(mut self, interval: Option<Duration>) -> Self {
+        self.save_interval = interval;
+        self
+    }
+
+    // Serve this server's changes to followers connecting to `addr`
+    // (see replication/)
+    pub fn replicate_on(self, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        self.shared.replication.listen(addr)?;
+        Ok(self)
+    }
+
+    // Follow the primary replicating on `primary` ("host:port"): copy its
+    // data and refuse writes until promoted
+    pub fn follow(self, primary: &str) -> Self {
+        self.shared.replication.set_primary(primary);
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Where followers connect, if replicate_on was called
+    pub fn replication_addr(&self) -> Option<SocketAddr> {
+        self.shared.replication.listen_addr()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(&self.shared)
+    }
+
+    pub fn replication_handle(&self) -> ReplicationHandle {
+        ReplicationHandle::new(&self.shared)
+    }
+
+    // Serve until a client sends SHUTDOWN; changes are saved before returning
+    pub fn run(self) -> io::Result<()> {
+        server::run(self.listener, self.shared, self.save_interval, serve)
+    }
+}
+
+// How long writing to a subscribed client may block before it's dropped
+const SUBSCRIBED_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The writing half of a connection: the replies, and the messages forwarded
+// by another thread
+struct Output {
+    writer: BufWriter<TcpStream>,
+    protocol: u8,
+}
+
+fn lock(output: &Mutex<Output>) -> MutexGuard<'_, Output> {
+    output
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn serve(stream: TcpStream, shared: &Arc<Shared>) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let output = Arc::new(Mutex::new(Output {
+        writer: BufWriter::new(stream),
+        protocol: 2,
+    }));
+    let mut connection = Connection::new();
+    let mut forwarding = false;
+
+    while !connection.closing {
+        let request = match protocol::read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                let mut out = lock(&output);
+                Reply::Error(format!("ERR {}", e))
+                    .write_to(&mut out.writer, connection.protocol)?;
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        // Holding the output while executing keeps a message published after
+        // a SUBSCRIBE from overtaking its reply
+        let mut out = lock(&output);
+        for reply in connection.execute(request, shared) {
+            reply.write_to(&mut out.writer, connection.protocol)?;
+        }
+        out.protocol = connection.protocol;
+        // Pipelined requests still waiting in the buffer are answered first
+        if reader.buffer().is_empty() {
+            out.writer.flush()?;
+        }
+        drop(out);
+        if !forwarding && let Some(subscriber) = connection.subscriber() {
+            forwarding = true;
+            reader
+                .get_ref()
+                .set_write_timeout(Some(SUBSCRIBED_WRITE_TIMEOUT))?;
+            let (subscriber, output) = (Arc::clone(subscriber), Arc::clone(&output));
+            thread::spawn(move || forward(&subscriber, &output));
+        }
+    }
+    lock(&output).writer.flush()
+}
+
+// Write the subscriber's messages to the client until the connection ends
+// (dropping the Connection closes the subscriber) or the client is cut off
+// for falling behind, which ends the connection
+fn forward(subscriber: &Subscriber, output: &Mutex<Output>) {
+    while let Some(message) = subscriber.next_message() {
+        let mut out = lock(output);
+        let protocol = out.protocol;
+        let mut written = message_reply(&message).write_to(&mut out.writer, protocol);
+        // Write what else is waiting before flushing
+        while written.is_ok()
+            && let Some(message) = subscriber.try_next()
+        {
+            written = message_reply(&message).write_to(&mut out.writer, protocol);
+        }
+        if written.and_then(|()| out.writer.flush()).is_err() {
+            subscriber.close();
+            let _ = out.writer.get_ref().shutdown(Shutdown::Both);
+            return;
+        }
+    }
+    if subscriber.is_cut_off() {
+        let _ = lock(output).writer.get_ref().shutdown(Shutdown::Both);
+    }
+}
@@ -0,0 +1,158 @@
+// The RESP wire format (https://redis.io/docs/reference/protocol-spec/)
+//
+// Requests are arrays of bulk strings:  *2\r\n$3\r\nGET\r\n$4\r\nkey1\r\n
+// or "inline" lines, as typed into telnet: GET key1\r\n
+//
+// Replies are written in RESP2 unless the client switched to RESP3 with
+// HELLO 3; the difference here is only how null, maps and pushes look.
+
+use std::io::{self, BufRead, Read, Write};
+
+// Largest bulk string and array a request may contain (Redis' defaults)
+const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024;
+const MAX_ARRAY_LENGTH: usize = 1024 * 1024;
+// ... and the longest line (an inline command or a length header)
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Simple(String), // +OK
+    Error(String),  // -ERR message (the text starts with the error code)
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>), // RESP3 map; a flat array in RESP2
+    Push(Vec<Reply>),         // RESP3 out-of-band data; an array in RESP2
+}
+
+impl Reply {
+    pub fn ok() -> Reply {
+        Reply::Simple("OK".to_string())
+    }
+
+    // A generic "ERR ..." error (errors with their own code, such as NOPROTO,
+    // are built with Reply::Error directly)
+    pub fn error(message: impl std::fmt::Display) -> Reply {
+        Reply::Error(format!("ERR {}", message))
+    }
+
+    pub fn bulk(text: impl Into<String>) -> Reply {
+        Reply::Bulk(text.into().into_bytes())
+    }
+
+    pub fn write_to(&self, out: &mut impl Write, protocol: u8) -> io::Result<()> {
+        match self {
+            Reply::Simple(text) => write!(out, "+{}\r\n", text),
+            Reply::Error(text) => write!(out, "-{}\r\n", text),
+            Reply::Integer(n) => write!(out, ":{}\r\n", n),
+            Reply::Bulk(bytes) => {
+                write!(out, "${}\r\n", bytes.len())?;
+                out.write_all(bytes)?;
+                out.write_all(b"\r\n")
+            }
+            Reply::Null if protocol >= 3 => out.write_all(b"_\r\n"),
+            Reply::Null => out.write_all(b"$-1\r\n"),
+            Reply::Array(items) => write_items(out, '*', items, protocol),
+            Reply::Push(items) if protocol >= 3 => write_items(out, '>', items, protocol),
+            Reply::Push(items) => write_items(out, '*', items, protocol),
+            Reply::Map(pairs) if protocol >= 3 => {
+                write!(out, "%{}\r\n", pairs.len())?;
+                for (key, value) in pairs {
+                    key.write_to(out, protocol)?;
+                    value.write_to(out, protocol)?;
+                }
+                Ok(())
+            }
+            Reply::Map(pairs) => {
+                write!(out, "*{}\r\n", pairs.len() * 2)?;
+                for (key, value) in pairs {
+                    key.write_to(out, protocol)?;
+                    value.write_to(out, protocol)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_items(out: &mut impl Write, tag: char, items: &[Reply], protocol: u8) -> io::Result<()> {
+    write!(out, "{}{}\r\n", tag, items.len())?;
+    for item in items {
+        item.write_to(out, protocol)?;
+    }
+    Ok(())
+}
+
+// The next request: its arguments, None at end of stream. Malformed input is
+// an InvalidData error; the caller answers it and closes the connection.
+pub fn read_request(input: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    loop {
+        let Some(line) = read_line(input)? else {
+            return Ok(None);
+        };
+        if let Some(count) = line.strip_prefix(b"*") {
+            let count = parse_length(count, MAX_ARRAY_LENGTH)?;
+            let mut args = Vec::with_capacity(count.min(1024));
+            for _ in 0..count {
+                args.push(read_bulk(input)?);
+            }
+            return Ok(Some(args));
+        }
+        // Inline command; blank lines are skipped
+        let args: Vec<Vec<u8>> = line
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|word| !word.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        if !args.is_empty() {
+            return Ok(Some(args));
+        }
+    }
+}
+
+fn read_bulk(input: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let line = read_line(input)?.ok_or_else(|| protocol_error("unexpected end of stream"))?;
+    let length = line
+        .strip_prefix(b"$")
+        .ok_or_else(|| protocol_error("expected '$'"))?;
+    let length = parse_length(length, MAX_BULK_LENGTH)?;
+    let mut bulk = vec![0; length + 2];
+    input.read_exact(&mut bulk)?;
+    if !bulk.ends_with(b"\r\n") {
+        return Err(protocol_error("bulk string not followed by CRLF"));
+    }
+    bulk.truncate(length);
+    Ok(bulk)
+}
+
+// A line without its line ending; None at end of stream
+fn read_line(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if input.take(MAX_LINE_LENGTH).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(protocol_error("line too long or not terminated"));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_length(digits: &[u8], max: usize) -> io::Result<usize> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|text| text.parse::<usize>().ok())
+        .filter(|length| *length <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Protocol error: {}", message),
+    )
+}
@@ -0,0 +1,613 @@
+// NEW: Schemas - say what values under a key prefix (or in a collection) must look like
+//
+//   let user = Schema::object()
+//       .property("name", Schema::string().min_length(1))
+//       .property("age", Schema::integer().minimum(0.0))
+//       .property("email", Schema::string().pattern(r"^[^@]+@[^@]+$")?)
+//       .required("name")
+//       .required("age");
+//   db.set_schema("user:", user)?;
+//   db.insert("user:1".to_string(), value)?; // fails if the value doesn't fit
+//
+// Or from a JSON Schema document (a subset of it, see Schema::from_value):
+//   {"type": "object", "required": ["name"],
+//    "properties": {"name": {"type": "string"}, "age": {"type": "integer", "minimum": 0}}}
+//
+// Writes through the database and collection API are checked against every
+// schema whose prefix the key starts with. Setting a schema doesn't touch the
+// data already stored; validate_all lists what doesn't fit.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Value;
+
+// The types a schema can ask for. Besides the JSON Schema ones, every Value
+// variant without a JSON counterpart has its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SchemaType {
+    Null,
+    Boolean,
+    Integer,
+    Number, // Integer, Float or Decimal
+    String,
+    Array,
+    Object,
+    Bytes,
+    Timestamp,
+    Decimal,
+    Uuid,
+}
+
+impl SchemaType {
+    fn parse(name: &str) -> Option<SchemaType> {
+        Some(match name {
+            "null" => SchemaType::Null,
+            "boolean" => SchemaType::Boolean,
+            "integer" => SchemaType::Integer,
+            "number" => SchemaType::Number,
+            "string" => SchemaType::String,
+            "array" => SchemaType::Array,
+            "object" => SchemaType::Object,
+            "bytes" => SchemaType::Bytes,
+            "timestamp" => SchemaType::Timestamp,
+            "decimal" => SchemaType::Decimal,
+            "uuid" => SchemaType::Uuid,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SchemaType::Null => "null",
+            SchemaType::Boolean => "boolean",
+            SchemaType::Integer => "integer",
+            SchemaType::Number => "number",
+            SchemaType::String => "string",
+            SchemaType::Array => "array",
+            SchemaType::Object => "object",
+            SchemaType::Bytes => "bytes",
+            SchemaType::Timestamp => "timestamp",
+            SchemaType::Decimal => "decimal",
+            SchemaType::Uuid => "uuid",
+        }
+    }
+
+    // Integer is strict: 1.0 is a Float, and a schema asking for integers rejects it
+    fn accepts(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (SchemaType::Null, Value::Null)
+                | (SchemaType::Boolean, Value::Boolean(_))
+                | (SchemaType::Integer, Value::Integer(_))
+                | (
+                    SchemaType::Number,
+                    Value::Integer(_) | Value::Float(_) | Value::Decimal(_)
+                )
+                | (SchemaType::String, Value::String(_))
+                | (SchemaType::Array, Value::Array(_))
+                | (SchemaType::Object, Value::Object(_))
+                | (SchemaType::Bytes, Value::Bytes(_))
+                | (SchemaType::Timestamp, Value::Timestamp(_))
+                | (SchemaType::Decimal, Value::Decimal(_))
+                | (SchemaType::Uuid, Value::Uuid(_))
+        )
+    }
+}
+
+impl fmt::Display for SchemaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Every check is optional; Schema::any() accepts everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    types: Vec<SchemaType>, // the value must have one of these (empty = any type)
+    enum_values: Option<Vec<Value>>,
+    // Numbers (compared exactly, whatever their kind)
+    minimum: Option<Value>,
+    maximum: Option<Value>,
+    exclusive_minimum: Option<Value>,
+    exclusive_maximum: Option<Value>,
+    // Strings (lengths count characters)
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Pattern>,
+    // Arrays
+    items: Option<Box<Schema>>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    // Objects
+    properties: BTreeMap<String, Schema>,
+    required: Vec<String>,
+    additional_properties: bool,
+}
+
+impl Schema {
+    pub fn any() -> Self {
+        Schema {
+            additional_properties: true,
+            ..Schema::default()
+        }
+    }
+
+    pub fn of_type(schema_type: SchemaType) -> Self {
+        Schema::any().or_type(schema_type)
+    }
+
+    pub fn null() -> Self {
+        Schema::of_type(SchemaType::Null)
+    }
+
+    pub fn boolean() -> Self {
+        Schema::of_type(SchemaType::Boolean)
+    }
+
+    pub fn integer() -> Self {
+        Schema::of_type(SchemaType::Integer)
+    }
+
+    pub fn number() -> Self {
+        Schema::of_type(SchemaType::Number)
+    }
+
+    pub fn string() -> Self {
+        Schema::of_type(SchemaType::String)
+    }
+
+    pub fn object() -> Self {
+        Schema::of_type(SchemaType::Object)
+    }
+
+    pub fn array(items: Schema) -> Self {
+        Schema::of_type(SchemaType::Array).items(items)
+    }
+
+    // Also accept another type, e.g. Schema::string().or_type(SchemaType::Null)
+    pub fn or_type(mut self, schema_type: SchemaType) -> Self {
+        if !self.types.contains(&schema_type) {
+            self.types.push(schema_type);
+        }
+        self
+    }
+
+    // The value must equal one of these
+    pub fn one_of(mut self, values: Vec<Value>) -> Self {
+        self.enum_values = Some(values);
+        self
+    }
+
+    pub fn minimum(mut self, bound: f64) -> Self {
+        self.minimum = Some(Value::Float(bound));
+        self
+    }
+
+    pub fn maximum(mut self, bound: f64) -> Self {
+        self.maximum = Some(Value::Float(bound));
+        self
+    }
+
+    pub fn exclusive_minimum(mut self, bound: f64) -> Self {
+        self.exclusive_minimum = Some(Value::Float(bound));
+        self
+    }
+
+    pub fn exclusive_maximum(mut self, bound: f64) -> Self {
+        self.exclusive_maximum = Some(Value::Float(bound));
+        self
+    }
+
+    pub fn min_length(mut self, length: usize) -> Self {
+        self.min_length = Some(length);
+        self
+    }
+
+    pub fn max_length(mut self, length: usize) -> Self {
+        self.max_length = Some(length);
+        self
+    }
+
+    // The string must match the regular expression somewhere (anchor it with ^...$)
+    pub fn pattern(mut self, pattern: &str) -> Result<Self, String> {
+        let regex = Regex::new(pattern).map_err(|e| format!("invalid pattern: {}", e))?;
+        self.pattern = Some(Pattern(regex));
+        Ok(self)
+    }
+
+    // Every array element must fit this schema
+    pub fn items(mut self, items: Schema) -> Self {
+        self.items = Some(Box::new(items));
+        self
+    }
+
+    pub fn min_items(mut self, count: usize) -> Self {
+        self.min_items = Some(count);
+        self
+    }
+
+    pub fn max_items(mut self, count: usize) -> Self {
+        self.max_items = Some(count);
+        self
+    }
+
+    // If the field is present, it must fit this schema
+    pub fn property(mut self, name: &str, schema: Schema) -> Self {
+        self.properties.insert(name.to_string(), schema);
+        self
+    }
+
+    pub fn required(mut self, name: &str) -> Self {
+        if !self.required.iter().any(|field| field == name) {
+            self.required.push(name.to_string());
+        }
+        self
+    }
+
+    // false: fields not listed with property() are rejected
+    pub fn additional_properties(mut self, allowed: bool) -> Self {
+        self.additional_properties = allowed;
+        self
+    }
+
+    // ---------- Checking values ----------
+
+    // The first thing about the value that doesn't fit
+    pub fn validate(&self, value: &Value) -> Result<(), ValidationError> {
+        match self.violations(value).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    // Everything about the value that doesn't fit
+    pub fn violations(&self, value: &Value) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        self.check(value, "", &mut errors);
+        errors
+    }
+
+    fn check(&self, value: &Value, path: &str, errors: &mut Vec<ValidationError>) {
+        let mut fail = |message: String| errors.push(ValidationError::new(path, message));
+
+        if !self.types.is_empty() && !self.types.iter().any(|t| t.accepts(value)) {
+            let expected: Vec<&str> = self.types.iter().map(SchemaType::name).collect();
+            fail(format!(
+                "expected {}, found {}",
+                expected.join(" or "),
+                value.type_name()
+            ));
+            return; // the other checks would only repeat the mismatch
+        }
+
+        if let Some(allowed) = &self.enum_values
+            && !allowed.contains(value)
+        {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            fail(format!("{} is not one of [{}]", value, allowed.join(", ")));
+        }
+
+        match value {
+            Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => {
+                self.check_range(value, &mut fail)
+            }
+            Value::String(s) => self.check_string(s, &mut fail),
+            Value::Array(elements) => {
+                let count = elements.len();
+                if let Some(min) = self.min_items
+                    && count < min
+                {
+                    fail(format!("has {} items, at least {} required", count, min));
+                }
+                if let Some(max) = self.max_items
+                    && count > max
+                {
+                    fail(format!("has {} items, at most {} allowed", count, max));
+                }
+                if let Some(items) = &self.items {
+                    for (i, element) in elements.iter().enumerate() {
+                        items.check(element, &format!("{}[{}]", path, i), errors);
+                    }
+                }
+            }
+            Value::Object(fields) => {
+                for name in &self.required {
+                    if !fields.contains_key(name) {
+                        errors.push(ValidationError::new(
+                            &field_path(path, name),
+                            "required field is missing".to_string(),
+                        ));
+                    }
+                }
+                // Sorted, so errors come out in the same order every time
+                let mut names: Vec<&String> = fields.keys().collect();
+                names.sort();
+                for name in names {
+                    let inner = field_path(path, name);
+                    match self.properties.get(name) {
+                        Some(schema) => schema.check(&fields[name], &inner, errors),
+                        None if !self.additional_properties => errors.push(ValidationError::new(
+                            &inner,
+                            "field is not allowed by the schema".to_string(),
+                        )),
+                        None => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_range(&self, value: &Value, fail: &mut impl FnMut(String)) {
+        if let Some(min) = &self.minimum
+            && value < min
+        {
+            fail(format!("{} is less than the minimum {}", value, min));
+        }
+        if let Some(max) = &self.maximum
+            && value > max
+        {
+            fail(format!("{} is greater than the maximum {}", value, max));
+        }
+        if let Some(min) = &self.exclusive_minimum
+            && value <= min
+        {
+            fail(format!("{} is not greater than {}", value, min));
+        }
+        if let Some(max) = &self.exclusive_maximum
+            && value >= max
+        {
+            fail(format!("{} is not less than {}", value, max));
+        }
+    }
+
+    fn check_string(&self, s: &str, fail: &mut impl FnMut(String)) {
+        let length = s.chars().count();
+        if let Some(min) = self.min_length
+            && length < min
+        {
+            fail(format!(
+                "is {} characters long, at least {} required",
+                length, min
+            ));
+        }
+        if let Some(max) = self.max_length
+            && length > max
+        {
+            fail(format!(
+                "is {} characters long, at most {} allowed",
+                length, max
+            ));
+        }
+        if let Some(Pattern(regex)) = &self.pattern
+            && !regex.is_match(s)
+        {
+            fail(format!(
+                "{:?} doesn't match the pattern {}",
+                s,
+                regex.as_str()
+            ));
+        }
+    }
+
+    // ---------- JSON Schema ----------
+
+    // Build a schema from a JSON Schema document held in a Value.
+    // Supported keywords: type (a name or a list of names), enum, const,
+    // minimum, maximum, exclusiveMinimum, exclusiveMaximum (numbers), minLength,
+    // maxLength, pattern, items, minItems, maxItems, properties, required and
+    // additionalProperties (true/false). Besides the JSON Schema type names,
+    // "bytes", "timestamp", "decimal" and "uuid" name the matching Value types.
+    // Annotations ($schema, $id, title, description, default, examples) are
+    // ignored; any other keyword is an error rather than silently not checked.
+    pub fn from_value(document: &Value) -> Result<Schema, String> {
+        parse_schema(document, "")
+    }
+
+    #[cfg(feature = "json")]
+    pub fn from_json_str(text: &str) -> Result<Schema, String> {
+        let document = Value::from_json_str(text).map_err(|e| e.to_string())?;
+        Schema::from_value(&document)
+    }
+}
+
+fn parse_schema(document: &Value, path: &str) -> Result<Schema, String> {
+    let at = |keyword: &str| field_path(path, keyword);
+    let Value::Object(keywords) = document else {
+        // true/false are valid schemas too: everything / nothing
+        return match document {
+            Value::Boolean(true) => Ok(Schema::any()),
+            Value::Boolean(false) => Ok(Schema::any().one_of(Vec::new())),
+            other => Err(format!(
+                "at `{}`: a schema must be an object, found {}",
+                path,
+                other.type_name()
+            )),
+        };
+    };
+
+    let mut schema = Schema::any();
+    let mut names: Vec<&String> = keywords.keys().collect();
+    names.sort();
+    for name in names {
+        let value = &keywords[name];
+        match name.as_str() {
+            "type" => {
+                let names = match value {
+                    Value::String(name) => vec![name.as_str()],
+                    Value::Array(names) => names
+                        .iter()
+                        .map(|type_name| match type_name {
+                            Value::String(type_name) => Ok(type_name.as_str()),
+                            _ => Err(format!("at `{}`: type names must be strings", at(name))),
+                        })
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(format!("at `{}`: expected a string or a list", at(name))),
+                };
+                for type_name in names {
+                    let schema_type = SchemaType::parse(type_name).ok_or_else(|| {
+                        format!("at `{}`: unknown type '{}'", at(name), type_name)
+                    })?;
+                    schema = schema.or_type(schema_type);
+                }
+            }
+            "enum" => match value {
+                Value::Array(values) => schema.enum_values = Some(values.clone()),
+                _ => return Err(format!("at `{}`: expected a list", at(name))),
+            },
+            "const" => schema.enum_values = Some(vec![value.clone()]),
+            "minimum" => schema.minimum = Some(number(value, &at(name))?),
+            "maximum" => schema.maximum = Some(number(value, &at(name))?),
+            "exclusiveMinimum" => schema.exclusive_minimum = Some(number(value, &at(name))?),
+            "exclusiveMaximum" => schema.exclusive_maximum = Some(number(value, &at(name))?),
+            "minLength" => schema.min_length = Some(count(value, &at(name))?),
+            "maxLength" => schema.max_length = Some(count(value, &at(name))?),
+            "minItems" => schema.min_items = Some(count(value, &at(name))?),
+            "maxItems" => schema.max_items = Some(count(value, &at(name))?),
+            "pattern" => {
+                let Value::String(pattern) = value else {
+                    return Err(format!("at `{}`: expected a string", at(name)));
+                };
+                schema = schema
+                    .pattern(pattern)
+                    .map_err(|e| format!("at `{}`: {}", at(name), e))?;
+            }
+            "items" => schema.items = Some(Box::new(parse_schema(value, &at(name))?)),
+            "properties" => {
+                let Value::Object(properties) = value else {
+                    return Err(format!("at `{}`: expected an object", at(name)));
+                };
+                for (field, document) in properties {
+                    let inner = field_path(&at(name), field);
+                    schema
+                        .properties
+                        .insert(field.clone(), parse_schema(document, &inner)?);
+                }
+            }
+            "required" => {
+                let Value::Array(fields) = value else {
+                    return Err(format!("at `{}`: expected a list", at(name)));
+                };
+                for field in fields {
+                    let Value::String(field) = field else {
+                        return Err(format!("at `{}`: field names must be strings", at(name)));
+                    };
+                    schema = schema.required(field);
+                }
+            }
+            "additionalProperties" => match value {
+                Value::Boolean(allowed) => schema.additional_properties = *allowed,
+                _ => {
+                    return Err(format!(
+                        "at `{}`: only true or false are supported",
+                        at(name)
+                    ));
+                }
+            },
+            "$schema" | "$id" | "title" | "description" | "default" | "examples" => {}
+            other => return Err(format!("at `{}`: unsupported keyword '{}'", path, other)),
+        }
+    }
+    Ok(schema)
+}
+
+fn number(value: &Value, path: &str) -> Result<Value, String> {
+    match value {
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => Ok(value.clone()),
+        other => Err(format!(
+            "at `{}`: expected a number, found {}",
+            path,
+            other.type_name()
+        )),
+    }
+}
+
+fn count(value: &Value, path: &str) -> Result<usize, String> {
+    match value {
+        Value::Integer(n) if *n >= 0 => Ok(*n as usize),
+        _ => Err(format!("at `{}`: expected a non-negative integer", path)),
+    }
+}
+
+fn field_path(parent: &str, field: &str) -> String {
+    if parent.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", parent, field)
+    }
+}
+
+// A compiled regex, saved as its source text
+#[derive(Debug, Clone)]
+struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Regex::new(&source)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// A value that doesn't fit its schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub key: Option<String>, // the entry's key, when the value came from (or was going to) a database
+    pub path: String,        // field path inside the value, "" for the value itself
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(path: &str, message: String) -> Self {
+        ValidationError {
+            key: None,
+            path: path.to_string(),
+            message,
+        }
+    }
+
+    pub(crate) fn for_key(mut self, key: &str) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(key) = &self.key {
+            write!(f, "key '{}': ", key)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "at `{}`: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Lets the io::Result write methods use `?`; the ValidationError stays
+// reachable through io::Error::get_ref / into_inner
+impl From<ValidationError> for io::Error {
+    fn from(e: ValidationError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
@@ -0,0 +1,156 @@
+// NEW: What the network servers (resp/, http/) have in common
+//
+// A server owns the Database behind a mutex; each connection gets its own
+// thread and locks the database for one command or request at a time. Auto-save
+// is turned off: writes mark the database dirty and a background thread saves
+// it, at most every `save_interval`; whatever is still unsaved is saved when
+// the server stops.
+//
+// A server can also replicate (see replication/): serve its changes to
+// followers, or follow a primary and refuse writes from its own clients.
+
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::Database;
+use crate::pubsub::PubSub;
+use crate::replication::{self, Replication};
+
+pub(crate) const DEFAULT_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+// What the connection threads share
+pub(crate) struct Shared {
+    db: Mutex<Database>,
+    dirty: AtomicBool, // written since the last save
+    shutdown: AtomicBool,
+    addr: SocketAddr,
+    pub(crate) replication: Replication,
+    // The database's pub/sub channels, used without locking it
+    pub(crate) pubsub: PubSub,
+}
+
+impl Shared {
+    pub(crate) fn new(mut db: Database, addr: SocketAddr) -> Arc<Shared> {
+        db.set_auto_save(false);
+        let pubsub = db.pubsub();
+        Arc::new(Shared {
+            db: Mutex::new(db),
+            dirty: AtomicBool::new(false),
+            shutdown: AtomicBool::new(false),
+            addr,
+            replication: Replication::default(),
+            pubsub,
+        })
+    }
+
+    // A panic in one connection shouldn't take the others down with it
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Database> {
+        self.db
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // After every write: it's saved later, and sent to followers now
+    pub(crate) fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+        self.replication.notify_write();
+    }
+
+    // A follower only changes what its primary changed
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.replication.is_following()
+    }
+
+    // Save now (SAVE); `db` is the caller's lock
+    pub(crate) fn save(&self, db: &Database) -> io::Result<()> {
+        db.save()?;
+        self.dirty.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // Forget unsaved changes, so stopping doesn't save them (SHUTDOWN NOSAVE)
+    pub(crate) fn discard_changes(&self) {
+        self.dirty.store(false, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn shut_down(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the accept loops so they notice
+        let _ = TcpStream::connect(self.addr);
+        self.replication.shut_down();
+    }
+
+    fn save_if_dirty(&self) -> io::Result<()> {
+        if self.dirty.swap(false, Ordering::SeqCst)
+            && let Err(e) = self.lock().save()
+        {
+            self.dirty.store(true, Ordering::SeqCst); // try again next time
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+// Stops a running server from another thread; see the servers' shutdown_handle()
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shared: Arc<Shared>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(shared: &Arc<Shared>) -> Self {
+        ShutdownHandle {
+            shared: Arc::clone(shared),
+        }
+    }
+
+    // run() returns once the unsaved changes are saved; connections that are
+    // open keep being served until their clients leave
+    pub fn shutdown(&self) {
+        self.shared.shut_down();
+    }
+}
+
+// Accept connections and hand each to `serve` on its own thread, until shut down
+pub(crate) fn run(
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    save_interval: Option<Duration>,
+    serve: fn(TcpStream, &Arc<Shared>) -> io::Result<()>,
+) -> io::Result<()> {
+    replication::start(&shared)?;
+    if let Some(interval) = save_interval {
+        let shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            while !shared.is_shutting_down() {
+                thread::sleep(interval);
+                let _ = shared.save_if_dirty(); // failures are retried next time
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        if shared.is_shutting_down() {
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || {
+                    let _ = serve(stream, &shared); // a dropped client is nothing to report
+                });
+            }
+            // Out of file descriptors and the like: wait and try again
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+    shared.save_if_dirty()
+}
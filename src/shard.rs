@@ -0,0 +1,610 @@
+// NEW: Sharding - one logical database split across several Databases
+//
+//   let mut db = ShardedDatabase::open("data/users", 4)?;   // 4 shards to start with
+//   db.insert("user:1".to_string(), alice)?;                // goes to one shard
+//   let adults = db.query(Condition::GreaterThan("age".to_string(), 17));
+//   let new = db.split(db.shard_of("user:1"))?;              // that shard, cut in two
+//   db.merge(new, 0)?;                                       // ... and put back
+//
+// Each shard is a Database with a file of its own ("data/users.shard-0",
+// "data/users.shard-1", ...), so a shard is saved without touching the others
+// and holds only part of the data in memory structures of its own. Single-key
+// operations go to the key's shard; query, list_keys, count and
+// keys_with_prefix ask every shard (queries run on a thread per shard) and
+// merge what they return.
+//
+// Keys are placed by consistent hashing: a ring of 2^64 positions, with
+// POINTS_PER_SHARD points per shard at first. A key belongs to the shard of the
+// first point at or after its hash. split() gives every other point of a shard
+// to a new shard, which takes over about half of its keys; merge() gives all of
+// a shard's points to another. Either way only the keys whose shard changes
+// are moved, and the other shards are left alone.
+//
+// The ring is saved in "data/users.shards". Moving keys is crash-safe: a
+// resharding first marks the ring as untidy, copies the keys, saves the new
+// ring, then deletes the copied keys; a database opened while untidy drops
+// every key a shard holds but the ring doesn't give it, which finishes or
+// undoes the move.
+//
+// Only the database's own keys are sharded (not collections). A transaction's
+// keys may be on several shards: it's checked on all of them before anything
+// is written, but each shard saves its part on its own. A key that moves to
+// another shard gets a new version (see Database::version).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::invalid_input;
+use crate::schema::Schema;
+use crate::store::{Store, StoreError};
+use crate::transaction::{Transaction, TransactionError};
+use crate::{Condition, Database, StorageEngine, Value};
+
+// Where a new ShardedDatabase puts each shard on the ring
+const POINTS_PER_SHARD: u32 = 64;
+// The extension of the file holding the ring
+const RING_FILE: &str = "shards";
+
+pub type ShardId = u32;
+
+pub struct ShardedDatabase {
+    path: String,
+    storage: StorageEngine, // for the ring's file
+    ring: Ring,
+    shards: BTreeMap<ShardId, Database>,
+    auto_save: bool,
+    verbose: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Ring {
+    points: BTreeMap<u64, ShardId>, // position -> shard
+    next_id: ShardId,
+    // A resharding started and may not have finished (see the top of the file)
+    untidy: bool,
+}
+
+// Result of ShardedDatabase::stats
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardStats {
+    pub id: ShardId,
+    pub keys: usize,
+    pub points: usize, // its share of the ring, out of the points of all shards
+}
+
+impl ShardedDatabase {
+    // Open the database at `path`, or create it with `shards` empty shards;
+    // an existing database keeps the shards it has
+    pub fn open(path: &str, shards: u32) -> io::Result<ShardedDatabase> {
+        let storage = StorageEngine::new(path);
+        let ring = match storage.load_sidecar::<Ring>(RING_FILE)? {
+            Some(ring) => ring,
+            None if shards == 0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "a sharded database needs at least one shard",
+                ));
+            }
+            None => Ring::new(shards),
+        };
+        let mut db = ShardedDatabase {
+            path: path.to_string(),
+            storage,
+            ring,
+            shards: BTreeMap::new(),
+            auto_save: true,
+            verbose: false,
+        };
+        for id in db.ring.shard_ids() {
+            let shard = db.open_shard(id)?;
+            db.shards.insert(id, shard);
+        }
+        if db.ring.untidy {
+            db.tidy()?;
+        }
+        db.save_ring()?;
+        Ok(db)
+    }
+
+    // Applies to every shard, as Database::set_auto_save
+    pub fn set_auto_save(&mut self, enabled: bool) {
+        self.auto_save = enabled;
+        for shard in self.shards.values_mut() {
+            shard.set_auto_save(enabled);
+        }
+    }
+
+    // The shards' progress messages are off unless this turns them on
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+        for shard in self.shards.values_mut() {
+            shard.set_verbose(verbose);
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        for shard in self.shards.values() {
+            shard.save()?;
+        }
+        self.save_ring()
+    }
+
+    // ---------- Shards ----------
+
+    pub fn shard_ids(&self) -> Vec<ShardId> {
+        self.shards.keys().copied().collect()
+    }
+
+    // The shard the key belongs to
+    pub fn shard_of(&self, key: &str) -> ShardId {
+        self.ring.owner(key)
+    }
+
+    pub fn shard(&self, id: ShardId) -> Option<&Database> {
+        self.shards.get(&id)
+    }
+
+    pub fn stats(&self) -> Vec<ShardStats> {
+        let mut points: BTreeMap<ShardId, usize> = BTreeMap::new();
+        for id in self.ring.points.values() {
+            *points.entry(*id).or_default() += 1;
+        }
+        self.shards
+            .iter()
+            .map(|(id, shard)| ShardStats {
+                id: *id,
+                keys: shard.count(),
+                points: points[id],
+            })
+            .collect()
+    }
+
+    // Move about half of a shard's keys to a new shard; returns the new
+    // shard's id. Fails on a shard with a single point on the ring.
+    pub fn split(&mut self, id: ShardId) -> io::Result<ShardId> {
+        let taken: Vec<u64> = self
+            .ring
+            .points_of(id)
+            .into_iter()
+            .skip(1)
+            .step_by(2)
+            .collect();
+        if taken.is_empty() {
+            return Err(invalid_input(format!("shard {} can't be split", id)));
+        }
+        let new = self.ring.next_id;
+        self.ring.next_id += 1;
+        self.ring.untidy = true;
+        self.save_ring()?;
+
+        // A crash may have left files of a shard that got no further than this
+        let mut shard = self.open_shard(new)?;
+        shard.clear();
+        shard.delete_files()?;
+        let source = self.shard_checked(id)?;
+        for prefix in source.list_schemas() {
+            if let Some(schema) = source.schema(&prefix) {
+                shard.set_schema(&prefix, schema.clone())?;
+            }
+        }
+        for field in source.list_indexes() {
+            shard.create_index(&field)?;
+        }
+        let mut ring = self.ring.clone();
+        for point in taken {
+            ring.points.insert(point, new);
+        }
+        self.shards.insert(new, shard);
+        self.move_keys(id, &ring)?;
+        Ok(new)
+    }
+
+    // Move all of a shard's keys to another shard, and remove it; returns how
+    // many keys moved
+    pub fn merge(&mut self, from: ShardId, into: ShardId) -> io::Result<usize> {
+        if from == into || !self.shards.contains_key(&into) {
+            return Err(invalid_input(format!(
+                "can't merge shard {} into shard {}",
+                from, into
+            )));
+        }
+        let count = self.shard_checked(from)?.count();
+        self.ring.untidy = true;
+        self.save_ring()?;
+        let mut ring = self.ring.clone();
+        for owner in ring.points.values_mut() {
+            if *owner == from {
+                *owner = into;
+            }
+        }
+        self.move_keys(from, &ring)?;
+        if let Some(shard) = self.shards.remove(&from) {
+            shard.delete_files()?;
+        }
+        Ok(count)
+    }
+
+    // ---------- Keys ----------
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.shard_for(key).get(key)
+    }
+
+    pub fn get_with_version(&self, key: &str) -> Option<(Value, u64)> {
+        self.shard_for(key).get_with_version(key)
+    }
+
+    pub fn exists(&self, key: &str) -> bool {
+        self.shard_for(key).exists(key)
+    }
+
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        self.shard_for(key).ttl(key)
+    }
+
+    pub fn batch_get(&self, keys: Vec<&str>) -> HashMap<String, Value> {
+        let mut found = HashMap::new();
+        for key in keys {
+            if let Some(value) = self.get(key) {
+                found.insert(key.to_string(), value);
+            }
+        }
+        found
+    }
+
+    pub fn insert(&mut self, key: String, value: Value) -> io::Result<()> {
+        self.shard_for_mut(&key).insert(key, value)
+    }
+
+    pub fn insert_with_ttl(&mut self, key: String, value: Value, ttl: Duration) -> io::Result<()> {
+        self.shard_for_mut(&key).insert_with_ttl(key, value, ttl)
+    }
+
+    pub fn update(&mut self, key: String, value: Value) -> Result<(), String> {
+        self.shard_for_mut(&key).update(key, value)
+    }
+
+    pub fn delete(&mut self, key: &str) -> Result<(), String> {
+        self.shard_for_mut(key).delete(key)
+    }
+
+    // All or nothing: every value is checked against its shard's schemas
+    // before any is inserted
+    pub fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> io::Result<usize> {
+        for (key, value) in &entries {
+            self.shard_for(key)
+                .check_schema(key, value)
+                .map_err(|e| invalid_input(e.to_string()))?;
+        }
+        let count = entries.len();
+        let mut parts: BTreeMap<ShardId, Vec<(String, Value)>> = BTreeMap::new();
+        for (key, value) in entries {
+            parts
+                .entry(self.shard_of(&key))
+                .or_default()
+                .push((key, value));
+        }
+        for (id, part) in parts {
+            self.shard_mut(id).batch_insert(part)?;
+        }
+        Ok(count)
+    }
+
+    pub fn batch_delete(&mut self, keys: Vec<&str>) -> usize {
+        let mut parts: BTreeMap<ShardId, Vec<&str>> = BTreeMap::new();
+        for key in keys {
+            parts.entry(self.shard_of(key)).or_default().push(key);
+        }
+        parts
+            .into_iter()
+            .map(|(id, part)| self.shard_mut(id).batch_delete(part))
+            .sum()
+    }
+
+    pub fn clear(&mut self) {
+        for shard in self.shards.values_mut() {
+            shard.clear();
+        }
+    }
+
+    // Every part is checked before the first is written (see the top of the file)
+    pub fn commit(&mut self, tx: Transaction) -> Result<(), TransactionError> {
+        let parts = tx.split_by(|key| self.shard_of(key));
+        for (id, part) in &parts {
+            part.check(&self.shards[id])?;
+        }
+        for (id, part) in parts {
+            self.shard_mut(id).commit(part)?;
+        }
+        Ok(())
+    }
+
+    // ---------- Across all shards ----------
+
+    // Sorted as Database::query sorts
+    pub fn query(&self, condition: Condition) -> Vec<(String, Value)> {
+        let mut results: Vec<(String, Value)> = if self.shards.len() == 1 {
+            self.shards
+                .values()
+                .flat_map(|shard| shard.query(condition.clone()))
+                .collect()
+        } else {
+            thread::scope(|scope| {
+                let running: Vec<_> = self
+                    .shards
+                    .values()
+                    .map(|shard| {
+                        let condition = condition.clone();
+                        scope.spawn(move || shard.query(condition))
+                    })
+                    .collect();
+                running
+                    .into_iter()
+                    .flat_map(|query| query.join().expect("a query thread panicked"))
+                    .collect()
+            })
+        };
+        match condition.near_point() {
+            Some((field, center)) => {
+                let distance = |value: &Value| {
+                    field
+                        .point_of(value)
+                        .map_or(f64::INFINITY, |point| point.distance_to(center))
+                };
+                results.sort_by(|(a_key, a), (b_key, b)| {
+                    distance(a)
+                        .total_cmp(&distance(b))
+                        .then_with(|| a_key.cmp(b_key))
+                });
+            }
+            None => results.sort_by(|(a, _), (b, _)| a.cmp(b)),
+        }
+        results
+    }
+
+    pub fn list_keys(&self) -> Vec<String> {
+        self.merge_keys(|shard| shard.list_keys())
+    }
+
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.merge_keys(|shard| shard.keys_with_prefix(prefix))
+    }
+
+    pub fn count(&self) -> usize {
+        self.shards.values().map(Database::count).sum()
+    }
+
+    // On every shard, and on the shards split() adds later
+    pub fn create_index(&mut self, field: &str) -> io::Result<bool> {
+        let mut created = false;
+        for shard in self.shards.values_mut() {
+            created |= shard.create_index(field)?;
+        }
+        Ok(created)
+    }
+
+    // On every shard, and on the shards split() adds later
+    pub fn set_schema(&mut self, prefix: &str, schema: Schema) -> io::Result<()> {
+        for shard in self.shards.values_mut() {
+            shard.set_schema(prefix, schema.clone())?;
+        }
+        Ok(())
+    }
+
+    // ---------- Helpers ----------
+
+    fn open_shard(&self, id: ShardId) -> io::Result<Database> {
+        let mut shard = Database::new(&format!("{}.shard-{}", self.path, id));
+        shard.set_verbose(self.verbose);
+        shard.set_auto_save(self.auto_save);
+        shard.load()?;
+        Ok(shard)
+    }
+
+    fn save_ring(&self) -> io::Result<()> {
+        self.storage.save_sidecar(RING_FILE, &self.ring)
+    }
+
+    fn shard_for(&self, key: &str) -> &Database {
+        &self.shards[&self.shard_of(key)]
+    }
+
+    fn shard_for_mut(&mut self, key: &str) -> &mut Database {
+        self.shard_mut(self.shard_of(key))
+    }
+
+    fn shard_mut(&mut self, id: ShardId) -> &mut Database {
+        self.shards
+            .get_mut(&id)
+            .expect("every shard on the ring is open")
+    }
+
+    fn shard_checked(&self, id: ShardId) -> io::Result<&Database> {
+        self.shards
+            .get(&id)
+            .ok_or_else(|| invalid_input(format!("no shard {}", id)))
+    }
+
+    fn merge_keys(&self, keys_of: impl Fn(&Database) -> Vec<String>) -> Vec<String> {
+        let mut keys: Vec<String> = self.shards.values().flat_map(keys_of).collect();
+        keys.sort();
+        keys
+    }
+
+    // Copy the keys of shard `from` that `ring` gives to other shards, save
+    // them there, switch to `ring`, then delete them from `from`
+    fn move_keys(&mut self, from: ShardId, ring: &Ring) -> io::Result<()> {
+        let source = self.shard_mut(from);
+        source.purge_expired();
+        let moving: Vec<(String, Value, Option<Duration>)> = source
+            .list_keys()
+            .into_iter()
+            .filter(|key| ring.owner(key) != from)
+            .filter_map(|key| {
+                let value = source.get(&key)?;
+                let ttl = source.ttl(&key);
+                Some((key, value, ttl))
+            })
+            .collect();
+
+        let mut targets = BTreeSet::new();
+        for (key, value, ttl) in &moving {
+            let id = ring.owner(key);
+            let target = self.shard_mut(id);
+            target.set_auto_save(false);
+            match ttl {
+                Some(ttl) => target.insert_with_ttl(key.clone(), value.clone(), *ttl)?,
+                None => target.insert(key.clone(), value.clone())?,
+            }
+            targets.insert(id);
+        }
+        for id in targets {
+            let auto_save = self.auto_save;
+            let target = self.shard_mut(id);
+            target.set_auto_save(auto_save);
+            target.save()?;
+        }
+
+        self.ring.points = ring.points.clone();
+        self.save_ring()?;
+        let source = self.shard_mut(from);
+        source.batch_delete(moving.iter().map(|(key, _, _)| key.as_str()).collect());
+        source.save()?;
+        self.ring.untidy = false;
+        self.save_ring()
+    }
+
+    // After a resharding that may not have finished: drop the keys the ring
+    // gives to other shards, and the files of shards no longer on it
+    fn tidy(&mut self) -> io::Result<()> {
+        for (id, shard) in self.shards.iter_mut() {
+            let strays: Vec<String> = shard
+                .list_keys()
+                .into_iter()
+                .filter(|key| self.ring.owner(key) != *id)
+                .collect();
+            shard.batch_delete(strays.iter().map(String::as_str).collect());
+            shard.save()?;
+        }
+        for id in 0..self.ring.next_id {
+            if !self.shards.contains_key(&id) {
+                self.open_shard(id)?.delete_files()?;
+            }
+        }
+        self.ring.untidy = false;
+        Ok(())
+    }
+}
+
+impl Ring {
+    fn new(shards: u32) -> Ring {
+        let mut points = BTreeMap::new();
+        for id in 0..shards {
+            for point in 0..POINTS_PER_SHARD {
+                points.insert(hash(format!("shard-{}-{}", id, point).as_bytes()), id);
+            }
+        }
+        Ring {
+            points,
+            next_id: shards,
+            untidy: false,
+        }
+    }
+
+    fn owner(&self, key: &str) -> ShardId {
+        let position = hash(key.as_bytes());
+        let (_, id) = self
+            .points
+            .range(position..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .expect("the ring has at least one point");
+        *id
+    }
+
+    fn points_of(&self, id: ShardId) -> Vec<u64> {
+        self.points
+            .iter()
+            .filter(|(_, owner)| **owner == id)
+            .map(|(point, _)| *point)
+            .collect()
+    }
+
+    fn shard_ids(&self) -> BTreeSet<ShardId> {
+        self.points.values().copied().collect()
+    }
+}
+
+// FNV-1a, then mixed (splitmix64's finalizer) so that similar keys land far
+// apart. Fixed, unlike std's hasher, because the ring is saved.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        h ^= *byte as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+impl Store for ShardedDatabase {
+    fn get(&self, key: &str) -> Result<Option<Value>, StoreError> {
+        Ok(ShardedDatabase::get(self, key))
+    }
+
+    fn get_with_version(&self, key: &str) -> Result<Option<(Value, u64)>, StoreError> {
+        Ok(ShardedDatabase::get_with_version(self, key))
+    }
+
+    fn batch_get(&self, keys: Vec<&str>) -> Result<HashMap<String, Value>, StoreError> {
+        Ok(ShardedDatabase::batch_get(self, keys))
+    }
+
+    fn query(&self, condition: Condition) -> Result<Vec<(String, Value)>, StoreError> {
+        Ok(ShardedDatabase::query(self, condition))
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        Ok(ShardedDatabase::keys_with_prefix(self, prefix))
+    }
+
+    fn count(&self) -> Result<usize, StoreError> {
+        Ok(ShardedDatabase::count(self))
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), StoreError> {
+        Ok(ShardedDatabase::insert(self, key, value)?)
+    }
+
+    fn insert_with_ttl(
+        &mut self,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<(), StoreError> {
+        Ok(ShardedDatabase::insert_with_ttl(self, key, value, ttl)?)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, StoreError> {
+        Ok(ShardedDatabase::delete(self, key).is_ok())
+    }
+
+    fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> Result<usize, StoreError> {
+        Ok(ShardedDatabase::batch_insert(self, entries)?)
+    }
+
+    fn batch_delete(&mut self, keys: Vec<&str>) -> Result<usize, StoreError> {
+        Ok(ShardedDatabase::batch_delete(self, keys))
+    }
+
+    fn commit(&mut self, tx: Transaction) -> Result<(), StoreError> {
+        Ok(ShardedDatabase::commit(self, tx)?)
+    }
+}
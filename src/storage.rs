@@ -1,15 +1,30 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     fs::{File, OpenOptions},
     io::{self, Read, Write},
     path::Path,
 };
 
+use serde::{Serialize, de::DeserializeOwned};
+
 use crate::Value;
 
+// NEW: Files holding more than the plain key-value map (collections, expiry
+// times) start with this tag. A plain map starts with its length instead, so
+// the two can't be mistaken for each other and old files still load.
+const IMAGE_MAGIC: &[u8; 8] = b"LTDBIMG1";
+
+// What load_file found on disk
+pub enum DataFile<T> {
+    Missing,
+    Entries(BTreeMap<String, Value>), // a plain key-value map (the original format)
+    Image(T),                         // a tagged image written by save_image
+}
+
 // StorageEngine handles all disk I/O operations
 pub struct StorageEngine {
     file_path: String, // This just stores the path to our database file as a String
+    verbose: bool,     // print progress messages (on by default)
 }
 
 impl StorageEngine {
@@ -20,22 +35,31 @@ impl StorageEngine {
         // We need to_string() because we want to store the path permanently
         StorageEngine {
             file_path: file_path.to_string(),
+            verbose: true,
         }
     }
 
+    // NEW: Turn the progress messages on or off
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
     // Save the entire database to disk
     // Uses bincode for fast binary serialization
     // is not a special return type. It is simply a type alias. In the standard library (std::io): pub type Result<T> = std::result::Result<T, std::io::Error>; So: io::Result<()> is exactly equivalent to: Result<(), std::io::Error>
-    pub fn save(&self, data: &HashMap<String, Value>) -> io::Result<()> {
-        println!("💾 Saving database to '{}'...", self.file_path);
+    pub fn save(&self, data: &BTreeMap<String, Value>) -> io::Result<()> {
+        if self.verbose {
+            println!("💾 Saving database to '{}'...", self.file_path);
+        }
 
-        // Serialize the HashMap to bytes
-        let encoded = bincode::serialize(data)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        // Serialize the BTreeMap to bytes
+        // (bincode writes any map as a length followed by key-value pairs, so files
+        // written back when the store was a HashMap load exactly the same way)
+        let encoded = bincode::serialize(data).map_err(|e| io::Error::other(e.to_string()))?;
 
         // Let's break this down:
         //
-        // bincode::serialize(data) - Converts our HashMap into bytes (Vec<u8>)
+        // bincode::serialize(data) - Converts our BTreeMap into bytes (Vec<u8>)
         //   Returns: Result<Vec<u8>, Box<dyn Error>>
         //
         // .map_err(...) - If there's an error, convert it to io::Error
@@ -43,8 +67,7 @@ impl StorageEngine {
         //
         // |e| - This is a closure (anonymous function) with parameter e
         //
-        // io::Error::new(...) - Creates a new IO error
-        //   io::ErrorKind::Other - The type of error
+        // io::Error::other(...) - Creates a new IO error of kind ErrorKind::Other
         //   e.to_string() - Convert the bincode error to a String message
         //
         // ? - The question mark operator
@@ -76,25 +99,31 @@ impl StorageEngine {
         //   This ensures data survives even if power goes out!
         //   This is called "flushing" or "syncing"
 
-        println!("✓ Saved {} entries ({} bytes)", data.len(), encoded.len());
+        if self.verbose {
+            println!("✓ Saved {} entries ({} bytes)", data.len(), encoded.len());
+        }
         Ok(())
     }
 
     // Load the entire database from disk
-    // Returns: io::Result<HashMap<String, Value>>
-    //   Ok(HashMap) if successful
+    // Returns: io::Result<BTreeMap<String, Value>>
+    //   Ok(BTreeMap) if successful
     //   Err(io::Error) if file doesn't exist or can't be read
-    pub fn load(&self) -> io::Result<HashMap<String, Value>> {
+    pub fn load(&self) -> io::Result<BTreeMap<String, Value>> {
         // Check if file exists
 
         if !Path::new(&self.file_path).exists() {
             // Path::new() - Creates a Path object from string
             // .exists() - Returns true if file exists
-            println!("ℹ No existing database file found, starting fresh");
-            return Ok(HashMap::new());
+            if self.verbose {
+                println!("ℹ No existing database file found, starting fresh");
+            }
+            return Ok(BTreeMap::new());
         }
 
-        println!("📂 Loading database from '{}'...", self.file_path);
+        if self.verbose {
+            println!("📂 Loading database from '{}'...", self.file_path);
+        }
 
         // Read all bytes from file
         let mut file = File::open(&self.file_path)?;
@@ -115,24 +144,69 @@ impl StorageEngine {
 
         // Now 'buffer' contains all the bytes from the file
 
-        // Deserialize bytes back into HashMap
-        let data: HashMap<String, Value> = bincode::deserialize(&buffer)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        // Deserialize bytes back into BTreeMap
+        let data: BTreeMap<String, Value> =
+            bincode::deserialize(&buffer).map_err(|e| io::Error::other(e.to_string()))?;
 
-        // bincode::deserialize(&buffer) - Converts bytes back to HashMap
+        // bincode::deserialize(&buffer) - Converts bytes back to BTreeMap
         //   &buffer - Borrows the byte vector
-        //   Returns: Result<HashMap<String, Value>, Box<dyn Error>>
+        //   Returns: Result<BTreeMap<String, Value>, Box<dyn Error>>
         //
         // .map_err(...) - Convert bincode error to io::Error (same as in save)
         //
         // ? - If deserialization fails, return error
         //
-        // : HashMap<String, Value> - Explicitly tells Rust what type to deserialize into
+        // : BTreeMap<String, Value> - Explicitly tells Rust what type to deserialize into
 
-        println!("✓ Loaded {} entries ({} bytes)", data.len(), buffer.len());
+        if self.verbose {
+            println!("✓ Loaded {} entries ({} bytes)", data.len(), buffer.len());
+        }
         Ok(data)
     }
 
+    // Save a tagged image (see IMAGE_MAGIC) instead of a plain map
+    pub fn save_image<T: Serialize>(&self, image: &T) -> io::Result<()> {
+        if self.verbose {
+            println!("💾 Saving database to '{}'...", self.file_path);
+        }
+        let mut encoded = IMAGE_MAGIC.to_vec();
+        bincode::serialize_into(&mut encoded, image)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let mut file = File::create(&self.file_path)?;
+        file.write_all(&encoded)?;
+        file.sync_all()?;
+        if self.verbose {
+            println!("✓ Saved {} bytes", encoded.len());
+        }
+        Ok(())
+    }
+
+    // Load whichever format the file is in
+    pub fn load_file<T: DeserializeOwned>(&self) -> io::Result<DataFile<T>> {
+        if !self.exists() {
+            if self.verbose {
+                println!("ℹ No existing database file found, starting fresh");
+            }
+            return Ok(DataFile::Missing);
+        }
+        if self.verbose {
+            println!("📂 Loading database from '{}'...", self.file_path);
+        }
+        let buffer = std::fs::read(&self.file_path)?;
+        let loaded = match buffer.strip_prefix(IMAGE_MAGIC.as_slice()) {
+            Some(image) => DataFile::Image(
+                bincode::deserialize(image).map_err(|e| io::Error::other(e.to_string()))?,
+            ),
+            None => DataFile::Entries(
+                bincode::deserialize(&buffer).map_err(|e| io::Error::other(e.to_string()))?,
+            ),
+        };
+        if self.verbose {
+            println!("✓ Loaded {} bytes", buffer.len());
+        }
+        Ok(loaded)
+    }
+
     // Append a single key-value pair to the file (write-ahead log style)
     // This is more efficient for single writes but we'll improve this later
     pub fn append(&self, key: &str, value: &Value) -> io::Result<()> {
@@ -140,12 +214,11 @@ impl StorageEngine {
         // In Stage 5, we'll make this a proper write-ahead log
 
         // Create a single-entry map
-        let mut entry = HashMap::new();
+        let mut entry = BTreeMap::new();
         entry.insert(key.to_string(), value.clone());
 
         // Serialize it
-        let encoded = bincode::serialize(&entry)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        let encoded = bincode::serialize(&entry).map_err(|e| io::Error::other(e.to_string()))?;
 
         // Append to file
         let mut file = OpenOptions::new()
@@ -157,6 +230,98 @@ impl StorageEngine {
         Ok(())
     }
 
+    // NEW: Files stored next to the database file, e.g. "mydata.db.vectors"
+    // Used for data that belongs to the database but not to the key-value map
+    fn sidecar_path(&self, extension: &str) -> String {
+        format!("{}.{}", self.file_path, extension)
+    }
+
+    pub fn save_sidecar<T: Serialize>(&self, extension: &str, data: &T) -> io::Result<()> {
+        let encoded = bincode::serialize(data).map_err(|e| io::Error::other(e.to_string()))?;
+        let mut file = File::create(self.sidecar_path(extension))?;
+        file.write_all(&encoded)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    // Returns Ok(None) if the file doesn't exist
+    pub fn load_sidecar<T: DeserializeOwned>(&self, extension: &str) -> io::Result<Option<T>> {
+        let path = self.sidecar_path(extension);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let buffer = std::fs::read(&path)?;
+        let data = bincode::deserialize(&buffer).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Some(data))
+    }
+
+    // NEW: Sidecars that grow - records are appended, each one after its
+    // length, and the file is synced
+    pub fn append_sidecar<T: Serialize>(&self, extension: &str, records: &[T]) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        for record in records {
+            let encoded =
+                bincode::serialize(record).map_err(|e| io::Error::other(e.to_string()))?;
+            buffer.extend_from_slice(&(encoded.len() as u64).to_le_bytes());
+            buffer.extend_from_slice(&encoded);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.sidecar_path(extension))?;
+        file.write_all(&buffer)?;
+        file.sync_all()
+    }
+
+    // Every record append_sidecar wrote, or Ok(None) if the file doesn't
+    // exist. A record cut short (by a crash while appending) is cut off the
+    // file, so that the next records appended can be read back.
+    pub fn load_sidecar_records<T: DeserializeOwned>(
+        &self,
+        extension: &str,
+    ) -> io::Result<Option<Vec<T>>> {
+        let path = self.sidecar_path(extension);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let buffer = std::fs::read(&path)?;
+        let mut records = Vec::new();
+        let mut at = 0;
+        while let Some(length) = buffer.get(at..at + 8) {
+            let length = u64::from_le_bytes(length.try_into().expect("8 bytes")) as usize;
+            let Some(encoded) = buffer.get(at + 8..(at + 8).saturating_add(length)) else {
+                break;
+            };
+            records
+                .push(bincode::deserialize(encoded).map_err(|e| io::Error::other(e.to_string()))?);
+            at += 8 + length;
+        }
+        if at < buffer.len() {
+            OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(at as u64)?;
+        }
+        Ok(Some(records))
+    }
+
+    // Replace the records of an append_sidecar file all at once (through a
+    // temporary file, so a crash leaves the old records or the new ones)
+    pub fn rewrite_sidecar<T: Serialize>(&self, extension: &str, records: &[T]) -> io::Result<()> {
+        let temporary = format!("{}.tmp", extension);
+        self.delete_sidecar(&temporary)?;
+        self.append_sidecar(&temporary, records)?;
+        std::fs::rename(self.sidecar_path(&temporary), self.sidecar_path(extension))
+    }
+
+    pub fn delete_sidecar(&self, extension: &str) -> io::Result<()> {
+        let path = self.sidecar_path(extension);
+        if Path::new(&path).exists() {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
     // Check if storage file exists
     pub fn exists(&self) -> bool {
         Path::new(&self.file_path).exists()
@@ -166,7 +331,9 @@ impl StorageEngine {
     pub fn delete_file(&self) -> io::Result<()> {
         if self.exists() {
             std::fs::remove_file(&self.file_path)?;
-            println!("✓ Deleted storage file");
+            if self.verbose {
+                println!("✓ Deleted storage file");
+            }
         }
         Ok(())
     }
@@ -0,0 +1,217 @@
+// NEW: One interface over an embedded Database and a remote Client
+//
+//   fn record_visit(store: &mut impl Store, user: &str) -> Result<(), StoreError> {
+//       let key = format!("visits:{}", user);
+//       let visits = store.get_typed::<u64>(&key)?.unwrap_or(0);
+//       store.insert_typed(key, &(visits + 1))
+//   }
+//
+//   record_visit(&mut db, "alice")?;       // in-process
+//   record_visit(&mut client, "alice")?;   // over the network (see client/)
+//
+// The methods mirror Database's, except that every one can fail: a Database
+// only fails on bad values and failed saves, a Client also when the server
+// can't be reached. Reads take &self and writes &mut self, as on Database.
+// A Database's own methods come first in method calls, so the trait only
+// takes over in code that is generic over Store (or a `dyn Store`).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::transaction::{Transaction, TransactionError};
+use crate::typed::{self, TypedError};
+use crate::{Condition, Database, Value};
+
+pub trait Store {
+    fn get(&self, key: &str) -> Result<Option<Value>, StoreError>;
+
+    // The value and its version (see Database::version)
+    fn get_with_version(&self, key: &str) -> Result<Option<(Value, u64)>, StoreError>;
+
+    // The keys that exist, with their values
+    fn batch_get(&self, keys: Vec<&str>) -> Result<HashMap<String, Value>, StoreError>;
+
+    // Matching entries in key order
+    fn query(&self, condition: Condition) -> Result<Vec<(String, Value)>, StoreError>;
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError>;
+
+    fn count(&self) -> Result<usize, StoreError>;
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), StoreError>;
+
+    fn insert_with_ttl(
+        &mut self,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<(), StoreError>;
+
+    // Whether the key existed
+    fn delete(&mut self, key: &str) -> Result<bool, StoreError>;
+
+    // All or nothing: one invalid value and none are inserted
+    fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> Result<usize, StoreError>;
+
+    // How many of the keys existed
+    fn batch_delete(&mut self, keys: Vec<&str>) -> Result<usize, StoreError>;
+
+    fn commit(&mut self, tx: Transaction) -> Result<(), StoreError>;
+
+    fn get_typed<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StoreError>
+    where
+        Self: Sized,
+    {
+        match self.get(key)? {
+            Some(value) => Ok(Some(typed::from_value(value).map_err(|e| e.for_key(key))?)),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_typed<T: Serialize + ?Sized>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), StoreError>
+    where
+        Self: Sized,
+    {
+        let value = typed::to_value(value).map_err(|e| e.for_key(&key))?;
+        self.insert(key, value)
+    }
+
+    fn query_typed<T: DeserializeOwned>(
+        &self,
+        condition: Condition,
+    ) -> Result<Vec<(String, T)>, StoreError>
+    where
+        Self: Sized,
+    {
+        self.query(condition)?
+            .into_iter()
+            .map(|(key, value)| match typed::from_value(value) {
+                Ok(value) => Ok((key, value)),
+                Err(e) => Err(e.for_key(&key).into()),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreError {
+    // A key a transaction read was written by someone else before commit
+    Conflict(String),
+    // The store refused a value or request, e.g. a schema violation
+    Invalid(String),
+    // A value doesn't convert to or from the requested Rust type
+    Typed(TypedError),
+    // The server couldn't be reached or didn't answer in time
+    Unavailable(String),
+    // Saving failed, or the server failed in some other way
+    Io(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Conflict(key) => write!(
+                f,
+                "conflict: key '{}' changed since the transaction read it",
+                key
+            ),
+            StoreError::Invalid(message) => write!(f, "{}", message),
+            StoreError::Typed(error) => write!(f, "{}", error),
+            StoreError::Unavailable(message) => write!(f, "server unavailable: {}", message),
+            StoreError::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<TypedError> for StoreError {
+    fn from(error: TypedError) -> Self {
+        StoreError::Typed(error)
+    }
+}
+
+impl From<TransactionError> for StoreError {
+    fn from(error: TransactionError) -> Self {
+        match error {
+            TransactionError::Conflict(key) => StoreError::Conflict(key),
+            TransactionError::Invalid(error) => StoreError::Invalid(error.to_string()),
+            TransactionError::Io(message) => StoreError::Io(message),
+        }
+    }
+}
+
+// Schema violations and other refusals are InvalidInput (see schema.rs)
+impl From<io::Error> for StoreError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::InvalidInput {
+            StoreError::Invalid(error.to_string())
+        } else {
+            StoreError::Io(error.to_string())
+        }
+    }
+}
+
+impl Store for Database {
+    fn get(&self, key: &str) -> Result<Option<Value>, StoreError> {
+        Ok(Database::get(self, key))
+    }
+
+    fn get_with_version(&self, key: &str) -> Result<Option<(Value, u64)>, StoreError> {
+        Ok(Database::get_with_version(self, key))
+    }
+
+    fn batch_get(&self, keys: Vec<&str>) -> Result<HashMap<String, Value>, StoreError> {
+        Ok(Database::batch_get(self, keys))
+    }
+
+    fn query(&self, condition: Condition) -> Result<Vec<(String, Value)>, StoreError> {
+        Ok(Database::query(self, condition))
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        Ok(Database::keys_with_prefix(self, prefix))
+    }
+
+    fn count(&self) -> Result<usize, StoreError> {
+        Ok(Database::count(self))
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), StoreError> {
+        Ok(Database::insert(self, key, value)?)
+    }
+
+    fn insert_with_ttl(
+        &mut self,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<(), StoreError> {
+        Ok(Database::insert_with_ttl(self, key, value, ttl)?)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<bool, StoreError> {
+        Ok(Database::delete(self, key).is_ok())
+    }
+
+    fn batch_insert(&mut self, entries: Vec<(String, Value)>) -> Result<usize, StoreError> {
+        Ok(Database::batch_insert(self, entries)?)
+    }
+
+    fn batch_delete(&mut self, keys: Vec<&str>) -> Result<usize, StoreError> {
+        Ok(Database::batch_delete(self, keys))
+    }
+
+    fn commit(&mut self, tx: Transaction) -> Result<(), StoreError> {
+        Ok(Database::commit(self, tx)?)
+    }
+}
@@ -0,0 +1,180 @@
+// NEW: Transactions - several writes that happen together or not at all
+//
+//   let mut tx = Transaction::new();
+//   let from = tx.get(&db, "account:1");            // reads see the transaction's own writes
+//   tx.insert("account:1".to_string(), debited);
+//   tx.insert("account:2".to_string(), credited);
+//   tx.delete("pending:7");
+//   db.commit(tx)?;                                  // all of it, or (on error) none of it
+//
+// A transaction is optimistic: it holds no lock and only buffers writes. It
+// remembers the version of every key it read (see Database::version), and
+// commit refuses with Conflict if one of those keys was written in the
+// meantime. Keys that were only written are not checked (last writer wins).
+// Dropping a transaction without committing it discards it.
+//
+// Transaction::read does the same as get against any Store, so the same code
+// can run against a remote Client (see store.rs):
+//   let from = tx.read(&client, "account:1")?;
+//   client.commit(tx)?;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::schema::ValidationError;
+use crate::store::{Store, StoreError};
+use crate::{Database, Value};
+
+#[derive(Debug, Default, Clone)]
+pub struct Transaction {
+    // key -> the value to write (None = delete), in key order
+    writes: BTreeMap<String, Option<Value>>,
+    // key -> version it had when first read (0 = it didn't exist)
+    reads: BTreeMap<String, u64>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Transaction::default()
+    }
+
+    // The value as this transaction sees it: its own pending write if there
+    // is one, the database's value otherwise
+    pub fn get(&mut self, db: &Database, key: &str) -> Option<Value> {
+        if let Some(pending) = self.writes.get(key) {
+            return pending.clone();
+        }
+        self.reads
+            .entry(key.to_string())
+            .or_insert_with(|| live_version(db, key));
+        db.get(key)
+    }
+
+    // get() for any Store, e.g. a remote Client
+    pub fn read<S: Store + ?Sized>(
+        &mut self,
+        store: &S,
+        key: &str,
+    ) -> Result<Option<Value>, StoreError> {
+        if let Some(pending) = self.writes.get(key) {
+            return Ok(pending.clone());
+        }
+        let found = store.get_with_version(key)?;
+        let version = found.as_ref().map_or(0, |(_, version)| *version);
+        self.reads.entry(key.to_string()).or_insert(version);
+        Ok(found.map(|(value, _)| value))
+    }
+
+    // Commit only if `key` is still at `version` (0 = doesn't exist), as if
+    // the transaction had read it then; for versions learnt some other way,
+    // e.g. from get_with_version or an HTTP ETag
+    pub fn expect_version(&mut self, key: &str, version: u64) {
+        self.reads.insert(key.to_string(), version);
+    }
+
+    pub fn insert(&mut self, key: String, value: Value) {
+        self.writes.insert(key, Some(value));
+    }
+
+    pub fn delete(&mut self, key: &str) {
+        self.writes.insert(key.to_string(), None);
+    }
+
+    // Versions the commit will check, in key order
+    pub fn reads(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.reads
+            .iter()
+            .map(|(key, version)| (key.as_str(), *version))
+    }
+
+    // Pending writes in key order (None = delete)
+    pub fn writes(&self) -> impl Iterator<Item = (&str, Option<&Value>)> {
+        self.writes
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_ref()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    // Number of pending writes
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    // Database::commit runs every check before the first write, so a refused
+    // transaction changes nothing
+    pub(crate) fn check(&self, db: &Database) -> Result<(), TransactionError> {
+        for (key, version) in &self.reads {
+            if live_version(db, key) != *version {
+                return Err(TransactionError::Conflict(key.clone()));
+            }
+        }
+        for (key, value) in &self.writes {
+            if let Some(value) = value {
+                db.check_schema(key, value)
+                    .map_err(TransactionError::Invalid)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn into_writes(self) -> BTreeMap<String, Option<Value>> {
+        self.writes
+    }
+
+    // One transaction per owner of the keys (see shard.rs), each with the
+    // reads and writes of its keys
+    pub(crate) fn split_by<K: Ord>(self, owner: impl Fn(&str) -> K) -> BTreeMap<K, Transaction> {
+        let mut parts: BTreeMap<K, Transaction> = BTreeMap::new();
+        for (key, version) in self.reads {
+            parts
+                .entry(owner(&key))
+                .or_default()
+                .reads
+                .insert(key, version);
+        }
+        for (key, value) in self.writes {
+            parts
+                .entry(owner(&key))
+                .or_default()
+                .writes
+                .insert(key, value);
+        }
+        parts
+    }
+}
+
+// A missing or expired key reads as version 0
+fn live_version(db: &Database, key: &str) -> u64 {
+    db.version(key).unwrap_or(0)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionError {
+    // A key the transaction read was written by someone else before commit
+    Conflict(String),
+    // A value doesn't fit its key's schema
+    Invalid(ValidationError),
+    // Everything was applied but saving the database failed
+    Io(String),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Conflict(key) => {
+                write!(
+                    f,
+                    "conflict: key '{}' changed since the transaction read it",
+                    key
+                )
+            }
+            TransactionError::Invalid(error) => write!(f, "{}", error),
+            TransactionError::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
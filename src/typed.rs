@@ -0,0 +1,791 @@
+// NEW: Store Rust types directly, through serde
+//
+//   #[derive(Serialize, Deserialize)]
+//   struct User { name: String, age: u32, email: Option<String> }
+//
+//   let value = typed::to_value(&user)?;        // Value::Object {...}
+//   let user: User = typed::from_value(value)?;
+//
+// How Rust data maps to Value:
+//   - bool -> Boolean, integers -> Integer (u64/i128 above i64 range is an error),
+//     f32/f64 -> Float, char/&str/String -> String, byte buffers -> Bytes
+//   - None and () -> Null, Some(x) -> x
+//   - Vec, tuples, arrays -> Array
+//   - structs and maps -> Object (map keys must be strings or integers)
+//   - enums: unit variants -> String("Variant"), others -> Object { "Variant": data }
+// Reading back is lenient where it's lossless: an Integer fills an f64 field,
+// Timestamp reads as an integer (nanoseconds), Decimal and Uuid as strings.
+//
+// Errors say where in the document they happened: "at `address.zip`: invalid
+// type: string \"abc\", expected u32".
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedError {
+    pub key: Option<String>, // database key of the entry, when reading from a Database
+    pub path: String,        // field path inside the value, "" for the value itself
+    pub message: String,
+}
+
+impl TypedError {
+    fn new(message: String) -> Self {
+        TypedError {
+            key: None,
+            path: String::new(),
+            message,
+        }
+    }
+
+    // Errors are created deep inside the value and bubble up; the innermost
+    // field to see an error without a path is where it happened
+    fn at(mut self, path: &str) -> Self {
+        if self.path.is_empty() {
+            self.path = path.to_string();
+        }
+        self
+    }
+
+    // Serialization errors bubble up the same way, but each level knows only
+    // its own field, so the path is built from the inside out
+    fn inside(mut self, segment: &str) -> Self {
+        self.path = join(segment, &self.path);
+        self
+    }
+
+    pub(crate) fn for_key(mut self, key: &str) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(key) = &self.key {
+            write!(f, "key '{}': ", key)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "at `{}`: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TypedError {}
+
+impl ser::Error for TypedError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        TypedError::new(msg.to_string())
+    }
+}
+
+impl de::Error for TypedError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        TypedError::new(msg.to_string())
+    }
+}
+
+// Convert any serializable value into a Value
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, TypedError> {
+    value.serialize(ValueSerializer)
+}
+
+// Build a T out of a Value
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, TypedError> {
+    T::deserialize(ValueDeserializer {
+        value,
+        path: String::new(),
+    })
+}
+
+// "address" + "zip" -> "address.zip", "tags" + "[2]" -> "tags[2]"
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() || field.is_empty() || field.starts_with('[') {
+        format!("{}{}", path, field)
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+fn index(i: usize) -> String {
+    format!("[{}]", i)
+}
+
+// ---------- Serializer: T -> Value ----------
+
+pub struct ValueSerializer;
+
+fn integer<N: TryInto<i64> + fmt::Display + Copy>(n: N) -> Result<Value, TypedError> {
+    n.try_into()
+        .map(Value::Integer)
+        .map_err(|_| TypedError::new(format!("integer {} does not fit in an i64", n)))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = TypedError;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, TypedError> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, TypedError> {
+        Ok(Value::Integer(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, TypedError> {
+        integer(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, TypedError> {
+        Ok(Value::Float(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, TypedError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, TypedError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, TypedError> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, TypedError> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value, TypedError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, TypedError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, TypedError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, TypedError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, TypedError> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, TypedError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, TypedError> {
+        let mut object = HashMap::new();
+        object.insert(
+            variant.to_string(),
+            to_value(value).map_err(|e| e.inside(variant))?,
+        );
+        Ok(Value::Object(object))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, TypedError> {
+        Ok(SerializeArray {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, TypedError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, TypedError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, TypedError> {
+        Ok(SerializeArray {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeObject, TypedError> {
+        Ok(SerializeObject {
+            fields: HashMap::new(),
+            next_key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeObject, TypedError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeObject, TypedError> {
+        Ok(SerializeObject {
+            fields: HashMap::new(),
+            next_key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+// Wrap enum variant data as { "Variant": data }
+fn wrap_variant(variant: Option<&'static str>, value: Value) -> Value {
+    match variant {
+        Some(name) => {
+            let mut object = HashMap::new();
+            object.insert(name.to_string(), value);
+            Value::Object(object)
+        }
+        None => value,
+    }
+}
+
+fn in_variant(variant: Option<&'static str>, error: TypedError) -> TypedError {
+    match variant {
+        Some(name) => error.inside(name),
+        None => error,
+    }
+}
+
+pub struct SerializeArray {
+    items: Vec<Value>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeArray {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TypedError> {
+        let position = self.items.len();
+        let item =
+            to_value(value).map_err(|e| in_variant(self.variant, e.inside(&index(position))))?;
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, TypedError> {
+        Ok(wrap_variant(self.variant, Value::Array(self.items)))
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Value;
+    type Error = TypedError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TypedError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Value;
+    type Error = TypedError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TypedError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Value;
+    type Error = TypedError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TypedError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Value;
+    type Error = TypedError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TypedError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+pub struct SerializeObject {
+    fields: HashMap<String, Value>,
+    next_key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeObject {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), TypedError> {
+        let value = to_value(value).map_err(|e| in_variant(self.variant, e.inside(&key)))?;
+        self.fields.insert(key, value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, TypedError> {
+        Ok(wrap_variant(self.variant, Value::Object(self.fields)))
+    }
+}
+
+impl ser::SerializeMap for SerializeObject {
+    type Ok = Value;
+    type Error = TypedError;
+
+    // Object keys are strings: string keys are used as they are, integer keys
+    // are written in decimal, anything else is an error
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), TypedError> {
+        let key = match to_value(key)? {
+            Value::String(s) => s,
+            Value::Integer(i) => i.to_string(),
+            other => {
+                return Err(TypedError::new(format!(
+                    "map keys must be strings or integers, found {}",
+                    other.type_name()
+                )));
+            }
+        };
+        self.next_key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), TypedError> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| TypedError::new("map value without a key".to_string()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = Value;
+    type Error = TypedError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), TypedError> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeObject {
+    type Ok = Value;
+    type Error = TypedError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), TypedError> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, TypedError> {
+        self.finish()
+    }
+}
+
+// ---------- Deserializer: Value -> T ----------
+
+pub struct ValueDeserializer {
+    value: Value,
+    path: String, // where this value sits in the document, for error messages
+}
+
+impl ValueDeserializer {
+    // The value as serde describes it in "invalid type" messages
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match &self.value {
+            Value::String(s) => de::Unexpected::Str(s),
+            Value::Integer(i) => de::Unexpected::Signed(*i),
+            Value::Float(f) => de::Unexpected::Float(*f),
+            Value::Boolean(b) => de::Unexpected::Bool(*b),
+            Value::Array(_) => de::Unexpected::Seq,
+            Value::Object(_) => de::Unexpected::Map,
+            Value::Null => de::Unexpected::Unit,
+            Value::Bytes(b) => de::Unexpected::Bytes(b),
+            Value::Timestamp(_) => de::Unexpected::Other("timestamp"),
+            Value::Decimal(_) => de::Unexpected::Other("decimal"),
+            Value::Uuid(_) => de::Unexpected::Other("uuid"),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = TypedError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+        let path = self.path.clone();
+        let result = match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Integer(i) | Value::Timestamp(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Decimal(d) => visitor.visit_string(d.to_string()),
+            Value::Uuid(u) => visitor.visit_string(u.to_string()),
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            Value::Array(items) => visitor.visit_seq(ArrayAccess {
+                items: items.into_iter().enumerate(),
+                path: self.path,
+            }),
+            Value::Object(fields) => visitor.visit_map(ObjectAccess {
+                fields: fields.into_iter(),
+                pending: None,
+                path: self.path,
+            }),
+        };
+        result.map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TypedError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // "Variant" for unit variants, { "Variant": data } for the others
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypedError> {
+        let path = self.path.clone();
+        let (variant, data) = match self.value {
+            Value::String(variant) => (variant, None),
+            Value::Object(fields) if fields.len() == 1 => {
+                let (variant, data) = fields.into_iter().next().expect("one field");
+                (variant, Some(data))
+            }
+            _ => {
+                let message = format!(
+                    "expected an enum (a string or an object with one key), found {}",
+                    self.value.type_name()
+                );
+                return Err(TypedError::new(message).at(&path));
+            }
+        };
+        let data = data.map(|value| ValueDeserializer {
+            value,
+            path: join(&path, &variant),
+        });
+        visitor
+            .visit_enum(EnumDeserializer { variant, data })
+            .map_err(|e| e.at(&path))
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+        match self.value {
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+        .map_err(|e: TypedError| e.at(&self.path))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ArrayAccess {
+    items: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+    path: String,
+}
+
+impl<'de> SeqAccess<'de> for ArrayAccess {
+    type Error = TypedError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, TypedError> {
+        match self.items.next() {
+            Some((position, value)) => {
+                let path = join(&self.path, &index(position));
+                seed.deserialize(ValueDeserializer {
+                    value,
+                    path: path.clone(),
+                })
+                .map(Some)
+                .map_err(|e| e.at(&path))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct ObjectAccess {
+    fields: std::collections::hash_map::IntoIter<String, Value>,
+    pending: Option<(String, Value)>, // the entry whose key was just read
+    path: String,
+}
+
+impl<'de> MapAccess<'de> for ObjectAccess {
+    type Error = TypedError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TypedError> {
+        match self.fields.next() {
+            Some((key, value)) => {
+                let parsed = seed.deserialize(KeyDeserializer(key.clone()))?;
+                self.pending = Some((key, value));
+                Ok(Some(parsed))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, TypedError> {
+        let (key, value) = self
+            .pending
+            .take()
+            .ok_or_else(|| TypedError::new("value requested before its key".to_string()))?;
+        let path = join(&self.path, &key);
+        seed.deserialize(ValueDeserializer {
+            value,
+            path: path.clone(),
+        })
+        .map_err(|e| e.at(&path))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+// Object keys are strings; integer map keys (HashMap<u32, _>) are parsed back
+struct KeyDeserializer(String);
+
+macro_rules! parse_key {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+                match self.0.parse() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(_) => visitor.visit_string(self.0),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = TypedError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypedError> {
+        visitor.visit_string(self.0)
+    }
+
+    parse_key! {
+        deserialize_i8 => visit_i8, deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32, deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8, deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32, deserialize_u64 => visit_u64
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    data: Option<ValueDeserializer>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = TypedError;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer), TypedError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantDeserializer { data: self.data }))
+    }
+}
+
+struct VariantDeserializer {
+    data: Option<ValueDeserializer>,
+}
+
+impl VariantDeserializer {
+    fn data(self, expected: &str) -> Result<ValueDeserializer, TypedError> {
+        self.data
+            .ok_or_else(|| TypedError::new(format!("expected {}, found a unit variant", expected)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer {
+    type Error = TypedError;
+
+    fn unit_variant(self) -> Result<(), TypedError> {
+        match self.data {
+            None => Ok(()),
+            Some(data) => de::Deserialize::deserialize(data),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, TypedError> {
+        seed.deserialize(self.data("a newtype variant")?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TypedError> {
+        de::Deserializer::deserialize_seq(self.data("a tuple variant")?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypedError> {
+        de::Deserializer::deserialize_map(self.data("a struct variant")?, visitor)
+    }
+}
@@ -0,0 +1,491 @@
+// NEW: Scalar types behind the newer Value variants
+//
+//   - Decimal:   exact base-10 number (money!), mantissa * 10^-scale
+//   - Uuid:      128-bit identifier, printed as 8-4-4-4-12 hex digits
+//   - Timestamp: stored in Value as i64 nanoseconds since 1970-01-01 UTC;
+//                the helpers here convert to and from RFC 3339 text
+//   - base64 helpers for moving Bytes in and out of text
+
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+// ---------- Decimal ----------
+
+// Digits after the decimal point a Decimal can carry
+pub const MAX_DECIMAL_SCALE: u32 = 28;
+
+// Exact decimal number: 12.50 is Decimal { mantissa: 1250, scale: 2 }
+// The scale is kept as written ("12.50" prints as 12.50), but comparisons are
+// by numeric value, so 12.50 == 12.5
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "RawDecimal")]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+// A Decimal as read from a file, before its scale is checked (arithmetic
+// and comparisons assume scale <= MAX_DECIMAL_SCALE)
+#[derive(Deserialize)]
+struct RawDecimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl TryFrom<RawDecimal> for Decimal {
+    type Error = String;
+
+    fn try_from(raw: RawDecimal) -> Result<Self, Self::Error> {
+        Decimal::new(raw.mantissa, raw.scale).ok_or_else(|| {
+            format!(
+                "decimal scale {} is larger than {}",
+                raw.scale, MAX_DECIMAL_SCALE
+            )
+        })
+    }
+}
+
+fn pow10(exponent: u32) -> i128 {
+    10i128.pow(exponent)
+}
+
+impl Decimal {
+    // None if the scale is larger than MAX_DECIMAL_SCALE
+    pub fn new(mantissa: i128, scale: u32) -> Option<Decimal> {
+        (scale <= MAX_DECIMAL_SCALE).then_some(Decimal { mantissa, scale })
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    // Same value with trailing zeros removed: 12.50 -> 12.5
+    pub fn normalize(&self) -> Decimal {
+        let mut d = *self;
+        while d.scale > 0 && d.mantissa % 10 == 0 {
+            d.mantissa /= 10;
+            d.scale -= 1;
+        }
+        d
+    }
+
+    // Shortest decimal that prints like the float; None for NaN, infinity
+    // and numbers too large to fit
+    pub fn from_f64(f: f64) -> Option<Decimal> {
+        if !f.is_finite() {
+            return None;
+        }
+        f.to_string().parse().ok()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Going through text gives the closest float, mantissa / 10^scale may not
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let a = self.mantissa.checked_mul(pow10(scale - self.scale))?;
+        let b = other.mantissa.checked_mul(pow10(scale - other.scale))?;
+        Some(Decimal {
+            mantissa: a.checked_add(b)?,
+            scale,
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        self.checked_add(&Decimal {
+            mantissa: other.mantissa.checked_neg()?,
+            scale: other.scale,
+        })
+    }
+
+    // Digits beyond MAX_DECIMAL_SCALE are rounded half away from zero
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        let mut mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        let mut scale = self.scale + other.scale;
+        if scale > MAX_DECIMAL_SCALE {
+            let divisor = pow10(scale - MAX_DECIMAL_SCALE);
+            let rounded = mantissa / divisor;
+            let remainder = (mantissa % divisor).abs();
+            mantissa = if remainder * 2 >= divisor {
+                rounded + mantissa.signum()
+            } else {
+                rounded
+            };
+            scale = MAX_DECIMAL_SCALE;
+        }
+        Some(Decimal { mantissa, scale })
+    }
+
+    // (integer part, fraction scaled to MAX_DECIMAL_SCALE digits): comparing
+    // these pairs compares the numbers without overflowing
+    pub(crate) fn parts(&self) -> (i128, i128) {
+        let unit = pow10(self.scale);
+        let fraction = self.mantissa.rem_euclid(unit) * pow10(MAX_DECIMAL_SCALE - self.scale);
+        (self.mantissa.div_euclid(unit), fraction)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(i: i64) -> Self {
+        Decimal {
+            mantissa: i as i128,
+            scale: 0,
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.parts().cmp(&other.parts())
+    }
+}
+
+// Equal numbers hash the same, whatever their scale
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let d = self.normalize();
+        d.mantissa.hash(state);
+        d.scale.hash(state);
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        // Pad so there is at least one digit before the point: 5 (scale 2) -> 0.05
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+// "-12.50", "3", "+0.001"
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid decimal '{}'", s);
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if fraction.len() > MAX_DECIMAL_SCALE as usize {
+            return Err(format!(
+                "decimal '{}' has more than {} digits after the point",
+                s, MAX_DECIMAL_SCALE
+            ));
+        }
+
+        let mut mantissa: i128 = 0;
+        for c in whole.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or_else(invalid)?;
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(digit as i128))
+                .ok_or_else(|| format!("decimal '{}' is too large", s))?;
+        }
+        Ok(Decimal {
+            mantissa: if negative { -mantissa } else { mantissa },
+            scale: fraction.len() as u32,
+        })
+    }
+}
+
+// ---------- Uuid ----------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Uuid([u8; 16]);
+
+impl Uuid {
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Uuid(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    pub fn nil() -> Self {
+        Uuid([0; 16])
+    }
+
+    // Random (version 4) UUID
+    // Randomness comes from the standard library's randomly keyed hasher, so we
+    // don't need an RNG dependency; fine for identifiers, not for secrets
+    pub fn new_v4() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let count = COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+
+        let mut bytes = [0u8; 16];
+        for (half, chunk) in bytes.chunks_mut(8).enumerate() {
+            let random = RandomState::new().hash_one((count, nanos, half));
+            chunk.copy_from_slice(&random.to_le_bytes());
+        }
+        bytes[6] = (bytes[6] & 0x0f) | 0x40; // version 4
+        bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+        Uuid(bytes)
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+// "67e55044-10b1-426f-9247-bb680e5fe0c8" (hyphens optional, any case)
+impl FromStr for Uuid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid UUID '{}'", s);
+        let hex: Vec<char> = s.chars().filter(|c| *c != '-').collect();
+        let hyphens = s.len() - hex.len();
+        if hex.len() != 32 || !(hyphens == 0 || (hyphens == 4 && s.len() == 36)) {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; 16];
+        for (i, pair) in hex.chunks(2).enumerate() {
+            let high = pair[0].to_digit(16).ok_or_else(invalid)?;
+            let low = pair[1].to_digit(16).ok_or_else(invalid)?;
+            bytes[i] = (high * 16 + low) as u8;
+        }
+        Ok(Uuid(bytes))
+    }
+}
+
+// ---------- Timestamps ----------
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// Nanoseconds since the Unix epoch (None if out of the i64 range, ~1677..2262)
+pub fn timestamp_from_system_time(time: SystemTime) -> Option<i64> {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => i64::try_from(after.as_nanos()).ok(),
+        Err(before) => i64::try_from(before.duration().as_nanos()).ok().map(|n| -n),
+    }
+}
+
+pub fn timestamp_to_system_time(nanos: i64) -> SystemTime {
+    let magnitude = std::time::Duration::from_nanos(nanos.unsigned_abs());
+    if nanos >= 0 {
+        UNIX_EPOCH + magnitude
+    } else {
+        UNIX_EPOCH - magnitude
+    }
+}
+
+pub fn timestamp_now() -> i64 {
+    timestamp_from_system_time(SystemTime::now()).unwrap_or(0)
+}
+
+// Days since 1970-01-01 for a civil date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Inverse of days_from_civil: (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// RFC 3339 in UTC, e.g. "2024-03-01T12:30:00.25Z" (fraction only when non-zero)
+pub fn format_timestamp(nanos: i64) -> String {
+    let seconds = nanos.div_euclid(NANOS_PER_SECOND);
+    let fraction = nanos.rem_euclid(NANOS_PER_SECOND);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+    let second_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    );
+    if fraction != 0 {
+        let digits = format!("{:09}", fraction);
+        text.push('.');
+        text.push_str(digits.trim_end_matches('0'));
+    }
+    text.push('Z');
+    text
+}
+
+// Parse RFC 3339: "2024-03-01T12:30:00Z", "2024-03-01 12:30:00.123+02:00"
+// Returns nanoseconds since the epoch in UTC
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+    let bytes = text.as_bytes();
+    if bytes.len() < 20 || !matches!(bytes[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let part = text.get(range)?;
+        if part.bytes().all(|b| b.is_ascii_digit()) {
+            part.parse().ok()
+        } else {
+            None
+        }
+    };
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if separators.iter().any(|(i, c)| bytes[*i] != *c) {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month as u32) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Optional fraction, then Z or an offset
+    let mut rest = &text[19..];
+    let mut fraction = 0;
+    if let Some(after_point) = rest.strip_prefix('.') {
+        let length = after_point.bytes().take_while(u8::is_ascii_digit).count();
+        if length == 0 {
+            return None;
+        }
+        // Digits past nanoseconds are dropped
+        let digits = &after_point[..length.min(9)];
+        fraction = digits.parse::<i64>().ok()? * 10i64.pow(9 - digits.len() as u32);
+        rest = &after_point[length..];
+    }
+    let offset_seconds = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let digits = [h1, h2, m1, m2];
+            if !digits.iter().all(|d| d.is_ascii_digit()) {
+                return None;
+            }
+            let value = |a: &u8, b: &u8| ((a - b'0') * 10 + (b - b'0')) as i64;
+            let offset = value(h1, h2) * 3600 + value(m1, m2) * 60;
+            if *sign == b'-' { -offset } else { offset }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month as u32, day as u32);
+    let seconds = days * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second - offset_seconds;
+    seconds.checked_mul(NANOS_PER_SECOND)?.checked_add(fraction)
+}
+
+fn days_in_month(year: i64, month: u32) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// ---------- base64 ----------
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard base64 with '=' padding
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// Accepts padded or unpadded standard base64; None on anything else
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = buffer << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    // A single leftover character can't encode a whole byte
+    (bits < 6).then_some(data)
+}
@@ -1,12 +1,17 @@
 //  Defines the Value enum and its methods
 
+use crate::types::{self, Decimal, MAX_DECIMAL_SCALE, Uuid};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
 
 // Enum to represent different value types our database can store
 // This is like a union of different types
 // Serialize and Deserialize let us save/load data to/from disk
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)] // These let us copy and print Values . The PartialEq trait allows you to compare instances of a type to check for equality and enables use of the == and != operators. 
+#[derive(Clone, Debug, Serialize, Deserialize)] // These let us copy and print Values. Comparing, hashing and printing are implemented by hand below
 pub enum Value {
     // Enums can hold different types of data. Each variant can store its own data type!
     String(String),
@@ -16,32 +21,15 @@ pub enum Value {
     Array(Vec<Value>),
     Object(HashMap<String, Value>),
     Null,
+    // NEW: New variants go at the end: the file format stores the variant's
+    // position, so files written before they existed still load
+    Bytes(Vec<u8>),   // binary data
+    Timestamp(i64),   // nanoseconds since 1970-01-01 00:00:00 UTC
+    Decimal(Decimal), // exact decimal number (see types::Decimal)
+    Uuid(Uuid),
 }
 
 impl Value {
-    // Convert Value to a displayable string
-    pub fn to_string(&self) -> String {
-        match self {
-            Value::String(s) => s.clone(), // type & because "s" reference to the string stored inside the enum , same for below as well
-            Value::Integer(i) => i.to_string(),
-            Value::Float(f) => f.to_string(),
-            Value::Boolean(b) => b.to_string(),
-            Value::Array(arr) => {
-                let items: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
-                format!("[{}]", items.join(", "))
-            }
-            Value::Object(obj) => {
-                let items: Vec<String> = obj
-                    .iter()
-                    .map(|(k, v)| format!("{}: {}", k, v.to_string()))
-                    .collect();
-
-                format!("{{{}}}", items.join(", "))
-            }
-            Value::Null => "null".to_string(),
-        }
-    }
-
     pub fn as_integer(&self) -> Option<i64> {
         match self {
             Value::Integer(i) => Some(*i), // i is a reference to the integer stored inside the enum, because self is borrowed (&self). the * is the dereference operator.take the value inside the i instead of the reference to it.
@@ -74,6 +62,383 @@ impl Value {
             Value::Array(_) => "Array",
             Value::Object(_) => "Object",
             Value::Null => "Null",
+            Value::Bytes(_) => "Bytes",
+            Value::Timestamp(_) => "Timestamp",
+            Value::Decimal(_) => "Decimal",
+            Value::Uuid(_) => "Uuid",
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    // Nanoseconds since the epoch
+    pub fn as_timestamp(&self) -> Option<i64> {
+        match self {
+            Value::Timestamp(nanos) => Some(*nanos),
+            _ => None,
+        }
+    }
+
+    pub fn as_system_time(&self) -> Option<SystemTime> {
+        self.as_timestamp().map(types::timestamp_to_system_time)
+    }
+
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Decimal(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match self {
+            Value::Uuid(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    // NEW: Helpers for moving old representations to the new variants
+    // (base64 strings -> Bytes, integer times -> Timestamp, floats -> Decimal)
+
+    pub fn timestamp_now() -> Value {
+        Value::Timestamp(types::timestamp_now())
+    }
+
+    pub fn timestamp_from_secs(seconds: i64) -> Option<Value> {
+        seconds.checked_mul(1_000_000_000).map(Value::Timestamp)
+    }
+
+    pub fn timestamp_from_millis(millis: i64) -> Option<Value> {
+        millis.checked_mul(1_000_000).map(Value::Timestamp)
+    }
+
+    // RFC 3339 text, e.g. "2024-03-01T12:30:00Z"
+    pub fn parse_timestamp(text: &str) -> Option<Value> {
+        types::parse_timestamp(text).map(Value::Timestamp)
+    }
+
+    pub fn bytes_from_base64(text: &str) -> Option<Value> {
+        types::base64_decode(text).map(Value::Bytes)
+    }
+
+    pub fn parse_decimal(text: &str) -> Option<Value> {
+        text.parse().ok().map(Value::Decimal)
+    }
+
+    pub fn parse_uuid(text: &str) -> Option<Value> {
+        text.parse().ok().map(Value::Uuid)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
+impl From<Uuid> for Value {
+    fn from(u: Uuid) -> Self {
+        Value::Uuid(u)
+    }
+}
+
+// Times before 1677 or after 2262 don't fit in i64 nanoseconds and are clamped
+impl From<SystemTime> for Value {
+    fn from(time: SystemTime) -> Self {
+        let nanos = types::timestamp_from_system_time(time).unwrap_or_else(|| {
+            if time > SystemTime::UNIX_EPOCH {
+                i64::MAX
+            } else {
+                i64::MIN
+            }
+        });
+        Value::Timestamp(nanos)
+    }
+}
+
+// NEW: Comparing, hashing and printing
+//
+// Values of different kinds are ordered by kind:
+//   Null < Boolean < numbers < Timestamp < String < Bytes < Uuid < Array < Object
+// Numbers (Integer, Float, Decimal) compare by numeric value across variants;
+// when two are numerically equal the variant breaks the tie
+// (Integer < Float < Decimal), so Integer(1) sorts right before Float(1.0) but
+// the two are not ==. Floats are totally ordered: -0.0 equals 0.0, and NaN
+// equals itself and sorts after every other number. Arrays compare element by
+// element, objects compare their entries in key order.
+//
+// Eq and Hash agree with this order, so Values can be map keys, be sorted,
+// and go into HashSets.
+
+impl Value {
+    fn kind_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => 2,
+            Value::Timestamp(_) => 3,
+            Value::String(_) => 4,
+            Value::Bytes(_) => 5,
+            Value::Uuid(_) => 6,
+            Value::Array(_) => 7,
+            Value::Object(_) => 8,
+        }
+    }
+
+    // Object entries in key order (HashMap iteration order is arbitrary)
+    fn sorted_entries(fields: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+        let mut entries: Vec<_> = fields.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+}
+
+// Numeric order of two numbers of any variant, ignoring the variant itself
+pub(crate) fn compare_numbers(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => x.cmp(y),
+        (Value::Float(x), Value::Float(y)) => compare_floats(*x, *y),
+        (Value::Decimal(x), Value::Decimal(y)) => x.cmp(y),
+        (Value::Integer(x), Value::Float(y)) => compare_integer_float(*x, *y),
+        (Value::Float(x), Value::Integer(y)) => compare_integer_float(*y, *x).reverse(),
+        (Value::Decimal(x), Value::Integer(y)) => x.cmp(&Decimal::from(*y)),
+        (Value::Integer(x), Value::Decimal(y)) => Decimal::from(*x).cmp(y),
+        (Value::Decimal(x), Value::Float(y)) => compare_decimal_float(x, *y),
+        (Value::Float(x), Value::Decimal(y)) => compare_decimal_float(y, *x).reverse(),
+        _ => Ordering::Equal,
+    }
+}
+
+// NaN after everything, -0.0 == 0.0
+fn compare_floats(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+// Exact, without rounding the integer to a float first (2^53 + 1 > 2^53 as f64)
+fn compare_integer_float(i: i64, f: f64) -> Ordering {
+    const TWO_POW_63: f64 = 9_223_372_036_854_775_808.0;
+    if f.is_nan() || f >= TWO_POW_63 {
+        return Ordering::Less;
+    }
+    if f < -TWO_POW_63 {
+        return Ordering::Greater;
+    }
+    let whole = f.trunc();
+    match i.cmp(&(whole as i64)) {
+        Ordering::Equal => compare_floats(whole, f),
+        ordering => ordering,
+    }
+}
+
+// Exact as well: integer parts first, then the fractions. A finite float's
+// fraction is a / 2^k, the decimal's is digits / 10^28 = digits / (2^28 * 5^28),
+// so the two compare in integers without rounding either
+fn compare_decimal_float(d: &Decimal, f: f64) -> Ordering {
+    const TWO_POW_127: f64 = 170_141_183_460_469_231_731_687_303_715_884_105_728.0;
+    // Past every decimal (a mantissa is an i128)
+    if f.is_nan() || f >= TWO_POW_127 {
+        return Ordering::Less;
+    }
+    if f < -TWO_POW_127 {
+        return Ordering::Greater;
+    }
+    let whole = f.floor();
+    let (integer, digits) = d.parts();
+    match integer.cmp(&(whole as i128)) {
+        Ordering::Equal => compare_fraction(digits as u128, f - whole),
+        ordering => ordering,
+    }
+}
+
+// digits / 10^MAX_DECIMAL_SCALE against a float in [0, 1)
+fn compare_fraction(digits: u128, fraction: f64) -> Ordering {
+    const MANTISSA: u64 = (1 << 52) - 1;
+    const SCALE: i32 = MAX_DECIMAL_SCALE as i32;
+    if fraction == 0.0 {
+        return digits.cmp(&0);
+    }
+    let bits = fraction.to_bits();
+    let exponent = (bits >> 52) as i32; // the sign bit is 0
+    let (a, k) = match exponent {
+        0 => ((bits & MANTISSA) as u128, 1074), // subnormal
+        _ => (((bits & MANTISSA) | (1 << 52)) as u128, 1075 - exponent),
+    };
+    let five = 5u128.pow(MAX_DECIMAL_SCALE);
+    if k >= SCALE {
+        // digits * 2^(k - 28) against a * 5^28
+        let target = a * five;
+        let shift = (k - SCALE) as u32;
+        let (quotient, exact) = match shift {
+            0..128 => (target >> shift, target & ((1 << shift) - 1) == 0),
+            _ => (0, false),
+        };
+        match digits.cmp(&quotient) {
+            Ordering::Equal if !exact => Ordering::Less,
+            ordering => ordering,
+        }
+    } else {
+        // fraction < 1, so a < 2^k and this stays below 2^93
+        digits.cmp(&((a * five) << (SCALE - k)))
+    }
+}
+
+fn number_variant(value: &Value) -> u8 {
+    match value {
+        Value::Integer(_) => 0,
+        Value::Float(_) => 1,
+        _ => 2,
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => compare_floats(*a, *b) == Ordering::Equal,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+            (Value::Decimal(a), Value::Decimal(b)) => a == b,
+            (Value::Uuid(a), Value::Uuid(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let rank = self.kind_rank().cmp(&other.kind_rank());
+        if rank != Ordering::Equal {
+            return rank;
+        }
+        match (self, other) {
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::Uuid(a), Value::Uuid(b)) => a.cmp(b),
+            (Value::Array(a), Value::Array(b)) => a.cmp(b),
+            (Value::Object(a), Value::Object(b)) => {
+                Value::sorted_entries(a).cmp(&Value::sorted_entries(b))
+            }
+            // Same rank and not one of the above: both are numbers (or both Null)
+            _ => compare_numbers(self, other)
+                .then_with(|| number_variant(self).cmp(&number_variant(other))),
+        }
+    }
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::String(s) => s.hash(state),
+            Value::Integer(i) => i.hash(state),
+            Value::Float(f) => {
+                // Equal floats must hash the same: one NaN, one zero
+                let bits = if f.is_nan() {
+                    f64::NAN.to_bits()
+                } else if *f == 0.0 {
+                    0
+                } else {
+                    f.to_bits()
+                };
+                bits.hash(state);
+            }
+            Value::Boolean(b) => b.hash(state),
+            Value::Array(items) => items.hash(state),
+            Value::Object(fields) => Value::sorted_entries(fields).hash(state),
+            Value::Null => {}
+            Value::Bytes(b) => b.hash(state),
+            Value::Timestamp(t) => t.hash(state),
+            Value::Decimal(d) => d.hash(state),
+            Value::Uuid(u) => u.hash(state),
+        }
+    }
+}
+
+// Strings are double-quoted and escaped, objects list their keys sorted:
+//   {"name": "Alice", "scores": [1, 2.5], "tags": null}
+// Floats always show they are floats (1.0, 1e300, NaN, inf), bytes are hex
+// (0x00ff), timestamps RFC 3339, decimals and UUIDs print as written
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write_quoted(f, s),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in Value::sorted_entries(fields).into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_quoted(f, key)?;
+                    write!(f, ": {}", value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Null => write!(f, "null"),
+            Value::Bytes(bytes) => {
+                write!(f, "0x")?;
+                for b in bytes {
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+            Value::Timestamp(nanos) => write!(f, "{}", types::format_timestamp(*nanos)),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Uuid(u) => write!(f, "{}", u),
+        }
+    }
+}
+
+// JSON-style escapes: \" \\ \n \r \t, other control characters as \u00XX
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
         }
     }
+    write!(f, "\"")
 }
@@ -0,0 +1,500 @@
+// NEW: Vector similarity search over Array fields (embeddings)
+//
+// A vector index is declared on a field holding an array of numbers, e.g.
+// "embedding": [0.12, -0.4, ...]. Two backends are available:
+//   - Exact: compares the query against every vector (always correct, O(n))
+//   - Hnsw:  Hierarchical Navigable Small World graph, an approximate index
+//            that only visits a small part of the data (fast, may miss a few)
+// Distances are "smaller is closer" for every metric:
+//   - Cosine:     1 - cos(angle)       (0 = same direction, 2 = opposite)
+//   - DotProduct: -(a . b)             (larger dot product = closer)
+//   - L2:         Euclidean distance
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    Cosine,
+    DotProduct,
+    L2,
+}
+
+impl Metric {
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::L2 => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+            Metric::DotProduct => -dot(a, b),
+            Metric::Cosine => {
+                let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot(a, b) / norms
+                }
+            }
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// HNSW tuning knobs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HnswParams {
+    pub m: usize,               // links per node and level (level 0 gets 2 * m)
+    pub ef_construction: usize, // candidates considered while inserting
+    pub ef_search: usize,       // candidates considered while searching
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        HnswParams {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VectorIndexKind {
+    Exact,
+    Hnsw(HnswParams),
+}
+
+// Options for Database::create_vector_index
+// Built with chained calls: VectorIndexOptions::hnsw(Metric::Cosine).dimension(384)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VectorIndexOptions {
+    pub metric: Metric,
+    pub kind: VectorIndexKind,
+    // Expected vector length; None = taken from the first vector indexed
+    pub dimension: Option<usize>,
+}
+
+impl VectorIndexOptions {
+    pub fn exact(metric: Metric) -> Self {
+        VectorIndexOptions {
+            metric,
+            kind: VectorIndexKind::Exact,
+            dimension: None,
+        }
+    }
+
+    pub fn hnsw(metric: Metric) -> Self {
+        VectorIndexOptions {
+            metric,
+            kind: VectorIndexKind::Hnsw(HnswParams::default()),
+            dimension: None,
+        }
+    }
+
+    pub fn dimension(mut self, dimension: usize) -> Self {
+        self.dimension = Some(dimension);
+        self
+    }
+
+    pub fn params(mut self, params: HnswParams) -> Self {
+        self.kind = VectorIndexKind::Hnsw(params);
+        self
+    }
+}
+
+// One result of Database::nearest, closest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbor {
+    pub key: String,
+    pub distance: f32,
+    pub value: Value,
+}
+
+// Read a vector out of a value: an array whose items are all numbers
+pub fn vector_of(value: &Value) -> Option<Vec<f32>> {
+    let Value::Array(items) = value else {
+        return None;
+    };
+    if items.is_empty() {
+        return None;
+    }
+    items
+        .iter()
+        .map(|item| match item {
+            Value::Float(f) => Some(*f as f32),
+            Value::Integer(i) => Some(*i as f32),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Backend {
+    Exact(BTreeMap<String, Vec<f32>>),
+    Hnsw(Hnsw),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorIndex {
+    options: VectorIndexOptions,
+    dimension: Option<usize>,
+    backend: Backend,
+}
+
+impl VectorIndex {
+    pub fn new(options: VectorIndexOptions) -> Self {
+        let backend = match options.kind {
+            VectorIndexKind::Exact => Backend::Exact(BTreeMap::new()),
+            VectorIndexKind::Hnsw(params) => Backend::Hnsw(Hnsw::new(params, options.metric)),
+        };
+        VectorIndex {
+            options,
+            dimension: options.dimension,
+            backend,
+        }
+    }
+
+    pub fn options(&self) -> VectorIndexOptions {
+        self.options
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.backend {
+            Backend::Exact(vectors) => vectors.len(),
+            Backend::Hnsw(graph) => graph.by_key.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        match &self.backend {
+            Backend::Exact(vectors) => vectors.keys().cloned().collect(),
+            Backend::Hnsw(graph) => graph.by_key.keys().cloned().collect(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[f32]> {
+        match &self.backend {
+            Backend::Exact(vectors) => vectors.get(key).map(|v| v.as_slice()),
+            Backend::Hnsw(graph) => graph
+                .by_key
+                .get(key)
+                .map(|id| graph.nodes[*id].vector.as_slice()),
+        }
+    }
+
+    // Index a key's vector; vectors of the wrong length are skipped
+    pub fn add(&mut self, key: &str, vector: Vec<f32>) {
+        match self.dimension {
+            Some(dimension) if dimension != vector.len() => return,
+            None => self.dimension = Some(vector.len()),
+            _ => {}
+        }
+        match &mut self.backend {
+            Backend::Exact(vectors) => {
+                vectors.insert(key.to_string(), vector);
+            }
+            Backend::Hnsw(graph) => graph.insert(key, vector),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        match &mut self.backend {
+            Backend::Exact(vectors) => {
+                vectors.remove(key);
+            }
+            Backend::Hnsw(graph) => graph.remove(key),
+        }
+    }
+
+    // Drop every vector but keep the options
+    pub fn clear(&mut self) {
+        *self = VectorIndex::new(self.options);
+    }
+
+    // The k closest keys accepted by `accept`, closest first
+    pub fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        accept: &dyn Fn(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        match &self.backend {
+            Backend::Exact(vectors) => {
+                // Keep the k best in a max-heap keyed on distance
+                let mut best: BinaryHeap<(Distance, &String)> = BinaryHeap::new();
+                for (key, vector) in vectors {
+                    if !accept(key) {
+                        continue;
+                    }
+                    best.push((Distance(self.options.metric.distance(query, vector)), key));
+                    if best.len() > k {
+                        best.pop();
+                    }
+                }
+                let mut results: Vec<(String, f32)> = best
+                    .into_iter()
+                    .map(|(d, key)| (key.clone(), d.0))
+                    .collect();
+                results.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+                results
+            }
+            Backend::Hnsw(graph) => graph.search(query, k, accept),
+        }
+    }
+}
+
+// f32 with a total order so it can live in a BinaryHeap
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Distance(f32);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    key: String,
+    vector: Vec<f32>,
+    neighbors: Vec<Vec<usize>>, // one link list per level the node lives on
+    deleted: bool,
+}
+
+// The HNSW graph
+// Deleted nodes stay in the graph as tombstones (they still help routing) and
+// the graph is rebuilt once more than half of it is tombstones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Hnsw {
+    params: HnswParams,
+    metric: Metric,
+    nodes: Vec<Node>,
+    by_key: HashMap<String, usize>,
+    entry: Option<usize>,
+    max_level: usize,
+    deleted: usize,
+    rng: u64, // xorshift state for level assignment (kept so reloads stay deterministic)
+}
+
+impl Hnsw {
+    fn new(params: HnswParams, metric: Metric) -> Self {
+        Hnsw {
+            params,
+            metric,
+            nodes: Vec::new(),
+            by_key: HashMap::new(),
+            entry: None,
+            max_level: 0,
+            deleted: 0,
+            rng: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+
+    // Levels follow a geometric distribution: each level has ~1/m of the nodes below
+    fn random_level(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = (self.rng >> 11) as f64 / (1u64 << 53) as f64;
+        let scale = 1.0 / (self.params.m.max(2) as f64).ln();
+        ((-uniform.max(f64::MIN_POSITIVE).ln() * scale) as usize).min(16)
+    }
+
+    fn distance_to(&self, query: &[f32], id: usize) -> Distance {
+        Distance(self.metric.distance(query, &self.nodes[id].vector))
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.params.m * 2
+        } else {
+            self.params.m
+        }
+    }
+
+    fn insert(&mut self, key: &str, vector: Vec<f32>) {
+        if self.by_key.contains_key(key) {
+            self.remove(key);
+        }
+
+        let level = self.random_level();
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            key: key.to_string(),
+            vector,
+            neighbors: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.by_key.insert(key.to_string(), id);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            self.max_level = level;
+            return;
+        };
+
+        let query = self.nodes[id].vector.clone();
+        // Walk down the upper levels greedily towards the new vector
+        let mut entry_points = vec![entry];
+        for l in (level + 1..=self.max_level).rev() {
+            let closest = self.search_layer(&query, &entry_points, 1, l);
+            entry_points = vec![closest[0].1];
+        }
+
+        // On every level the new node shares with the graph, link it to its closest nodes
+        for l in (0..=level.min(self.max_level)).rev() {
+            let found = self.search_layer(&query, &entry_points, self.params.ef_construction, l);
+            let links: Vec<usize> = found
+                .iter()
+                .map(|(_, n)| *n)
+                .filter(|n| *n != id && !self.nodes[*n].deleted)
+                .take(self.params.m)
+                .collect();
+            self.nodes[id].neighbors[l] = links.clone();
+            for neighbor in links {
+                self.nodes[neighbor].neighbors[l].push(id);
+                if self.nodes[neighbor].neighbors[l].len() > self.max_links(l) {
+                    self.prune(neighbor, l);
+                }
+            }
+            entry_points = found.into_iter().map(|(_, n)| n).collect();
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry = Some(id);
+        }
+    }
+
+    // Keep only the closest links of a node that has too many
+    fn prune(&mut self, id: usize, level: usize) {
+        let vector = self.nodes[id].vector.clone();
+        let mut links = std::mem::take(&mut self.nodes[id].neighbors[level]);
+        links.sort_by_key(|n| self.distance_to(&vector, *n));
+        links.truncate(self.max_links(level));
+        self.nodes[id].neighbors[level] = links;
+    }
+
+    fn remove(&mut self, key: &str) {
+        let Some(id) = self.by_key.remove(key) else {
+            return;
+        };
+        self.nodes[id].deleted = true;
+        self.deleted += 1;
+        if self.deleted * 2 > self.nodes.len() {
+            self.rebuild();
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let live: Vec<Node> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .filter(|n| !n.deleted)
+            .collect();
+        let rng = self.rng;
+        *self = Hnsw::new(self.params, self.metric);
+        self.rng = rng;
+        for node in live {
+            self.insert(&node.key, node.vector);
+        }
+    }
+
+    // Best-first search on one level, returns up to `ef` (distance, node) pairs, closest first
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[usize],
+        ef: usize,
+        level: usize,
+    ) -> Vec<(Distance, usize)> {
+        let mut visited: HashSet<usize> = entry_points.iter().copied().collect();
+        let mut candidates: BinaryHeap<Reverse<(Distance, usize)>> = BinaryHeap::new();
+        let mut found: BinaryHeap<(Distance, usize)> = BinaryHeap::new();
+        for &id in entry_points {
+            let d = self.distance_to(query, id);
+            candidates.push(Reverse((d, id)));
+            found.push((d, id));
+        }
+
+        while let Some(Reverse((d, id))) = candidates.pop() {
+            if let Some(&(worst, _)) = found.peek()
+                && d > worst
+                && found.len() >= ef
+            {
+                break;
+            }
+            for &neighbor in &self.nodes[id].neighbors[level] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let nd = self.distance_to(query, neighbor);
+                let worst = found.peek().map(|(w, _)| *w);
+                if found.len() < ef || worst.is_some_and(|w| nd < w) {
+                    candidates.push(Reverse((nd, neighbor)));
+                    found.push((nd, neighbor));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut result = found.into_vec();
+        result.sort();
+        result
+    }
+
+    fn search(&self, query: &[f32], k: usize, accept: &dyn Fn(&str) -> bool) -> Vec<(String, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut entry_points = vec![entry];
+        for l in (1..=self.max_level).rev() {
+            let closest = self.search_layer(query, &entry_points, 1, l);
+            entry_points = vec![closest[0].1];
+        }
+
+        // With a filter many candidates may be rejected: widen the search until
+        // we have k accepted results or have looked at the whole graph
+        let mut ef = self.params.ef_search.max(k);
+        loop {
+            let results: Vec<(String, f32)> = self
+                .search_layer(query, &entry_points, ef, 0)
+                .into_iter()
+                .filter(|(_, id)| !self.nodes[*id].deleted && accept(&self.nodes[*id].key))
+                .take(k)
+                .map(|(d, id)| (self.nodes[id].key.clone(), d.0))
+                .collect();
+            if results.len() >= k || ef >= self.nodes.len() {
+                return results;
+            }
+            ef *= 2;
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// NEW: Watching keys - a feed of what the writes did
+//
+//   let mut users = db.watch("user:")?;                         // a key prefix
+//   let mut adults = db.watch(Condition::GreaterThan("age".to_string(), 17))?;
+//   db.insert("user:1".to_string(), alice)?;
+//   for event in users.try_iter() {
+//       println!("{}: {:?}", event.seq, event.change);         // Insert { key: "user:1", .. }
+//   }
+//   // Later, maybe in another process: carry on after the last event seen
+//   let mut users = db.watch_from("user:", users.last_seq())?;
+//
+// Every write to the database's own keys (insert, update, delete, the batch
+// ops, transactions, clear, ...) reports what it did as a KeyChange, with the
+// value before and after. A key whose TTL ran out is reported as an Expire
+// when its entry is removed: by the next write to it, by purge_expired, or
+// when the file is loaded again.
+//
+// Every event has a sequence number, counting up by one per event for the
+// life of the database. Events are saved with the data (in a file next to it,
+// written before the data file) and the last RETAINED are kept, so a consumer
+// that remembers the last sequence number it handled can resume from there
+// with watch_from, across restarts of either side. A write that was never
+// saved never happened, and neither did its event.
+//
+// The feed starts with the first watch on a database and, once its file
+// exists, with every load. Collections aren't watched, and neither is a
+// database loading a snapshot (replication followers, Raft nodes catching
+// up); the changes they replay one by one are reported.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::index::Indexes;
+use crate::{Condition, StorageEngine, Value};
+
+// Events kept, in memory and on disk, for watchers that resume
+const RETAINED: usize = 100_000;
+// The extension of the file holding them
+const EVENTS_FILE: &str = "events";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub seq: u64,
+    pub change: KeyChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum KeyChange {
+    Insert { key: String, value: Value },
+    Update { key: String, old: Value, new: Value },
+    Delete { key: String, old: Value },
+    Expire { key: String, old: Value },
+    Clear, // every key is gone
+}
+
+impl KeyChange {
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            KeyChange::Insert { key, .. }
+            | KeyChange::Update { key, .. }
+            | KeyChange::Delete { key, .. }
+            | KeyChange::Expire { key, .. } => Some(key),
+            KeyChange::Clear => None,
+        }
+    }
+}
+
+// Which events a watcher gets; a Clear goes to every watcher
+#[derive(Debug, Clone)]
+pub enum WatchFilter {
+    Prefix(String), // "" = every key
+    // The value before or after the change matches (so a watcher also sees
+    // a value stop matching)
+    Condition(Condition),
+}
+
+impl WatchFilter {
+    // Full-text conditions are checked with the analyzer of the field's index
+    fn matches(&self, change: &KeyChange, indexes: &Indexes) -> bool {
+        let (key, values) = match change {
+            KeyChange::Clear => return true,
+            KeyChange::Insert { key, value } => (key, vec![value]),
+            KeyChange::Update { key, old, new } => (key, vec![old, new]),
+            KeyChange::Delete { key, old } | KeyChange::Expire { key, old } => (key, vec![old]),
+        };
+        match self {
+            WatchFilter::Prefix(prefix) => key.starts_with(prefix.as_str()),
+            WatchFilter::Condition(condition) => {
+                let analyzer_for = |field: &str| indexes.analyzer_for(field);
+                values
+                    .into_iter()
+                    .any(|value| condition.matches_entry_with(key, value, &analyzer_for))
+            }
+        }
+    }
+}
+
+impl From<&str> for WatchFilter {
+    fn from(prefix: &str) -> Self {
+        WatchFilter::Prefix(prefix.to_string())
+    }
+}
+
+impl From<String> for WatchFilter {
+    fn from(prefix: String) -> Self {
+        WatchFilter::Prefix(prefix)
+    }
+}
+
+impl From<Condition> for WatchFilter {
+    fn from(condition: Condition) -> Self {
+        WatchFilter::Condition(condition)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchError {
+    // The events after the requested one are no longer kept; the oldest kept
+    Gone { oldest: u64 },
+    // The requested event hasn't happened (yet); the latest that has
+    Ahead { latest: u64 },
+    // The saved events couldn't be read
+    Io(String),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::Gone { oldest } => write!(
+                f,
+                "the events requested are no longer kept (the oldest is {})",
+                oldest
+            ),
+            WatchError::Ahead { latest } => {
+                write!(f, "no such event yet (the latest is {})", latest)
+            }
+            WatchError::Io(message) => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for WatchError {}
+
+// The events for one watch, in order. Iterating waits for each next event;
+// it ends when the database is dropped.
+pub struct Watcher {
+    events: Receiver<WatchEvent>,
+    last_seq: u64,
+}
+
+impl Watcher {
+    // The sequence number of the last event taken (or of the event the watch
+    // started after): pass it to watch_from to carry on from here
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    // The next event, if one is waiting
+    pub fn try_next(&mut self) -> Option<WatchEvent> {
+        let event = self.events.try_recv().ok()?;
+        Some(self.took(event))
+    }
+
+    // The events waiting, without waiting for more
+    pub fn try_iter(&mut self) -> impl Iterator<Item = WatchEvent> + '_ {
+        std::iter::from_fn(|| self.try_next())
+    }
+
+    // The next event, waiting at most `timeout` for one
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<WatchEvent> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => Some(self.took(event)),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    fn took(&mut self, event: WatchEvent) -> WatchEvent {
+        self.last_seq = event.seq;
+        event
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        let event = self.events.recv().ok()?;
+        Some(self.took(event))
+    }
+}
+
+// The feed of the database's own keyspace (Keyspace::feed)
+#[derive(Default)]
+pub(crate) struct Feed {
+    seq: u64, // the last event's
+    recent: VecDeque<WatchEvent>,
+    watchers: Vec<(WatchFilter, Sender<WatchEvent>)>,
+    // Behind a lock because Database::save takes &self
+    file: Mutex<EventFile>,
+}
+
+#[derive(Default)]
+struct EventFile {
+    unsaved: Vec<WatchEvent>,
+    records: usize, // in the file; rewritten with `recent` past 2 * RETAINED
+}
+
+impl Feed {
+    // The feed saved next to the data file, if there is one
+    pub(crate) fn open(storage: &StorageEngine) -> io::Result<Option<Feed>> {
+        let Some(events) = storage.load_sidecar_records::<WatchEvent>(EVENTS_FILE)? else {
+            return Ok(None);
+        };
+        let records = events.len();
+        let recent: VecDeque<WatchEvent> = events
+            .into_iter()
+            .skip(records.saturating_sub(RETAINED))
+            .collect();
+        Ok(Some(Feed {
+            seq: recent.back().map_or(0, |event| event.seq),
+            recent,
+            watchers: Vec::new(),
+            file: Mutex::new(EventFile {
+                unsaved: Vec::new(),
+                records,
+            }),
+        }))
+    }
+
+    pub(crate) fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub(crate) fn emit(&mut self, change: KeyChange, indexes: &Indexes) {
+        self.seq += 1;
+        let event = WatchEvent {
+            seq: self.seq,
+            change,
+        };
+        // A watcher whose Watcher was dropped goes
+        self.watchers.retain(|(filter, sender)| {
+            !filter.matches(&event.change, indexes) || sender.send(event.clone()).is_ok()
+        });
+        if self.recent.len() == RETAINED {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(event.clone());
+        self.lock_file().unsaved.push(event);
+    }
+
+    // From now on (None), or from the event after `after`
+    pub(crate) fn subscribe(
+        &mut self,
+        filter: WatchFilter,
+        after: Option<u64>,
+        indexes: &Indexes,
+    ) -> Result<Watcher, WatchError> {
+        let after = after.unwrap_or(self.seq);
+        if after > self.seq {
+            return Err(WatchError::Ahead { latest: self.seq });
+        }
+        let oldest = self.recent.front().map_or(self.seq + 1, |event| event.seq);
+        if after + 1 < oldest {
+            return Err(WatchError::Gone { oldest });
+        }
+        let (sender, events) = mpsc::channel();
+        for event in self.recent.iter().filter(|event| event.seq > after) {
+            if filter.matches(&event.change, indexes) {
+                let _ = sender.send(event.clone());
+            }
+        }
+        self.watchers.push((filter, sender));
+        Ok(Watcher {
+            events,
+            last_seq: after,
+        })
+    }
+
+    // Append the events since the last save to the file (creating it: from
+    // now on, loading the database starts the feed)
+    pub(crate) fn save(&self, storage: &StorageEngine) -> io::Result<()> {
+        let mut file = self.lock_file();
+        if file.records + file.unsaved.len() > 2 * RETAINED {
+            let recent: Vec<&WatchEvent> = self.recent.iter().collect();
+            storage.rewrite_sidecar(EVENTS_FILE, &recent)?;
+            file.records = recent.len();
+        } else {
+            storage.append_sidecar(EVENTS_FILE, &file.unsaved)?;
+            file.records += file.unsaved.len();
+        }
+        file.unsaved.clear();
+        Ok(())
+    }
+
+    pub(crate) fn delete_file(storage: &StorageEngine) -> io::Result<()> {
+        storage.delete_sidecar(EVENTS_FILE)
+    }
+
+    fn lock_file(&self) -> std::sync::MutexGuard<'_, EventFile> {
+        self.file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,115 @@
+// Auto-save: with it on (the default), every write is on disk when the call
+// returns, so a database opened from the same file sees it
+
+mod common;
+
+use std::time::Duration;
+
+use common::{open, temp_db};
+use littledb::{Condition, QueryOutput, Value};
+
+#[test]
+fn conditional_deletes_are_saved() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.insert("a".to_string(), Value::Integer(1)).unwrap();
+    db.insert("b".to_string(), Value::Integer(2)).unwrap();
+
+    let prefix = |prefix: &str| Condition::KeyPrefix(prefix.to_string());
+    assert!(!db.delete_if("b", &prefix("a")).unwrap());
+    assert!(db.delete_if("a", &prefix("a")).unwrap());
+    assert!(!db.delete_if("a", &prefix("a")).unwrap());
+
+    let reopened = open(&path);
+    assert_eq!(reopened.list_keys(), vec!["b"]);
+}
+
+#[test]
+fn conditional_writes_are_saved() {
+    let path = temp_db();
+    let mut db = open(&path);
+    assert!(
+        db.insert_if_absent("a".to_string(), Value::Integer(1))
+            .unwrap()
+    );
+    assert!(
+        !db.insert_if_absent("a".to_string(), Value::Integer(9))
+            .unwrap()
+    );
+    db.upsert_with("b", Value::Integer(10), |_| Value::Null)
+        .unwrap();
+    db.upsert_with("b", Value::Null, |current| match current {
+        Value::Integer(n) => Value::Integer(n * 2),
+        other => other.clone(),
+    })
+    .unwrap();
+    db.insert("c".to_string(), Value::Integer(1)).unwrap();
+    let any = Condition::KeyPrefix("c".to_string());
+    assert!(db.update_if("c", &any, Value::Integer(3)).unwrap());
+
+    let reopened = open(&path);
+    assert_eq!(reopened.get("a"), Some(Value::Integer(1)));
+    assert_eq!(reopened.get("b"), Some(Value::Integer(20)));
+    assert_eq!(reopened.get("c"), Some(Value::Integer(3)));
+}
+
+#[test]
+fn query_language_deletes_are_saved() {
+    let path = temp_db();
+    let mut db = open(&path);
+    for (key, age) in [("user:1", 20), ("user:2", 40), ("user:3", 60)] {
+        let statement = format!("INSERT INTO '{}' VALUE {{ age: {} }}", key, age);
+        littledb::ql::execute(&mut db, &statement).unwrap();
+    }
+    assert_eq!(
+        littledb::ql::execute(&mut db, "DELETE FROM 'user:' WHERE age > 30"),
+        Ok(QueryOutput::Affected(2))
+    );
+
+    let reopened = open(&path);
+    assert_eq!(reopened.list_keys(), vec!["user:1"]);
+}
+
+#[test]
+fn expiry_changes_are_saved() {
+    let path = temp_db();
+    let mut db = open(&path);
+    let hour = Duration::from_secs(3600);
+    db.insert("a".to_string(), Value::Integer(1)).unwrap();
+    db.insert("b".to_string(), Value::Integer(2)).unwrap();
+    assert!(db.expire("a", hour).unwrap());
+    assert!(db.expire("b", hour).unwrap());
+    assert!(!db.expire("missing", hour).unwrap());
+    assert!(db.persist("b").unwrap());
+    assert!(!db.persist("b").unwrap());
+
+    let reopened = open(&path);
+    assert!(reopened.ttl("a").is_some_and(|ttl| ttl <= hour));
+    assert_eq!(reopened.ttl("b"), None);
+}
+
+#[test]
+fn collection_writes_are_saved() {
+    let path = temp_db();
+    let mut db = open(&path);
+    let hour = Duration::from_secs(3600);
+    let mut users = db.collection("users");
+    for (key, n) in [("ann", 1), ("bob", 2), ("cy", 3)] {
+        users.insert(key.to_string(), Value::Integer(n)).unwrap();
+    }
+    users.update("ann", Value::Integer(10)).unwrap();
+    users.delete("bob").unwrap();
+    assert!(users.expire("cy", hour).unwrap());
+    let mut cities = db.collection("cities");
+    cities
+        .insert("rome".to_string(), Value::Integer(1))
+        .unwrap();
+    cities.clear().unwrap();
+
+    let mut reopened = open(&path);
+    let users = reopened.collection("users");
+    assert_eq!(users.list_keys(), vec!["ann", "cy"]);
+    assert_eq!(users.get("ann"), Some(Value::Integer(10)));
+    assert!(users.ttl("cy").is_some());
+    assert_eq!(reopened.collection("cities").count(), 0);
+}
@@ -0,0 +1,275 @@
+// The littledb command-line tool, run as a separate process: what each
+// command prints and the exit status it ends with
+#![cfg(feature = "cli")]
+
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use common::{open, temp_db};
+use littledb::{Schema, Value};
+use serde_json::{Value as Json, json};
+
+struct Run {
+    status: i32,
+    stdout: String,
+    stderr: String,
+}
+
+fn finished(output: Output) -> Run {
+    Run {
+        status: output.status.code().expect("exited, not killed"),
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+fn littledb(db: &str, args: &[&str]) -> Run {
+    let output = Command::new(env!("CARGO_BIN_EXE_littledb"))
+        .arg("--db")
+        .arg(db)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    finished(output)
+}
+
+fn littledb_with_input(db: &str, args: &[&str], input: &str) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_littledb"))
+        .arg("--db")
+        .arg(db)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    finished(child.wait_with_output().unwrap())
+}
+
+// A command that must succeed; its standard output
+fn ok(db: &str, args: &[&str]) -> String {
+    let run = littledb(db, args);
+    assert_eq!(run.status, 0, "{:?}: {}", args, run.stderr);
+    run.stdout
+}
+
+fn json_of(text: &str) -> Json {
+    serde_json::from_str(text).unwrap_or_else(|e| panic!("{}: {}", e, text))
+}
+
+#[test]
+fn set_then_get() {
+    let db = temp_db();
+    assert_eq!(
+        ok(&db, &["set", "user:1", r#"{"name": "Ann", "age": 31}"#]),
+        "set 'user:1' (version 1)\n"
+    );
+    assert_eq!(
+        json_of(&ok(&db, &["get", "user:1"])),
+        json!({"name": "Ann", "age": 31})
+    );
+    let got = json_of(&ok(&db, &["--json", "get", "user:1"]));
+    assert_eq!(got["value"]["name"], "Ann");
+    assert_eq!(got["version"], 1);
+    assert_eq!(got["ttl_ms"], Json::Null);
+
+    // The file is a normal database
+    assert_eq!(
+        open(&db).get("user:1").unwrap().get_field("age"),
+        Some(&Value::Integer(31))
+    );
+
+    // Text, standard input and expiry times
+    ok(&db, &["set", "note", "not json", "--string"]);
+    assert_eq!(
+        open(&db).get("note"),
+        Some(Value::String("not json".to_string()))
+    );
+    let run = littledb_with_input(&db, &["set", "list", "--ttl", "1h"], "[1, 2]");
+    assert_eq!(run.status, 0, "{}", run.stderr);
+    let got = json_of(&ok(&db, &["--json", "get", "list"]));
+    assert_eq!(got["value"], json!([1, 2]));
+    assert!(got["ttl_ms"].as_u64().unwrap() > 3_500_000);
+
+    let run = littledb(&db, &["set", "bad", "{nope"]);
+    assert_eq!(run.status, 1);
+    assert!(run.stderr.contains("use --string"), "{}", run.stderr);
+    let run = littledb(&db, &["get", "user:9"]);
+    assert_eq!(run.status, 1);
+    assert_eq!(run.stderr, "error: Key 'user:9' not found\n");
+}
+
+#[test]
+fn query_filters_sorts_and_explains() {
+    let db = temp_db();
+    for (key, name, age) in [("u1", "Ann", 31), ("u2", "Bob", 25), ("u3", "Cy", 40)] {
+        ok(
+            &db,
+            &["set", key, &json!({"name": name, "age": age}).to_string()],
+        );
+    }
+
+    let filter = r#"{"age": {"$gt": 28}}"#;
+    let found = json_of(&ok(
+        &db,
+        &["--json", "query", filter, "--sort", "age", "--desc"],
+    ));
+    let keys: Vec<&str> = found
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, vec!["u3", "u1"]);
+
+    let text = ok(&db, &["query", filter, "--limit", "1", "--select", "name"]);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("u1  "), "{}", text);
+    assert!(!lines[0].contains("31"), "{}", text);
+    assert_eq!(lines[1], "(1 result)");
+
+    let plan = ok(&db, &["query", filter, "--explain"]);
+    assert!(plan.contains("FullScan"), "{}", plan);
+
+    // A wrong filter is a mistake on the command line
+    let run = littledb(&db, &["query", r#"{"age": {"$near": 1}}"#]);
+    assert_eq!(run.status, 2, "{}", run.stderr);
+    let run = littledb(&db, &["query", filter, "--desc"]);
+    assert_eq!(run.status, 2);
+    assert!(run.stderr.contains("--desc needs --sort"), "{}", run.stderr);
+}
+
+#[test]
+fn export_then_import() {
+    let db = temp_db();
+    ok(&db, &["set", "a:1", r#"{"n": 1}"#]);
+    ok(&db, &["set", "a:2", r#"[true, null]"#]);
+    ok(&db, &["set", "b:1", r#""x""#]);
+
+    assert_eq!(
+        json_of(&ok(&db, &["export"])),
+        json!({"a:1": {"n": 1}, "a:2": [true, null], "b:1": "x"})
+    );
+    let lines = ok(&db, &["export", "--format", "jsonl", "--prefix", "a:"]);
+    assert_eq!(
+        lines,
+        "{\"key\":\"a:1\",\"value\":{\"n\":1}}\n{\"key\":\"a:2\",\"value\":[true,null]}\n"
+    );
+
+    // Into a file, and back into another database
+    let file = format!("{}.jsonl", temp_db());
+    assert_eq!(
+        ok(&db, &["export", &file]),
+        format!("exported 3 entries to '{}'\n", file)
+    );
+    let copy = temp_db();
+    assert_eq!(ok(&copy, &["import", &file]), "imported 3 entries\n");
+    assert_eq!(ok(&copy, &["export"]), ok(&db, &["export"]));
+    std::fs::remove_file(&file).unwrap();
+
+    let run = littledb_with_input(
+        &copy,
+        &["import", "-", "--format", "jsonl"],
+        "{\"key\": 1}\n",
+    );
+    assert_eq!(run.status, 1);
+    assert_eq!(run.stderr, "error: line 1: expected a string \"key\"\n");
+}
+
+#[test]
+fn verify_reports_values_that_do_not_fit() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.insert("n:1".to_string(), Value::Integer(1)).unwrap();
+    db.insert("n:2".to_string(), Value::String("two".to_string()))
+        .unwrap();
+    db.collection("things")
+        .insert("t".to_string(), Value::Null)
+        .unwrap();
+
+    assert_eq!(
+        ok(&path, &["verify"]),
+        format!(
+            "'{}' loads: 3 entries in the database and 1 collection(s)\nno problems found\n",
+            path
+        )
+    );
+
+    db.set_schema("n:", Schema::integer()).unwrap();
+    let run = littledb(&path, &["verify"]);
+    assert_eq!(run.status, 1);
+    assert!(
+        run.stdout
+            .contains("key 'n:2': expected integer, found String"),
+        "{}",
+        run.stdout
+    );
+    assert_eq!(run.stderr, "error: 1 value doesn't fit its schema\n");
+    let report = json_of(&littledb(&path, &["--json", "verify"]).stdout);
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["entries"], 3);
+    assert_eq!(report["problems"].as_array().unwrap().len(), 1);
+
+    // A file that doesn't load fails too
+    std::fs::write(&path, b"not a database").unwrap();
+    let run = littledb(&path, &["verify"]);
+    assert_eq!(run.status, 1);
+    assert!(
+        run.stderr.starts_with("error: can't read"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn a_missing_database_is_a_failure() {
+    let db = temp_db();
+    for args in [
+        &["get", "k"][..],
+        &["query", "{}"],
+        &["export"],
+        &["verify"],
+        &["list"],
+    ] {
+        let run = littledb(&db, args);
+        assert_eq!(run.status, 1, "{:?}", args);
+        assert_eq!(run.stdout, "");
+        assert_eq!(run.stderr, format!("error: no database at '{}'\n", db));
+    }
+    let run = littledb(&db, &["--json", "verify"]);
+    assert_eq!(
+        json_of(&run.stderr),
+        json!({"error": format!("no database at '{}'", db)})
+    );
+    // Looking didn't create it
+    assert!(!std::path::Path::new(&db).exists());
+}
+
+#[test]
+fn command_line_mistakes_exit_with_2() {
+    let db = temp_db();
+    ok(&db, &["set", "k", "1"]);
+    for args in [
+        &[][..],
+        &["frobnicate"],
+        &["get"],
+        &["set", "k", "1", "extra"],
+        &["list", "--sort", "x"],
+        &["set", "k", "1", "--ttl", "soon"],
+    ] {
+        let run = littledb(&db, args);
+        assert_eq!(run.status, 2, "{:?}: {}", args, run.stderr);
+        assert!(run.stderr.starts_with("error: "), "{}", run.stderr);
+    }
+    assert!(ok(&db, &["help"]).contains("query <filter>"));
+}
@@ -0,0 +1,394 @@
+// The Rust client against a REST server on localhost, and the Store trait
+// shared by the client and an embedded Database
+#![cfg(feature = "json")]
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{open, temp_db};
+use littledb::http::Server;
+use littledb::server::ShutdownHandle;
+use littledb::{
+    Client, ClientOptions, Condition, Database, GeoField, GeoPoint, Schema, Store, StoreError,
+    Transaction, Value,
+};
+use serde::{Deserialize, Serialize};
+
+struct Running {
+    addr: SocketAddr,
+    stop: ShutdownHandle,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Running {
+    fn stop(self) {
+        self.stop.shutdown();
+        self.thread.join().unwrap();
+    }
+}
+
+fn start_on(addr: &str, db: Database) -> Running {
+    let server = Server::bind(addr, db).unwrap().save_interval(None);
+    let addr = server.local_addr().unwrap();
+    let stop = server.shutdown_handle();
+    let thread = thread::spawn(move || server.run().unwrap());
+    Running { addr, stop, thread }
+}
+
+fn start(db: Database) -> Running {
+    start_on("127.0.0.1:0", db)
+}
+
+// One HTTP message (request or response): the head and a Content-Length body;
+// None once the stream is closed
+fn read_message(reader: &mut BufReader<TcpStream>) -> Option<Vec<u8>> {
+    let mut message = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        message.extend_from_slice(line.as_bytes());
+        if line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    message.extend_from_slice(&body);
+    Some(message)
+}
+
+// Passes requests on to `upstream`, except that the answer to the first
+// DELETE is thrown away and its connection closed, as if the network had
+// dropped it after the server applied the delete
+fn lose_first_delete_answer(upstream: SocketAddr) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let lost = Arc::new(AtomicBool::new(false));
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            let lost = Arc::clone(&lost);
+            thread::spawn(move || {
+                let server = TcpStream::connect(upstream).unwrap();
+                let mut from_client = BufReader::new(client.try_clone().unwrap());
+                let mut from_server = BufReader::new(server.try_clone().unwrap());
+                let (mut to_client, mut to_server) = (client, server);
+                while let Some(request) = read_message(&mut from_client) {
+                    to_server.write_all(&request).unwrap();
+                    let response = read_message(&mut from_server).unwrap();
+                    if request.starts_with(b"DELETE ") && !lost.swap(true, Ordering::SeqCst) {
+                        return; // both connections close
+                    }
+                    to_client.write_all(&response).unwrap();
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn age(n: i64) -> Value {
+    Value::Object(
+        [("age".to_string(), Value::Integer(n))]
+            .into_iter()
+            .collect(),
+    )
+}
+
+// Written once against Store, run below on a Database and on a Client
+fn exercise(store: &mut impl Store) {
+    store
+        .insert("user:1".to_string(), Value::Integer(1))
+        .unwrap();
+    store
+        .batch_insert(vec![
+            ("user:2".to_string(), age(2)),
+            ("user:3".to_string(), age(3)),
+            ("other".to_string(), text("x")),
+        ])
+        .unwrap();
+    assert_eq!(store.get("user:2").unwrap(), Some(age(2)));
+    assert_eq!(store.get("missing").unwrap(), None);
+    assert_eq!(store.count().unwrap(), 4);
+    assert_eq!(
+        store.keys_with_prefix("user:").unwrap(),
+        vec!["user:1", "user:2", "user:3"]
+    );
+    let found = store.batch_get(vec!["user:1", "missing", "other"]).unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found["other"], text("x"));
+
+    let matches = store
+        .query(Condition::And(vec![
+            Condition::KeyPrefix("user:".to_string()),
+            Condition::GreaterThan("age".to_string(), 1),
+        ]))
+        .unwrap();
+    let keys: Vec<&str> = matches.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["user:2", "user:3"]);
+
+    assert!(store.delete("user:1").unwrap());
+    assert!(!store.delete("user:1").unwrap());
+    assert_eq!(store.batch_delete(vec!["user:2", "nope"]).unwrap(), 1);
+
+    // An optimistic transaction: read, write, commit
+    let mut tx = Transaction::new();
+    let three = tx.read(store, "user:3").unwrap();
+    assert_eq!(three, Some(age(3)));
+    tx.insert("user:3".to_string(), Value::Integer(30));
+    tx.delete("other");
+    store.commit(tx).unwrap();
+    assert_eq!(store.get("user:3").unwrap(), Some(Value::Integer(30)));
+    assert_eq!(store.get("other").unwrap(), None);
+
+    // ... which fails if what it read has changed
+    let mut stale = Transaction::new();
+    stale.read(store, "user:3").unwrap();
+    stale.insert("user:3".to_string(), Value::Integer(31));
+    store
+        .insert("user:3".to_string(), Value::Integer(32))
+        .unwrap();
+    assert_eq!(
+        store.commit(stale),
+        Err(StoreError::Conflict("user:3".to_string()))
+    );
+    assert_eq!(store.get("user:3").unwrap(), Some(Value::Integer(32)));
+
+    store
+        .insert_with_ttl("brief".to_string(), text("b"), Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(store.get("brief").unwrap(), Some(text("b")));
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(store.get("brief").unwrap(), None);
+}
+
+#[test]
+fn the_same_code_runs_embedded_and_remote() {
+    let mut db = open(&temp_db());
+    db.set_auto_save(false);
+    exercise(&mut db);
+
+    let server = start(open(&temp_db()));
+    let mut client = Client::connect(server.addr).unwrap();
+    exercise(&mut client);
+    server.stop();
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+    age: u32,
+}
+
+#[test]
+fn typed_documents() {
+    let server = start(open(&temp_db()));
+    let mut client = Client::connect(server.addr).unwrap();
+    let alice = User {
+        name: "Alice".to_string(),
+        age: 30,
+    };
+    client
+        .insert_typed("user:alice".to_string(), &alice)
+        .unwrap();
+    assert_eq!(client.get_typed::<User>("user:alice").unwrap(), Some(alice));
+    let users: Vec<(String, User)> = client
+        .query_typed(Condition::KeyPrefix("user:".to_string()))
+        .unwrap();
+    assert_eq!(users.len(), 1);
+
+    client
+        .insert("user:bad".to_string(), text("not a user"))
+        .unwrap();
+    let error = client.get_typed::<User>("user:bad").unwrap_err();
+    assert!(matches!(error, StoreError::Typed(e) if e.key.as_deref() == Some("user:bad")));
+    server.stop();
+}
+
+#[test]
+fn server_side_refusals() {
+    let mut db = open(&temp_db());
+    db.set_schema("user:", Schema::integer()).unwrap();
+    let server = start(db);
+    let client = Client::connect(server.addr).unwrap();
+
+    assert!(matches!(
+        client.insert("user:1".to_string(), text("one")),
+        Err(StoreError::Invalid(message)) if message.contains("user:1")
+    ));
+    // Nothing of a refused batch is written
+    let batch = client.batch_insert(vec![
+        ("user:2".to_string(), Value::Integer(2)),
+        ("user:3".to_string(), text("three")),
+    ]);
+    assert!(matches!(batch, Err(StoreError::Invalid(_))));
+    assert_eq!(client.get("user:2").unwrap(), None);
+    assert!(matches!(
+        client.insert("f".to_string(), Value::Float(f64::NAN)),
+        Err(StoreError::Invalid(_))
+    ));
+
+    // Keys that need escaping in a URL
+    for key in ["a b", "a/b?c=d&e", "100%", "ключ"] {
+        client.insert(key.to_string(), text(key)).unwrap();
+        assert_eq!(client.get(key).unwrap(), Some(text(key)));
+    }
+    assert_eq!(
+        client.keys_with_prefix("a").unwrap(),
+        vec!["a b", "a/b?c=d&e"]
+    );
+    server.stop();
+}
+
+#[test]
+fn many_keys_are_listed_page_by_page() {
+    let server = start(open(&temp_db()));
+    let client = Client::connect(server.addr).unwrap();
+    let entries = (0..2500)
+        .map(|i| (format!("k:{:05}", i), Value::Integer(i)))
+        .collect();
+    assert_eq!(client.batch_insert(entries).unwrap(), 2500);
+    let keys = client.keys_with_prefix("k:").unwrap();
+    assert_eq!(keys.len(), 2500);
+    assert_eq!(keys[2499], "k:02499");
+    assert_eq!(client.stats().unwrap().total_entries, 2500);
+    server.stop();
+}
+
+#[test]
+fn one_client_shared_by_threads() {
+    let server = start(open(&temp_db()));
+    let client =
+        Arc::new(Client::connect_with(server.addr, ClientOptions::new().pool_size(4)).unwrap());
+    let workers: Vec<_> = (0..8)
+        .map(|worker| {
+            let client = Arc::clone(&client);
+            thread::spawn(move || {
+                for i in 0..50 {
+                    let key = format!("w{}:{}", worker, i);
+                    client.insert(key.clone(), Value::Integer(i)).unwrap();
+                    assert_eq!(client.get(&key).unwrap(), Some(Value::Integer(i)));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(client.count().unwrap(), 400);
+    server.stop();
+}
+
+#[test]
+fn unreachable_servers_and_retries() {
+    // Nothing listens on a port that was just freed
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let options = ClientOptions::new().retries(3, Duration::from_millis(20));
+    let started = Instant::now();
+    let refused = Client::connect_with(("127.0.0.1", port), options.clone());
+    assert!(
+        matches!(refused, Err(StoreError::Unavailable(message)) if message.contains("4 attempts"))
+    );
+    // 20 + 40 + 80 ms of waiting between the attempts
+    assert!(started.elapsed() >= Duration::from_millis(140));
+
+    // A restarted server is picked up again: the pooled connection is dead,
+    // so the read fails once and the retry opens a new one
+    let path = temp_db();
+    let server = start(open(&path));
+    let addr = server.addr;
+    let client = Client::connect_with(addr, options).unwrap();
+    client.insert("k".to_string(), text("v")).unwrap();
+    server.stop();
+    let server = start_on(&addr.to_string(), open(&path));
+    assert_eq!(client.get("k").unwrap(), Some(text("v")));
+    server.stop();
+}
+
+#[test]
+fn a_delete_whose_answer_was_lost_still_reports_the_key_deleted() {
+    let server = start(open(&temp_db()));
+    let proxy = lose_first_delete_answer(server.addr);
+    let options = ClientOptions::new().retries(2, Duration::from_millis(10));
+    let client = Client::connect_with(proxy, options).unwrap();
+    client.insert("k".to_string(), text("v")).unwrap();
+
+    // The first attempt deleted the key; the retry finds it gone
+    assert!(client.delete("k").unwrap());
+    assert_eq!(client.get("k").unwrap(), None);
+    // Answered the first time, a missing key is reported missing
+    assert!(!client.delete("k").unwrap());
+    server.stop();
+}
+
+#[test]
+fn nan_and_infinity_are_not_sent_in_queries() {
+    let server = start(open(&temp_db()));
+    let client = Client::connect(server.addr).unwrap();
+    // Sent as null, NaN would find this one
+    let unknown = Value::Object([("age".to_string(), Value::Null)].into_iter().collect());
+    client.insert("unknown".to_string(), unknown).unwrap();
+    let location = GeoField::array("location");
+    for condition in [
+        Condition::Equals("age".to_string(), Value::Float(f64::NAN)),
+        Condition::Not(Box::new(Condition::Or(vec![Condition::Equals(
+            "age".to_string(),
+            Value::Array(vec![Value::Float(f64::INFINITY)]),
+        )]))),
+        Condition::NearPoint(location.clone(), GeoPoint::new(0.0, 0.0), f64::INFINITY),
+        Condition::WithinPolygon(location, vec![GeoPoint::new(f64::NAN, 0.0)]),
+    ] {
+        assert!(
+            matches!(client.query(condition.clone()), Err(StoreError::Invalid(_))),
+            "{}",
+            condition
+        );
+    }
+    // Finite floats are fine
+    let finite = Condition::Equals("age".to_string(), Value::Float(1.5));
+    assert_eq!(client.query(finite).unwrap(), vec![]);
+    server.stop();
+}
+
+#[test]
+fn slow_servers_time_out() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut held = Vec::new();
+        for stream in listener.incoming() {
+            held.push(stream);
+        }
+    });
+    let options = ClientOptions::new()
+        .timeout(Duration::from_millis(100))
+        .retries(1, Duration::from_millis(10));
+    let started = Instant::now();
+    let result = Client::connect_with(addr, options);
+    assert!(matches!(result, Err(StoreError::Unavailable(_))));
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_secs(5));
+}
@@ -0,0 +1,176 @@
+// Collections: index definitions that survive a reload, and moving keys in
+// with migrate_prefix
+
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use common::{open, temp_db};
+use littledb::planner::Operation;
+use littledb::{Analyzer, Condition, GeoField, GeoPoint, Schema, Value};
+
+fn person(name: &str, age: i64, bio: &str, lat: f64, lng: f64) -> Value {
+    Value::Object(HashMap::from([
+        ("name".to_string(), Value::String(name.to_string())),
+        ("age".to_string(), Value::Integer(age)),
+        ("bio".to_string(), Value::String(bio.to_string())),
+        ("lat".to_string(), Value::Float(lat)),
+        ("lng".to_string(), Value::Float(lng)),
+    ]))
+}
+
+fn keys(rows: Vec<(String, Value)>) -> Vec<String> {
+    rows.into_iter().map(|(key, _)| key).collect()
+}
+
+// The operation at the bottom of a plan: an index, or a scan
+fn access(plan: littledb::QueryPlan) -> Operation {
+    let mut node = plan.root;
+    while let Some(child) = node.children.into_iter().next() {
+        node = child;
+    }
+    node.operation
+}
+
+#[test]
+fn indexes_are_defined_again_after_a_reload() {
+    let path = temp_db();
+    let location = GeoField::lat_lng("lat", "lng");
+    let mut db = open(&path);
+    db.insert(
+        "ann".to_string(),
+        person("Ann", 31, "running and cycling", 59.91, 10.75),
+    )
+    .unwrap();
+    db.insert(
+        "bob".to_string(),
+        person("Bob", 25, "a runner", 52.52, 13.40),
+    )
+    .unwrap();
+    db.create_index("age").unwrap();
+    db.create_text_index("bio", Analyzer::new().with_stemming())
+        .unwrap();
+    db.create_geo_index(location.clone()).unwrap();
+    let mut users = db.collection("users");
+    let people = (0..50)
+        .map(|i| {
+            (
+                format!("u{:02}", i),
+                person(&format!("U{}", i), i, "chess", 0.0, 0.0),
+            )
+        })
+        .collect();
+    users.batch_insert(people).unwrap();
+    users.create_index("name").unwrap();
+    users.create_text_index("bio", Analyzer::default()).unwrap();
+
+    let mut reopened = open(&path);
+    assert_eq!(reopened.list_indexes(), vec!["age"]);
+    assert_eq!(reopened.list_text_indexes(), vec!["bio"]);
+    assert_eq!(reopened.list_geo_indexes(), vec!["lat,lng"]);
+    // Filled again from the data, and used by the planner
+    let young = Condition::LessThan("age".to_string(), 30);
+    assert_eq!(keys(reopened.query(young.clone())), vec!["bob"]);
+    assert!(matches!(
+        access(reopened.explain(young)),
+        Operation::IndexRange { .. }
+    ));
+    // The analyzer comes back too: "run" only finds "running" when stemmed
+    let hits = reopened.search("bio", "run", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].key, "ann");
+    let near_oslo = Condition::NearPoint(location, GeoPoint::new(59.9, 10.7), 10_000.0);
+    assert_eq!(keys(reopened.query(near_oslo.clone())), vec!["ann"]);
+    assert!(matches!(
+        access(reopened.explain(near_oslo)),
+        Operation::GeoSearch { .. }
+    ));
+
+    let mut users = reopened.collection("users");
+    assert_eq!(users.list_indexes(), vec!["name"]);
+    assert_eq!(users.stats().text_indexes, vec!["bio"]);
+    let u7 = Condition::Equals("name".to_string(), Value::String("U7".to_string()));
+    assert_eq!(keys(users.query(u7.clone())), vec!["u07"]);
+    assert!(matches!(
+        access(users.explain(u7)),
+        Operation::IndexLookup { .. }
+    ));
+
+    // Dropping an index is saved as well
+    assert!(users.drop_index("name").unwrap());
+    assert!(reopened.drop_index("age").unwrap());
+    let mut reopened = open(&path);
+    assert!(reopened.list_indexes().is_empty());
+    assert_eq!(reopened.list_text_indexes(), vec!["bio"]);
+    assert!(reopened.collection("users").list_indexes().is_empty());
+}
+
+#[test]
+fn the_index_file_goes_with_its_collection() {
+    let path = temp_db();
+    let sidecar = format!("{}.users.indexes", path);
+    let mut db = open(&path);
+    db.collection("users").create_index("age").unwrap();
+    assert!(Path::new(&sidecar).exists());
+    assert!(!Path::new(&format!("{}.indexes", path)).exists());
+    assert!(db.drop_collection("users").unwrap());
+    assert!(!Path::new(&sidecar).exists());
+}
+
+#[test]
+fn migrate_prefix_moves_keys_into_a_collection() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.insert("user:ann".to_string(), person("Ann", 31, "", 0.0, 0.0))
+        .unwrap();
+    db.insert_with_ttl(
+        "user:bob".to_string(),
+        person("Bob", 25, "", 0.0, 0.0),
+        Duration::from_secs(600),
+    )
+    .unwrap();
+    db.insert("users".to_string(), Value::Integer(2)).unwrap();
+    db.insert("order:1".to_string(), Value::Integer(7)).unwrap();
+
+    assert_eq!(db.migrate_prefix("user:", "users").unwrap(), 2);
+    assert_eq!(db.list_keys(), vec!["order:1", "users"]);
+    let users = db.collection("users");
+    assert_eq!(users.list_keys(), vec!["ann", "bob"]);
+    assert_eq!(users.get("ann"), Some(person("Ann", 31, "", 0.0, 0.0)));
+    assert_eq!(users.ttl("ann"), None);
+    assert!(users.ttl("bob").unwrap() > Duration::from_secs(590));
+
+    // Saved, expiry times included
+    let mut reopened = open(&path);
+    assert_eq!(reopened.list_keys(), vec!["order:1", "users"]);
+    let users = reopened.collection("users");
+    assert_eq!(users.list_keys(), vec!["ann", "bob"]);
+    assert!(users.ttl("bob").is_some());
+
+    // A prefix nothing has moves nothing, into an existing collection
+    assert_eq!(reopened.migrate_prefix("user:", "users").unwrap(), 0);
+    assert_eq!(reopened.collection("users").count(), 2);
+}
+
+#[test]
+fn migrate_prefix_moves_nothing_unless_every_value_fits() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.insert("item:a".to_string(), Value::Integer(1)).unwrap();
+    db.insert("item:b".to_string(), Value::String("two".to_string()))
+        .unwrap();
+    db.collection("items")
+        .set_schema("", Schema::integer())
+        .unwrap();
+
+    let error = db.migrate_prefix("item:", "items").unwrap_err();
+    assert!(error.to_string().contains("'b'"), "{}", error);
+    assert_eq!(db.list_keys(), vec!["item:a", "item:b"]);
+    assert_eq!(db.collection("items").count(), 0);
+
+    db.insert("item:b".to_string(), Value::Integer(2)).unwrap();
+    assert_eq!(db.migrate_prefix("item:", "items").unwrap(), 2);
+    assert_eq!(open(&path).collection("items").count(), 2);
+}
@@ -0,0 +1,121 @@
+// Helpers shared by the integration tests: database files under the system
+// temp directory, and a small RESP client
+#![allow(dead_code)] // every test file uses its own part of these
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use littledb::Database;
+
+// A fresh database file under the system temp directory, named after the
+// test file ("littledb-resp-<pid>-<n>.db")
+pub fn temp_db() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "littledb-{}-{}-{}.db",
+        env!("CARGO_CRATE_NAME").replace('_', "-"),
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+pub fn open(path: &str) -> Database {
+    let mut db = Database::new(path);
+    db.set_verbose(false);
+    db.load().unwrap();
+    db
+}
+
+// What a RESP server sent back, decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resp {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(String),
+    Null,
+    Array(Vec<Resp>),
+    Map(Vec<(Resp, Resp)>),
+    Push(Vec<Resp>),
+}
+
+pub fn bulk(text: &str) -> Resp {
+    Resp::Bulk(text.to_string())
+}
+
+pub fn ok() -> Resp {
+    Resp::Simple("OK".to_string())
+}
+
+// The stream is open to tests that write raw bytes (inline commands,
+// malformed requests) or read to the end
+pub struct Client {
+    pub reader: BufReader<TcpStream>,
+    pub writer: TcpStream,
+}
+
+impl Client {
+    pub fn connect(addr: SocketAddr) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    pub fn send(&mut self, args: &[&str]) {
+        let mut request = format!("*{}\r\n", args.len());
+        for arg in args {
+            request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        self.writer.write_all(request.as_bytes()).unwrap();
+    }
+
+    pub fn call(&mut self, args: &[&str]) -> Resp {
+        self.send(args);
+        self.read()
+    }
+
+    pub fn read(&mut self) -> Resp {
+        let line = self.line();
+        let (tag, rest) = line.split_at(1);
+        match tag {
+            "+" => Resp::Simple(rest.to_string()),
+            "-" => Resp::Error(rest.to_string()),
+            ":" => Resp::Integer(rest.parse().unwrap()),
+            "_" => Resp::Null,
+            "$" if rest == "-1" => Resp::Null,
+            "$" => {
+                let mut data = vec![0; rest.parse::<usize>().unwrap() + 2];
+                self.reader.read_exact(&mut data).unwrap();
+                data.truncate(data.len() - 2);
+                Resp::Bulk(String::from_utf8(data).unwrap())
+            }
+            "*" if rest == "-1" => Resp::Null,
+            "*" => Resp::Array((0..rest.parse().unwrap()).map(|_| self.read()).collect()),
+            "%" => Resp::Map(
+                (0..rest.parse().unwrap())
+                    .map(|_| (self.read(), self.read()))
+                    .collect(),
+            ),
+            ">" => Resp::Push((0..rest.parse().unwrap()).map(|_| self.read()).collect()),
+            _ => panic!("unexpected reply line {:?}", line),
+        }
+    }
+
+    pub fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        assert!(line.ends_with("\r\n"), "truncated reply {:?}", line);
+        line.truncate(line.len() - 2);
+        line
+    }
+}
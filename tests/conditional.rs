@@ -0,0 +1,102 @@
+// Conditional writes: insert_if_absent, upsert_with and the condition-gated
+// update_if / delete_if
+
+use std::cell::Cell;
+use std::thread;
+use std::time::Duration;
+
+use littledb::{Condition, Database, Value};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-conditional-unsaved.db");
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+#[test]
+fn insert_if_absent_never_overwrites() {
+    let mut db = database();
+    assert!(db.insert_if_absent("k".to_string(), text("first")).unwrap());
+    assert!(
+        !db.insert_if_absent("k".to_string(), text("second"))
+            .unwrap()
+    );
+    assert_eq!(db.get("k"), Some(text("first")));
+    // Null is a value like any other
+    db.insert("null".to_string(), Value::Null).unwrap();
+    assert!(!db.insert_if_absent("null".to_string(), text("x")).unwrap());
+
+    // An expired key is absent
+    assert!(db.expire("k", Duration::from_millis(1)).unwrap());
+    thread::sleep(Duration::from_millis(5));
+    assert!(db.insert_if_absent("k".to_string(), text("third")).unwrap());
+    assert_eq!(db.get("k"), Some(text("third")));
+    assert_eq!(db.ttl("k"), None);
+}
+
+#[test]
+fn upsert_with_stores_the_default_or_the_update() {
+    let mut db = database();
+    let calls = Cell::new(0);
+    let add_one = |current: &Value| {
+        calls.set(calls.get() + 1);
+        match current {
+            Value::Integer(n) => Value::Integer(n + 1),
+            other => other.clone(),
+        }
+    };
+
+    // A missing key gets the default, without calling update
+    assert_eq!(
+        db.upsert_with("visits", Value::Integer(1), add_one)
+            .unwrap(),
+        Value::Integer(1)
+    );
+    assert_eq!(calls.get(), 0);
+    assert_eq!(
+        db.upsert_with("visits", Value::Integer(1), add_one)
+            .unwrap(),
+        Value::Integer(2)
+    );
+    assert_eq!(
+        db.upsert_with("visits", Value::Integer(1), add_one)
+            .unwrap(),
+        Value::Integer(3)
+    );
+    assert_eq!(calls.get(), 2);
+    assert_eq!(db.get("visits"), Some(Value::Integer(3)));
+
+    // Whatever update returns is stored, even another type
+    db.upsert_with("visits", Value::Null, |_| text("many"))
+        .unwrap();
+    assert_eq!(db.get("visits"), Some(text("many")));
+}
+
+#[test]
+fn update_if_and_delete_if_need_a_matching_value() {
+    let mut db = database();
+    let status = |s: &str| Value::Object([("status".to_string(), text(s))].into());
+    let pending = Condition::Equals("status".to_string(), text("pending"));
+    db.insert("order:1".to_string(), status("pending")).unwrap();
+    db.insert("order:2".to_string(), status("shipped")).unwrap();
+
+    assert!(
+        db.update_if("order:1", &pending, status("shipped"))
+            .unwrap()
+    );
+    assert!(!db.update_if("order:1", &pending, status("lost")).unwrap());
+    assert!(!db.update_if("order:9", &pending, status("new")).unwrap());
+    assert_eq!(db.get("order:1"), Some(status("shipped")));
+    assert_eq!(db.get("order:9"), None);
+
+    let shipped = Condition::Equals("status".to_string(), text("shipped"));
+    assert!(!db.delete_if("order:2", &pending).unwrap());
+    assert!(db.delete_if("order:2", &shipped).unwrap());
+    assert!(!db.delete_if("order:2", &shipped).unwrap());
+    assert_eq!(db.list_keys(), vec!["order:1"]);
+}
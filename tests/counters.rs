@@ -0,0 +1,167 @@
+// Atomic counters, compare-and-swap and per-key versions
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use littledb::{Database, Value};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-counters-unsaved.db");
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn invalid(result: io::Result<impl std::fmt::Debug>) -> String {
+    let error = result.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    error.to_string()
+}
+
+// A key whose TTL has run out but that is still in the store
+fn expired(db: &mut Database, key: &str, value: Value) {
+    db.insert(key.to_string(), value).unwrap();
+    assert!(db.expire(key, Duration::from_millis(1)).unwrap());
+    thread::sleep(Duration::from_millis(5));
+    assert!(!db.exists(key));
+}
+
+#[test]
+fn incr_by_adds_to_integers() {
+    let mut db = database();
+    assert_eq!(db.incr_by("hits", 5).unwrap(), 5);
+    assert_eq!(db.incr_by("hits", -7).unwrap(), -2);
+    assert_eq!(db.get("hits"), Some(Value::Integer(-2)));
+
+    db.insert("max".to_string(), Value::Integer(i64::MAX))
+        .unwrap();
+    assert_eq!(
+        invalid(db.incr_by("max", 1)),
+        format!("Key 'max': {} + 1 overflows", i64::MAX)
+    );
+    assert_eq!(db.get("max"), Some(Value::Integer(i64::MAX)));
+    db.insert("min".to_string(), Value::Integer(i64::MIN))
+        .unwrap();
+    assert!(db.incr_by("min", -1).is_err());
+
+    for value in [text("7"), Value::Float(1.0), Value::Null] {
+        db.insert("other".to_string(), value.clone()).unwrap();
+        assert!(invalid(db.incr_by("other", 1)).contains("not an Integer"));
+        assert_eq!(db.get("other"), Some(value));
+    }
+}
+
+#[test]
+fn incr_float_adds_to_any_number() {
+    let mut db = database();
+    assert_eq!(db.incr_float("temp", 1.5).unwrap(), 1.5);
+    assert_eq!(db.incr_float("temp", -0.25).unwrap(), 1.25);
+    db.insert("count".to_string(), Value::Integer(2)).unwrap();
+    assert_eq!(db.incr_float("count", 0.5).unwrap(), 2.5);
+    assert_eq!(db.get("count"), Some(Value::Float(2.5)));
+
+    db.insert("name".to_string(), text("x")).unwrap();
+    assert_eq!(
+        invalid(db.incr_float("name", 1.0)),
+        "Key 'name' holds a String, not a number"
+    );
+
+    // Overflowing to infinity, or adding NaN, leaves the value alone
+    db.insert("big".to_string(), Value::Float(f64::MAX))
+        .unwrap();
+    assert!(invalid(db.incr_float("big", f64::MAX)).contains("not a finite number"));
+    assert_eq!(db.get("big"), Some(Value::Float(f64::MAX)));
+    assert!(db.incr_float("temp", f64::NAN).is_err());
+    assert_eq!(db.get("temp"), Some(Value::Float(1.25)));
+}
+
+#[test]
+fn compare_and_swap_writes_only_over_the_expected_value() {
+    let mut db = database();
+    // None expects the key to be missing
+    assert!(db.compare_and_swap("lock", None, text("ann")).unwrap());
+    assert!(!db.compare_and_swap("lock", None, text("bob")).unwrap());
+    assert!(
+        !db.compare_and_swap("lock", Some(text("bob")), text("cy"))
+            .unwrap()
+    );
+    assert_eq!(db.get("lock"), Some(text("ann")));
+    assert!(
+        db.compare_and_swap("lock", Some(text("ann")), text("bob"))
+            .unwrap()
+    );
+    assert_eq!(db.get("lock"), Some(text("bob")));
+    // Values are compared by variant: 1 is not 1.0
+    db.insert("n".to_string(), Value::Integer(1)).unwrap();
+    assert!(
+        !db.compare_and_swap("n", Some(Value::Float(1.0)), Value::Integer(2))
+            .unwrap()
+    );
+}
+
+#[test]
+fn versions_count_writes_and_guard_set_if_version() {
+    let mut db = database();
+    assert_eq!(db.version("doc"), None);
+    // 0 means the key must not exist yet
+    assert!(db.set_if_version("doc", 0, text("a")).unwrap());
+    let (value, first) = db.get_with_version("doc").unwrap();
+    assert_eq!(value, text("a"));
+    assert!(!db.set_if_version("doc", 0, text("b")).unwrap());
+
+    db.insert("doc".to_string(), text("b")).unwrap();
+    let second = db.version("doc").unwrap();
+    assert!(second > first);
+    // A stale version is a conflict, and writes nothing
+    assert!(!db.set_if_version("doc", first, text("c")).unwrap());
+    assert_eq!(db.get("doc"), Some(text("b")));
+    assert!(db.set_if_version("doc", second, text("c")).unwrap());
+    assert!(db.version("doc").unwrap() > second);
+
+    // Every kind of write moves the version on
+    let before = db.version("doc").unwrap();
+    db.compare_and_swap("doc", Some(text("c")), Value::Integer(1))
+        .unwrap();
+    db.incr_by("doc", 1).unwrap();
+    db.incr_float("doc", 1.0).unwrap();
+    assert!(db.version("doc").unwrap() >= before + 3);
+
+    db.delete("doc").unwrap();
+    assert_eq!(db.version("doc"), None);
+    assert_eq!(db.get_with_version("doc"), None);
+}
+
+#[test]
+fn expired_keys_have_no_version() {
+    let mut db = database();
+    expired(&mut db, "session", text("old"));
+    assert_eq!(db.version("session"), None);
+    assert_eq!(db.get_with_version("session"), None);
+
+    // The key counts as missing for every conditional write
+    expired(&mut db, "a", text("old"));
+    assert!(db.set_if_version("a", 0, text("new")).unwrap());
+    assert_eq!(db.get("a"), Some(text("new")));
+    expired(&mut db, "b", text("old"));
+    assert!(db.compare_and_swap("b", None, text("new")).unwrap());
+    expired(&mut db, "c", Value::Integer(40));
+    assert_eq!(db.incr_by("c", 2).unwrap(), 2);
+    expired(&mut db, "d", Value::Float(4.0));
+    assert_eq!(db.incr_float("d", 0.5).unwrap(), 0.5);
+}
+
+#[test]
+fn versions_of_expired_keys_do_not_match_their_old_version() {
+    let mut db = database();
+    db.insert("k".to_string(), text("v1")).unwrap();
+    let old = db.version("k").unwrap();
+    assert!(db.expire("k", Duration::from_millis(1)).unwrap());
+    thread::sleep(Duration::from_millis(5));
+    assert!(!db.set_if_version("k", old, text("v2")).unwrap());
+    assert_eq!(db.get("k"), None);
+}
@@ -0,0 +1,266 @@
+// JSON filter documents: what they parse into, and where errors point
+#![cfg(feature = "json")]
+
+use littledb::{Condition, FilterError, GeoField, GeoPoint, Value};
+
+fn parse(text: &str) -> Condition {
+    Condition::from_json(text).unwrap_or_else(|e| panic!("{}: {}", text, e))
+}
+
+// Conditions aren't PartialEq (a Regex isn't); their Debug form is exact
+fn assert_parses(text: &str, expected: Condition) {
+    assert_eq!(
+        format!("{:?}", parse(text)),
+        format!("{:?}", expected),
+        "{}",
+        text
+    );
+}
+
+fn equals(field: &str, value: Value) -> Condition {
+    Condition::Equals(field.to_string(), value)
+}
+
+fn unknown(path: &str, operator: &str) -> FilterError {
+    FilterError::UnknownOperator {
+        path: path.to_string(),
+        operator: operator.to_string(),
+    }
+}
+
+fn mismatch(path: &str, expected: &str, found: &str) -> FilterError {
+    FilterError::TypeMismatch {
+        path: path.to_string(),
+        expected: expected.to_string(),
+        found: found.to_string(),
+    }
+}
+
+#[test]
+fn gte_and_lte_fold_into_between() {
+    let age = || "age".to_string();
+    assert_parses(
+        r#"{"age": {"$gte": 18, "$lte": 65}}"#,
+        Condition::Between(age(), 18, 65),
+    );
+    assert_parses(
+        r#"{"age": {"$gte": 18}}"#,
+        Condition::Between(age(), 18, i64::MAX),
+    );
+    assert_parses(
+        r#"{"age": {"$lte": 65}}"#,
+        Condition::Between(age(), i64::MIN, 65),
+    );
+    assert_parses(
+        r#"{"age": {"$lte": 65, "$gt": 20}}"#,
+        Condition::And(vec![
+            Condition::Between(age(), i64::MIN, 65),
+            Condition::GreaterThan(age(), 20),
+        ]),
+    );
+    for condition in [
+        Condition::Between(age(), 18, 65),
+        Condition::Between(age(), 18, i64::MAX),
+        Condition::Between(age(), i64::MIN, 65),
+    ] {
+        assert_parses(&condition.to_json(), condition);
+    }
+}
+
+#[test]
+fn comparisons_take_integers_only() {
+    let age = || "age".to_string();
+    assert_parses(r#"{"age": {"$gt": -3}}"#, Condition::GreaterThan(age(), -3));
+    assert_parses(
+        r#"{"age": {"$lt": 9223372036854775807}}"#,
+        Condition::LessThan(age(), i64::MAX),
+    );
+    for operator in ["$gt", "$lt", "$gte", "$lte"] {
+        let path = format!("$.age.{}", operator);
+        for (argument, found) in [
+            ("2.5", "a float"),
+            ("5.0", "a float"),
+            ("1e3", "a float"),
+            ("9223372036854775808", "an integer larger than an i64"),
+        ] {
+            let text = format!(r#"{{"age": {{"{}": {}}}}}"#, operator, argument);
+            assert_eq!(
+                Condition::from_json(&text).unwrap_err(),
+                mismatch(&path, "an integer", found),
+                "{}",
+                text
+            );
+        }
+    }
+}
+
+#[test]
+fn in_and_nin_are_ors_of_equalities() {
+    let any = || {
+        Condition::Or(vec![
+            equals("tag", Value::String("a".to_string())),
+            equals("tag", Value::Integer(2)),
+            equals("tag", Value::Null),
+        ])
+    };
+    assert_parses(r#"{"tag": {"$in": ["a", 2, null]}}"#, any());
+    assert_parses(
+        r#"{"tag": {"$nin": ["a", 2, null]}}"#,
+        Condition::Not(Box::new(any())),
+    );
+    assert_parses(r#"{"tag": {"$in": []}}"#, Condition::Or(vec![]));
+
+    let tagged = |tag: Value| Value::Object([("tag".to_string(), tag)].into());
+    let condition = parse(r#"{"tag": {"$nin": ["a", 2]}}"#);
+    assert!(!condition.matches(&tagged(Value::Integer(2))));
+    assert!(condition.matches(&tagged(Value::String("b".to_string()))));
+}
+
+// Values JSON has a type for come back as they were, the others the way
+// json.rs writes them
+#[test]
+fn equality_values_round_trip() {
+    let text = |s: &str| Value::String(s.to_string());
+    let same = |value: Value| (value.clone(), value);
+    let operator_like = Value::Object([("$gt".to_string(), Value::Integer(1))].into());
+    let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    let cases = [
+        same(Value::Null),
+        same(Value::Boolean(false)),
+        same(Value::Integer(i64::MIN)),
+        same(Value::Float(3.0)),
+        same(Value::Float(-0.25)),
+        same(text("say \"hi\"")),
+        same(Value::Array(vec![Value::Integer(1), Value::Float(2.5)])),
+        same(operator_like),
+        (Value::Bytes(vec![1, 2, 3]), text("AQID")),
+        (Value::Timestamp(0), text("1970-01-01T00:00:00Z")),
+        (Value::Decimal("12.50".parse().unwrap()), text("12.50")),
+        (Value::Uuid(uuid.parse().unwrap()), text(uuid)),
+        (Value::Float(f64::NAN), Value::Null),
+        (Value::Float(f64::NEG_INFINITY), Value::Null),
+    ];
+    for (value, expected) in cases {
+        let json = equals("field", value).to_json();
+        assert_parses(&json, equals("field", expected));
+    }
+}
+
+#[test]
+fn exists_takes_a_boolean() {
+    let exists = || Condition::Exists("email".to_string());
+    assert_parses(r#"{"email": {"$exists": true}}"#, exists());
+    assert_parses(
+        r#"{"email": {"$exists": false}}"#,
+        Condition::Not(Box::new(exists())),
+    );
+    assert_parses(&exists().to_json(), exists());
+}
+
+#[test]
+fn geo_operators() {
+    let berlin = GeoPoint::new(52.5, 13.4);
+    assert_parses(
+        r#"{"location": {"$near": {"$point": [13.4, 52.5], "$maxDistance": 5000}}}"#,
+        Condition::NearPoint(GeoField::from_name("location"), berlin, 5000.0),
+    );
+    assert_parses(
+        r#"{"lat,lng": {"$within": {"$box": [[13.3, 52.4], [13.5, 52.6]]}}}"#,
+        Condition::WithinBox(
+            GeoField::from_name("lat,lng"),
+            GeoPoint::new(52.4, 13.3),
+            GeoPoint::new(52.6, 13.5),
+        ),
+    );
+    let triangle = vec![
+        GeoPoint::new(52.4, 13.3),
+        GeoPoint::new(52.4, 13.5),
+        GeoPoint::new(52.6, 13.4),
+    ];
+    let polygon = Condition::WithinPolygon(GeoField::from_name("location"), triangle);
+    assert_parses(
+        r#"{"location": {"$within": {"$polygon": [[13.3, 52.4], [13.5, 52.4], [13.4, 52.6]]}}}"#,
+        polygon.clone(),
+    );
+    assert_parses(&polygon.to_json(), polygon);
+}
+
+#[test]
+fn unknown_operators_point_at_the_operator() {
+    for (text, error) in [
+        (r#"{"$nand": []}"#, unknown("$.$nand", "$nand")),
+        (
+            r#"{"$or": [{"$xor": 1}]}"#,
+            unknown("$.$or[0].$xor", "$xor"),
+        ),
+        (r#"{"age": {"$gtt": 5}}"#, unknown("$.age.$gtt", "$gtt")),
+        (
+            r#"{"age": {"$not": {"$bad": 5}}}"#,
+            unknown("$.age.$not.$bad", "$bad"),
+        ),
+        (
+            r#"{"at": {"$near": {"$point": [0, 0], "$max": 1}}}"#,
+            unknown("$.at.$near.$max", "$max"),
+        ),
+        (
+            r#"{"at": {"$within": {"$circle": 5}}}"#,
+            unknown("$.at.$within.$circle", "$circle"),
+        ),
+    ] {
+        assert_eq!(Condition::from_json(text).unwrap_err(), error, "{}", text);
+    }
+}
+
+#[test]
+fn reports_each_kind_of_error() {
+    assert!(matches!(
+        Condition::from_json(r#"{"age": "#),
+        Err(FilterError::InvalidJson(_))
+    ));
+    for (text, error) in [
+        (
+            r#"[1]"#,
+            mismatch("$", "a filter document (object)", "an array"),
+        ),
+        (
+            r#"{"age": {"$gt": "5"}}"#,
+            mismatch("$.age.$gt", "an integer", "a string"),
+        ),
+        (
+            r#"{"age": {"$lte": 1.5}}"#,
+            mismatch("$.age.$lte", "an integer", "a float"),
+        ),
+        (
+            r#"{"tag": {"$in": "a"}}"#,
+            mismatch("$.tag.$in", "an array", "a string"),
+        ),
+        (
+            r#"{"$and": {}}"#,
+            mismatch("$.$and", "an array of filter documents", "an object"),
+        ),
+        (
+            r#"{"e": {"$exists": 1}}"#,
+            mismatch("$.e.$exists", "a boolean", "an integer"),
+        ),
+        (
+            r#"{"at": {"$near": {"$point": [0, 0]}}}"#,
+            mismatch("$.at.$near", "a $maxDistance", "an object"),
+        ),
+        (
+            r#"{"at": {"$within": {"$box": [[0, 0]]}}}"#,
+            mismatch("$.at.$within.$box", "two points", "an array"),
+        ),
+        (
+            r#"{"at": {"$within": {"$polygon": [[0, 0], [1, "x"]]}}}"#,
+            mismatch("$.at.$within.$polygon[1]", "a [lng, lat] point", "an array"),
+        ),
+    ] {
+        assert_eq!(Condition::from_json(text).unwrap_err(), error, "{}", text);
+    }
+    let Err(FilterError::InvalidRegex { path, .. }) =
+        Condition::from_json(r#"{"name": {"$regex": "(a"}}"#)
+    else {
+        panic!("expected an invalid regex");
+    };
+    assert_eq!(path, "$.name.$regex");
+}
@@ -0,0 +1,166 @@
+// Full-text search: ranking, query syntax, and one analyzer per field for
+// every way a Matches condition is checked
+
+use std::collections::HashMap;
+
+use littledb::{Analyzer, Condition, Database, Value};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-fulltext-unsaved.db");
+    db.set_auto_save(false);
+    db
+}
+
+fn post(body: &str) -> Value {
+    Value::Object(HashMap::from([(
+        "body".to_string(),
+        Value::String(body.to_string()),
+    )]))
+}
+
+fn posts(db: &mut Database, bodies: &[(&str, &str)]) {
+    for (key, body) in bodies {
+        db.insert(key.to_string(), post(body)).unwrap();
+    }
+}
+
+fn matches(query: &str) -> Condition {
+    Condition::Matches("body".to_string(), query.to_string())
+}
+
+fn keys(results: Vec<(String, Value)>) -> Vec<String> {
+    results.into_iter().map(|(key, _)| key).collect()
+}
+
+fn hits(db: &Database, query: &str) -> Vec<String> {
+    db.search("body", query, 10)
+        .unwrap()
+        .into_iter()
+        .map(|hit| hit.key)
+        .collect()
+}
+
+#[test]
+fn bm25_ranks_frequent_rare_and_short_first() {
+    let mut db = database();
+    posts(
+        &mut db,
+        &[
+            (
+                "once",
+                "rust is a language and this text goes on for a while longer",
+            ),
+            (
+                "twice",
+                "rust rust is a language and this text goes on for a while",
+            ),
+            ("short", "rust language"),
+            ("rare", "a language called zig"),
+            ("other", "nothing to see here"),
+        ],
+    );
+    db.create_text_index("body", Analyzer::default()).unwrap();
+
+    // More occurrences and a shorter text both score higher
+    assert_eq!(hits(&db, "rust"), vec!["short", "twice", "once"]);
+    // A rare word weighs more than a common one
+    assert_eq!(hits(&db, "zig OR language")[0], "rare");
+    let scores: Vec<f64> = db
+        .search("body", "rust", 10)
+        .unwrap()
+        .iter()
+        .map(|hit| hit.score)
+        .collect();
+    assert!(
+        scores
+            .windows(2)
+            .all(|pair| pair[0] > pair[1] && pair[1] > 0.0)
+    );
+    assert_eq!(db.search("body", "rust", 1).unwrap().len(), 1);
+    assert!(db.search("title", "rust", 10).is_err());
+}
+
+#[test]
+fn phrases_and_or_groups() {
+    let mut db = database();
+    posts(
+        &mut db,
+        &[
+            ("p1", "the quick brown fox"),
+            ("p2", "brown quick fox"),
+            ("p3", "a quick, brown dog"),
+            ("p4", "lazy dog sleeps"),
+        ],
+    );
+    let cases = [
+        ("\"quick brown\"", vec!["p1", "p3"]),
+        ("\"quick brown\" fox", vec!["p1"]),
+        ("\"brown quick\"", vec!["p2"]),
+        ("\"quick fox\"", vec!["p2"]),
+        ("\"fox quick\"", vec![]),
+        ("fox OR lazy", vec!["p1", "p2", "p4"]),
+        ("quick fox OR dog", vec!["p1", "p2", "p3", "p4"]),
+        ("quick dog OR sleeps", vec!["p3", "p4"]),
+        ("\"quick brown\" dog OR \"lazy dog\"", vec!["p3", "p4"]),
+        ("QUICK Brown", vec!["p1", "p2", "p3"]),
+        ("cat OR", vec![]),
+    ];
+    // The same answers with and without an index
+    for (query, expected) in &cases {
+        assert_eq!(keys(db.query(matches(query))), *expected, "{}", query);
+    }
+    db.create_text_index("body", Analyzer::default()).unwrap();
+    for (query, expected) in &cases {
+        assert_eq!(keys(db.query(matches(query))), *expected, "{}", query);
+        let mut found = hits(&db, query);
+        found.sort();
+        assert_eq!(found, *expected, "{}", query);
+    }
+}
+
+#[test]
+fn conditions_use_the_analyzer_of_the_index() {
+    let mut db = database();
+    posts(
+        &mut db,
+        &[
+            ("run", "she runs every day"),
+            ("ran", "he was running late"),
+            ("walk", "they walked home"),
+        ],
+    );
+    db.create_text_index("body", Analyzer::new().with_stemming())
+        .unwrap();
+    let mut watcher = db.watch(matches("walking")).unwrap();
+    let stemmed = || matches("run");
+
+    // Through the index, and re-checked after it
+    assert_eq!(keys(db.query(stemmed())), vec!["ran", "run"]);
+    // Beside an unindexed condition, which means a full scan
+    let either = Condition::Or(vec![
+        stemmed(),
+        Condition::Contains("body".to_string(), "home".to_string()),
+    ]);
+    assert_eq!(keys(db.query(either)), vec!["ran", "run", "walk"]);
+    // Under NOT, which no index answers
+    assert_eq!(
+        keys(db.query(Condition::Not(Box::new(stemmed())))),
+        vec!["walk"]
+    );
+    // Conditional writes
+    assert!(
+        db.update_if("run", &stemmed(), post("she runs daily"))
+            .unwrap()
+    );
+    assert!(db.delete_if("ran", &stemmed()).unwrap());
+    assert!(!db.delete_if("walk", &stemmed()).unwrap());
+    // Watches
+    db.insert("walk".to_string(), post("they walk home"))
+        .unwrap();
+    db.insert("run".to_string(), post("she runs home")).unwrap();
+    let watched: Vec<Option<String>> = watcher
+        .try_iter()
+        .map(|event| event.change.key().map(str::to_string))
+        .collect();
+    assert_eq!(watched, vec![Some("walk".to_string())]);
+}
@@ -0,0 +1,455 @@
+// The REST server, driven over localhost with a small HTTP/1.1 client
+#![cfg(feature = "json")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use common::{open, temp_db};
+use littledb::http::Server;
+use littledb::server::ShutdownHandle;
+use littledb::{Database, Schema, Value};
+use serde_json::{Value as Json, json};
+
+struct Reply {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(&self) -> Json {
+        serde_json::from_slice(&self.body).expect("the body should be JSON")
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+// One keep-alive connection
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    fn send(&mut self, method: &str, target: &str, headers: &[(&str, &str)], body: &str) {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n",
+            method,
+            target,
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        self.writer.write_all(request.as_bytes()).unwrap();
+    }
+
+    fn request(
+        &mut self,
+        method: &str,
+        target: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> Reply {
+        self.send(method, target, headers, body);
+        self.read(method == "HEAD")
+    }
+
+    fn get(&mut self, target: &str) -> Reply {
+        self.request("GET", target, &[], "")
+    }
+
+    fn put(&mut self, target: &str, body: &str) -> Reply {
+        self.request("PUT", target, &[], body)
+    }
+
+    fn read(&mut self, head: bool) -> Reply {
+        let status_line = self.line();
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        let mut headers = HashMap::new();
+        loop {
+            let line = self.line();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        }
+        let length = if head {
+            0
+        } else {
+            headers
+                .get("content-length")
+                .map_or(0, |length| length.parse().unwrap())
+        };
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        Reply {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        assert!(line.ends_with("\r\n"), "truncated response {:?}", line);
+        line.truncate(line.len() - 2);
+        line
+    }
+}
+
+struct Running {
+    addr: SocketAddr,
+    stop: ShutdownHandle,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Running {
+    fn client(&self) -> Client {
+        Client::connect(self.addr)
+    }
+
+    fn stop(self) {
+        self.stop.shutdown();
+        self.thread.join().unwrap();
+    }
+}
+
+fn start(db: Database) -> Running {
+    let server = Server::bind("127.0.0.1:0", db).unwrap().save_interval(None);
+    let addr = server.local_addr().unwrap();
+    let stop = server.shutdown_handle();
+    let thread = thread::spawn(move || server.run().unwrap());
+    Running { addr, stop, thread }
+}
+
+fn start_fresh() -> Running {
+    start(open(&temp_db()))
+}
+
+#[test]
+fn put_get_delete() {
+    let server = start_fresh();
+    let mut c = server.client();
+
+    let missing = c.get("/keys/user:1");
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.json(), json!({"error": "Key 'user:1' not found"}));
+
+    let created = c.put("/keys/user:1", r#"{"name": "Alice", "age": 30}"#);
+    assert_eq!(created.status, 201);
+    assert_eq!(created.json()["key"], "user:1");
+    let replaced = c.put("/keys/user:1", r#"{"name": "Alice", "age": 31}"#);
+    assert_eq!(replaced.status, 200);
+    assert_ne!(created.header("etag"), replaced.header("etag"));
+
+    let got = c.get("/keys/user:1");
+    assert_eq!(got.status, 200);
+    assert_eq!(got.header("content-type"), Some("application/json"));
+    assert_eq!(got.json(), json!({"name": "Alice", "age": 31}));
+    assert_eq!(got.header("etag"), replaced.header("etag"));
+
+    // Percent-encoded keys
+    assert_eq!(c.put("/keys/a%20b%2Fc", "[1, 2]").status, 201);
+    assert_eq!(c.get("/keys/a%20b%2Fc").json(), json!([1, 2]));
+
+    let head = c.request("HEAD", "/keys/user:1", &[], "");
+    assert_eq!(head.status, 200);
+    assert!(head.body.is_empty());
+
+    assert_eq!(c.request("DELETE", "/keys/user:1", &[], "").status, 204);
+    assert_eq!(c.request("DELETE", "/keys/user:1", &[], "").status, 404);
+    assert_eq!(c.get("/keys/user:1").status, 404);
+
+    server.stop();
+}
+
+#[test]
+fn bad_requests() {
+    let server = start_fresh();
+    let mut c = server.client();
+
+    let invalid = c.put("/keys/k", "{not json");
+    assert_eq!(invalid.status, 400);
+    assert!(
+        invalid.json()["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid JSON")
+    );
+    assert_eq!(c.get("/nowhere").status, 404);
+    let wrong_method = c.request("POST", "/keys/k", &[], "");
+    assert_eq!(wrong_method.status, 405);
+    assert!(wrong_method.header("allow").unwrap().contains("PUT"));
+    assert_eq!(c.put("/keys/k?ttl=soon", "1").status, 400);
+    assert_eq!(
+        c.request("POST", "/query", &[], r#"{"age": {"$bogus": 1}}"#)
+            .status,
+        400
+    );
+    assert_eq!(c.get("/keys?limit=0").status, 400);
+
+    // Still usable after all that
+    assert_eq!(c.put("/keys/k", "1").status, 201);
+    server.stop();
+}
+
+#[test]
+fn patch_json_patch_and_merge_patch() {
+    let server = start_fresh();
+    let mut c = server.client();
+    c.put("/keys/doc", r#"{"name": "Alice", "tags": ["a"]}"#);
+
+    let patched = c.request(
+        "PATCH",
+        "/keys/doc",
+        &[("Content-Type", "application/json-patch+json")],
+        r#"[{"op": "add", "path": "/tags/-", "value": "b"}, {"op": "replace", "path": "/name", "value": "Al"}]"#,
+    );
+    assert_eq!(patched.status, 200);
+    assert_eq!(patched.json(), json!({"name": "Al", "tags": ["a", "b"]}));
+
+    let merged = c.request(
+        "PATCH",
+        "/keys/doc",
+        &[("Content-Type", "application/merge-patch+json")],
+        r#"{"tags": null, "age": 3}"#,
+    );
+    assert_eq!(merged.status, 200);
+    assert_eq!(merged.json(), json!({"name": "Al", "age": 3}));
+
+    let failed_test = c.request(
+        "PATCH",
+        "/keys/doc",
+        &[],
+        r#"[{"op": "test", "path": "/name", "value": "Bob"}]"#,
+    );
+    assert_eq!(failed_test.status, 409);
+    assert_eq!(
+        c.request("PATCH", "/keys/doc", &[], r#"{"op": 1}"#).status,
+        400
+    );
+    assert_eq!(c.request("PATCH", "/keys/missing", &[], "[]").status, 404);
+
+    server.stop();
+}
+
+#[test]
+fn etag_conditional_requests() {
+    let server = start_fresh();
+    let mut c = server.client();
+
+    // If-None-Match: * creates only
+    let created = c.request("PUT", "/keys/k", &[("If-None-Match", "*")], "1");
+    assert_eq!(created.status, 201);
+    assert_eq!(
+        c.request("PUT", "/keys/k", &[("If-None-Match", "*")], "2")
+            .status,
+        412
+    );
+    let etag = created.header("etag").unwrap().to_string();
+
+    // Unchanged: 304 with no body
+    let cached = c.request("GET", "/keys/k", &[("If-None-Match", &etag)], "");
+    assert_eq!(cached.status, 304);
+    assert!(cached.body.is_empty());
+    assert_eq!(
+        c.request("GET", "/keys/k", &[("If-None-Match", "\"999\"")], "")
+            .status,
+        200
+    );
+
+    // If-Match: a write based on an old read is refused
+    let updated = c.request("PUT", "/keys/k", &[("If-Match", &etag)], "3");
+    assert_eq!(updated.status, 200);
+    let stale = c.request("PUT", "/keys/k", &[("If-Match", &etag)], "4");
+    assert_eq!(stale.status, 412);
+    let stale_patch = c.request(
+        "PATCH",
+        "/keys/k",
+        &[
+            ("If-Match", &etag),
+            ("Content-Type", "application/merge-patch+json"),
+        ],
+        "5",
+    );
+    assert_eq!(stale_patch.status, 412);
+    assert_eq!(
+        c.request("DELETE", "/keys/k", &[("If-Match", &etag)], "")
+            .status,
+        412
+    );
+    assert_eq!(c.get("/keys/k").json(), json!(3));
+
+    let fresh = updated.header("etag").unwrap();
+    let weak = format!("W/{}", fresh);
+    assert_eq!(
+        c.request("DELETE", "/keys/k", &[("If-Match", &weak)], "")
+            .status,
+        204
+    );
+    assert_eq!(
+        c.request("PUT", "/keys/k", &[("If-Match", "*")], "6")
+            .status,
+        412
+    );
+
+    server.stop();
+}
+
+#[test]
+fn query_with_options() {
+    let server = start_fresh();
+    let mut c = server.client();
+    for (key, name, age) in [
+        ("u:1", "Alice", 30),
+        ("u:2", "Bob", 25),
+        ("u:3", "Carol", 35),
+    ] {
+        c.put(
+            &format!("/keys/{}", key),
+            &json!({"name": name, "age": age}).to_string(),
+        );
+    }
+
+    let adults = c.request("POST", "/query", &[], r#"{"age": {"$gte": 30}}"#);
+    assert_eq!(adults.status, 200);
+    assert_eq!(
+        adults.json(),
+        json!({"count": 2, "results": [
+            {"key": "u:1", "value": {"name": "Alice", "age": 30}},
+            {"key": "u:3", "value": {"name": "Carol", "age": 35}},
+        ]})
+    );
+
+    let page = c.request("POST", "/query?sort=-age&limit=2&select=name", &[], "");
+    let names: Vec<Json> = page.json()["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["value"]["name"].clone())
+        .collect();
+    assert_eq!(names, vec![json!("Carol"), json!("Alice")]);
+
+    server.stop();
+}
+
+#[test]
+fn list_keys_with_cursor() {
+    let server = start_fresh();
+    let mut c = server.client();
+    for i in 0..25 {
+        c.put(&format!("/keys/item:{:02}", i), "true");
+    }
+    c.put("/keys/other", "true");
+
+    let mut keys = Vec::new();
+    let mut target = "/keys?prefix=item:&limit=10".to_string();
+    loop {
+        let page = c.get(&target).json();
+        keys.extend(page["keys"].as_array().unwrap().iter().cloned());
+        match page["next"].as_str() {
+            Some(next) => target = next.to_string(),
+            None => {
+                assert_eq!(page["cursor"], Json::Null);
+                break;
+            }
+        }
+    }
+    assert_eq!(keys.len(), 25);
+    assert_eq!(keys[0], json!("item:00"));
+    assert_eq!(keys[24], json!("item:24"));
+
+    let all = c.get("/keys").json();
+    assert_eq!(all["keys"].as_array().unwrap().len(), 26);
+    let after = c.get("/keys?cursor=item%3A23").json();
+    assert_eq!(after["keys"], json!(["item:24", "other"]));
+
+    server.stop();
+}
+
+#[test]
+fn stats_and_schema_errors() {
+    let mut db = open(&temp_db());
+    db.set_schema("user:", Schema::object().property("age", Schema::integer()))
+        .unwrap();
+    let server = start(db);
+    let mut c = server.client();
+
+    assert_eq!(c.put("/keys/user:1", r#"{"age": "old"}"#).status, 422);
+    assert_eq!(c.put("/keys/user:1", r#"{"age": 40}"#).status, 201);
+
+    let stats = c.get("/stats");
+    assert_eq!(stats.status, 200);
+    assert_eq!(stats.json()["total_entries"], 1);
+    assert_eq!(stats.json()["auto_save_enabled"], false);
+
+    server.stop();
+}
+
+#[test]
+fn keep_alive_pipelining_and_close() {
+    let server = start_fresh();
+    let mut c = server.client();
+
+    // Several requests sent at once are answered in order
+    for i in 0..50 {
+        c.send("PUT", &format!("/keys/n{}", i), &[], &i.to_string());
+        c.send("GET", &format!("/keys/n{}", i), &[], "");
+    }
+    for i in 0..50 {
+        assert_eq!(c.read(false).status, 201);
+        assert_eq!(c.read(false).json(), json!(i));
+    }
+
+    // Connection: close ends the connection after the response
+    let last = c.request("GET", "/keys/n1", &[("Connection", "close")], "");
+    assert_eq!(last.header("connection"), Some("close"));
+    let mut rest = Vec::new();
+    assert_eq!(c.reader.read_to_end(&mut rest).unwrap(), 0);
+
+    // 100-continue is answered before the body is sent
+    let mut c = server.client();
+    c.writer
+        .write_all(b"PUT /keys/big HTTP/1.1\r\nContent-Length: 4\r\nExpect: 100-continue\r\n\r\n")
+        .unwrap();
+    assert_eq!(c.read(false).status, 100);
+    c.writer.write_all(b"true").unwrap();
+    assert_eq!(c.read(false).status, 201);
+
+    server.stop();
+}
+
+#[test]
+fn changes_are_saved_when_the_server_stops() {
+    let path = temp_db();
+    let server = start(open(&path));
+    server.client().put("/keys/kept", r#""yes""#);
+    server.stop();
+    assert_eq!(
+        open(&path).get("kept"),
+        Some(Value::String("yes".to_string()))
+    );
+}
@@ -0,0 +1,174 @@
+// JSON conversions: numeric variants, NaN and infinity, key order, and the
+// values JSON has no type for
+#![cfg(feature = "json")]
+
+use std::collections::HashMap;
+
+use littledb::{JsonError, Value};
+use serde_json::{Value as Json, json};
+
+fn object(fields: &[(&str, Value)]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn parse(text: &str) -> Value {
+    Value::from_json_str(text).unwrap_or_else(|e| panic!("{}: {}", text, e))
+}
+
+fn print(value: &Value) -> String {
+    value.to_json_string().unwrap()
+}
+
+#[test]
+fn numbers_keep_their_variant() {
+    let cases = [
+        ("3", Value::Integer(3)),
+        ("3.0", Value::Float(3.0)),
+        ("1e3", Value::Float(1000.0)),
+        ("-2.5", Value::Float(-2.5)),
+        ("9223372036854775807", Value::Integer(i64::MAX)),
+        ("-9223372036854775808", Value::Integer(i64::MIN)),
+        // Past the i64 range: a Float, with the precision that comes with it
+        ("9223372036854775808", Value::Float(9223372036854775808.0)),
+        ("-9223372036854775809", Value::Float(-9223372036854775808.0)),
+        ("18446744073709551616", Value::Float(18446744073709551616.0)),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(parse(text), expected, "{}", text);
+    }
+
+    // Whole-number floats are written with a fraction or an exponent, so they
+    // come back as floats
+    for (value, text) in [
+        (Value::Integer(i64::MAX), "9223372036854775807"),
+        (Value::Integer(i64::MIN), "-9223372036854775808"),
+        (Value::Float(3.0), "3.0"),
+        (Value::Float(-0.0), "-0.0"),
+        (Value::Float(1e20), "1e+20"),
+        (Value::Float(0.1), "0.1"),
+    ] {
+        assert_eq!(print(&value), text);
+        assert_eq!(parse(text), value, "{}", text);
+    }
+}
+
+#[test]
+fn nan_and_infinity_are_errors_that_say_where() {
+    let non_finite = |path: &str| JsonError::NonFiniteFloat {
+        path: path.to_string(),
+    };
+    let prices = Value::Array(vec![Value::Float(1.5), Value::Float(f64::NAN)]);
+    let nested = object(&[(
+        "item",
+        object(&[
+            ("name", Value::String("pen".to_string())),
+            ("prices", prices),
+        ]),
+    )]);
+    assert_eq!(nested.to_json_string(), Err(non_finite("$.item.prices[1]")));
+    assert_eq!(
+        nested.to_json_string_pretty(),
+        Err(non_finite("$.item.prices[1]"))
+    );
+    assert_eq!(
+        Json::try_from(&Value::Float(f64::INFINITY)),
+        Err(non_finite("$"))
+    );
+    assert_eq!(
+        Json::try_from(Value::Array(vec![Value::Float(f64::NEG_INFINITY)])),
+        Err(non_finite("$[0]"))
+    );
+    assert_eq!(
+        non_finite("$.a").to_string(),
+        "$.a: NaN and infinity can't be written as JSON"
+    );
+}
+
+#[test]
+fn objects_list_their_keys_sorted() {
+    let value = object(&[
+        ("zeta", Value::Integer(1)),
+        ("alpha", Value::Null),
+        (
+            "mid",
+            object(&[("b", Value::Boolean(true)), ("a", Value::Array(vec![]))]),
+        ),
+        ("Beta", Value::String("x".to_string())),
+    ]);
+    let text = r#"{"Beta":"x","alpha":null,"mid":{"a":[],"b":true},"zeta":1}"#;
+    assert_eq!(print(&value), text);
+    assert_eq!(parse(text), value);
+    // Whatever order the text had
+    assert_eq!(
+        print(&parse(
+            r#"{"zeta": 1, "mid": {"b": true, "a": []}, "alpha": null, "Beta": "x"}"#
+        )),
+        text
+    );
+    assert_eq!(
+        value.to_json_string_pretty().unwrap(),
+        "{\n  \"Beta\": \"x\",\n  \"alpha\": null,\n  \"mid\": {\n    \"a\": [],\n    \"b\": true\n  },\n  \"zeta\": 1\n}"
+    );
+}
+
+#[test]
+fn serde_json_values_convert_both_ways() {
+    let json = json!({"name": "Ann", "tags": ["a", 1, 2.5, null], "nested": {"ok": false}});
+    let value = Value::from(&json);
+    assert_eq!(
+        value,
+        object(&[
+            ("name", Value::String("Ann".to_string())),
+            (
+                "tags",
+                Value::Array(vec![
+                    Value::String("a".to_string()),
+                    Value::Integer(1),
+                    Value::Float(2.5),
+                    Value::Null,
+                ])
+            ),
+            ("nested", object(&[("ok", Value::Boolean(false))])),
+        ])
+    );
+    assert_eq!(Json::try_from(&value).unwrap(), json);
+    assert_eq!(Value::from(json.clone()), value);
+}
+
+#[test]
+fn values_without_a_json_type_are_written_as_strings() {
+    let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    for (value, text) in [
+        (Value::Bytes(b"hi!".to_vec()), "aGkh"),
+        (Value::Timestamp(1_500_000_000), "1970-01-01T00:00:01.5Z"),
+        (Value::Decimal("-12.50".parse().unwrap()), "-12.50"),
+        (Value::Uuid(uuid.parse().unwrap()), uuid),
+    ] {
+        let printed = print(&value);
+        assert_eq!(printed, format!("\"{}\"", text));
+        assert_eq!(parse(&printed), Value::String(text.to_string()));
+    }
+}
+
+#[test]
+fn parse_errors_give_line_and_column() {
+    let error = Value::from_json_str("{\n  \"a\": 1,\n  \"b\": }").unwrap_err();
+    let JsonError::Parse {
+        message,
+        line,
+        column,
+    } = &error
+    else {
+        panic!("{:?}", error);
+    };
+    assert_eq!((*line, *column), (3, 8));
+    assert!(!message.contains(" at line"), "{}", message);
+    assert_eq!(error.to_string(), format!("line 3, column 8: {}", message));
+    assert!(Value::from_json_str("").is_err());
+    assert!(Value::from_json_str("[1, 2").is_err());
+}
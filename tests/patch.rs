@@ -0,0 +1,82 @@
+// JSON Patch test operations compare as RFC 6902 section 4.6 says
+
+use std::collections::HashMap;
+
+use littledb::patch::apply;
+use littledb::{Decimal, PatchError, PatchOp, Value};
+
+fn object(fields: &[(&str, Value)]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn decimal(text: &str) -> Value {
+    Value::Decimal(text.parse::<Decimal>().unwrap())
+}
+
+fn passes(document: &Value, path: &str, expected: Value) -> bool {
+    match apply(document, &[PatchOp::Test(path.to_string(), expected)]) {
+        Ok(_) => true,
+        Err(PatchError::TestFailed(failed)) => {
+            assert_eq!(failed, path);
+            false
+        }
+        Err(other) => panic!("{}: {}", path, other),
+    }
+}
+
+#[test]
+fn numbers_are_equal_whatever_their_variant() {
+    let document = object(&[
+        ("int", Value::Integer(1)),
+        ("float", Value::Float(1.0)),
+        ("decimal", decimal("1.50")),
+        ("big", Value::Integer(9007199254740993)),
+        (
+            "nested",
+            object(&[(
+                "list",
+                Value::Array(vec![Value::Integer(2), Value::Float(0.5)]),
+            )]),
+        ),
+    ]);
+    assert!(passes(&document, "/int", Value::Float(1.0)));
+    assert!(passes(&document, "/float", Value::Integer(1)));
+    assert!(passes(&document, "/int", decimal("1.0")));
+    assert!(passes(&document, "/decimal", Value::Float(1.5)));
+    assert!(passes(&document, "/decimal", decimal("1.5")));
+    assert!(passes(
+        &document,
+        "/nested",
+        object(&[(
+            "list",
+            Value::Array(vec![Value::Float(2.0), decimal("0.50")])
+        )])
+    ));
+
+    // Still exact: 2^53 + 1 isn't the float 2^53
+    assert!(!passes(&document, "/big", Value::Float(9007199254740992.0)));
+    assert!(!passes(&document, "/int", Value::Float(1.1)));
+    assert!(!passes(&document, "/int", Value::String("1".to_string())));
+    assert!(!passes(&document, "/int", Value::Boolean(true)));
+    assert!(!passes(
+        &document,
+        "/nested/list",
+        Value::Array(vec![Value::Integer(2)])
+    ));
+    assert!(!passes(
+        &document,
+        "/nested",
+        object(&[
+            (
+                "list",
+                Value::Array(vec![Value::Integer(2), Value::Float(0.5)])
+            ),
+            ("extra", Value::Null),
+        ])
+    ));
+}
@@ -0,0 +1,310 @@
+// Query plans: whatever access path the planner picks, the results are those
+// of a full scan
+
+use std::collections::HashMap;
+
+use littledb::planner::Operation;
+use littledb::{Analyzer, Condition, Database, Decimal, GeoField, GeoPoint, Value};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-planner-unsaved.db");
+    db.set_auto_save(false);
+    db
+}
+
+fn object(fields: &[(&str, Value)]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn decimal(text: &str) -> Value {
+    Value::Decimal(text.parse::<Decimal>().unwrap())
+}
+
+fn keys(results: Vec<(String, Value)>) -> Vec<String> {
+    results.into_iter().map(|(key, _)| key).collect()
+}
+
+// The results of every condition, before and after `index` creates indexes
+fn compare_with_index(
+    db: &mut Database,
+    index: impl FnOnce(&mut Database),
+    conditions: &[Condition],
+) {
+    let scanned: Vec<Vec<String>> = conditions
+        .iter()
+        .map(|c| keys(db.query(c.clone())))
+        .collect();
+    index(db);
+    for (condition, expected) in conditions.iter().zip(scanned) {
+        assert_eq!(
+            keys(db.query(condition.clone())),
+            expected,
+            "{} with {}",
+            condition,
+            db.explain(condition.clone())
+        );
+    }
+}
+
+fn uses_operation(
+    plan_node: &littledb::planner::PlanNode,
+    wanted: &dyn Fn(&Operation) -> bool,
+) -> bool {
+    wanted(&plan_node.operation)
+        || plan_node
+            .children
+            .iter()
+            .any(|child| uses_operation(child, wanted))
+}
+
+#[test]
+fn index_ranges_keep_decimals_between_integers() {
+    let mut db = database();
+    for i in 0..200 {
+        db.insert(
+            format!("k{:03}", i),
+            object(&[("price", Value::Integer(i))]),
+        )
+        .unwrap();
+    }
+    for (key, price) in [
+        ("dec", decimal("98.5")),
+        ("neg", decimal("-0.5")),
+        ("huge", decimal("100000000000000000000")),
+        ("tiny", decimal("-100000000000000000000")),
+        ("ts", Value::Timestamp(150)),
+        ("float", Value::Float(98.5)),
+    ] {
+        db.insert(key.to_string(), object(&[("price", price)]))
+            .unwrap();
+    }
+    let price = || "price".to_string();
+    let conditions = [
+        Condition::GreaterThan(price(), 98),
+        Condition::LessThan(price(), 99),
+        Condition::LessThan(price(), 0),
+        Condition::GreaterThan(price(), 197),
+        Condition::GreaterThan(price(), i64::MAX),
+        Condition::LessThan(price(), i64::MIN),
+        Condition::Between(price(), 98, 99),
+        Condition::Between(price(), 5, 4),
+        Condition::Between(price(), i64::MIN, 3),
+    ];
+    compare_with_index(
+        &mut db,
+        |db| {
+            db.create_index("price").unwrap();
+        },
+        &conditions,
+    );
+
+    let greater = Condition::GreaterThan(price(), 197);
+    assert!(uses_operation(
+        &db.explain(greater.clone()).root,
+        &|operation| { matches!(operation, Operation::IndexRange { .. }) }
+    ));
+    assert_eq!(keys(db.query(greater)), vec!["huge", "k198", "k199"]);
+}
+
+#[test]
+fn float_keys_match_what_a_scan_matches() {
+    let mut db = database();
+    let floats = [
+        0.0,
+        -0.0,
+        1.5,
+        f64::NAN,
+        -f64::NAN,
+        f64::from_bits(f64::NAN.to_bits() | 1),
+        f64::INFINITY,
+    ];
+    for (i, score) in floats.iter().enumerate() {
+        db.insert(
+            format!("k{}", i),
+            object(&[("score", Value::Float(*score))]),
+        )
+        .unwrap();
+    }
+    let conditions: Vec<Condition> = floats
+        .iter()
+        .map(|score| Condition::Equals("score".to_string(), Value::Float(*score)))
+        .collect();
+    compare_with_index(
+        &mut db,
+        |db| {
+            db.create_index("score").unwrap();
+        },
+        &conditions,
+    );
+
+    assert_eq!(keys(db.query(conditions[1].clone())), vec!["k0", "k1"]);
+    assert_eq!(
+        keys(db.query(conditions[3].clone())),
+        vec!["k3", "k4", "k5"]
+    );
+    let zero = db.explain(conditions[0].clone()).root;
+    assert!(uses_operation(&zero, &|operation| {
+        matches!(operation, Operation::IndexLookup { .. })
+    }));
+}
+
+// 400 users and 100 orders with every kind of indexable field
+fn people() -> Database {
+    let mut db = database();
+    let cities = ["Berlin", "Paris", "Rome", "Oslo", "Lima"];
+    let words = ["quick", "brown", "fox", "lazy", "dog", "jumps"];
+    for i in 0..400i64 {
+        let mut fields = vec![
+            ("age", Value::Integer(i % 90)),
+            ("city", Value::String(cities[i as usize % 5].to_string())),
+            ("team", Value::Integer(i % 7)),
+            (
+                "bio",
+                Value::String(format!(
+                    "The {} {} met a {}",
+                    words[i as usize % 6],
+                    words[i as usize % 4],
+                    words[(i / 3) as usize % 6]
+                )),
+            ),
+            (
+                "location",
+                Value::Array(vec![
+                    Value::Float(13.0 + (i % 20) as f64 * 0.05),
+                    Value::Float(52.0 + (i / 20) as f64 * 0.05),
+                ]),
+            ),
+        ];
+        // Some users lack fields, or have them in another type
+        if i % 11 == 0 {
+            fields.retain(|(name, _)| *name != "age");
+        }
+        if i % 13 == 0 {
+            fields[1] = ("city", Value::Integer(5));
+        }
+        if i % 17 == 0 {
+            fields[0] = ("age", decimal("30.5"));
+        }
+        db.insert(format!("user:{:03}", i), object(&fields))
+            .unwrap();
+    }
+    for i in 0..100i64 {
+        db.insert(
+            format!("order:{:03}", i),
+            object(&[
+                ("age", Value::Integer(i)),
+                ("city", Value::String("Paris".to_string())),
+            ]),
+        )
+        .unwrap();
+    }
+    db
+}
+
+fn index_everything(db: &mut Database) {
+    for field in ["age", "city", "team"] {
+        db.create_index(field).unwrap();
+    }
+    db.create_text_index("bio", Analyzer::default()).unwrap();
+    db.create_geo_index(GeoField::array("location")).unwrap();
+}
+
+fn text(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+#[test]
+fn planned_queries_return_what_a_full_scan_returns() {
+    let field = |name: &str| name.to_string();
+    let city = |name: &str| Condition::Equals(field("city"), text(name));
+    let location = || GeoField::array("location");
+    let berlin = GeoPoint::new(52.5, 13.4);
+    let conditions = vec![
+        city("Rome"),
+        Condition::Equals(field("city"), Value::Integer(5)),
+        Condition::Equals(field("age"), decimal("30.5")),
+        Condition::Equals(field("age"), Value::Integer(30)),
+        Condition::Between(field("age"), 20, 30),
+        Condition::GreaterThan(field("age"), 85),
+        Condition::LessThan(field("age"), 3),
+        Condition::KeyPrefix(field("order:")),
+        Condition::Matches(field("bio"), "lazy dog".to_string()),
+        Condition::Matches(field("bio"), "\"brown fox\" OR jumps".to_string()),
+        Condition::NearPoint(location(), berlin, 8000.0),
+        Condition::WithinBox(
+            location(),
+            GeoPoint::new(52.1, 13.1),
+            GeoPoint::new(52.3, 13.4),
+        ),
+        // Intersections
+        Condition::And(vec![
+            city("Rome"),
+            Condition::Equals(field("team"), Value::Integer(3)),
+            Condition::Between(field("age"), 10, 40),
+        ]),
+        Condition::And(vec![
+            Condition::KeyPrefix(field("user:")),
+            city("Paris"),
+            Condition::LessThan(field("age"), 10),
+        ]),
+        Condition::And(vec![
+            Condition::Matches(field("bio"), "fox".to_string()),
+            Condition::NearPoint(location(), berlin, 10000.0),
+            Condition::Equals(field("team"), Value::Integer(1)),
+        ]),
+        // Unions
+        Condition::Or(vec![city("Oslo"), city("Lima")]),
+        Condition::Or(vec![
+            Condition::Equals(field("team"), Value::Integer(6)),
+            Condition::GreaterThan(field("age"), 80),
+            Condition::KeyPrefix(field("order:09")),
+        ]),
+        Condition::Or(vec![
+            Condition::And(vec![city("Rome"), Condition::LessThan(field("age"), 20)]),
+            Condition::Matches(field("bio"), "jumps".to_string()),
+        ]),
+        // Only partly indexed: the rest is filtered
+        Condition::And(vec![
+            city("Berlin"),
+            Condition::Contains(field("bio"), "lazy".to_string()),
+        ]),
+        Condition::Or(vec![
+            city("Berlin"),
+            Condition::Contains(field("bio"), "lazy".to_string()),
+        ]),
+        Condition::And(vec![
+            Condition::Not(Box::new(city("Berlin"))),
+            Condition::Between(field("age"), 40, 42),
+        ]),
+        Condition::And(vec![]),
+        Condition::Or(vec![]),
+    ];
+    let mut db = people();
+    compare_with_index(&mut db, index_everything, &conditions);
+
+    let plan = |condition: &Condition| db.explain(condition.clone()).root;
+    assert!(uses_operation(&plan(&conditions[13]), &|operation| {
+        *operation == Operation::Intersection
+    }));
+    assert!(uses_operation(&plan(&conditions[15]), &|operation| {
+        *operation == Operation::Union
+    }));
+    assert!(uses_operation(&plan(&conditions[16]), &|operation| {
+        matches!(operation, Operation::KeyPrefixScan(_))
+    }));
+    assert!(uses_operation(&plan(&conditions[10]), &|operation| {
+        matches!(operation, Operation::GeoSearch { .. })
+    }));
+    assert!(uses_operation(&plan(&conditions[9]), &|operation| {
+        matches!(operation, Operation::TextSearch { .. })
+    }));
+    // An OR with an unindexed branch has to scan
+    assert!(uses_operation(&plan(&conditions[19]), &|operation| {
+        *operation == Operation::FullScan
+    }));
+}
@@ -0,0 +1,136 @@
+// Pub/sub through the embedded API: fan-out, patterns, slow subscribers
+
+use std::thread;
+use std::time::Duration;
+
+use littledb::{Database, Message, Overflow, Value};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-pubsub-unsaved.db");
+    db.set_verbose(false);
+    db
+}
+
+fn text(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+fn payloads(messages: impl Iterator<Item = Message>) -> Vec<Value> {
+    messages.map(|message| message.payload).collect()
+}
+
+#[test]
+fn fans_out_to_every_subscriber() {
+    let db = database();
+    let subscribers: Vec<_> = (0..5).map(|_| db.subscriber()).collect();
+    for subscriber in &subscribers {
+        assert_eq!(subscriber.subscribe("news"), 1);
+    }
+    let other = db.subscriber();
+    other.subscribe("sport");
+
+    assert_eq!(db.publish("news", text("hello")), 5);
+    assert_eq!(db.publish("nobody", text("lost")), 0);
+    for subscriber in &subscribers {
+        assert_eq!(
+            subscriber.try_next(),
+            Some(Message {
+                channel: "news".to_string(),
+                pattern: None,
+                payload: text("hello"),
+            })
+        );
+        assert_eq!(subscriber.try_next(), None);
+    }
+    assert_eq!(other.try_next(), None);
+
+    // Messages published after unsubscribing (or dropping) don't arrive
+    assert_eq!(subscribers[0].unsubscribe("news"), 0);
+    drop(other);
+    assert_eq!(db.publish("news", text("again")), 4);
+    assert_eq!(subscribers[0].try_next(), None);
+    assert_eq!(db.pubsub().channels(None), vec!["news"]);
+    assert_eq!(db.pubsub().subscriber_count("news"), 4);
+}
+
+#[test]
+fn matches_patterns() {
+    let db = database();
+    let subscriber = db.subscriber();
+    subscriber.psubscribe("orders.*");
+    subscriber.subscribe("orders.new");
+    assert_eq!(subscriber.psubscribe("audit.[ab]?"), 3);
+
+    assert_eq!(db.publish("orders.new", Value::Integer(1)), 2);
+    assert_eq!(db.publish("orders", Value::Integer(2)), 0);
+    assert_eq!(db.publish("audit.b1", Value::Integer(3)), 1);
+    assert_eq!(db.publish("audit.c1", Value::Integer(4)), 0);
+
+    let received: Vec<(Option<String>, Value)> = std::iter::from_fn(|| subscriber.try_next())
+        .map(|message| (message.pattern, message.payload))
+        .collect();
+    assert_eq!(
+        received,
+        vec![
+            (None, Value::Integer(1)),
+            (Some("orders.*".to_string()), Value::Integer(1)),
+            (Some("audit.[ab]?".to_string()), Value::Integer(3)),
+        ]
+    );
+    assert_eq!(subscriber.patterns(), vec!["audit.[ab]?", "orders.*"]);
+    assert_eq!(db.pubsub().pattern_count(), 2);
+    assert_eq!(db.pubsub().channels(Some("orders.*")), vec!["orders.new"]);
+}
+
+#[test]
+fn slow_subscribers_lose_messages_or_are_cut_off() {
+    let pubsub = database().pubsub();
+    let dropping = pubsub.subscriber_with(3, Overflow::Drop);
+    let mut cut = pubsub.subscriber_with(3, Overflow::Disconnect);
+    let fast = pubsub.subscriber();
+    for subscriber in [&dropping, &cut, &fast] {
+        subscriber.subscribe("ticks");
+    }
+    for i in 0..5 {
+        pubsub.publish("ticks", Value::Integer(i));
+    }
+
+    // Publishing didn't wait; the slow ones kept what fit
+    let first_three: Vec<Value> = (0..3).map(Value::Integer).collect();
+    assert_eq!(dropping.dropped(), 2);
+    assert!(!dropping.is_cut_off());
+    assert_eq!(
+        payloads(std::iter::from_fn(|| dropping.try_next())),
+        first_three
+    );
+    pubsub.publish("ticks", Value::Integer(5));
+    assert_eq!(dropping.try_next().unwrap().payload, Value::Integer(5));
+
+    assert!(cut.is_cut_off());
+    assert!(cut.channels().is_empty());
+    assert_eq!(payloads(cut.by_ref()), first_three); // then the iterator ends
+    assert_eq!(cut.subscribe("ticks"), 0);
+
+    assert_eq!(fast.dropped(), 0);
+    assert_eq!(payloads(std::iter::from_fn(|| fast.try_next())).len(), 6);
+}
+
+#[test]
+fn subscribers_wait_on_other_threads() {
+    let db = database();
+    let pubsub = db.pubsub();
+    let mut subscriber = db.subscriber();
+    subscriber.subscribe("jobs");
+    let worker = thread::spawn(move || payloads(subscriber.by_ref().take(3)));
+    for i in 0..3 {
+        assert_eq!(pubsub.publish("jobs", Value::Integer(i)), 1);
+    }
+    assert_eq!(
+        worker.join().unwrap(),
+        (0..3).map(Value::Integer).collect::<Vec<_>>()
+    );
+
+    let idle = db.subscriber();
+    idle.subscribe("jobs");
+    assert_eq!(idle.next_timeout(Duration::from_millis(10)), None);
+}
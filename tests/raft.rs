@@ -0,0 +1,393 @@
+// Raft clusters: over the simulated network, and over TCP on localhost
+
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use littledb::keyspace::CollectionOptions;
+use littledb::raft::{Command, Event, RaftConfig, RaftError, RaftHandle, Role, Simulator};
+use littledb::{Database, Value};
+
+fn insert(key: &str, value: i64) -> Command {
+    Command::insert(key, Value::Integer(value))
+}
+
+// Every running node applied the leader's last entry
+fn settled(sim: &Simulator) -> bool {
+    let Some(leader) = sim.leader() else {
+        return false;
+    };
+    let last = sim.node(leader).status().last_index;
+    sim.running()
+        .into_iter()
+        .all(|id| sim.node(id).status().applied == last)
+}
+
+fn assert_same_data(sim: &Simulator) {
+    let mut running = sim.running().into_iter();
+    let first = running.next().unwrap();
+    let expected = sim.node(first).database().list_keys();
+    for id in running {
+        let db = sim.node(id).database();
+        assert_eq!(db.list_keys(), expected, "node {} differs", id);
+        for key in &expected {
+            assert_eq!(db.get(key), sim.node(first).database().get(key));
+        }
+    }
+}
+
+#[test]
+fn elects_one_leader_of_three_and_of_five() {
+    for nodes in [3, 5] {
+        let mut sim = Simulator::new(nodes, 1);
+        assert!(sim.run_until(1000, |sim| sim.leader().is_some()));
+        sim.run(50);
+        let leader = sim.leader().unwrap();
+        let term = sim.node(leader).status().term;
+        for id in 1..=nodes {
+            let status = sim.node(id).status();
+            assert_eq!(status.leader, Some(leader));
+            assert_eq!(status.term, term);
+            let role = if id == leader {
+                Role::Leader
+            } else {
+                Role::Follower
+            };
+            assert_eq!(status.role, role);
+            assert_eq!(status.members.len(), nodes as usize);
+        }
+    }
+}
+
+#[test]
+fn replicates_writes_to_every_node() {
+    let mut sim = Simulator::new(5, 2);
+    for i in 0..20 {
+        sim.write(vec![insert(&format!("k{}", i), i)]).unwrap();
+    }
+    sim.write(vec![insert("a", 1), insert("b", 2), Command::delete("k0")])
+        .unwrap();
+    assert!(sim.run_until(500, settled));
+    assert_same_data(&sim);
+    let db = sim.node(3).database();
+    assert_eq!(db.count(), 21);
+    assert_eq!(db.get("b"), Some(Value::Integer(2)));
+    assert_eq!(db.get("k0"), None);
+}
+
+#[test]
+fn followers_refuse_writes_and_reads() {
+    let mut sim = Simulator::new(3, 3);
+    assert!(sim.run_until(1000, |sim| sim.leader().is_some()));
+    sim.run(20);
+    let leader = sim.leader().unwrap();
+    let follower = (1..=3).find(|id| *id != leader).unwrap();
+    let node = sim.node_mut(follower);
+    assert_eq!(
+        node.propose(vec![insert("k", 1)]),
+        Err(RaftError::NotLeader(Some(leader)))
+    );
+    assert_eq!(node.read(), Err(RaftError::NotLeader(Some(leader))));
+}
+
+#[test]
+fn elects_a_new_leader_when_the_leader_crashes() {
+    let mut sim = Simulator::new(3, 4);
+    sim.write(vec![insert("before", 1)]).unwrap();
+    let old = sim.leader().unwrap();
+    let old_term = sim.node(old).status().term;
+    sim.crash(old);
+    sim.write(vec![insert("after", 2)]).unwrap();
+    let new = sim.leader().unwrap();
+    assert_ne!(new, old);
+    assert!(sim.node(new).status().term > old_term);
+    assert_eq!(sim.read(|db| db.get("before")), Ok(Some(Value::Integer(1))));
+
+    // The old leader comes back as a follower and catches up
+    sim.restart(old);
+    assert!(sim.run_until(1000, settled));
+    assert_eq!(sim.node(old).role(), Role::Follower);
+    assert_eq!(
+        sim.node(old).database().get("after"),
+        Some(Value::Integer(2))
+    );
+    assert_same_data(&sim);
+}
+
+#[test]
+fn a_minority_cannot_write_and_a_majority_can() {
+    let mut sim = Simulator::new(5, 5);
+    sim.write(vec![insert("k", 1)]).unwrap();
+    let old = sim.leader().unwrap();
+    let others: Vec<u64> = (1..=5).filter(|id| *id != old).collect();
+    let buddy = others[0];
+    sim.partition(&[&[old, buddy], &others[1..]]);
+
+    // The stranded leader takes the write but can't commit it
+    let ticket = sim.node_mut(old).propose(vec![insert("k", 2)]).unwrap();
+    sim.run(300);
+    assert!(sim.node(old).status().commit < ticket.index);
+    assert_eq!(sim.node(old).database().get("k"), Some(Value::Integer(1)));
+
+    // The majority elects its own leader and goes on
+    let new = sim.leader().unwrap();
+    assert!(others[1..].contains(&new));
+    sim.write(vec![insert("k", 3)]).unwrap();
+
+    // Healed, the old leader's write is replaced
+    sim.heal();
+    assert!(sim.run_until(1000, settled));
+    assert_same_data(&sim);
+    assert_eq!(sim.read(|db| db.get("k")), Ok(Some(Value::Integer(3))));
+    assert_eq!(sim.node(old).role(), Role::Follower);
+}
+
+#[test]
+fn survives_message_loss_and_delay() {
+    let mut sim = Simulator::new(5, 6);
+    sim.set_loss(0.2);
+    sim.set_delay(1, 5);
+    for i in 0..30 {
+        // A write lost along with its leader is written again
+        loop {
+            match sim.write(vec![insert(&format!("k{}", i), i)]) {
+                Ok(_) => break,
+                Err(RaftError::Dropped | RaftError::NotLeader(_)) => continue,
+                Err(e) => panic!("write {}: {}", i, e),
+            }
+        }
+    }
+    sim.set_loss(0.0);
+    assert!(sim.run_until(2000, settled));
+    assert_same_data(&sim);
+    assert_eq!(sim.node(1).database().count(), 30);
+}
+
+#[test]
+fn compacts_the_log_and_catches_up_with_a_snapshot() {
+    let config = RaftConfig {
+        snapshot_after: 10,
+        seed: 7,
+        ..RaftConfig::default()
+    };
+    let mut sim = Simulator::with_config(3, config);
+    sim.write(vec![insert("first", 0)]).unwrap();
+    let leader = sim.leader().unwrap();
+    let lagging = (1..=3).find(|id| *id != leader).unwrap();
+    sim.crash(lagging);
+    for i in 0..50 {
+        sim.write(vec![insert(&format!("k{}", i), i)]).unwrap();
+    }
+    let status = sim.node(leader).status();
+    assert!(status.snapshot_index > 40, "{:?}", status);
+    assert!(status.last_index - status.snapshot_index <= 10);
+
+    // Too far behind for the log: it's sent the snapshot
+    sim.restart(lagging);
+    assert!(sim.run_until(1000, settled));
+    assert!(sim.node(lagging).status().snapshot_index > 40);
+    assert_same_data(&sim);
+    assert_eq!(sim.node(lagging).database().count(), 51);
+
+    // A restarted node comes back from its own snapshot and log
+    sim.crash(leader);
+    sim.restart(leader);
+    assert!(sim.run_until(1000, settled));
+    assert_eq!(sim.node(leader).database().count(), 51);
+}
+
+#[test]
+fn reads_see_every_finished_write() {
+    let mut sim = Simulator::new(3, 8);
+    for i in 0..10 {
+        sim.write(vec![insert("counter", i)]).unwrap();
+        assert_eq!(
+            sim.read(|db| db.get("counter")),
+            Ok(Some(Value::Integer(i)))
+        );
+    }
+
+    // A leader cut off from the others (its messages go nowhere) can't
+    // confirm it still leads
+    let leader = sim.leader().unwrap();
+    let node = sim.node_mut(leader);
+    let read = node.read().unwrap();
+    for _ in 0..300 {
+        node.tick();
+        node.take_messages();
+        assert!(!node.take_events().contains(&Event::ReadReady(read)));
+    }
+}
+
+#[test]
+fn adds_and_removes_members() {
+    let mut sim = Simulator::new(3, 9);
+    sim.write(vec![insert("k", 1)]).unwrap();
+
+    sim.add_node(4);
+    sim.add_member(4).unwrap();
+    sim.add_node(5);
+    sim.add_member(5).unwrap();
+    assert!(sim.run_until(1000, settled));
+    let leader = sim.leader().unwrap();
+    assert_eq!(sim.node(leader).status().members.len(), 5);
+    assert_eq!(sim.node(5).database().get("k"), Some(Value::Integer(1)));
+    assert_eq!(sim.node(5).status().members.len(), 5);
+
+    // Removing the leader: it steps down once the change is committed
+    sim.remove_member(leader).unwrap();
+    sim.crash(leader);
+    sim.write(vec![insert("k", 2)]).unwrap();
+    let new = sim.leader().unwrap();
+    assert_ne!(new, leader);
+    let members = sim.node(new).status().members;
+    assert_eq!(members.len(), 4);
+    assert!(!members.contains_key(&leader));
+    assert!(sim.run_until(1000, settled));
+    assert_same_data(&sim);
+}
+
+#[test]
+fn applies_ttls_and_collections() {
+    let mut sim = Simulator::new(3, 10);
+    sim.write(vec![
+        Command::CreateCollection {
+            name: "users".to_string(),
+            options: CollectionOptions::new(),
+        },
+        Command::Insert {
+            collection: Some("users".to_string()),
+            key: "ann".to_string(),
+            value: Value::Integer(30),
+            ttl: None,
+        },
+        Command::Insert {
+            collection: None,
+            key: "session".to_string(),
+            value: Value::Integer(1),
+            ttl: Some(Duration::from_millis(1)),
+        },
+    ])
+    .unwrap();
+    assert!(sim.run_until(500, settled));
+    thread::sleep(Duration::from_millis(20));
+    for id in 1..=3 {
+        let db = sim.node(id).database();
+        assert!(db.has_collection("users"));
+        assert_eq!(db.get("session"), None);
+    }
+
+    sim.write(vec![Command::DropCollection {
+        name: "users".to_string(),
+    }])
+    .unwrap();
+    assert!(sim.run_until(500, settled));
+    assert!(!sim.node(1).database().has_collection("users"));
+}
+
+#[test]
+fn runs_the_same_way_from_the_same_seed() {
+    let run = |seed| {
+        let mut sim = Simulator::new(5, seed);
+        sim.set_loss(0.1);
+        sim.set_delay(1, 4);
+        for i in 0..10 {
+            let _ = sim.write(vec![insert(&format!("k{}", i), i)]);
+        }
+        sim.run(100);
+        (1..=5).map(|id| sim.node(id).status()).collect::<Vec<_>>()
+    };
+    assert_eq!(run(11), run(11));
+}
+
+// ---------- Over TCP ----------
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn scratch(name: &str) -> Database {
+    let path =
+        std::env::temp_dir().join(format!("littledb-raft-{}-{}.db", std::process::id(), name));
+    for ext in ["", ".raft", ".raft-snapshot"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), ext));
+    }
+    Database::new(path.to_str().unwrap())
+}
+
+fn await_leader(nodes: &[&RaftHandle]) -> RaftHandle {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Some(node) = nodes.iter().find(|node| node.status().role == Role::Leader) {
+            return (*node).clone();
+        }
+        assert!(Instant::now() < deadline, "no leader elected");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn runs_a_cluster_over_tcp() {
+    let config = RaftConfig {
+        tick: Duration::from_millis(10),
+        ..RaftConfig::default()
+    };
+    let members: BTreeMap<u64, String> = (1..=3)
+        .map(|id| (id, format!("127.0.0.1:{}", free_port())))
+        .collect();
+    let nodes: Vec<RaftHandle> = (1..=3)
+        .map(|id| {
+            RaftHandle::start(
+                id,
+                members.clone(),
+                scratch(&format!("node{}", id)),
+                config.clone(),
+            )
+            .unwrap()
+        })
+        .collect();
+    let leader = await_leader(&nodes.iter().collect::<Vec<_>>());
+    leader.write(vec![insert("k", 1)]).unwrap();
+    assert_eq!(leader.read(|db| db.get("k")), Ok(Some(Value::Integer(1))));
+
+    let follower = nodes.iter().find(|node| node.id() != leader.id()).unwrap();
+    assert_eq!(
+        follower.write(vec![insert("k", 2)]),
+        Err(RaftError::NotLeader(Some(leader.id())))
+    );
+
+    // A fourth node joins
+    let addr = format!("127.0.0.1:{}", free_port());
+    let joiner = RaftHandle::join(4, &addr, scratch("node4"), config.clone()).unwrap();
+    leader.add_member(4, &addr).unwrap();
+    leader.write(vec![insert("k", 3)]).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while joiner.status().applied < leader.status().commit {
+        assert!(Instant::now() < deadline, "the new node didn't catch up");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(joiner.status().members.len(), 4);
+
+    // The leader goes; the others elect another
+    leader.shutdown();
+    assert!(matches!(
+        leader.write(vec![insert("k", 4)]),
+        Err(RaftError::Io(_))
+    ));
+    let rest: Vec<&RaftHandle> = nodes
+        .iter()
+        .chain([&joiner])
+        .filter(|node| node.id() != leader.id())
+        .collect();
+    let new = await_leader(&rest);
+    new.write(vec![insert("k", 5)]).unwrap();
+    assert_eq!(new.read(|db| db.get("k")), Ok(Some(Value::Integer(5))));
+    for node in nodes.iter().chain([&joiner]) {
+        node.shutdown();
+    }
+}
@@ -0,0 +1,290 @@
+// Primaries and followers on localhost: in this process, and as separate
+// littledb-server processes
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{Client, Resp, bulk, ok, open, temp_db};
+use littledb::replication::Role;
+use littledb::resp::Server;
+use littledb::{Database, ReplicationHandle, Value};
+
+impl Client {
+    fn info(&mut self) -> String {
+        match self.call(&["INFO"]) {
+            Resp::Bulk(info) => info,
+            other => panic!("INFO answered {:?}", other),
+        }
+    }
+}
+
+struct Running {
+    addr: SocketAddr,
+    replication_addr: Option<SocketAddr>,
+    replication: ReplicationHandle,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Running {
+    fn client(&self) -> Client {
+        Client::connect(self.addr)
+    }
+
+    // SHUTDOWN saves (a follower's position included), NOSAVE doesn't
+    fn stop(self, how: &[&str]) {
+        assert_eq!(self.client().call(how), ok());
+        self.handle.join().unwrap();
+    }
+}
+
+fn start(db: Database, replicate: bool, primary: Option<SocketAddr>) -> Running {
+    let mut server = Server::bind("127.0.0.1:0", db).unwrap().save_interval(None);
+    if replicate {
+        server = server.replicate_on("127.0.0.1:0").unwrap();
+    }
+    if let Some(primary) = primary {
+        server = server.follow(&primary.to_string());
+    }
+    let addr = server.local_addr().unwrap();
+    let replication_addr = server.replication_addr();
+    let replication = server.replication_handle();
+    Running {
+        addr,
+        replication_addr,
+        replication,
+        handle: thread::spawn(move || server.run().unwrap()),
+    }
+}
+
+fn start_primary(db: Database) -> (Running, SocketAddr) {
+    let primary = start(db, true, None);
+    let addr = primary.replication_addr.unwrap();
+    (primary, addr)
+}
+
+// Replication is asynchronous: poll until it has caught up
+fn eventually(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn wait_for(client: &mut Client, key: &str, expected: Resp) {
+    eventually(&format!("{} = {:?}", key, expected), || {
+        client.call(&["GET", key]) == expected
+    });
+}
+
+#[test]
+fn follower_gets_a_snapshot_then_the_changes() {
+    let mut db = open(&temp_db());
+    db.insert("before".to_string(), Value::String("snapshot".to_string()))
+        .unwrap();
+    db.collection("users")
+        .insert("u1".to_string(), Value::Integer(1))
+        .unwrap();
+    let (primary, primary_addr) = start_primary(db);
+    let follower_path = temp_db();
+    let follower = start(open(&follower_path), false, Some(primary_addr));
+    let mut p = primary.client();
+    let mut f = follower.client();
+
+    wait_for(&mut f, "before", bulk("snapshot"));
+
+    assert_eq!(p.call(&["SET", "after", "streamed"]), ok());
+    assert_eq!(p.call(&["SET", "session", "s", "EX", "100"]), ok());
+    assert_eq!(p.call(&["DEL", "before"]), Resp::Integer(1));
+    wait_for(&mut f, "before", Resp::Null);
+    assert_eq!(f.call(&["GET", "after"]), bulk("streamed"));
+    assert!(matches!(
+        f.call(&["TTL", "session"]),
+        Resp::Integer(99 | 100)
+    ));
+    assert_eq!(p.call(&["PERSIST", "session"]), Resp::Integer(1));
+    eventually("PERSIST", || {
+        f.call(&["TTL", "session"]) == Resp::Integer(-1)
+    });
+
+    primary.stop(&["SHUTDOWN", "NOSAVE"]);
+    follower.stop(&["SHUTDOWN"]);
+    let mut copy = open(&follower_path);
+    assert_eq!(copy.collection("users").get("u1"), Some(Value::Integer(1)));
+}
+
+#[test]
+fn followers_refuse_writes_and_report_lag() {
+    let (primary, primary_addr) = start_primary(open(&temp_db()));
+    let follower = start(open(&temp_db()), false, Some(primary_addr));
+    let mut p = primary.client();
+    let mut f = follower.client();
+
+    assert_eq!(p.call(&["SET", "k", "v"]), ok());
+    wait_for(&mut f, "k", bulk("v"));
+    assert!(matches!(
+        f.call(&["SET", "k", "mine"]),
+        Resp::Error(message) if message.starts_with("READONLY")
+    ));
+    assert!(matches!(f.call(&["DEL", "k"]), Resp::Error(_)));
+    assert_eq!(f.call(&["GET", "k"]), bulk("v"));
+
+    eventually("the follower's acknowledgement", || {
+        let stats = primary.replication.stats().unwrap();
+        stats.followers.len() == 1 && stats.followers[0].lag == 0
+    });
+    let stats = follower.replication.stats().unwrap();
+    assert_eq!(stats.role, Role::Follower);
+    assert!(stats.connected);
+    assert_eq!(stats.lag, 0);
+    assert_eq!(stats.primary, Some(primary_addr.to_string()));
+    assert_eq!(stats.id, primary.replication.stats().unwrap().id);
+
+    let info = f.info();
+    assert!(info.contains("role:slave\r\n"), "{}", info);
+    assert!(info.contains("master_link_status:up\r\n"), "{}", info);
+    let info = p.info();
+    assert!(info.contains("role:master\r\n"), "{}", info);
+    assert!(info.contains("connected_slaves:1\r\n"), "{}", info);
+
+    follower.stop(&["SHUTDOWN", "NOSAVE"]);
+    primary.stop(&["SHUTDOWN", "NOSAVE"]);
+}
+
+#[test]
+fn a_restarted_follower_catches_up() {
+    let (primary, primary_addr) = start_primary(open(&temp_db()));
+    let follower_path = temp_db();
+    let follower = start(open(&follower_path), false, Some(primary_addr));
+    let mut p = primary.client();
+
+    assert_eq!(p.call(&["SET", "one", "1"]), ok());
+    wait_for(&mut follower.client(), "one", bulk("1"));
+    follower.stop(&["SHUTDOWN"]);
+
+    assert_eq!(p.call(&["SET", "two", "2"]), ok());
+    assert_eq!(p.call(&["DEL", "one"]), Resp::Integer(1));
+    let follower = start(open(&follower_path), false, Some(primary_addr));
+    let mut f = follower.client();
+    wait_for(&mut f, "two", bulk("2"));
+    assert_eq!(f.call(&["GET", "one"]), Resp::Null);
+    assert_eq!(
+        follower.replication.stats().unwrap().lsn,
+        primary.replication.stats().unwrap().lsn
+    );
+
+    follower.stop(&["SHUTDOWN", "NOSAVE"]);
+    primary.stop(&["SHUTDOWN", "NOSAVE"]);
+}
+
+#[test]
+fn promotion_and_re_pointing() {
+    let (primary, primary_addr) = start_primary(open(&temp_db()));
+    let a = start(open(&temp_db()), true, Some(primary_addr));
+    let b = start(open(&temp_db()), false, Some(primary_addr));
+    let mut p = primary.client();
+    let mut ca = a.client();
+    let mut cb = b.client();
+
+    assert_eq!(p.call(&["SET", "k", "from the old primary"]), ok());
+    wait_for(&mut ca, "k", bulk("from the old primary"));
+    wait_for(&mut cb, "k", bulk("from the old primary"));
+    primary.stop(&["SHUTDOWN", "NOSAVE"]);
+
+    assert_eq!(ca.call(&["REPLICAOF", "NO", "ONE"]), ok());
+    assert_eq!(a.replication.stats().unwrap().role, Role::Primary);
+    assert!(!a.replication.promote()); // already
+    assert_eq!(ca.call(&["SET", "k", "from the new primary"]), ok());
+
+    let a_addr = a.replication_addr.unwrap();
+    let port = a_addr.port().to_string();
+    assert_eq!(cb.call(&["REPLICAOF", "127.0.0.1", &port]), ok());
+    wait_for(&mut cb, "k", bulk("from the new primary"));
+    assert!(matches!(cb.call(&["SET", "k", "x"]), Resp::Error(_)));
+    assert_eq!(
+        b.replication.stats().unwrap().id,
+        a.replication.stats().unwrap().id
+    );
+
+    b.stop(&["SHUTDOWN", "NOSAVE"]);
+    a.stop(&["SHUTDOWN", "NOSAVE"]);
+}
+
+// ---------- Separate processes ----------
+
+struct Process {
+    child: Child,
+    lines: std::io::Lines<BufReader<std::process::ChildStdout>>,
+}
+
+impl Process {
+    fn spawn(args: &[&str]) -> Process {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_littledb-server"))
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        Process { child, lines }
+    }
+
+    // The address at the end of the next line starting with `prefix`
+    fn address(&mut self, prefix: &str) -> SocketAddr {
+        for line in &mut self.lines {
+            let line = line.unwrap();
+            if line.starts_with(prefix) {
+                return line.rsplit(' ').next().unwrap().parse().unwrap();
+            }
+        }
+        panic!("littledb-server never printed {:?}", prefix);
+    }
+
+    // The process may exit before the reply is out, so there may be none
+    fn shut_down(mut self, addr: SocketAddr) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"*2\r\n$8\r\nSHUTDOWN\r\n$6\r\nNOSAVE\r\n")
+            .unwrap();
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+#[test]
+fn separate_processes() {
+    let primary_path = temp_db();
+    let mut primary = Process::spawn(&[
+        "--db",
+        &primary_path,
+        "--port",
+        "0",
+        "--replicate-on",
+        "127.0.0.1:0",
+    ]);
+    let primary_addr = primary.address("littledb-server: '");
+    let replication_addr = primary.address("littledb-server: replication on");
+
+    let follower_path = temp_db();
+    let mut follower = Process::spawn(&[
+        "--db",
+        &follower_path,
+        "--port",
+        "0",
+        "--follow",
+        &replication_addr.to_string(),
+    ]);
+    let follower_addr = follower.address("littledb-server: '");
+
+    let mut p = Client::connect(primary_addr);
+    let mut f = Client::connect(follower_addr);
+    assert_eq!(p.call(&["SET", "shared", "across processes"]), ok());
+    wait_for(&mut f, "shared", bulk("across processes"));
+    assert!(matches!(f.call(&["SET", "x", "y"]), Resp::Error(_)));
+
+    follower.shut_down(follower_addr);
+    primary.shut_down(primary_addr);
+}
@@ -0,0 +1,504 @@
+// The Redis-protocol server, driven over localhost with a small RESP client
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+
+use common::{Client, Resp, bulk, ok, open, temp_db};
+use littledb::resp::Server;
+use littledb::{Database, Value};
+
+fn start(db: Database) -> (SocketAddr, thread::JoinHandle<()>) {
+    let server = Server::bind("127.0.0.1:0", db).unwrap().save_interval(None);
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || server.run().unwrap());
+    (addr, handle)
+}
+
+fn start_fresh() -> (SocketAddr, thread::JoinHandle<()>) {
+    start(open(&temp_db()))
+}
+
+fn stop(addr: SocketAddr, handle: thread::JoinHandle<()>) {
+    let reply = Client::connect(addr).call(&["SHUTDOWN", "NOSAVE"]);
+    assert_eq!(reply, ok());
+    handle.join().unwrap();
+}
+
+fn is_error(reply: &Resp, start: &str) -> bool {
+    matches!(reply, Resp::Error(message) if message.starts_with(start))
+}
+
+#[test]
+fn strings_get_set_del_exists() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    assert_eq!(c.call(&["PING"]), Resp::Simple("PONG".to_string()));
+    assert_eq!(c.call(&["ECHO", "hi"]), bulk("hi"));
+    assert_eq!(c.call(&["GET", "missing"]), Resp::Null);
+    assert_eq!(c.call(&["SET", "greeting", "hello world"]), ok());
+    assert_eq!(c.call(&["GET", "greeting"]), bulk("hello world"));
+    assert_eq!(c.call(&["set", "other", "x"]), ok()); // commands are case-insensitive
+    assert_eq!(
+        c.call(&["EXISTS", "greeting", "other", "missing", "greeting"]),
+        Resp::Integer(3)
+    );
+    assert_eq!(c.call(&["DEL", "greeting", "missing"]), Resp::Integer(1));
+    assert_eq!(c.call(&["GET", "greeting"]), Resp::Null);
+    assert_eq!(c.call(&["DBSIZE"]), Resp::Integer(1));
+
+    stop(addr, handle);
+}
+
+#[test]
+fn set_options() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    assert_eq!(c.call(&["SET", "k", "1", "NX"]), ok());
+    assert_eq!(c.call(&["SET", "k", "2", "NX"]), Resp::Null);
+    assert_eq!(c.call(&["GET", "k"]), bulk("1"));
+    assert_eq!(c.call(&["SET", "k", "3", "XX"]), ok());
+    assert_eq!(c.call(&["SET", "absent", "3", "XX"]), Resp::Null);
+    assert_eq!(c.call(&["EXISTS", "absent"]), Resp::Integer(0));
+
+    assert_eq!(c.call(&["SET", "session", "s", "EX", "100"]), ok());
+    let ttl = c.call(&["TTL", "session"]);
+    assert!(matches!(ttl, Resp::Integer(99 | 100)), "{:?}", ttl);
+    assert_eq!(c.call(&["SET", "session", "t", "KEEPTTL"]), ok());
+    assert!(matches!(
+        c.call(&["TTL", "session"]),
+        Resp::Integer(99 | 100)
+    ));
+    assert_eq!(c.call(&["SET", "session", "u"]), ok()); // a plain SET drops the TTL
+    assert_eq!(c.call(&["TTL", "session"]), Resp::Integer(-1));
+
+    assert_eq!(c.call(&["SET", "brief", "b", "PX", "50"]), ok());
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(c.call(&["GET", "brief"]), Resp::Null);
+
+    assert!(is_error(
+        &c.call(&["SET", "k", "v", "NX", "XX"]),
+        "ERR syntax"
+    ));
+    assert!(is_error(
+        &c.call(&["SET", "k", "v", "EX", "0"]),
+        "ERR invalid expire"
+    ));
+    assert!(is_error(&c.call(&["SET", "k", "v", "EX"]), "ERR syntax"));
+    assert!(is_error(&c.call(&["SET", "k", "v", "BOGUS"]), "ERR syntax"));
+
+    stop(addr, handle);
+}
+
+#[test]
+fn counters() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    assert_eq!(c.call(&["INCR", "hits"]), Resp::Integer(1));
+    assert_eq!(c.call(&["INCRBY", "hits", "10"]), Resp::Integer(11));
+    assert_eq!(c.call(&["DECR", "hits"]), Resp::Integer(10));
+    assert_eq!(c.call(&["DECRBY", "hits", "4"]), Resp::Integer(6));
+    assert_eq!(c.call(&["GET", "hits"]), bulk("6"));
+
+    // A number stored with SET counts too, and keeps its TTL
+    assert_eq!(c.call(&["SET", "n", "41", "EX", "100"]), ok());
+    assert_eq!(c.call(&["INCR", "n"]), Resp::Integer(42));
+    assert!(matches!(c.call(&["TTL", "n"]), Resp::Integer(99 | 100)));
+
+    assert_eq!(c.call(&["SET", "word", "abc"]), ok());
+    assert!(is_error(
+        &c.call(&["INCR", "word"]),
+        "ERR value is not an integer"
+    ));
+    assert!(is_error(
+        &c.call(&["INCRBY", "hits", "x"]),
+        "ERR value is not an integer"
+    ));
+
+    stop(addr, handle);
+}
+
+#[test]
+fn concurrent_increments_are_not_lost() {
+    let (addr, handle) = start_fresh();
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            thread::spawn(move || {
+                let mut c = Client::connect(addr);
+                for _ in 0..250 {
+                    assert!(matches!(c.call(&["INCR", "shared"]), Resp::Integer(_)));
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(Client::connect(addr).call(&["GET", "shared"]), bulk("2000"));
+    stop(addr, handle);
+}
+
+#[test]
+fn expiry_commands() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    c.call(&["SET", "k", "v"]);
+    assert_eq!(c.call(&["TTL", "k"]), Resp::Integer(-1));
+    assert_eq!(c.call(&["TTL", "missing"]), Resp::Integer(-2));
+    assert_eq!(c.call(&["PTTL", "missing"]), Resp::Integer(-2));
+    assert_eq!(c.call(&["EXPIRE", "k", "60"]), Resp::Integer(1));
+    assert!(matches!(c.call(&["TTL", "k"]), Resp::Integer(59 | 60)));
+    let pttl = c.call(&["PTTL", "k"]);
+    assert!(
+        matches!(pttl, Resp::Integer(ms) if ms > 59_000 && ms <= 60_000),
+        "{:?}",
+        pttl
+    );
+    assert_eq!(c.call(&["PERSIST", "k"]), Resp::Integer(1));
+    assert_eq!(c.call(&["PERSIST", "k"]), Resp::Integer(0));
+    assert_eq!(c.call(&["TTL", "k"]), Resp::Integer(-1));
+    assert_eq!(c.call(&["EXPIRE", "missing", "60"]), Resp::Integer(0));
+
+    assert_eq!(c.call(&["PEXPIRE", "k", "40"]), Resp::Integer(1));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(c.call(&["EXISTS", "k"]), Resp::Integer(0));
+
+    c.call(&["SET", "gone", "v"]);
+    assert_eq!(c.call(&["EXPIRE", "gone", "0"]), Resp::Integer(1));
+    assert_eq!(c.call(&["GET", "gone"]), Resp::Null);
+
+    stop(addr, handle);
+}
+
+#[test]
+fn mget_mset() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    assert_eq!(c.call(&["MSET", "a", "1", "b", "2", "c", "3"]), ok());
+    assert_eq!(
+        c.call(&["MGET", "a", "missing", "c"]),
+        Resp::Array(vec![bulk("1"), Resp::Null, bulk("3")])
+    );
+    assert!(is_error(
+        &c.call(&["MSET", "a", "1", "b"]),
+        "ERR wrong number"
+    ));
+
+    stop(addr, handle);
+}
+
+#[test]
+fn keys_and_scan() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+    for i in 0..25 {
+        c.call(&["SET", &format!("user:{:02}", i), "x"]);
+    }
+    c.call(&["SET", "order:1", "x"]);
+    c.call(&["SET", "user:ab", "x"]);
+
+    let Resp::Array(all) = c.call(&["KEYS", "*"]) else {
+        panic!("KEYS should return an array")
+    };
+    assert_eq!(all.len(), 27);
+    assert_eq!(
+        c.call(&["KEYS", "user:1?"]),
+        Resp::Array((10..20).map(|i| bulk(&format!("user:{}", i))).collect())
+    );
+    assert_eq!(
+        c.call(&["KEYS", "user:[a-z]*"]),
+        Resp::Array(vec![bulk("user:ab")])
+    );
+    assert_eq!(c.call(&["KEYS", "*:1"]), Resp::Array(vec![bulk("order:1")]));
+    assert_eq!(c.call(&["KEYS", "nothing*"]), Resp::Array(vec![]));
+
+    // SCAN visits every matching key exactly once
+    let mut seen = Vec::new();
+    let mut cursor = "0".to_string();
+    let mut rounds = 0;
+    loop {
+        let reply = c.call(&["SCAN", &cursor, "MATCH", "user:*", "COUNT", "7"]);
+        let Resp::Array(parts) = reply else {
+            panic!("SCAN should return an array")
+        };
+        let [Resp::Bulk(next), Resp::Array(keys)] = &parts[..] else {
+            panic!("unexpected SCAN reply {:?}", parts)
+        };
+        seen.extend(keys.iter().cloned());
+        cursor = next.clone();
+        rounds += 1;
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), 26);
+    assert!(rounds >= 4);
+    assert!(is_error(&c.call(&["SCAN", "12345"]), "ERR invalid cursor"));
+
+    stop(addr, handle);
+}
+
+#[test]
+fn pipelined_commands_are_answered_in_order() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    let mut batch = String::new();
+    for i in 0..500 {
+        let value = i.to_string();
+        batch.push_str(&format!(
+            "*3\r\n$3\r\nSET\r\n${}\r\nk{}\r\n${}\r\n{}\r\n",
+            value.len() + 1,
+            value,
+            value.len(),
+            value
+        ));
+        batch.push_str(&format!(
+            "*2\r\n$3\r\nGET\r\n${}\r\nk{}\r\n",
+            value.len() + 1,
+            value
+        ));
+    }
+    c.writer.write_all(batch.as_bytes()).unwrap();
+    for i in 0..500 {
+        assert_eq!(c.read(), ok());
+        assert_eq!(c.read(), bulk(&i.to_string()));
+    }
+
+    stop(addr, handle);
+}
+
+#[test]
+fn inline_commands_and_protocol_errors() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+    c.writer
+        .write_all(b"SET inline yes\r\nGET inline\r\n")
+        .unwrap();
+    assert_eq!(c.read(), ok());
+    assert_eq!(c.read(), bulk("yes"));
+
+    assert!(is_error(&c.call(&["NOSUCHCOMMAND"]), "ERR unknown command"));
+    assert!(is_error(&c.call(&["GET"]), "ERR wrong number of arguments"));
+
+    // Malformed input gets an error and the connection is closed
+    c.writer.write_all(b"*1\r\n+oops\r\n").unwrap();
+    assert!(is_error(&c.read(), "ERR Protocol error"));
+    let mut rest = Vec::new();
+    assert_eq!(c.reader.read_to_end(&mut rest).unwrap(), 0);
+
+    stop(addr, handle);
+}
+
+#[test]
+fn resp3_hello() {
+    let (addr, handle) = start_fresh();
+    let mut c = Client::connect(addr);
+
+    // RESP2 until the client asks for more
+    assert_eq!(c.call(&["GET", "missing"]), Resp::Null);
+    let Resp::Map(info) = c.call(&["HELLO", "3"]) else {
+        panic!("HELLO 3 should answer with a map")
+    };
+    assert!(info.contains(&(bulk("proto"), Resp::Integer(3))));
+    c.send(&["GET", "missing"]);
+    assert_eq!(c.line(), "_");
+    assert!(is_error(&c.call(&["HELLO", "4"]), "NOPROTO"));
+
+    // HELLO 2 goes back: the map becomes a flat array
+    let Resp::Array(flat) = c.call(&["HELLO", "2"]) else {
+        panic!("HELLO 2 should answer with an array")
+    };
+    assert_eq!(flat.len() % 2, 0);
+    c.send(&["GET", "missing"]);
+    assert_eq!(c.line(), "$-1");
+
+    stop(addr, handle);
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn values_written_elsewhere_read_as_text() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.insert("count".to_string(), Value::Integer(7)).unwrap();
+    db.insert(
+        "doc".to_string(),
+        Value::from_json_str(r#"{"name": "Alice", "tags": ["a"]}"#).unwrap(),
+    )
+    .unwrap();
+    let (addr, handle) = start(db);
+    let mut c = Client::connect(addr);
+
+    assert_eq!(c.call(&["GET", "count"]), bulk("7"));
+    assert_eq!(
+        c.call(&["GET", "doc"]),
+        bulk(r#"{"name":"Alice","tags":["a"]}"#)
+    );
+    assert!(is_error(&c.call(&["INCR", "doc"]), "WRONGTYPE"));
+
+    stop(addr, handle);
+}
+
+#[test]
+fn save_and_shutdown_persist_changes() {
+    let path = temp_db();
+    let (addr, handle) = start(open(&path));
+    let mut c = Client::connect(addr);
+    c.call(&["SET", "saved", "1"]);
+    assert_eq!(c.call(&["SAVE"]), ok());
+    assert_eq!(
+        open(&path).get("saved"),
+        Some(Value::String("1".to_string()))
+    );
+
+    c.call(&["SET", "at-shutdown", "2"]);
+    assert_eq!(c.call(&["SHUTDOWN"]), ok());
+    handle.join().unwrap();
+    let db = open(&path);
+    assert_eq!(db.get("at-shutdown"), Some(Value::String("2".to_string())));
+
+    // NOSAVE drops what wasn't saved
+    let (addr, handle) = start(db);
+    Client::connect(addr).call(&["SET", "lost", "3"]);
+    stop(addr, handle);
+    assert_eq!(open(&path).get("lost"), None);
+}
+
+#[test]
+fn background_saves() {
+    let path = temp_db();
+    let server = Server::bind("127.0.0.1:0", open(&path))
+        .unwrap()
+        .save_interval(Some(Duration::from_millis(50)));
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || server.run().unwrap());
+
+    Client::connect(addr).call(&["SET", "k", "v"]);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(open(&path).get("k"), Some(Value::String("v".to_string())));
+    stop(addr, handle);
+}
+
+fn strings(items: &[&str]) -> Vec<Resp> {
+    items.iter().map(|item| bulk(item)).collect()
+}
+
+#[test]
+fn subscribe_and_publish() {
+    let (addr, handle) = start_fresh();
+    let mut publisher = Client::connect(addr);
+    let mut a = Client::connect(addr);
+    let mut b = Client::connect(addr);
+
+    a.send(&["SUBSCRIBE", "news", "sport"]);
+    let mut confirmation = strings(&["subscribe", "news"]);
+    confirmation.push(Resp::Integer(1));
+    assert_eq!(a.read(), Resp::Array(confirmation));
+    let mut confirmation = strings(&["subscribe", "sport"]);
+    confirmation.push(Resp::Integer(2));
+    assert_eq!(a.read(), Resp::Array(confirmation));
+    let mut confirmation = strings(&["psubscribe", "new*"]);
+    confirmation.push(Resp::Integer(1));
+    assert_eq!(b.call(&["PSUBSCRIBE", "new*"]), Resp::Array(confirmation));
+
+    assert_eq!(
+        publisher.call(&["PUBLISH", "news", "hello"]),
+        Resp::Integer(2)
+    );
+    assert_eq!(
+        publisher.call(&["PUBLISH", "sport", "goal"]),
+        Resp::Integer(1)
+    );
+    assert_eq!(
+        publisher.call(&["PUBLISH", "weather", "rain"]),
+        Resp::Integer(0)
+    );
+    assert_eq!(
+        a.read(),
+        Resp::Array(strings(&["message", "news", "hello"]))
+    );
+    assert_eq!(
+        a.read(),
+        Resp::Array(strings(&["message", "sport", "goal"]))
+    );
+    assert_eq!(
+        b.read(),
+        Resp::Array(strings(&["pmessage", "new*", "news", "hello"]))
+    );
+
+    assert_eq!(
+        publisher.call(&["PUBSUB", "CHANNELS"]),
+        Resp::Array(strings(&["news", "sport"]))
+    );
+    let mut counts = strings(&["news"]);
+    counts.push(Resp::Integer(1));
+    assert_eq!(
+        publisher.call(&["PUBSUB", "NUMSUB", "news"]),
+        Resp::Array(counts)
+    );
+    assert_eq!(publisher.call(&["PUBSUB", "NUMPAT"]), Resp::Integer(1));
+
+    // Subscribed, a RESP2 connection only handles subscriptions and PING
+    assert!(is_error(&a.call(&["GET", "k"]), "ERR Can't execute 'get'"));
+    assert_eq!(a.call(&["PING"]), Resp::Array(strings(&["pong", ""])));
+    a.send(&["UNSUBSCRIBE"]);
+    let mut confirmation = strings(&["unsubscribe", "news"]);
+    confirmation.push(Resp::Integer(1));
+    assert_eq!(a.read(), Resp::Array(confirmation));
+    let mut confirmation = strings(&["unsubscribe", "sport"]);
+    confirmation.push(Resp::Integer(0));
+    assert_eq!(a.read(), Resp::Array(confirmation));
+    assert_eq!(a.call(&["GET", "k"]), Resp::Null);
+    assert_eq!(
+        publisher.call(&["PUBLISH", "news", "again"]),
+        Resp::Integer(1)
+    );
+
+    // A closed connection's subscriptions end with it
+    drop(b);
+    for _ in 0..100 {
+        if publisher.call(&["PUBSUB", "NUMPAT"]) == Resp::Integer(0) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        publisher.call(&["PUBLISH", "news", "gone"]),
+        Resp::Integer(0)
+    );
+
+    stop(addr, handle);
+}
+
+#[test]
+fn resp3_subscribers_get_pushes_between_replies() {
+    let (addr, handle) = start_fresh();
+    let mut publisher = Client::connect(addr);
+    let mut c = Client::connect(addr);
+    let Resp::Map(_) = c.call(&["HELLO", "3"]) else {
+        panic!("HELLO 3 should answer with a map")
+    };
+
+    let mut confirmation = strings(&["subscribe", "events"]);
+    confirmation.push(Resp::Integer(1));
+    assert_eq!(c.call(&["SUBSCRIBE", "events"]), Resp::Push(confirmation));
+    // Other commands still work
+    assert_eq!(c.call(&["SET", "k", "v"]), ok());
+    assert_eq!(
+        publisher.call(&["PUBLISH", "events", "42"]),
+        Resp::Integer(1)
+    );
+    assert_eq!(c.read(), Resp::Push(strings(&["message", "events", "42"])));
+    assert_eq!(c.call(&["GET", "k"]), bulk("v"));
+
+    stop(addr, handle);
+}
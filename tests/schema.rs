@@ -0,0 +1,327 @@
+// Schemas: which writes they stop, what validate_all reports, and how
+// required, typed and nested fields are checked
+
+mod common;
+
+use std::collections::HashMap;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use common::{open, temp_db};
+use littledb::{
+    Database, Decimal, PatchError, PatchOp, Schema, SchemaType, ValidationError, Value,
+};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-schema-unsaved.db");
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn object(fields: &[(&str, Value)]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn user_schema() -> Schema {
+    Schema::object()
+        .property("name", Schema::string().min_length(1))
+        .property("age", Schema::integer().minimum(0.0))
+        .property("email", Schema::string().pattern("^[^@]+@[^@]+$").unwrap())
+        .property(
+            "address",
+            Schema::object()
+                .property("city", Schema::string())
+                .property("zip", Schema::string().pattern("^[0-9]{4}$").unwrap())
+                .required("city"),
+        )
+        .property("tags", Schema::array(Schema::string()).max_items(3))
+        .required("name")
+        .required("age")
+}
+
+fn user(name: &str, age: i64) -> Value {
+    object(&[("name", text(name)), ("age", Value::Integer(age))])
+}
+
+fn with(mut value: Value, field: &str, field_value: Value) -> Value {
+    if let Value::Object(fields) = &mut value {
+        fields.insert(field.to_string(), field_value);
+    }
+    value
+}
+
+// The ValidationError inside an io::Error from a write
+fn rejected(result: io::Result<impl std::fmt::Debug>) -> ValidationError {
+    let error = result.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<ValidationError>())
+        .unwrap_or_else(|| panic!("not a ValidationError: {}", error))
+        .clone()
+}
+
+#[test]
+fn writes_that_do_not_fit_are_refused() {
+    let mut db = database();
+    db.set_schema("user:", user_schema()).unwrap();
+
+    db.insert("user:1".to_string(), user("Ann", 31)).unwrap();
+    let error = rejected(db.insert("user:2".to_string(), user("Bob", -1)));
+    assert_eq!(error.key.as_deref(), Some("user:2"));
+    assert_eq!(error.path, "age");
+    assert_eq!(db.get("user:2"), None);
+    // Other keys aren't checked
+    db.insert("order:1".to_string(), Value::Integer(-1))
+        .unwrap();
+
+    // Every kind of write is checked
+    assert!(db.update("user:1".to_string(), text("Ann")).is_err());
+    let ttl = Duration::from_secs(60);
+    let error = rejected(db.insert_with_ttl("user:3".to_string(), user("", 1), ttl));
+    assert_eq!(error.path, "name");
+    assert!(
+        rejected(db.insert_if_absent("user:4".to_string(), Value::Null))
+            .key
+            .is_some()
+    );
+    let patch = db.patch("user:1", vec![PatchOp::Unset("name".to_string())]);
+    assert!(matches!(patch, Err(PatchError::Invalid(e)) if e.path == "name"));
+    assert_eq!(db.get("user:1"), Some(user("Ann", 31)));
+
+    // A batch is written whole or not at all
+    let batch = vec![
+        ("user:5".to_string(), user("Cy", 5)),
+        ("user:6".to_string(), user("Di", -6)),
+    ];
+    assert_eq!(
+        rejected(db.batch_insert(batch)).key.as_deref(),
+        Some("user:6")
+    );
+    assert_eq!(db.get("user:5"), None);
+
+    // Without the schema, anything goes again
+    assert!(db.remove_schema("user:").unwrap());
+    assert!(!db.remove_schema("user:").unwrap());
+    db.insert("user:2".to_string(), user("Bob", -1)).unwrap();
+}
+
+#[test]
+fn every_schema_whose_prefix_matches_applies() {
+    let mut db = database();
+    db.set_schema("", Schema::any().or_type(SchemaType::Object))
+        .unwrap();
+    db.set_schema("user:", Schema::object().required("name"))
+        .unwrap();
+    db.set_schema("user:admin:", Schema::object().required("role"))
+        .unwrap();
+    assert_eq!(db.list_schemas(), vec!["", "user:", "user:admin:"]);
+
+    assert!(db.insert("plain".to_string(), Value::Integer(1)).is_err());
+    db.insert("plain".to_string(), object(&[])).unwrap();
+    assert!(db.insert("user:1".to_string(), object(&[])).is_err());
+    db.insert("user:1".to_string(), object(&[("name", text("a"))]))
+        .unwrap();
+    let error = rejected(db.insert("user:admin:1".to_string(), object(&[("name", text("a"))])));
+    assert_eq!(
+        error.to_string(),
+        "key 'user:admin:1': at `role`: required field is missing"
+    );
+}
+
+#[test]
+fn required_typed_and_nested_fields() {
+    let schema = user_schema();
+    let messages = |value: &Value| -> Vec<String> {
+        schema
+            .violations(value)
+            .iter()
+            .map(ValidationError::to_string)
+            .collect()
+    };
+    assert!(schema.validate(&user("Ann", 31)).is_ok());
+    assert_eq!(
+        messages(&object(&[])),
+        vec![
+            "at `name`: required field is missing",
+            "at `age`: required field is missing"
+        ]
+    );
+    // Integer is strict, and a type mismatch is the only error for the field
+    assert_eq!(
+        messages(&with(user("Ann", 0), "age", Value::Float(31.0))),
+        vec!["at `age`: expected integer, found Float"]
+    );
+    assert_eq!(
+        messages(&Value::Integer(1)),
+        vec!["expected object, found Integer"]
+    );
+
+    // Errors deep inside carry the whole path
+    let address = object(&[("zip", text("12345")), ("street", text("Main St"))]);
+    let tags = Value::Array(vec![text("a"), Value::Integer(2), text("c"), text("d")]);
+    let nested = with(
+        with(with(user("Ann", 31), "address", address), "tags", tags),
+        "email",
+        text("nobody"),
+    );
+    let errors = schema.violations(&nested);
+    let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec!["address.city", "address.zip", "email", "tags", "tags[1]"]
+    );
+    assert_eq!(
+        errors[1].message,
+        "\"12345\" doesn't match the pattern ^[0-9]{4}$"
+    );
+    assert_eq!(errors[3].message, "has 4 items, at most 3 allowed");
+
+    // Closed objects and the other value types
+    let closed = Schema::object()
+        .property("id", Schema::of_type(SchemaType::Uuid))
+        .property("price", Schema::number().exclusive_minimum(0.0))
+        .additional_properties(false);
+    let price = Value::Decimal("9.99".parse::<Decimal>().unwrap());
+    let id = Value::Uuid("67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap());
+    assert!(
+        closed
+            .validate(&object(&[("id", id), ("price", price)]))
+            .is_ok()
+    );
+    let errors = closed.violations(&object(&[
+        ("id", text("67e55044-10b1-426f-9247-bb680e5fe0c8")),
+        ("price", Value::Integer(0)),
+        ("extra", Value::Null),
+    ]));
+    let found: Vec<(&str, &str)> = errors
+        .iter()
+        .map(|e| (e.path.as_str(), e.message.as_str()))
+        .collect();
+    assert_eq!(found[0], ("extra", "field is not allowed by the schema"));
+    assert_eq!(found[1], ("id", "expected uuid, found String"));
+    assert_eq!(found[2].0, "price");
+    assert_eq!(found.len(), 3);
+}
+
+#[test]
+fn validate_all_reports_what_was_stored_before_the_schema() {
+    let mut db = database();
+    db.insert("user:3".to_string(), text("not a user")).unwrap();
+    db.insert("user:1".to_string(), user("Ann", 31)).unwrap();
+    db.insert("user:2".to_string(), object(&[("age", Value::Integer(-2))]))
+        .unwrap();
+    db.insert("other".to_string(), Value::Null).unwrap();
+    db.insert_with_ttl("user:0".to_string(), Value::Null, Duration::from_millis(1))
+        .unwrap();
+    thread::sleep(Duration::from_millis(5));
+
+    // Setting the schema leaves the stored values alone
+    db.set_schema("user:", user_schema()).unwrap();
+    assert_eq!(db.count(), 4);
+    let errors: Vec<String> = db
+        .validate_all()
+        .iter()
+        .map(ValidationError::to_string)
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            "key 'user:2': at `name`: required field is missing",
+            "key 'user:2': at `age`: -2 is less than the minimum 0.0",
+            "key 'user:3': expected object, found String",
+        ]
+    );
+
+    db.delete("user:3").unwrap();
+    db.insert("user:2".to_string(), user("Bob", 2)).unwrap();
+    assert!(db.validate_all().is_empty());
+
+    // Collections report their own
+    let mut items = db.collection("items");
+    items.insert("a".to_string(), text("x")).unwrap();
+    items.set_schema("", Schema::integer()).unwrap();
+    assert_eq!(items.validate_all()[0].key.as_deref(), Some("a"));
+    assert!(
+        rejected(items.insert("b".to_string(), text("y")))
+            .key
+            .is_some()
+    );
+    assert!(db.validate_all().is_empty());
+}
+
+#[test]
+fn schemas_are_saved_with_the_data() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.set_schema("user:", user_schema()).unwrap();
+    db.collection("items")
+        .set_schema("", Schema::integer())
+        .unwrap();
+
+    let mut reopened = open(&path);
+    assert_eq!(reopened.schema("user:"), Some(&user_schema()));
+    assert!(reopened.insert("user:1".to_string(), user("", 1)).is_err());
+    assert!(
+        reopened
+            .collection("items")
+            .insert("a".to_string(), text("x"))
+            .is_err()
+    );
+}
+
+#[test]
+fn json_schema_documents() {
+    let document = object(&[
+        (
+            "$schema",
+            text("https://json-schema.org/draft/2020-12/schema"),
+        ),
+        ("type", text("object")),
+        ("required", Value::Array(vec![text("name")])),
+        (
+            "properties",
+            object(&[
+                ("name", object(&[("type", text("string"))])),
+                (
+                    "age",
+                    object(&[
+                        ("type", Value::Array(vec![text("integer"), text("null")])),
+                        ("minimum", Value::Integer(0)),
+                    ]),
+                ),
+            ]),
+        ),
+    ]);
+    let schema = Schema::from_value(&document).unwrap();
+    let person = |age: Value| object(&[("name", text("a")), ("age", age)]);
+    assert!(schema.validate(&person(Value::Null)).is_ok());
+    assert!(schema.validate(&person(Value::Integer(0))).is_ok());
+    assert!(schema.validate(&person(Value::Integer(-1))).is_err());
+    assert!(schema.validate(&person(Value::Float(1.5))).is_err());
+    assert!(schema.validate(&object(&[])).is_err());
+
+    assert_eq!(
+        Schema::from_value(&object(&[("format", text("email"))])).unwrap_err(),
+        "at ``: unsupported keyword 'format'"
+    );
+    assert_eq!(
+        Schema::from_value(&object(&[(
+            "properties",
+            object(&[("a", object(&[("type", text("text"))]))])
+        )]))
+        .unwrap_err(),
+        "at `properties.a.type`: unknown type 'text'"
+    );
+}
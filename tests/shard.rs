@@ -0,0 +1,198 @@
+// Sharded databases: placement, fan-out, resharding and reopening
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use littledb::shard::ShardedDatabase;
+use littledb::{Condition, Schema, Store, StoreError, Transaction, Value};
+
+// A fresh path in the temp directory; the files a test leaves are removed
+// when the next run of that test starts
+fn scratch(name: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("littledb-shard-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("db").to_str().unwrap().to_string()
+}
+
+fn fill(db: &mut ShardedDatabase, count: i64) {
+    let entries = (0..count)
+        .map(|i| (format!("user:{:04}", i), Value::Integer(i)))
+        .collect();
+    db.batch_insert(entries).unwrap();
+}
+
+fn all_entries(db: &ShardedDatabase) -> Vec<(String, Value)> {
+    db.list_keys()
+        .into_iter()
+        .map(|key| {
+            let value = db.get(&key).unwrap();
+            (key, value)
+        })
+        .collect()
+}
+
+// Every key is on the shard the ring gives it, and on no other
+fn assert_placed(db: &ShardedDatabase) {
+    for id in db.shard_ids() {
+        for key in db.shard(id).unwrap().list_keys() {
+            assert_eq!(db.shard_of(&key), id, "{} is on the wrong shard", key);
+        }
+    }
+}
+
+#[test]
+fn spreads_keys_over_the_shards() {
+    let mut db = ShardedDatabase::open(&scratch("spread"), 4).unwrap();
+    fill(&mut db, 1000);
+    assert_eq!(db.count(), 1000);
+    assert_eq!(db.shard_ids(), vec![0, 1, 2, 3]);
+    for stats in db.stats() {
+        assert!(stats.keys > 100, "{:?}", stats);
+        assert_eq!(stats.points, 64);
+    }
+    assert_placed(&db);
+    assert_eq!(db.get("user:0042"), Some(Value::Integer(42)));
+    assert!(db.exists("user:0999"));
+    assert_eq!(db.get("nobody"), None);
+}
+
+#[test]
+fn fans_out_queries_and_listings() {
+    let mut db = ShardedDatabase::open(&scratch("fan-out"), 3).unwrap();
+    fill(&mut db, 300);
+    db.insert("admin".to_string(), Value::Integer(-1)).unwrap();
+
+    let keys = db.list_keys();
+    assert_eq!(keys.len(), 301);
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(db.keys_with_prefix("user:00").len(), 100);
+
+    let people = (0..300)
+        .map(|i| {
+            let person = HashMap::from([("age".to_string(), Value::Integer(i))]);
+            (format!("person:{:04}", i), Value::Object(person))
+        })
+        .collect();
+    db.batch_insert(people).unwrap();
+    db.create_index("age").unwrap();
+    let found = db.query(Condition::GreaterThan("age".to_string(), 289));
+    let found_keys: Vec<&str> = found.iter().map(|(key, _)| key.as_str()).collect();
+    let expected: Vec<String> = (290..300).map(|i| format!("person:{:04}", i)).collect();
+    assert_eq!(found_keys, expected);
+    assert_eq!(db.query(Condition::KeyPrefix("adm".to_string())).len(), 1);
+}
+
+#[test]
+fn writes_go_to_the_key_shard() {
+    let mut db = ShardedDatabase::open(&scratch("writes"), 4).unwrap();
+    db.insert("a".to_string(), Value::Integer(1)).unwrap();
+    db.update("a".to_string(), Value::Integer(2)).unwrap();
+    assert!(db.update("b".to_string(), Value::Integer(2)).is_err());
+    db.insert_with_ttl(
+        "session".to_string(),
+        Value::Integer(1),
+        Duration::from_secs(60),
+    )
+    .unwrap();
+    assert!(db.ttl("session").is_some());
+    let shard = db.shard(db.shard_of("a")).unwrap();
+    assert_eq!(shard.get("a"), Some(Value::Integer(2)));
+
+    fill(&mut db, 50);
+    assert_eq!(
+        db.batch_delete(vec!["user:0001", "user:0002", "missing"]),
+        2
+    );
+    db.delete("a").unwrap();
+    assert!(db.delete("a").is_err());
+    assert_eq!(db.count(), 49);
+    db.clear();
+    assert_eq!(db.count(), 0);
+}
+
+#[test]
+fn checks_batches_and_transactions_on_every_shard_first() {
+    let mut db = ShardedDatabase::open(&scratch("all-or-nothing"), 4).unwrap();
+    db.set_schema("n:", Schema::integer()).unwrap();
+    let mut entries: Vec<(String, Value)> = (0..20)
+        .map(|i| (format!("n:{}", i), Value::Integer(i)))
+        .collect();
+    entries.push(("n:bad".to_string(), Value::String("x".to_string())));
+    assert!(db.batch_insert(entries).is_err());
+    assert_eq!(db.count(), 0);
+
+    fill(&mut db, 20);
+    let mut tx = Transaction::new();
+    for i in 0..20 {
+        tx.insert(format!("user:{:04}", i), Value::Integer(i * 10));
+    }
+    let mut stale = Transaction::new();
+    stale.expect_version("user:0007", 1_000_000);
+    stale.insert("user:0001".to_string(), Value::Integer(-1));
+    assert!(db.commit(stale).is_err());
+    assert_eq!(db.get("user:0001"), Some(Value::Integer(1)));
+    db.commit(tx).unwrap();
+    assert_eq!(db.get("user:0019"), Some(Value::Integer(190)));
+}
+
+#[test]
+fn splits_and_merges_shards() {
+    let path = scratch("reshard");
+    let mut db = ShardedDatabase::open(&path, 2).unwrap();
+    db.set_schema("user:", Schema::integer()).unwrap();
+    fill(&mut db, 500);
+    db.insert_with_ttl(
+        "session".to_string(),
+        Value::Integer(1),
+        Duration::from_secs(600),
+    )
+    .unwrap();
+    let before = all_entries(&db);
+    let size_of_0 = db.shard(0).unwrap().count();
+
+    let new = db.split(0).unwrap();
+    assert_eq!(new, 2);
+    assert_eq!(db.shard_ids(), vec![0, 1, 2]);
+    assert_placed(&db);
+    assert_eq!(all_entries(&db), before);
+    let (kept, taken) = (db.shard(0).unwrap().count(), db.shard(2).unwrap().count());
+    assert_eq!(kept + taken, size_of_0);
+    assert!(taken > size_of_0 / 4 && kept > size_of_0 / 4);
+    assert!(db.ttl("session").is_some());
+    // The new shard has the schemas of the one it was split from
+    assert!(db.shard(2).unwrap().schema("user:").is_some());
+
+    // Reopened, the shards and the ring are as they were
+    drop(db);
+    let mut db = ShardedDatabase::open(&path, 9).unwrap();
+    assert_eq!(db.shard_ids(), vec![0, 1, 2]);
+    assert_eq!(all_entries(&db), before);
+
+    let moved = db.merge(1, 2).unwrap();
+    assert!(moved > 0);
+    assert_eq!(db.shard_ids(), vec![0, 2]);
+    assert_placed(&db);
+    assert_eq!(all_entries(&db), before);
+    assert!(!PathBuf::from(format!("{}.shard-1", path)).exists());
+    assert!(db.merge(1, 2).is_err());
+    assert!(db.merge(0, 0).is_err());
+
+    drop(db);
+    let db = ShardedDatabase::open(&path, 1).unwrap();
+    assert_eq!(all_entries(&db), before);
+}
+
+#[test]
+fn works_as_a_store() {
+    fn bump(store: &mut impl Store, key: &str) -> Result<u64, StoreError> {
+        let next = store.get_typed::<u64>(key)?.unwrap_or(0) + 1;
+        store.insert_typed(key.to_string(), &next)?;
+        Ok(next)
+    }
+    let mut db = ShardedDatabase::open(&scratch("store"), 3).unwrap();
+    bump(&mut db, "visits").unwrap();
+    assert_eq!(bump(&mut db, "visits"), Ok(2));
+    assert_eq!(Store::count(&db), Ok(1));
+}
@@ -0,0 +1,217 @@
+// Transactions: commit, discarding, and the version checks that turn a
+// concurrent write into a conflict
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::{open, temp_db};
+use littledb::{Database, Schema, Store, Transaction, TransactionError, Value};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-transaction-unsaved.db");
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db
+}
+
+fn balance(db: &Database, key: &str) -> i64 {
+    match db.get(key) {
+        Some(Value::Integer(n)) => n,
+        other => panic!("{}: {:?}", key, other),
+    }
+}
+
+// Move `amount` from one account to the other, inside the transaction
+fn transfer(tx: &mut Transaction, db: &Database, from: &str, to: &str, amount: i64) {
+    let from_balance = match tx.get(db, from) {
+        Some(Value::Integer(n)) => n,
+        other => panic!("{:?}", other),
+    };
+    let to_balance = match tx.get(db, to) {
+        Some(Value::Integer(n)) => n,
+        other => panic!("{:?}", other),
+    };
+    tx.insert(from.to_string(), Value::Integer(from_balance - amount));
+    tx.insert(to.to_string(), Value::Integer(to_balance + amount));
+}
+
+fn accounts() -> Database {
+    let mut db = database();
+    db.insert("a".to_string(), Value::Integer(100)).unwrap();
+    db.insert("b".to_string(), Value::Integer(50)).unwrap();
+    db
+}
+
+#[test]
+fn commit_applies_every_write() {
+    let mut db = accounts();
+    db.insert("pending".to_string(), Value::Null).unwrap();
+    let mut tx = Transaction::new();
+    transfer(&mut tx, &db, "a", "b", 30);
+    tx.delete("pending");
+    tx.insert("log:1".to_string(), Value::String("a -> b".to_string()));
+
+    // Nothing is written before commit, but the transaction sees its writes
+    assert_eq!(balance(&db, "a"), 100);
+    assert_eq!(tx.get(&db, "a"), Some(Value::Integer(70)));
+    assert_eq!(tx.get(&db, "pending"), None);
+    assert_eq!(tx.len(), 4);
+    assert_eq!(
+        tx.writes().map(|(key, _)| key).collect::<Vec<_>>(),
+        vec!["a", "b", "log:1", "pending"]
+    );
+
+    db.commit(tx).unwrap();
+    assert_eq!((balance(&db, "a"), balance(&db, "b")), (70, 80));
+    assert_eq!(db.list_keys(), vec!["a", "b", "log:1"]);
+}
+
+#[test]
+fn dropping_a_transaction_rolls_it_back() {
+    let mut db = accounts();
+    {
+        let mut tx = Transaction::new();
+        transfer(&mut tx, &db, "a", "b", 30);
+        tx.delete("b");
+    }
+    assert_eq!((balance(&db, "a"), balance(&db, "b")), (100, 50));
+
+    // An empty transaction commits and changes nothing
+    let mut tx = Transaction::new();
+    assert_eq!(tx.get(&db, "a"), Some(Value::Integer(100)));
+    assert!(tx.is_empty());
+    let version = db.version("a");
+    db.commit(tx).unwrap();
+    assert_eq!(db.version("a"), version);
+}
+
+#[test]
+fn a_key_written_after_it_was_read_is_a_conflict() {
+    let mut db = accounts();
+    let mut tx = Transaction::new();
+    transfer(&mut tx, &db, "a", "b", 30);
+
+    // Someone else writes "b" in the meantime, with the same value even
+    db.insert("b".to_string(), Value::Integer(50)).unwrap();
+    assert_eq!(
+        db.commit(tx.clone()),
+        Err(TransactionError::Conflict("b".to_string()))
+    );
+    // Nothing was applied, not even the write to "a"
+    assert_eq!(balance(&db, "a"), 100);
+    assert_eq!(
+        TransactionError::Conflict("b".to_string()).to_string(),
+        "conflict: key 'b' changed since the transaction read it"
+    );
+
+    // Deleting counts as a change
+    let mut tx = Transaction::new();
+    transfer(&mut tx, &db, "a", "b", 30);
+    db.delete("a").unwrap();
+    assert_eq!(
+        db.commit(tx),
+        Err(TransactionError::Conflict("a".to_string()))
+    );
+}
+
+#[test]
+fn missing_keys_are_read_as_version_0() {
+    let mut db = database();
+    // Read as missing, then created by someone else
+    let mut tx = Transaction::new();
+    assert_eq!(tx.get(&db, "lock"), None);
+    assert_eq!(tx.reads().collect::<Vec<_>>(), vec![("lock", 0)]);
+    tx.insert("lock".to_string(), Value::String("ann".to_string()));
+    db.insert("lock".to_string(), Value::String("bob".to_string()))
+        .unwrap();
+    assert_eq!(
+        db.commit(tx),
+        Err(TransactionError::Conflict("lock".to_string()))
+    );
+
+    // A key that expired since it was read no longer has its version
+    let mut tx = Transaction::new();
+    assert!(tx.get(&db, "lock").is_some());
+    tx.insert("lock".to_string(), Value::String("ann".to_string()));
+    db.expire("lock", Duration::from_millis(1)).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    assert_eq!(
+        db.commit(tx),
+        Err(TransactionError::Conflict("lock".to_string()))
+    );
+
+    // ... and one read once it had expired reads as missing
+    let mut tx = Transaction::new();
+    assert_eq!(tx.get(&db, "lock"), None);
+    tx.insert("lock".to_string(), Value::String("cy".to_string()));
+    db.commit(tx).unwrap();
+    assert_eq!(db.get("lock"), Some(Value::String("cy".to_string())));
+}
+
+#[test]
+fn keys_only_written_are_not_checked() {
+    let mut db = accounts();
+    let mut tx = Transaction::new();
+    tx.insert("a".to_string(), Value::Integer(1));
+    db.insert("a".to_string(), Value::Integer(2)).unwrap();
+    db.commit(tx).unwrap();
+    assert_eq!(balance(&db, "a"), 1); // last writer wins
+
+    // Unless the transaction says which version it expects
+    let mut tx = Transaction::new();
+    let (_, version) = db.get_with_version("a").unwrap();
+    tx.expect_version("a", version);
+    tx.expect_version("new", 0);
+    tx.insert("a".to_string(), Value::Integer(3));
+    db.insert("a".to_string(), Value::Integer(4)).unwrap();
+    assert_eq!(
+        db.commit(tx),
+        Err(TransactionError::Conflict("a".to_string()))
+    );
+    assert_eq!(balance(&db, "a"), 4);
+}
+
+#[test]
+fn every_value_must_fit_its_schema() {
+    let mut db = accounts();
+    db.set_schema("", Schema::integer().minimum(0.0)).unwrap();
+    let mut tx = Transaction::new();
+    transfer(&mut tx, &db, "a", "b", 130);
+    let Err(TransactionError::Invalid(error)) = db.commit(tx) else {
+        panic!("the overdrawn account should be refused");
+    };
+    assert_eq!(error.key.as_deref(), Some("a"));
+    assert_eq!((balance(&db, "a"), balance(&db, "b")), (100, 50));
+}
+
+#[test]
+fn store_reads_record_versions_too() {
+    let mut db = accounts();
+    let mut tx = Transaction::new();
+    assert_eq!(tx.read(&db, "a").unwrap(), Some(Value::Integer(100)));
+    assert_eq!(tx.read(&db, "zzz").unwrap(), None);
+    assert_eq!(
+        tx.reads().collect::<Vec<_>>(),
+        vec![("a", db.version("a").unwrap()), ("zzz", 0)]
+    );
+    tx.insert("a".to_string(), Value::Integer(0));
+    assert_eq!(tx.read(&db, "a").unwrap(), Some(Value::Integer(0)));
+    db.insert("zzz".to_string(), Value::Null).unwrap();
+    assert!(Store::commit(&mut db, tx).is_err());
+}
+
+#[test]
+fn a_commit_is_saved_once() {
+    let path = temp_db();
+    let mut db = open(&path);
+    db.insert("a".to_string(), Value::Integer(100)).unwrap();
+    db.insert("b".to_string(), Value::Integer(50)).unwrap();
+    let mut tx = Transaction::new();
+    transfer(&mut tx, &db, "a", "b", 10);
+    db.commit(tx).unwrap();
+
+    let reopened = open(&path);
+    assert_eq!((balance(&reopened, "a"), balance(&reopened, "b")), (90, 60));
+}
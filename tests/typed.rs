@@ -0,0 +1,270 @@
+// Typed documents: how Rust data maps to Value and back, and what the errors
+// say when it doesn't fit
+
+use std::collections::{BTreeMap, HashMap};
+
+use littledb::{Condition, Database, TypedError, Value, from_value, to_value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Address {
+    city: String,
+    zip: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+    age: u32,
+    email: Option<String>,
+    tags: Vec<String>,
+    address: Address,
+    score: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Shape {
+    Point,
+    Circle(f64),
+    Pair(i32, i32),
+    Rect { width: u32, height: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Meters(f64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct UserId(u64);
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn object(fields: &[(&str, Value)]) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn ann() -> User {
+    User {
+        name: "Ann".to_string(),
+        age: 31,
+        email: None,
+        tags: vec!["admin".to_string(), "ops".to_string()],
+        address: Address {
+            city: "Oslo".to_string(),
+            zip: 150,
+        },
+        score: 7.5,
+    }
+}
+
+fn ann_value() -> Value {
+    object(&[
+        ("name", text("Ann")),
+        ("age", Value::Integer(31)),
+        ("email", Value::Null),
+        ("tags", Value::Array(vec![text("admin"), text("ops")])),
+        (
+            "address",
+            object(&[("city", text("Oslo")), ("zip", Value::Integer(150))]),
+        ),
+        ("score", Value::Float(7.5)),
+    ])
+}
+
+fn error_text<T: for<'de> Deserialize<'de>>(value: Value) -> String {
+    match from_value::<T>(value) {
+        Ok(_) => panic!("expected an error"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn structs_map_to_objects() {
+    assert_eq!(to_value(&ann()).unwrap(), ann_value());
+    assert_eq!(from_value::<User>(ann_value()).unwrap(), ann());
+
+    // Reading back is lenient where nothing is lost
+    let mut lenient = ann_value();
+    if let Value::Object(fields) = &mut lenient {
+        fields.insert("score".to_string(), Value::Integer(7));
+        fields.remove("email");
+        fields.insert("unknown".to_string(), Value::Boolean(true));
+    }
+    let read = from_value::<User>(lenient).unwrap();
+    assert_eq!((read.score, read.email), (7.0, None));
+}
+
+#[test]
+fn options_are_null_or_the_value() {
+    assert_eq!(to_value(&None::<u32>).unwrap(), Value::Null);
+    assert_eq!(to_value(&Some(3u32)).unwrap(), Value::Integer(3));
+    assert_eq!(from_value::<Option<u32>>(Value::Null).unwrap(), None);
+    assert_eq!(
+        from_value::<Option<u32>>(Value::Integer(3)).unwrap(),
+        Some(3)
+    );
+    let mut user = ann();
+    user.email = Some("ann@example.com".to_string());
+    let value = to_value(&user).unwrap();
+    assert_eq!(value.get_field("email"), Some(&text("ann@example.com")));
+    assert_eq!(from_value::<User>(value).unwrap(), user);
+    assert_eq!(to_value(&()).unwrap(), Value::Null);
+}
+
+#[test]
+fn enums_are_names_or_single_entry_objects() {
+    let cases = [
+        (Shape::Point, text("Point")),
+        (Shape::Circle(1.5), object(&[("Circle", Value::Float(1.5))])),
+        (
+            Shape::Pair(-1, 2),
+            object(&[(
+                "Pair",
+                Value::Array(vec![Value::Integer(-1), Value::Integer(2)]),
+            )]),
+        ),
+        (
+            Shape::Rect {
+                width: 3,
+                height: 4,
+            },
+            object(&[(
+                "Rect",
+                object(&[("width", Value::Integer(3)), ("height", Value::Integer(4))]),
+            )]),
+        ),
+    ];
+    for (shape, value) in cases {
+        assert_eq!(to_value(&shape).unwrap(), value, "{:?}", shape);
+        assert_eq!(from_value::<Shape>(value).unwrap(), shape);
+    }
+    assert!(
+        error_text::<Shape>(text("Triangle")).contains("unknown variant `Triangle`"),
+        "{}",
+        error_text::<Shape>(text("Triangle"))
+    );
+}
+
+#[test]
+fn maps_become_objects_with_string_keys() {
+    let scores = HashMap::from([("ann".to_string(), 3), ("bob".to_string(), -1)]);
+    let value = to_value(&scores).unwrap();
+    assert_eq!(
+        value,
+        object(&[("ann", Value::Integer(3)), ("bob", Value::Integer(-1))])
+    );
+    assert_eq!(from_value::<HashMap<String, i32>>(value).unwrap(), scores);
+
+    // Integer keys are written as their digits and parsed back
+    let by_id = BTreeMap::from([(1u32, "one".to_string()), (20, "twenty".to_string())]);
+    let value = to_value(&by_id).unwrap();
+    assert_eq!(value, object(&[("1", text("one")), ("20", text("twenty"))]));
+    assert_eq!(from_value::<BTreeMap<u32, String>>(value).unwrap(), by_id);
+
+    let by_flag = HashMap::from([(true, 1)]);
+    assert!(to_value(&by_flag).is_err());
+}
+
+#[test]
+fn newtypes_are_their_inner_value() {
+    assert_eq!(to_value(&Meters(2.5)).unwrap(), Value::Float(2.5));
+    assert_eq!(to_value(&UserId(42)).unwrap(), Value::Integer(42));
+    assert_eq!(
+        from_value::<Meters>(Value::Integer(3)).unwrap(),
+        Meters(3.0)
+    );
+    assert_eq!(
+        from_value::<UserId>(Value::Integer(42)).unwrap(),
+        UserId(42)
+    );
+    assert_eq!(
+        to_value(&vec![UserId(1), UserId(2)]).unwrap(),
+        Value::Array(vec![Value::Integer(1), Value::Integer(2)])
+    );
+}
+
+#[test]
+fn errors_say_where_they_happened() {
+    let mut value = ann_value();
+    if let Value::Object(fields) = &mut value {
+        fields.insert(
+            "address".to_string(),
+            object(&[("city", text("Oslo")), ("zip", text("abc"))]),
+        );
+    }
+    assert_eq!(
+        error_text::<User>(value),
+        "at `address.zip`: invalid type: string \"abc\", expected u32"
+    );
+
+    let mut value = ann_value();
+    if let Value::Object(fields) = &mut value {
+        fields.insert(
+            "tags".to_string(),
+            Value::Array(vec![text("a"), Value::Integer(5)]),
+        );
+    }
+    assert_eq!(
+        error_text::<User>(value),
+        "at `tags[1]`: invalid type: integer `5`, expected a string"
+    );
+
+    let mut value = ann_value();
+    if let Value::Object(fields) = &mut value {
+        fields.remove("name");
+    }
+    assert_eq!(error_text::<User>(value), "missing field `name`");
+    assert_eq!(
+        error_text::<u8>(Value::Integer(300)),
+        "invalid value: integer `300`, expected u8"
+    );
+
+    // Too large for an Integer
+    let error = to_value(&u64::MAX).unwrap_err();
+    assert!(error.message.contains("18446744073709551615"), "{}", error);
+    let nested = HashMap::from([("big".to_string(), vec![1, u64::MAX])]);
+    assert_eq!(to_value(&nested).unwrap_err().path, "big[1]");
+}
+
+#[test]
+fn databases_store_typed_documents() {
+    let mut db = Database::new("littledb-typed-unsaved.db");
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db.insert_typed("user:1".to_string(), &ann()).unwrap();
+    let mut bob = ann();
+    bob.name = "Bob".to_string();
+    bob.age = 25;
+    db.insert_typed("user:2".to_string(), &bob).unwrap();
+    db.insert("user:3".to_string(), text("not a user")).unwrap();
+
+    assert_eq!(db.get("user:1"), Some(ann_value()));
+    assert_eq!(db.get_typed::<User>("user:1").unwrap(), Some(ann()));
+    assert_eq!(db.get_typed::<User>("user:9").unwrap(), None);
+    let error: TypedError = db.get_typed::<User>("user:3").unwrap_err();
+    assert_eq!(error.key.as_deref(), Some("user:3"));
+    assert!(error.to_string().starts_with("key 'user:3': "), "{}", error);
+
+    let young = Condition::LessThan("age".to_string(), 30);
+    assert_eq!(
+        db.query_typed::<User>(young).unwrap(),
+        vec![("user:2".to_string(), bob)]
+    );
+    let everyone = Condition::KeyPrefix("user:".to_string());
+    assert_eq!(
+        db.query_typed::<User>(everyone).unwrap_err().key.as_deref(),
+        Some("user:3")
+    );
+    assert!(
+        db.insert_typed("big".to_string(), &u64::MAX)
+            .unwrap_err()
+            .to_string()
+            .starts_with("key 'big': ")
+    );
+}
@@ -0,0 +1,118 @@
+// Ordering values: numbers of different variants compare exactly, so the
+// order stays a total order (sorting, map keys and indexes rely on it)
+
+use std::cmp::Ordering;
+
+use littledb::{Decimal, Value};
+
+fn decimal(text: &str) -> Value {
+    Value::Decimal(text.parse::<Decimal>().unwrap())
+}
+
+// Numbers near the spots where f64 runs out of precision, in every variant
+fn numbers() -> Vec<Value> {
+    let mut numbers = vec![
+        decimal("9007199254740993.5"),
+        Value::Integer(9007199254740994),
+        Value::Float(9007199254740994.0),
+        Value::Integer(9007199254740993),
+        decimal("9007199254740993"),
+        Value::Float(9007199254740992.0),
+        decimal("0.1"),
+        Value::Float(0.1),
+        decimal("0.1000000000000000055511151231"),
+        decimal("0.1000000000000000055511151232"),
+        decimal("-0.1"),
+        Value::Float(-0.1),
+        Value::Float(0.5),
+        decimal("0.5"),
+        decimal("0.50"),
+        Value::Integer(0),
+        Value::Float(-0.0),
+        decimal("0"),
+        decimal("0.0000000000000000000000000001"),
+        Value::Float(1e-300),
+        Value::Float(f64::MIN_POSITIVE / 4.0),
+        Value::Integer(i64::MAX),
+        Value::Float(9_223_372_036_854_775_808.0), // 2^63,
+        decimal("9223372036854775807.5"),
+        Value::Integer(i64::MIN),
+        decimal("-9223372036854775808.5"),
+        Value::Float(-9_223_372_036_854_775_808.0),
+        decimal("170141183460469231731687303715884105727"),
+        Value::Float(1.7014118346046923e38),
+        Value::Float(1e300),
+        Value::Float(f64::INFINITY),
+        Value::Float(f64::NEG_INFINITY),
+        Value::Float(f64::NAN),
+        decimal("-12.75"),
+        Value::Float(-12.75),
+        Value::Integer(-13),
+    ];
+    numbers.extend((-3..=3).map(|i| Value::Float(i as f64 + 0.25)));
+    numbers
+}
+
+#[test]
+fn mixed_numbers_compare_transitively() {
+    let numbers = numbers();
+    for a in &numbers {
+        for b in &numbers {
+            assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{:?} vs {:?}", a, b);
+            for c in &numbers {
+                if a <= b && b <= c {
+                    assert!(
+                        a <= c,
+                        "{:?} <= {:?} <= {:?} but not {:?} <= {:?}",
+                        a,
+                        b,
+                        c,
+                        a,
+                        c
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn decimals_and_floats_compare_exactly() {
+    let d = decimal("9007199254740993.5");
+    let i = Value::Integer(9007199254740994);
+    let f = Value::Float(9007199254740994.0);
+    assert!(d < i && i < f && d < f);
+
+    // 0.1 as a float is 0.1000000000000000055511151231257827...
+    assert!(decimal("0.1") < Value::Float(0.1));
+    assert!(decimal("0.1000000000000000055511151231") < Value::Float(0.1));
+    assert!(decimal("0.1000000000000000055511151232") > Value::Float(0.1));
+    assert_eq!(decimal("0.5").cmp(&Value::Float(0.5)), Ordering::Greater); // equal: the variant decides
+    assert!(decimal("0.0000000000000000000000000001") > Value::Float(1e-300));
+    assert!(decimal("-12.75") > Value::Float(-12.76));
+    assert!(Value::Float(f64::NAN) > decimal("170141183460469231731687303715884105727"));
+
+    let mut sorted = numbers();
+    sorted.sort();
+    assert!(sorted.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn decimals_with_too_large_a_scale_are_not_loaded() {
+    // A Decimal is written as its mantissa, then its scale
+    let written = bincode::serialize(&(5i128, 2u32)).unwrap();
+    let read: Decimal = bincode::deserialize(&written).unwrap();
+    assert_eq!(read, "0.05".parse().unwrap());
+
+    let corrupt = bincode::serialize(&(5i128, 40u32)).unwrap();
+    assert!(bincode::deserialize::<Decimal>(&corrupt).is_err());
+    let value = bincode::serialize(&Value::Decimal("1.5".parse().unwrap())).unwrap();
+    let mut tampered = value.clone();
+    let scale_at = tampered.len() - 4;
+    tampered[scale_at] = 200;
+    assert!(bincode::deserialize::<Value>(&tampered).is_err());
+    assert_eq!(
+        bincode::deserialize::<Value>(&value).unwrap(),
+        Value::Decimal("1.5".parse().unwrap())
+    );
+}
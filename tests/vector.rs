@@ -0,0 +1,227 @@
+// Vector indexes: exact and HNSW search, the three metrics, and the sidecar
+// file the index is saved in
+
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use common::{open, temp_db};
+use littledb::{Condition, Database, Metric, Value, VectorIndexOptions};
+
+fn database() -> Database {
+    let mut db = Database::new("littledb-vector-unsaved.db");
+    db.set_verbose(false);
+    db.set_auto_save(false);
+    db
+}
+
+fn item(vector: &[f32], group: &str) -> Value {
+    Value::Object(HashMap::from([
+        (
+            "embedding".to_string(),
+            Value::Array(vector.iter().map(|x| Value::Float(*x as f64)).collect()),
+        ),
+        ("group".to_string(), Value::String(group.to_string())),
+    ]))
+}
+
+fn nearest(db: &Database, query: &[f32], k: usize) -> Vec<String> {
+    db.nearest("embedding", query, k, None)
+        .unwrap()
+        .into_iter()
+        .map(|neighbor| neighbor.key)
+        .collect()
+}
+
+// Deterministic pseudo-random vectors (a linear congruential generator)
+fn random_vectors(count: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((state >> 33) as f32 / (1u64 << 31) as f32) * 2.0 - 1.0
+    };
+    (0..count)
+        .map(|_| (0..dimension).map(|_| next()).collect())
+        .collect()
+}
+
+#[test]
+fn metrics_measure_the_expected_distances() {
+    let a = [1.0, 0.0];
+    let b = [3.0, 4.0];
+    assert_eq!(Metric::L2.distance(&a, &b), 20f32.sqrt());
+    assert_eq!(Metric::DotProduct.distance(&a, &b), -3.0);
+    assert!((Metric::Cosine.distance(&a, &b) - 0.4).abs() < 1e-6);
+    assert_eq!(Metric::Cosine.distance(&a, &[-2.0, 0.0]), 2.0);
+    // A zero vector has no direction
+    assert_eq!(Metric::Cosine.distance(&a, &[0.0, 0.0]), 1.0);
+}
+
+#[test]
+fn each_metric_ranks_its_own_way() {
+    let query = [1.0, 0.0];
+    let mut rankings = Vec::new();
+    for metric in [Metric::L2, Metric::Cosine, Metric::DotProduct] {
+        let mut db = database();
+        db.insert("long".to_string(), item(&[10.0, 0.0], "a"))
+            .unwrap();
+        db.insert("close".to_string(), item(&[0.9, 0.1], "a"))
+            .unwrap();
+        db.insert("aside".to_string(), item(&[0.0, 2.0], "a"))
+            .unwrap();
+        db.create_vector_index("embedding", VectorIndexOptions::exact(metric))
+            .unwrap();
+        rankings.push(nearest(&db, &query, 3));
+    }
+    assert_eq!(
+        rankings,
+        vec![
+            vec!["close", "aside", "long"], // L2: how far apart
+            vec!["long", "close", "aside"], // Cosine: the angle only
+            vec!["long", "close", "aside"], // Dot product: angle and length
+        ]
+    );
+}
+
+#[test]
+fn exact_search_returns_the_k_closest_in_order() {
+    let mut db = database();
+    for i in 0..20 {
+        let group = if i % 2 == 0 { "even" } else { "odd" };
+        db.insert(format!("p{:02}", i), item(&[i as f32, 0.0], group))
+            .unwrap();
+    }
+    // Not vectors: left out of the index
+    db.insert("text".to_string(), Value::String("x".to_string()))
+        .unwrap();
+    let mut mixed = item(&[0.0, 0.0], "even");
+    if let Value::Object(fields) = &mut mixed {
+        fields.insert(
+            "embedding".to_string(),
+            Value::Array(vec![Value::Integer(1), Value::Boolean(true)]),
+        );
+    }
+    db.insert("mixed".to_string(), mixed).unwrap();
+    db.create_vector_index("embedding", VectorIndexOptions::exact(Metric::L2))
+        .unwrap();
+
+    let neighbors = db.nearest("embedding", &[6.2, 0.0], 3, None).unwrap();
+    let found: Vec<(&str, f32)> = neighbors
+        .iter()
+        .map(|n| (n.key.as_str(), n.distance))
+        .collect();
+    assert_eq!(found[0].0, "p06");
+    assert_eq!(found[1].0, "p07");
+    assert_eq!(found[2].0, "p05");
+    assert!((found[0].1 - 0.2).abs() < 1e-5);
+    assert_eq!(neighbors[0].value, item(&[6.0, 0.0], "even"));
+
+    let odd = Condition::Equals("group".to_string(), Value::String("odd".to_string()));
+    let filtered: Vec<String> = db
+        .nearest("embedding", &[6.2, 0.0], 2, Some(odd))
+        .unwrap()
+        .into_iter()
+        .map(|n| n.key)
+        .collect();
+    assert_eq!(filtered, vec!["p07", "p05"]);
+    assert_eq!(nearest(&db, &[0.0, 0.0], 100).len(), 20);
+
+    // Writes after the index exists keep it up to date
+    db.insert("p06".to_string(), item(&[100.0, 0.0], "even"))
+        .unwrap();
+    db.delete("p07").unwrap();
+    assert_eq!(nearest(&db, &[6.2, 0.0], 2), vec!["p05", "p08"]);
+}
+
+#[test]
+fn vectors_must_have_the_index_dimension() {
+    let mut db = database();
+    db.insert("short".to_string(), item(&[1.0, 2.0], "a"))
+        .unwrap();
+    db.insert("right".to_string(), item(&[1.0, 2.0, 3.0], "a"))
+        .unwrap();
+    let options = VectorIndexOptions::exact(Metric::L2).dimension(3);
+    assert!(db.create_vector_index("embedding", options).unwrap());
+    assert!(!db.create_vector_index("embedding", options).unwrap());
+
+    assert_eq!(nearest(&db, &[0.0, 0.0, 0.0], 5), vec!["right"]);
+    assert_eq!(
+        db.nearest("embedding", &[0.0, 0.0], 5, None).unwrap_err(),
+        "Query vector has 2 dimensions, index on 'embedding' expects 3"
+    );
+    assert!(db.nearest("other", &[0.0, 0.0, 0.0], 5, None).is_err());
+
+    // Without a dimension, the first vector indexed sets it
+    let mut db = database();
+    db.insert("first".to_string(), item(&[1.0, 2.0], "a"))
+        .unwrap();
+    db.create_vector_index("embedding", VectorIndexOptions::exact(Metric::L2))
+        .unwrap();
+    db.insert("longer".to_string(), item(&[1.0, 2.0, 3.0], "a"))
+        .unwrap();
+    assert_eq!(nearest(&db, &[0.0, 0.0], 5), vec!["first"]);
+    assert!(db.nearest("embedding", &[0.0, 0.0, 0.0], 5, None).is_err());
+}
+
+#[test]
+fn hnsw_finds_nearly_what_exact_search_finds() {
+    let vectors = random_vectors(500, 16, 7);
+    for metric in [Metric::L2, Metric::Cosine, Metric::DotProduct] {
+        let mut exact = database();
+        let mut approximate = database();
+        for (i, vector) in vectors.iter().enumerate() {
+            exact.insert(format!("v{}", i), item(vector, "a")).unwrap();
+            approximate
+                .insert(format!("v{}", i), item(vector, "a"))
+                .unwrap();
+        }
+        exact
+            .create_vector_index("embedding", VectorIndexOptions::exact(metric))
+            .unwrap();
+        approximate
+            .create_vector_index("embedding", VectorIndexOptions::hnsw(metric))
+            .unwrap();
+
+        let (mut found, mut wanted) = (0, 0);
+        for query in random_vectors(30, 16, 99) {
+            let truth = nearest(&exact, &query, 10);
+            let answer = nearest(&approximate, &query, 10);
+            wanted += truth.len();
+            found += answer.iter().filter(|key| truth.contains(key)).count();
+        }
+        let recall = found as f64 / wanted as f64;
+        assert!(recall >= 0.9, "{:?}: recall {}", metric, recall);
+    }
+}
+
+#[test]
+fn the_index_is_saved_beside_the_database() {
+    let path = temp_db();
+    let sidecar = format!("{}.vectors", path);
+    let mut db = open(&path);
+    for (i, vector) in random_vectors(50, 4, 3).iter().enumerate() {
+        db.insert(format!("v{:02}", i), item(vector, "a")).unwrap();
+    }
+    assert!(!Path::new(&sidecar).exists());
+
+    // Auto-save writes it as soon as the index exists
+    db.create_vector_index("embedding", VectorIndexOptions::hnsw(Metric::Cosine))
+        .unwrap();
+    assert!(Path::new(&sidecar).exists());
+    let query = [0.5, -0.5, 0.25, 0.0];
+    let before = nearest(&db, &query, 5);
+    assert_eq!(nearest(&open(&path), &query, 5), before);
+
+    // Writes after it are saved with it
+    db.insert(before[0].clone(), item(&[-0.5, 0.5, -0.25, 0.0], "a"))
+        .unwrap();
+    assert_eq!(nearest(&open(&path), &query, 4), before[1..].to_vec());
+
+    // Dropping the index removes the file
+    assert!(db.drop_index("embedding").unwrap());
+    assert!(!Path::new(&sidecar).exists());
+    assert!(open(&path).nearest("embedding", &query, 5, None).is_err());
+}
@@ -0,0 +1,171 @@
+// Watching keys: events, filters, resuming after a restart
+
+mod common;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use common::open;
+use littledb::{Condition, KeyChange, Transaction, Value, WatchError, WatchEvent};
+
+fn scratch(name: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("littledb-watch-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("db").to_str().unwrap().to_string()
+}
+
+fn changes(events: impl Iterator<Item = WatchEvent>) -> Vec<KeyChange> {
+    events.map(|event| event.change).collect()
+}
+
+fn person(age: i64) -> Value {
+    Value::Object(HashMap::from([("age".to_string(), Value::Integer(age))]))
+}
+
+#[test]
+fn reports_every_kind_of_change() {
+    let mut db = open(&scratch("kinds"));
+    let mut watcher = db.watch("").unwrap();
+    db.insert("a".to_string(), Value::Integer(1)).unwrap();
+    db.update("a".to_string(), Value::Integer(2)).unwrap();
+    db.batch_insert(vec![("b".to_string(), Value::Integer(3))])
+        .unwrap();
+    db.delete("a").unwrap();
+    db.batch_delete(vec!["b", "missing"]);
+    db.insert_with_ttl(
+        "session".to_string(),
+        Value::Integer(9),
+        Duration::from_millis(1),
+    )
+    .unwrap();
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(db.purge_expired(), 1);
+    db.clear();
+
+    assert_eq!(
+        changes(watcher.try_iter()),
+        vec![
+            KeyChange::Insert {
+                key: "a".to_string(),
+                value: Value::Integer(1)
+            },
+            KeyChange::Update {
+                key: "a".to_string(),
+                old: Value::Integer(1),
+                new: Value::Integer(2)
+            },
+            KeyChange::Insert {
+                key: "b".to_string(),
+                value: Value::Integer(3)
+            },
+            KeyChange::Delete {
+                key: "a".to_string(),
+                old: Value::Integer(2)
+            },
+            KeyChange::Delete {
+                key: "b".to_string(),
+                old: Value::Integer(3)
+            },
+            KeyChange::Insert {
+                key: "session".to_string(),
+                value: Value::Integer(9)
+            },
+            KeyChange::Expire {
+                key: "session".to_string(),
+                old: Value::Integer(9)
+            },
+            KeyChange::Clear,
+        ]
+    );
+    assert_eq!(watcher.last_seq(), 8);
+    assert_eq!(db.watch_seq(), 8);
+}
+
+#[test]
+fn filters_by_prefix_and_condition() {
+    let mut db = open(&scratch("filters"));
+    let mut users = db.watch("user:").unwrap();
+    let mut adults = db
+        .watch(Condition::GreaterThan("age".to_string(), 17))
+        .unwrap();
+    db.insert("user:1".to_string(), person(30)).unwrap();
+    db.insert("user:2".to_string(), person(10)).unwrap();
+    db.insert("other".to_string(), person(40)).unwrap();
+    // Leaves the condition: still reported to `adults`
+    db.update("user:1".to_string(), person(15)).unwrap();
+
+    let user_keys: Vec<String> = users
+        .try_iter()
+        .map(|event| event.change.key().unwrap().to_string())
+        .collect();
+    assert_eq!(user_keys, vec!["user:1", "user:2", "user:1"]);
+    let adult_seqs: Vec<u64> = adults.try_iter().map(|event| event.seq).collect();
+    assert_eq!(adult_seqs, vec![1, 3, 4]);
+
+    db.clear();
+    assert_eq!(users.try_next().unwrap().change, KeyChange::Clear);
+    assert_eq!(adults.try_next().unwrap().change, KeyChange::Clear);
+}
+
+#[test]
+fn reports_transactions_and_waits_for_events() {
+    let mut db = open(&scratch("transactions"));
+    db.insert("stock".to_string(), Value::Integer(5)).unwrap();
+    let mut watcher = db.watch("").unwrap();
+    let mut tx = Transaction::new();
+    tx.insert("stock".to_string(), Value::Integer(4));
+    tx.insert("order:1".to_string(), Value::Integer(1));
+    db.commit(tx).unwrap();
+
+    let waiting = thread::spawn(move || watcher.by_ref().take(2).count());
+    assert_eq!(waiting.join().unwrap(), 2);
+}
+
+#[test]
+fn resumes_after_a_restart() {
+    let path = scratch("resume");
+    let mut db = open(&path);
+    let mut watcher = db.watch("k").unwrap();
+    for i in 0..5 {
+        db.insert(format!("k{}", i), Value::Integer(i)).unwrap();
+    }
+    // The consumer handled three events, then went away
+    let handled: Vec<WatchEvent> = watcher.try_iter().take(3).collect();
+    let last = handled.last().unwrap().seq;
+    drop(watcher);
+    drop(db);
+
+    // Events saved with the data carry on in the next process
+    let mut db = open(&path);
+    db.insert("k5".to_string(), Value::Integer(5)).unwrap();
+    assert_eq!(db.watch_seq(), 6);
+    let mut watcher = db.watch_from("k", last).unwrap();
+    let keys: Vec<String> = watcher
+        .try_iter()
+        .map(|event| event.change.key().unwrap().to_string())
+        .collect();
+    assert_eq!(keys, vec!["k3", "k4", "k5"]);
+
+    assert_eq!(
+        db.watch_from("k", 99).err(),
+        Some(WatchError::Ahead { latest: 6 })
+    );
+}
+
+#[test]
+fn keeps_no_feed_until_watched() {
+    let path = scratch("unwatched");
+    let mut db = open(&path);
+    db.insert("a".to_string(), Value::Integer(1)).unwrap();
+    assert_eq!(db.watch_seq(), 0);
+    assert!(!PathBuf::from(format!("{}.events", path)).exists());
+
+    let mut watcher = db.watch("").unwrap();
+    assert_eq!(watcher.last_seq(), 0);
+    db.insert("b".to_string(), Value::Integer(2)).unwrap();
+    assert_eq!(watcher.try_next().unwrap().seq, 1);
+    assert!(PathBuf::from(format!("{}.events", path)).exists());
+}